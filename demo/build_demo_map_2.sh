@@ -5,6 +5,7 @@
 	-o maps/demo2.sqwm \
 	--materials-dir materials \
 	--textures-dir textures \
+	--models-dir models \
 	--perform-csg \
 	--perform-advanced-splitter-plane-selection \
 	&&\
@@ -32,6 +32,11 @@ pub struct BSPMap
 	pub light_grid_columns: Vec<LightGridColumn>,
 	// Combined values of light samples for each column.
 	pub light_grid_samples: Vec<LightGridElement>,
+
+	// Static models (not a part of BSP tree), placed by map compiler.
+	pub detail_objects: Vec<DetailObject>,
+	// Detail object may be located in several leafs.
+	pub leafs_detail_objects: Vec<u32>,
 }
 
 #[repr(C)]
@@ -53,6 +58,8 @@ pub struct BSPLeaf
 	pub num_polygons: u32,
 	pub first_leaf_portal: u32,
 	pub num_leaf_portals: u32,
+	pub first_leaf_detail_object: u32,
+	pub num_leaf_detail_objects: u32,
 }
 
 #[repr(C)]
@@ -134,6 +141,20 @@ pub struct StringRef
 	pub size: u16,
 }
 
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct DetailObject
+{
+	pub position: Vec3f,
+	pub rotation: QuaternionF,
+	// Model file name.
+	pub model: StringRef,
+	pub flags: u32,
+}
+
+pub const DETAIL_OBJECT_FLAG_PER_PIXEL_LIGHTING: u32 = 1 << 0;
+pub const DETAIL_OBJECT_FLAG_USE_MATERIALS: u32 = 1 << 1;
+
 pub const MAX_TEXTURE_NAME_LEN: usize = 64;
 // UTF-8 values of texture (name, path, or some id). Remaining symbols are filled with nulls.
 pub type Texture = [u8; MAX_TEXTURE_NAME_LEN];
@@ -190,6 +190,9 @@ fn convert_leaf_to_compact_format(
 		num_polygons: polygons_splitted.len() as u32,
 		first_leaf_portal,
 		num_leaf_portals: leaf.portals.len() as u32,
+		// Detail objects are filled later.
+		first_leaf_detail_object: 0,
+		num_leaf_detail_objects: 0,
 	}
 }
 
@@ -150,6 +150,18 @@ pub fn save_map(bsp_map: &BSPMap, file_path: &Path) -> Result<(), std::io::Error
 		&mut header.lumps[LUMP_LIGHT_GRID_SAMPLES],
 		&mut offset,
 	)?;
+	write_lump(
+		&bsp_map.detail_objects,
+		&mut file,
+		&mut header.lumps[LUMP_DETAIL_OBJECTS],
+		&mut offset,
+	)?;
+	write_lump(
+		&bsp_map.leafs_detail_objects,
+		&mut file,
+		&mut header.lumps[LUMP_LEAFS_DETAIL_OBJECTS],
+		&mut offset,
+	)?;
 
 	// Write header again to update lumps headers.
 	file.seek(std::io::SeekFrom::Start(0))?;
@@ -219,6 +231,8 @@ pub fn load_map(file_path: &Path) -> Result<Option<BSPMap>, std::io::Error>
 			.iter()
 			.map(LightGridElementCompressed::decompress)
 			.collect(),
+		detail_objects: read_lump(&mut file, &header.lumps[LUMP_DETAIL_OBJECTS])?,
+		leafs_detail_objects: read_lump(&mut file, &header.lumps[LUMP_LEAFS_DETAIL_OBJECTS])?,
 	};
 
 	Ok(Some(map))
@@ -241,7 +255,7 @@ struct Lump
 }
 
 const BSP_MAP_ID: [u8; 4] = *b"SqwM";
-const BSP_MAP_VERSION: u32 = 11; // Change each time when format is changed!
+const BSP_MAP_VERSION: u32 = 12; // Change each time when format is changed!
 
 const MAX_LUMPS: usize = 32;
 
@@ -262,6 +276,8 @@ const LUMP_DIRECTIONAL_LIGHTMAPS_DATA: usize = 13;
 const LUMP_LIGHT_GRID_HEADER: usize = 14;
 const LUMP_LIGHT_GRID_COLUMNS: usize = 15;
 const LUMP_LIGHT_GRID_SAMPLES: usize = 16;
+const LUMP_DETAIL_OBJECTS: usize = 17;
+const LUMP_LEAFS_DETAIL_OBJECTS: usize = 18;
 
 fn write_lump<T>(
 	data: &[T],
@@ -0,0 +1,89 @@
+use super::{bbox::*, bsp_map_compact::*, map_file_common, math_types::*};
+
+// Entities with this class name are converted into detail objects - static models, drawn by engine itself.
+pub const DETAIL_OBJECT_CLASSNAME: &str = "misc_detail_model";
+
+// Collect detail objects from map entities and place them in BSP leafs.
+// Model bbox getter is used to find all leafs where detail object is located.
+// If it returns nothing, object is placed only in leaf containing its origin.
+pub fn build_detail_objects(map: &mut BSPMap, get_model_bbox: &mut dyn FnMut(&str) -> Option<BBox>)
+{
+	let detail_objects = map
+		.entities
+		.iter()
+		.filter_map(|entity| parse_detail_object(map, entity))
+		.collect::<Vec<_>>();
+
+	let mut leafs_detail_objects = vec![Vec::new(); map.leafs.len()];
+	let mut object_leafs = Vec::new();
+	for (detail_object_index, detail_object) in detail_objects.iter().enumerate()
+	{
+		let bbox = get_model_bbox(get_map_string(detail_object.model, map)).unwrap_or_else(BBox::zero);
+		let bbox_vertices = bbox
+			.get_corners_vertices()
+			.map(|v| detail_object.position + detail_object.rotation.rotate_vector(v));
+
+		object_leafs.clear();
+		get_convex_hull_bsp_leafs(map, &bbox_vertices, &mut object_leafs);
+		for &leaf_index in &object_leafs
+		{
+			leafs_detail_objects[leaf_index as usize].push(detail_object_index as u32);
+		}
+	}
+
+	map.detail_objects = detail_objects;
+	map.leafs_detail_objects.clear();
+	for (leaf, leaf_detail_objects) in map.leafs.iter_mut().zip(leafs_detail_objects)
+	{
+		leaf.first_leaf_detail_object = map.leafs_detail_objects.len() as u32;
+		leaf.num_leaf_detail_objects = leaf_detail_objects.len() as u32;
+		map.leafs_detail_objects.extend(leaf_detail_objects);
+	}
+}
+
+fn parse_detail_object(map: &BSPMap, entity: &Entity) -> Option<DetailObject>
+{
+	let mut is_detail_object = false;
+	let mut model = None;
+	let mut origin = None;
+	let mut angle = None;
+	let mut angles = None;
+	let mut flags = 0;
+
+	for key_value_pair in &map.key_value_pairs[(entity.first_key_value_pair as usize) ..
+		((entity.first_key_value_pair + entity.num_key_value_pairs) as usize)]
+	{
+		let key = get_map_string(key_value_pair.key, map);
+		let value = get_map_string(key_value_pair.value, map);
+		match key
+		{
+			"classname" => is_detail_object = value == DETAIL_OBJECT_CLASSNAME,
+			"model" => model = Some(key_value_pair.value),
+			"origin" => origin = map_file_common::parse_vec3(value).ok(),
+			"angle" => angle = map_file_common::parse_key_value_number(value).ok(),
+			"angles" => angles = map_file_common::parse_vec3(value).ok(),
+			"per_pixel_lighting" if map_file_common::parse_key_value_number(value).unwrap_or(0.0) != 0.0 =>
+			{
+				flags |= DETAIL_OBJECT_FLAG_PER_PIXEL_LIGHTING
+			},
+			"use_materials" if map_file_common::parse_key_value_number(value).unwrap_or(0.0) != 0.0 =>
+			{
+				flags |= DETAIL_OBJECT_FLAG_USE_MATERIALS
+			},
+			_ =>
+			{},
+		}
+	}
+
+	if !is_detail_object
+	{
+		return None;
+	}
+
+	Some(DetailObject {
+		position: origin?,
+		rotation: map_file_common::get_entity_rotation_from_angles(angles, angle),
+		model: model?,
+		flags,
+	})
+}
@@ -159,3 +159,21 @@ pub fn parse_brush_plane_vertex(it: &mut Iterator) -> ParseResult<Vec3f>
 
 	Ok(result)
 }
+
+// Calculate entity rotation from values of "angles" key (in degrees) or "angle" key (yaw in degrees).
+// "angles" key has priority.
+pub fn get_entity_rotation_from_angles(angles: Option<Vec3f>, angle: Option<f32>) -> QuaternionF
+{
+	const TO_RAD: f32 = std::f32::consts::PI / 180.0;
+	if let Some(angles) = angles
+	{
+		// Angles in Quake and Quake editors (like TrenchBroom) are in order YZX.
+		QuaternionF::from_angle_z(Rad(angles.y * TO_RAD)) *
+			QuaternionF::from_angle_y(Rad(angles.x * TO_RAD)) *
+			QuaternionF::from_angle_x(Rad(angles.z * TO_RAD))
+	}
+	else
+	{
+		QuaternionF::from_angle_z(Rad(angle.unwrap_or(0.0) * TO_RAD))
+	}
+}
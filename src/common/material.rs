@@ -1,4 +1,6 @@
-use super::{material_fire::*, material_function::*, material_water::*};
+use super::{
+	material_fire::*, material_function::*, material_procedural::*, material_refraction::*, material_water::*,
+};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
@@ -171,6 +173,17 @@ pub enum SpecialMaterialEffect
 	/// Regular texture (diffuse, normal map, roughness, etc.) is not affected.
 	Fire(FireEffect),
 
+	/// Unreal-style refraction (ice, glass, etc.).
+	/// Texture of this material is distorted using scrolling distortion layer (normal map or height map of other material).
+	/// Note that sizes of both textures must be power of two.
+	/// Emissive texture is not affected.
+	Refraction(RefractionEffect),
+
+	/// Generate texture by evaluating for each texel expression graph, specified in material.
+	/// Produces diffuse and/or emissive texture (depending on settings).
+	/// If diffuse texture isn't produced, regular texture of this material is used.
+	Procedural(ProceduralTextureEffect),
+
 	/// Draw skybox instead of regular texture.
 	Skybox(SkyboxParams),
 }
@@ -210,11 +223,18 @@ pub struct LayeredAnimationLayer
 {
 	/// Material, that will be used for this layer.
 	/// blending mode of this material is used for blending of result animated texture.
-	/// It's important to know, that blending doesn't work for normal and roughness data.
-	/// Normal/roughness is written only with blending mode = none or alpha test. Modulation is not used.
+	/// Normal/roughness blending is controlled by separate option (see below).
 	/// Note that only static material texture is used, layered animation of that material can't be used, because this can cause infinite recursion.
 	pub material_name: String,
 
+	/// How to mix normals and roughness of this layer with normals and roughness of previous layers.
+	/// If none - normal/roughness is written only with blending mode = none or alpha test. Modulation is not used.
+	/// If some - blend factor is calculated based on blending mode of layer material:
+	/// 1 for none and additive, 0.5 for average, alpha for alpha blend, 0 or 1 for alpha test.
+	/// This factor is multiplied by modulation (maximum of color components, clamped to range [0, 1]).
+	#[serde(default)]
+	pub normals_blending_mode: Option<LayerNormalsBlendingMode>,
+
 	/// If true - fetch not layer material itself, but its current framed animation frame.
 	#[serde(default)]
 	pub follow_framed_animation: bool,
@@ -235,6 +255,20 @@ pub struct LayeredAnimationLayer
 	pub modulate_color: Option<[SingleArgumentFunction; 3]>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq)]
+pub enum LayerNormalsBlendingMode
+{
+	/// Replace normal and roughness of previous layers with normal and roughness of this layer.
+	/// Replacement is performed only if blend factor is at least 0.5.
+	Replace,
+	/// Mix normals and roughness of previous layers and this layer, using blend factor as weight.
+	WeightedAverage,
+	/// Apply normal of this layer as detail normal on top of normal of previous layers (reoriented normal mapping).
+	/// Blend factor is used to interpolate between previous normal and result normal.
+	/// Roughness is mixed using weighted average.
+	Reoriented,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct SkyboxParams
 {
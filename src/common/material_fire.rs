@@ -26,11 +26,31 @@ pub struct FireEffect
 	#[serde(default)]
 	pub color: Option<[f32; 3]>,
 
+	/// If some - produce also diffuse texture based on heat map.
+	/// This allows to use fire texture for surfaces without emissive layer.
+	#[serde(default)]
+	pub diffuse: Option<FireDiffuse>,
+
 	/// Sources of heat.
 	/// Without any source fire texture is completely dark and boring.
 	pub heat_sources: Vec<HeatSource>,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct FireDiffuse
+{
+	/// If some - use gradient from black to this color.
+	/// Color is in range [0; 1], out of range values will be clamped.
+	/// If none - same gradient as for emissive texture will be used.
+	#[serde(default)]
+	pub color: Option<[f32; 3]>,
+
+	/// If true - write heat into alpha channel of diffuse texture.
+	/// Use it for materials with AlphaTest or AlphaBlend blending modes.
+	#[serde(default)]
+	pub alpha: bool,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[allow(clippy::large_enum_variant)]
 pub enum HeatSource
@@ -0,0 +1,126 @@
+use super::material_function::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ProceduralTextureEffect
+{
+	/// Size of result texture.
+	/// Both components must be at least MAX_MIP.
+	pub resolution_log2: [u32; 2],
+
+	/// Number of texture updates, performed per second.
+	#[serde(default = "default_update_frequency")]
+	pub update_frequency: f32,
+
+	/// Nodes of expression graph, that is evaluated for each texel.
+	/// Node may use as arguments only previous nodes (referenced by index in this list).
+	pub nodes: Vec<ProceduralTextureNode>,
+
+	/// If some - produce diffuse texture, using values of these nodes as R, G, B components.
+	/// Values are in range [0; 1], out of range values will be clamped.
+	/// Normals are flat, roughness is taken from material.
+	#[serde(default)]
+	pub color: Option<[u32; 3]>,
+
+	/// If some - write value of this node into alpha channel of diffuse texture.
+	/// Use it for materials with AlphaTest or AlphaBlend blending modes.
+	#[serde(default)]
+	pub alpha: Option<u32>,
+
+	/// If some - produce emissive texture, using values of these nodes as R, G, B components.
+	/// Values are in range [0; 1], out of range values will be clamped.
+	#[serde(default)]
+	pub emissive: Option<[u32; 3]>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub enum ProceduralTextureNode
+{
+	/// y = c
+	Constant(f32),
+
+	/// Horizontal texture coordinate in range [0; 1).
+	U,
+
+	/// Vertical texture coordinate in range [0; 1).
+	V,
+
+	/// Current time (in seconds).
+	Time,
+
+	/// Apply function to value of other node.
+	Function
+	{
+		function: SingleArgumentFunction,
+		/// Node, which value is used as function argument.
+		arg: u32,
+	},
+
+	/// y = a + b
+	Add(u32, u32),
+
+	/// y = a - b
+	Sub(u32, u32),
+
+	/// y = a * b
+	Mul(u32, u32),
+
+	/// y = min(a, b)
+	Min(u32, u32),
+
+	/// y = max(a, b)
+	Max(u32, u32),
+
+	/// y = x - floor(x). Useful for repeating patterns.
+	Fract(u32),
+
+	/// Smooth tileable value noise in range [0; 1].
+	/// Coordinates are taken from given nodes and are wrapped into range [0; 1).
+	Noise
+	{
+		u: u32,
+		v: u32,
+
+		/// Number of noise cells along each axis.
+		#[serde(default = "default_noise_cells")]
+		cells: [u32; 2],
+
+		/// Use different seeds for different independent noise nodes.
+		#[serde(default)]
+		seed: u32,
+	},
+
+	/// Fetch texture of given material.
+	/// Coordinates are taken from given nodes and are wrapped into range [0; 1).
+	TextureSample
+	{
+		material_name: String,
+		u: u32,
+		v: u32,
+
+		#[serde(default)]
+		channel: ProceduralTextureChannel,
+	},
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+pub enum ProceduralTextureChannel
+{
+	Red,
+	Green,
+	Blue,
+	Alpha,
+	/// Average of RGB components.
+	#[default]
+	Brightness,
+}
+
+fn default_update_frequency() -> f32
+{
+	30.0
+}
+
+fn default_noise_cells() -> [u32; 2]
+{
+	[8, 8]
+}
@@ -0,0 +1,52 @@
+use super::material_function::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct RefractionEffect
+{
+	/// Material, which texture is used as distortion layer.
+	/// Texture of material with this effect itself is used as base (refracted) layer.
+	pub distortion_material_name: String,
+
+	/// What data of distortion layer is used for distortion.
+	#[serde(default)]
+	pub distortion_source: RefractionDistortionSource,
+
+	/// Distortion strength (in pixels).
+	/// For normals - shift for normal with XY length = 1.
+	/// For heights - shift for height change from 0 to 1 along single pixel.
+	#[serde(default = "default_strength")]
+	pub strength: f32,
+
+	/// How to perform shift (in pixels) of base layer, depending on time.
+	#[serde(default)]
+	pub base_tex_coord_shift: [SingleArgumentFunction; 2],
+
+	/// How to perform shift (in pixels) of distortion layer, depending on time.
+	#[serde(default)]
+	pub distortion_tex_coord_shift: [SingleArgumentFunction; 2],
+
+	/// Number of texture updates, performed per second.
+	#[serde(default = "default_update_frequency")]
+	pub update_frequency: f32,
+}
+
+#[derive(Serialize, Deserialize, Debug, Copy, Clone, PartialEq, Default)]
+pub enum RefractionDistortionSource
+{
+	/// Use XY components of distortion layer normal map.
+	#[default]
+	Normals,
+	/// Use diffuse texture brightness of distortion layer as height map.
+	Heights,
+}
+
+fn default_strength() -> f32
+{
+	4.0
+}
+
+fn default_update_frequency() -> f32
+{
+	30.0
+}
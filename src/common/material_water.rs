@@ -21,6 +21,15 @@ pub struct WaterEffect
 	#[serde(default)]
 	pub color_texture_apply_mode: ColorTextureApplyMode,
 
+	/// Scale of color modulation for modes with modulation by wave height or slope.
+	#[serde(default = "default_one")]
+	pub color_modulation_scale: f32,
+
+	/// If some - produce emissive texture based on wave height (glowing lava, radioactive pools, etc.).
+	/// Note that emissive layer of this material must be present in order to draw result emissive texture.
+	#[serde(default)]
+	pub emissive: Option<WaterEmissive>,
+
 	/// Sources of wave field distortion.
 	/// Without any source water texture is completely flat and boring.
 	pub wave_sources: Vec<WaveSource>,
@@ -38,6 +47,12 @@ pub enum ColorTextureApplyMode
 	/// Deform source texture, based on calculated water normals. Only X component of normal is used.
 	/// Such deformation is faster, than previous, but looks not so good.
 	SourceTextureNormalDeformedX,
+	/// Modulate source texture by wave height.
+	/// color = source_color * (1 + height * color_modulation_scale)
+	SourceTextureModulatedByHeight,
+	/// Modulate source texture by wave slope. Texture is darker on steep slopes.
+	/// color = source_color * (1 - slope * color_modulation_scale)
+	SourceTextureModulatedBySlope,
 }
 
 impl Default for ColorTextureApplyMode
@@ -48,6 +63,24 @@ impl Default for ColorTextureApplyMode
 	}
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct WaterEmissive
+{
+	/// Wave height, that is mapped to gradient start.
+	#[serde(default = "default_emissive_height_min")]
+	pub height_min: f32,
+
+	/// Wave height, that is mapped to gradient end.
+	#[serde(default = "default_one")]
+	pub height_max: f32,
+
+	/// If some - use gradient from black to this color.
+	/// Color is in range [0; 1], out of range values will be clamped.
+	/// If none - emissive image will be used as gradient image (gradient along U axis).
+	#[serde(default)]
+	pub color: Option<[f32; 3]>,
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub enum WaveSource
 {
@@ -138,6 +171,11 @@ fn default_fluidity() -> f32
 	200.0
 }
 
+fn default_emissive_height_min() -> f32
+{
+	-1.0
+}
+
 fn default_one() -> f32
 {
 	1.0
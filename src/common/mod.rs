@@ -9,6 +9,7 @@ pub mod clipping;
 pub mod clipping_bsp;
 pub mod clipping_polygon;
 pub mod color;
+pub mod detail_objects_builder;
 pub mod fixed_math;
 pub mod image;
 pub mod light_cube;
@@ -26,6 +27,8 @@ pub mod map_polygonizer;
 pub mod material;
 pub mod material_fire;
 pub mod material_function;
+pub mod material_procedural;
+pub mod material_refraction;
 pub mod material_water;
 pub mod math_types;
 pub mod matrix;
@@ -34,3 +37,9 @@ pub mod pvs;
 pub mod screenshot;
 pub mod shared_mut_slice;
 pub mod system_window;
+pub mod triangle_model;
+pub mod triangle_model_gltf;
+pub mod triangle_model_iqm;
+pub mod triangle_model_loading;
+pub mod triangle_model_md3;
+pub mod triangle_model_save_load;
@@ -1,4 +1,4 @@
-use crate::common::{bbox::*, math_types::*};
+use super::{bbox::*, math_types::*};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -6,7 +6,11 @@ pub struct TriangleModel
 {
 	pub animations: Vec<TriangleModelAnimation>,
 	pub frames_info: Vec<TriangleModelFrameInfo>,
+	// Meshes of all LODs.
 	pub meshes: Vec<TriangleModelMesh>,
+	// Ranges of meshes for each LOD, starting from most detailed LOD.
+	// If empty - all meshes belong to single LOD.
+	pub lods: Vec<TriangleModelLod>,
 	// Non-empty for skeleton-animated models.
 	pub bones: Vec<TriangleModelBoneInfo>,
 	// Frame info for all bones of specific frame. Has size = num_frames * num_bones.
@@ -14,6 +18,28 @@ pub struct TriangleModel
 	pub tc_shift: Vec2f,
 }
 
+// Skeleton animations without meshes, loaded from separate file.
+// May be shared between different models with (partially) same skeleton.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TriangleModelSkeletonAnimation
+{
+	pub animations: Vec<TriangleModelAnimation>,
+	// Skeleton of source model.
+	pub bones: Vec<TriangleModelBoneInfo>,
+	// Has size = num_frames * num_bones.
+	pub frame_bones: Vec<TriangleModelBoneFrame>,
+}
+
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct TriangleModelLod
+{
+	pub first_mesh: u32,
+	pub num_meshes: u32,
+	// Use this LOD if model screen size (in pixels) is less than this value.
+	// Ignored for first LOD.
+	pub max_screen_size: f32,
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 pub struct TriangleModelAnimation
 {
@@ -39,6 +65,9 @@ pub struct TriangleModelBoneInfo
 	// Parent index should be less than index of this bone -
 	// in order to calculate matrix for this bone after matrix of parent.
 	pub parent: u32,
+	// Bone transformation (in model space) in bind pose.
+	// Multiply it by final bone matrix to get bone transformation for current pose.
+	pub base_matrix: Mat4f,
 }
 
 #[derive(Copy, Clone, Serialize, Deserialize)]
@@ -81,6 +110,7 @@ pub struct VertexNonAnimated
 {
 	pub position: Vec3f,
 	pub normal: Vec3f,
+	pub tangent_space: VertexTangentSpace,
 	pub tex_coord: [f32; 2],
 }
 
@@ -95,6 +125,7 @@ pub struct VertexAnimatedVertexVariable
 {
 	pub position: Vec3f,
 	pub normal: Vec3f,
+	pub tangent_space: VertexTangentSpace,
 }
 
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
@@ -103,9 +134,21 @@ pub struct SkeletonAnimatedVertex
 	pub tex_coord: [f32; 2],
 	pub position: Vec3f,
 	pub normal: Vec3f,
+	pub tangent_space: VertexTangentSpace,
 	pub bones_description: [VertexBoneDescription; 4],
 }
 
+// Vectors of texture space, used for normal mapping.
+// Together with normal they form a basis for normals, stored in normal maps.
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+pub struct VertexTangentSpace
+{
+	// Direction of "u" texture coordinate growth.
+	pub tangent: Vec3f,
+	// Direction of "v" texture coordinate growth.
+	pub binormal: Vec3f,
+}
+
 #[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct VertexBoneDescription
 {
@@ -114,3 +157,26 @@ pub struct VertexBoneDescription
 }
 
 pub const MAX_TRIANGLE_MODEL_BONES: usize = 255;
+
+pub fn get_triangle_model_lod_meshes_range(model: &TriangleModel, lod: u32) -> std::ops::Range<usize>
+{
+	if let Some(lod) = model.lods.get(lod as usize)
+	{
+		let start = (lod.first_mesh as usize).min(model.meshes.len());
+		start .. (start + lod.num_meshes as usize).min(model.meshes.len())
+	}
+	else
+	{
+		0 .. model.meshes.len()
+	}
+}
+
+pub fn get_triangle_model_mesh_num_vertices(mesh: &TriangleModelMesh) -> usize
+{
+	match &mesh.vertex_data
+	{
+		VertexData::NonAnimated(v) => v.len(),
+		VertexData::VertexAnimated { constant, .. } => constant.len(),
+		VertexData::SkeletonAnimated(v) => v.len(),
+	}
+}
@@ -0,0 +1,1897 @@
+use super::{bbox::*, math_types::*, triangle_model::*, triangle_model_loading::*};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// Load glTF 2.0 model (both text ".gltf" and binary ".glb" containers are supported).
+// Non-skinned meshes are loaded as non-animated or vertex-animated (if they are affected by animation or morph targets).
+// Skinned meshes are loaded as skeleton-animated. Only one skin per model is supported.
+// Animations are sampled with fixed frame rate.
+pub fn load_model_gltf(file_path: &std::path::Path) -> Result<Option<TriangleModel>, std::io::Error>
+{
+	let file_contents = std::fs::read(file_path)?;
+
+	let (json_data, glb_binary_chunk) = if file_contents.starts_with(&GLB_MAGIC)
+	{
+		if let Some(r) = split_glb_chunks(&file_contents)
+		{
+			r
+		}
+		else
+		{
+			println!("Invalid GLB container");
+			return Ok(None);
+		}
+	}
+	else
+	{
+		(&file_contents[..], None)
+	};
+
+	let document = match serde_json::from_slice::<GltfDocument>(json_data)
+	{
+		Ok(d) => d,
+		Err(e) =>
+		{
+			println!("Failed to parse glTF JSON: {}", e);
+			return Ok(None);
+		},
+	};
+
+	let mut buffers = Vec::with_capacity(document.buffers.len());
+	for (buffer_index, buffer) in document.buffers.iter().enumerate()
+	{
+		let data = if let Some(uri) = &buffer.uri
+		{
+			if let Some(data_uri) = uri.strip_prefix("data:")
+			{
+				if let Some(d) = data_uri
+					.split_once(";base64,")
+					.and_then(|(_, data)| decode_base64(data))
+				{
+					d
+				}
+				else
+				{
+					println!("Unsupported glTF buffer data URI");
+					return Ok(None);
+				}
+			}
+			else
+			{
+				std::fs::read(file_path.with_file_name(uri))?
+			}
+		}
+		else if let (0, Some(chunk)) = (buffer_index, glb_binary_chunk)
+		{
+			chunk.to_vec()
+		}
+		else
+		{
+			println!("glTF buffer {} has no data", buffer_index);
+			return Ok(None);
+		};
+
+		if data.len() < buffer.byte_length
+		{
+			println!("glTF buffer {} is too small", buffer_index);
+			return Ok(None);
+		}
+		buffers.push(data);
+	}
+
+	match convert_model(&document, &buffers)
+	{
+		Ok(model) => Ok(Some(model)),
+		Err(e) =>
+		{
+			println!("Invalid glTF model: {}", e);
+			Ok(None)
+		},
+	}
+}
+
+// Frame rate used for animations sampling.
+const ANIMATION_FRAMES_PER_SECOND: f32 = 24.0;
+
+const GLB_MAGIC: [u8; 4] = *b"glTF";
+const GLB_CHUNK_JSON: u32 = 0x4E4F534A;
+const GLB_CHUNK_BIN: u32 = 0x004E4942;
+
+const GLTF_MODE_TRIANGLES: u32 = 4;
+
+const GLTF_COMPONENT_TYPE_BYTE: u32 = 5120;
+const GLTF_COMPONENT_TYPE_UNSIGNED_BYTE: u32 = 5121;
+const GLTF_COMPONENT_TYPE_SHORT: u32 = 5122;
+const GLTF_COMPONENT_TYPE_UNSIGNED_SHORT: u32 = 5123;
+const GLTF_COMPONENT_TYPE_UNSIGNED_INT: u32 = 5125;
+const GLTF_COMPONENT_TYPE_FLOAT: u32 = 5126;
+
+type GltfResult<T> = Result<T, String>;
+
+fn split_glb_chunks(file_contents: &[u8]) -> Option<(&[u8], Option<&[u8]>)>
+{
+	let read_u32 = |offset: usize| -> Option<u32> {
+		let bytes = file_contents.get(offset .. offset + 4)?;
+		Some(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+	};
+
+	// Header is magic, version and length.
+	if read_u32(4)? != 2
+	{
+		return None;
+	}
+	let total_length = (read_u32(8)? as usize).min(file_contents.len());
+
+	let mut json_chunk = None;
+	let mut binary_chunk = None;
+	let mut offset = 12;
+	while offset + 8 <= total_length
+	{
+		let chunk_length = read_u32(offset)? as usize;
+		let chunk_type = read_u32(offset + 4)?;
+		let chunk_data = file_contents.get(offset + 8 .. offset + 8 + chunk_length)?;
+		if chunk_type == GLB_CHUNK_JSON && json_chunk.is_none()
+		{
+			json_chunk = Some(chunk_data);
+		}
+		else if chunk_type == GLB_CHUNK_BIN && binary_chunk.is_none()
+		{
+			binary_chunk = Some(chunk_data);
+		}
+		// Chunks are 4-bytes aligned.
+		offset += 8 + ((chunk_length + 3) & !3);
+	}
+
+	Some((json_chunk?, binary_chunk))
+}
+
+fn convert_model(document: &GltfDocument, buffers: &[Vec<u8>]) -> GltfResult<TriangleModel>
+{
+	let num_nodes = document.nodes.len();
+
+	let mut node_parents = vec![None; num_nodes];
+	for (node_index, node) in document.nodes.iter().enumerate()
+	{
+		for &child in &node.children
+		{
+			*node_parents
+				.get_mut(child)
+				.ok_or_else(|| format!("Invalid child node {}", child))? = Some(node_index);
+		}
+	}
+
+	let root_nodes = if let Some(scene) = document.scenes.get(document.scene.unwrap_or(0))
+	{
+		scene.nodes.clone()
+	}
+	else
+	{
+		(0 .. num_nodes).filter(|&n| node_parents[n].is_none()).collect()
+	};
+
+	// Collect scene nodes in hierarchy order - parents before children.
+	let mut nodes_ordered = Vec::new();
+	let mut nodes_stack = root_nodes.iter().rev().cloned().collect::<Vec<_>>();
+	while let Some(node_index) = nodes_stack.pop()
+	{
+		let node = document
+			.nodes
+			.get(node_index)
+			.ok_or_else(|| format!("Invalid node {}", node_index))?;
+		if nodes_ordered.len() >= num_nodes
+		{
+			return Err("Nodes hierarchy contains cycles".to_string());
+		}
+		nodes_ordered.push(node_index);
+		nodes_stack.extend(node.children.iter().rev());
+	}
+
+	let frames = collect_frames(document, buffers)?;
+	let animated_nodes = collect_animated_nodes(document, &node_parents, &nodes_ordered);
+
+	// Calculate global transformations of all nodes for all frames.
+	let mut frames_node_matrices = Vec::with_capacity(frames.len());
+	for frame in &frames
+	{
+		frames_node_matrices.push(calculate_node_matrices(
+			document,
+			buffers,
+			&node_parents,
+			&nodes_ordered,
+			frame,
+		)?);
+	}
+
+	let skeleton = if let Some(skin_index) = nodes_ordered
+		.iter()
+		.filter(|&&n| document.nodes[n].mesh.is_some())
+		.find_map(|&n| document.nodes[n].skin)
+	{
+		Some(build_skeleton(
+			document,
+			buffers,
+			&node_parents,
+			skin_index,
+			&frames_node_matrices,
+		)?)
+	}
+	else
+	{
+		None
+	};
+
+	let mut meshes = Vec::new();
+	for &node_index in &nodes_ordered
+	{
+		let node = &document.nodes[node_index];
+		let mesh_index = if let Some(m) = node.mesh
+		{
+			m
+		}
+		else
+		{
+			continue;
+		};
+		let mesh = document
+			.meshes
+			.get(mesh_index)
+			.ok_or_else(|| format!("Invalid mesh {}", mesh_index))?;
+
+		for (primitive_index, primitive) in mesh.primitives.iter().enumerate()
+		{
+			if primitive.mode != GLTF_MODE_TRIANGLES
+			{
+				println!(
+					"Skipping glTF mesh {:?} primitive {} with unsupported mode {}",
+					mesh.name, primitive_index, primitive.mode
+				);
+				continue;
+			}
+
+			let vertex_data = if let (Some(skin_index), Some(skeleton)) = (node.skin, &skeleton)
+			{
+				if skin_index != skeleton.skin_index
+				{
+					println!(
+						"Skipping glTF mesh {:?} with skin {} - only one skin is supported",
+						mesh.name, skin_index
+					);
+					continue;
+				}
+				make_skeleton_animated_vertices(document, buffers, primitive, skeleton)?
+			}
+			else if animated_nodes[node_index] || (!primitive.targets.is_empty() && frames.len() > 1)
+			{
+				make_vertex_animated_vertices(document, buffers, primitive, node_index, &frames_node_matrices)?
+			}
+			else
+			{
+				make_non_animated_vertices(document, buffers, primitive, node_index, &frames_node_matrices[0])?
+			};
+
+			let (triangles, num_vertices) = read_triangles(document, buffers, primitive)?;
+			let vertex_data = finalize_vertex_data(vertex_data, &triangles, num_vertices);
+
+			let material_name = if let Some(material_index) = primitive.material
+			{
+				document
+					.materials
+					.get(material_index)
+					.map(|m| m.name.clone())
+					.unwrap_or_default()
+			}
+			else
+			{
+				String::new()
+			};
+
+			let name = if mesh.primitives.len() > 1
+			{
+				format!("{}_{}", mesh.name, primitive_index)
+			}
+			else
+			{
+				mesh.name.clone()
+			};
+
+			// Large primitives are split into several meshes.
+			meshes.extend(make_meshes_with_vertex_limit(
+				&name,
+				&material_name,
+				frames.len() as u32,
+				vertex_data,
+				&triangles,
+			));
+		}
+	}
+
+	if meshes.is_empty()
+	{
+		return Err("No meshes".to_string());
+	}
+
+	let frames_info = calculate_frames_info(&meshes, frames.len(), skeleton.as_ref());
+
+	let (bones, frame_bones) = if let Some(skeleton) = skeleton
+	{
+		(skeleton.bones, skeleton.frame_bones)
+	}
+	else
+	{
+		(Vec::new(), Vec::new())
+	};
+
+	let animations = document
+		.animations
+		.iter()
+		.enumerate()
+		.map(|(animation_index, animation)| {
+			let start_frame = frames
+				.iter()
+				.position(|f| f.animation == Some(animation_index))
+				.unwrap_or(0);
+			let num_frames = frames.iter().filter(|f| f.animation == Some(animation_index)).count();
+			TriangleModelAnimation {
+				name: if animation.name.is_empty()
+				{
+					format!("animation_{}", animation_index)
+				}
+				else
+				{
+					animation.name.clone()
+				},
+				start_frame: start_frame as u32,
+				num_frames: num_frames as u32,
+				frames_per_second: ANIMATION_FRAMES_PER_SECOND,
+				// glTF has no looping flag.
+				looped: true,
+			}
+		})
+		.collect();
+
+	// Add extra shift because we use texture coordinates floor, instead of linear interpolation.
+	let tc_shift = -Vec2f::new(0.5, 0.5);
+
+	Ok(TriangleModel {
+		animations,
+		frames_info,
+		meshes,
+		lods: Vec::new(),
+		bones,
+		frame_bones,
+		tc_shift,
+	})
+}
+
+// Convert glTF coordinates (Y - up, Z - forward, X - left) into engine coordinates (Z - up, X - forward, Y - left).
+fn get_coordinate_system_conversion_matrix() -> Mat4f
+{
+	Mat4f::new(
+		0.0, 1.0, 0.0, 0.0, //
+		0.0, 0.0, 1.0, 0.0, //
+		1.0, 0.0, 0.0, 0.0, //
+		0.0, 0.0, 0.0, 1.0, //
+	)
+}
+
+// Frame of result model - animation with time point.
+struct Frame
+{
+	animation: Option<usize>,
+	time: f32,
+}
+
+fn collect_frames(document: &GltfDocument, buffers: &[Vec<u8>]) -> GltfResult<Vec<Frame>>
+{
+	let mut frames = Vec::new();
+	for (animation_index, animation) in document.animations.iter().enumerate()
+	{
+		let mut duration: f32 = 0.0;
+		for sampler in &animation.samplers
+		{
+			let input = read_accessor(document, buffers, sampler.input)?;
+			duration = input.data.iter().fold(duration, |d, &t| d.max(t));
+		}
+
+		let num_frames = (duration * ANIMATION_FRAMES_PER_SECOND).ceil() as usize + 1;
+		for frame in 0 .. num_frames
+		{
+			frames.push(Frame {
+				animation: Some(animation_index),
+				time: (frame as f32) / ANIMATION_FRAMES_PER_SECOND,
+			});
+		}
+	}
+
+	if frames.is_empty()
+	{
+		// Create single frame for models without animations.
+		frames.push(Frame {
+			animation: None,
+			time: 0.0,
+		});
+	}
+
+	Ok(frames)
+}
+
+// Returns true for nodes with transformation or morph weights changed by animations (including changes of parents).
+fn collect_animated_nodes(document: &GltfDocument, node_parents: &[Option<usize>], nodes_ordered: &[usize])
+	-> Vec<bool>
+{
+	let mut animated_nodes = vec![false; document.nodes.len()];
+	for animation in &document.animations
+	{
+		for channel in &animation.channels
+		{
+			if let Some(flag) = channel.target.node.and_then(|n| animated_nodes.get_mut(n))
+			{
+				*flag = true;
+			}
+		}
+	}
+
+	for &node_index in nodes_ordered
+	{
+		if let Some(parent) = node_parents[node_index]
+		{
+			animated_nodes[node_index] |= animated_nodes[parent];
+		}
+	}
+
+	animated_nodes
+}
+
+// Local transformation of node.
+#[derive(Clone)]
+struct NodeTransform
+{
+	translation: Vec3f,
+	rotation: QuaternionF,
+	scale: Vec3f,
+	matrix: Option<Mat4f>,
+	morph_weights: Vec<f32>,
+}
+
+impl NodeTransform
+{
+	fn get_matrix(&self) -> Mat4f
+	{
+		if let Some(m) = self.matrix
+		{
+			return m;
+		}
+		Mat4f::from_translation(self.translation) *
+			Mat4f::from(self.rotation) *
+			Mat4f::from_nonuniform_scale(self.scale.x, self.scale.y, self.scale.z)
+	}
+}
+
+// Global transformations of nodes (in engine coordinate system) and morph weights for specific frame.
+struct NodeMatrices
+{
+	matrices: Vec<Mat4f>,
+	morph_weights: Vec<Vec<f32>>,
+}
+
+fn calculate_node_matrices(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	node_parents: &[Option<usize>],
+	nodes_ordered: &[usize],
+	frame: &Frame,
+) -> GltfResult<NodeMatrices>
+{
+	let mut transforms = document
+		.nodes
+		.iter()
+		.map(|node| NodeTransform {
+			translation: Vec3f::from(node.translation),
+			rotation: make_quaternion(&node.rotation),
+			scale: Vec3f::from(node.scale),
+			matrix: node.matrix.map(|m| Mat4f::from(make_mat4_array(&m))),
+			morph_weights: if node.weights.is_empty()
+			{
+				node.mesh
+					.and_then(|m| document.meshes.get(m))
+					.map(|m| m.weights.clone())
+					.unwrap_or_default()
+			}
+			else
+			{
+				node.weights.clone()
+			},
+		})
+		.collect::<Vec<_>>();
+
+	if let Some(animation) = frame.animation.and_then(|a| document.animations.get(a))
+	{
+		for channel in &animation.channels
+		{
+			let transform = if let Some(t) = channel.target.node.and_then(|n| transforms.get_mut(n))
+			{
+				t
+			}
+			else
+			{
+				continue;
+			};
+			let sampler = animation
+				.samplers
+				.get(channel.sampler)
+				.ok_or_else(|| format!("Invalid animation sampler {}", channel.sampler))?;
+
+			match channel.target.path.as_str()
+			{
+				"translation" =>
+				{
+					let v = sample_animation(document, buffers, sampler, frame.time, 3)?;
+					transform.translation = Vec3f::new(v[0], v[1], v[2]);
+				},
+				"rotation" =>
+				{
+					let v = sample_animation(document, buffers, sampler, frame.time, 4)?;
+					transform.rotation = make_quaternion(&[v[0], v[1], v[2], v[3]]).normalize();
+				},
+				"scale" =>
+				{
+					let v = sample_animation(document, buffers, sampler, frame.time, 3)?;
+					transform.scale = Vec3f::new(v[0], v[1], v[2]);
+				},
+				"weights" =>
+				{
+					let output = read_accessor(document, buffers, sampler.output)?;
+					let input = read_accessor(document, buffers, sampler.input)?;
+					let num_weights = output.data.len() /
+						input.data.len().max(1) /
+						if sampler.interpolation == "CUBICSPLINE" { 3 } else { 1 };
+					transform.morph_weights = sample_animation(document, buffers, sampler, frame.time, num_weights)?;
+				},
+				_ =>
+				{},
+			}
+		}
+	}
+
+	let conversion_matrix = get_coordinate_system_conversion_matrix();
+	let mut matrices = vec![conversion_matrix; document.nodes.len()];
+	for &node_index in nodes_ordered
+	{
+		let parent_matrix = if let Some(parent) = node_parents[node_index]
+		{
+			matrices[parent]
+		}
+		else
+		{
+			conversion_matrix
+		};
+		matrices[node_index] = parent_matrix * transforms[node_index].get_matrix();
+	}
+
+	Ok(NodeMatrices {
+		matrices,
+		morph_weights: transforms.into_iter().map(|t| t.morph_weights).collect(),
+	})
+}
+
+// Sample animation sampler output at given time.
+// Cubic spline interpolation is approximated with linear interpolation.
+fn sample_animation(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	sampler: &GltfAnimationSampler,
+	time: f32,
+	num_components: usize,
+) -> GltfResult<Vec<f32>>
+{
+	let input = read_accessor(document, buffers, sampler.input)?;
+	let output = read_accessor(document, buffers, sampler.output)?;
+	if input.data.is_empty() || num_components == 0
+	{
+		return Ok(vec![0.0; num_components]);
+	}
+
+	let is_cubic_spline = sampler.interpolation == "CUBICSPLINE";
+	// For cubic spline each key contains in-tangent, value and out-tangent.
+	let get_value = |key: usize| -> GltfResult<&[f32]> {
+		let (start, end) = if is_cubic_spline
+		{
+			((key * 3 + 1) * num_components, (key * 3 + 2) * num_components)
+		}
+		else
+		{
+			(key * num_components, (key + 1) * num_components)
+		};
+		output
+			.data
+			.get(start .. end)
+			.ok_or_else(|| "Invalid animation sampler output".to_string())
+	};
+
+	let next_key = input.data.iter().position(|&t| t > time).unwrap_or(input.data.len());
+	if next_key == 0
+	{
+		return Ok(get_value(0)?.to_vec());
+	}
+	if next_key == input.data.len()
+	{
+		return Ok(get_value(next_key - 1)?.to_vec());
+	}
+
+	let prev_key = next_key - 1;
+	let prev_value = get_value(prev_key)?;
+	if sampler.interpolation == "STEP"
+	{
+		return Ok(prev_value.to_vec());
+	}
+
+	let next_value = get_value(next_key)?;
+	let time_delta = input.data[next_key] - input.data[prev_key];
+	let k = if time_delta > 0.0
+	{
+		(time - input.data[prev_key]) / time_delta
+	}
+	else
+	{
+		0.0
+	};
+
+	if num_components == 4 && sampler.interpolation != "STEP"
+	{
+		// Use normalized linear interpolation for rotations. Take shortest path.
+		let dot = prev_value.iter().zip(next_value).map(|(a, b)| a * b).sum::<f32>();
+		let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+		return Ok(prev_value
+			.iter()
+			.zip(next_value)
+			.map(|(a, b)| a * (1.0 - k) + b * k * sign)
+			.collect());
+	}
+
+	Ok(prev_value
+		.iter()
+		.zip(next_value)
+		.map(|(a, b)| a * (1.0 - k) + b * k)
+		.collect())
+}
+
+struct Skeleton
+{
+	skin_index: usize,
+	// Map skin joint index to bone index.
+	joints_to_bones: Vec<u8>,
+	bones: Vec<TriangleModelBoneInfo>,
+	frame_bones: Vec<TriangleModelBoneFrame>,
+	// Final skinning matrices for all frames.
+	frame_skin_matrices: Vec<Mat4f>,
+}
+
+fn build_skeleton(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	node_parents: &[Option<usize>],
+	skin_index: usize,
+	frames_node_matrices: &[NodeMatrices],
+) -> GltfResult<Skeleton>
+{
+	let skin = document
+		.skins
+		.get(skin_index)
+		.ok_or_else(|| format!("Invalid skin {}", skin_index))?;
+	let num_bones = skin.joints.len();
+	if num_bones == 0 || num_bones > MAX_TRIANGLE_MODEL_BONES
+	{
+		return Err(format!("Invalid number of joints: {}", num_bones));
+	}
+
+	let inverse_bind_matrices = if let Some(accessor_index) = skin.inverse_bind_matrices
+	{
+		let accessor = read_accessor(document, buffers, accessor_index)?;
+		if accessor.num_components != 16 || accessor.data.len() < num_bones * 16
+		{
+			return Err("Invalid inverse bind matrices".to_string());
+		}
+		accessor
+			.data
+			.chunks_exact(16)
+			.map(|m| {
+				let mut arr = [0.0; 16];
+				arr.copy_from_slice(m);
+				Mat4f::from(make_mat4_array(&arr))
+			})
+			.collect()
+	}
+	else
+	{
+		vec![Mat4f::identity(); num_bones]
+	};
+
+	let num_nodes = node_parents.len();
+	if let Some(&joint_node) = skin.joints.iter().find(|&&j| j >= num_nodes)
+	{
+		return Err(format!("Invalid joint node {}", joint_node));
+	}
+
+	// Sort joints in hierarchy order - by depth.
+	// Limit depth by number of nodes, since nodes outside scene are not checked for cycles.
+	let get_depth = |mut node_index: usize| {
+		let mut depth = 0;
+		while let Some(parent) = node_parents[node_index]
+		{
+			node_index = parent;
+			depth += 1;
+			if depth > num_nodes
+			{
+				return Err("Nodes hierarchy contains cycles".to_string());
+			}
+		}
+		Ok(depth)
+	};
+	let joints_depths = skin
+		.joints
+		.iter()
+		.map(|&j| get_depth(j))
+		.collect::<GltfResult<Vec<_>>>()?;
+	let mut joints_sorted = (0 .. num_bones).collect::<Vec<_>>();
+	joints_sorted.sort_by_key(|&j| joints_depths[j]);
+
+	let mut joints_to_bones = vec![0; num_bones];
+	for (bone_index, &joint_index) in joints_sorted.iter().enumerate()
+	{
+		joints_to_bones[joint_index] = bone_index as u8;
+	}
+
+	let conversion_matrix = get_coordinate_system_conversion_matrix();
+	let inverse_conversion_matrix = conversion_matrix.transpose();
+
+	let mut bones = Vec::with_capacity(num_bones);
+	for &joint_index in &joints_sorted
+	{
+		let node_index = skin.joints[joint_index];
+		// Find nearest ancestor, which is a joint of this skin.
+		let mut parent = 0xFFFFFFFF;
+		let mut current_node = node_index;
+		while let Some(parent_node) = node_parents.get(current_node).cloned().flatten()
+		{
+			if let Some(parent_joint) = skin.joints.iter().position(|&j| j == parent_node)
+			{
+				parent = joints_to_bones[parent_joint] as u32;
+				break;
+			}
+			current_node = parent_node;
+		}
+
+		bones.push(TriangleModelBoneInfo {
+			name: document
+				.nodes
+				.get(node_index)
+				.map(|n| n.name.clone())
+				.unwrap_or_default(),
+			parent,
+			base_matrix: conversion_matrix *
+				inverse_bind_matrices[joint_index]
+					.invert()
+					.ok_or_else(|| "Non-invertible inverse bind matrix".to_string())?,
+		});
+	}
+
+	let mut frame_bones = Vec::with_capacity(frames_node_matrices.len() * num_bones);
+	let mut frame_skin_matrices = Vec::with_capacity(frames_node_matrices.len() * num_bones);
+	for node_matrices in frames_node_matrices
+	{
+		// Final skinning matrix transforms vertices from bind pose into current pose (both in engine coordinate system).
+		let skin_matrices_start = frame_skin_matrices.len();
+		for &joint_index in &joints_sorted
+		{
+			let node_matrix = node_matrices
+				.matrices
+				.get(skin.joints[joint_index])
+				.ok_or_else(|| format!("Invalid joint node {}", skin.joints[joint_index]))?;
+			frame_skin_matrices.push(node_matrix * inverse_bind_matrices[joint_index] * inverse_conversion_matrix);
+		}
+
+		// Store matrices relative to parent, since this is required by other code.
+		let skin_matrices = &frame_skin_matrices[skin_matrices_start ..];
+		for (bone, skin_matrix) in bones.iter().zip(skin_matrices)
+		{
+			let matrix = if let Some(parent_skin_matrix) = skin_matrices.get(bone.parent as usize)
+			{
+				parent_skin_matrix.invert().unwrap_or_else(Mat4f::identity) * skin_matrix
+			}
+			else
+			{
+				*skin_matrix
+			};
+			frame_bones.push(TriangleModelBoneFrame { matrix });
+		}
+	}
+
+	Ok(Skeleton {
+		skin_index,
+		joints_to_bones,
+		bones,
+		frame_bones,
+		frame_skin_matrices,
+	})
+}
+
+// Intermediate vertex data. Tangent space is calculated later, after reading triangles.
+enum VertexDataPrepared
+{
+	Static
+	{
+		positions: Vec<Vec3f>,
+		normals: Vec<Vec3f>,
+		tex_coords: Vec<[f32; 2]>,
+	},
+	PerFrame
+	{
+		// Size = number of vertices * number of frames.
+		positions: Vec<Vec3f>,
+		normals: Vec<Vec3f>,
+		tex_coords: Vec<[f32; 2]>,
+	},
+	Skinned
+	{
+		positions: Vec<Vec3f>,
+		normals: Vec<Vec3f>,
+		tex_coords: Vec<[f32; 2]>,
+		bones_descriptions: Vec<[VertexBoneDescription; 4]>,
+	},
+}
+
+fn make_non_animated_vertices(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	primitive: &GltfPrimitive,
+	node_index: usize,
+	node_matrices: &NodeMatrices,
+) -> GltfResult<VertexDataPrepared>
+{
+	let (positions, normals) =
+		read_morphed_positions_and_normals(document, buffers, primitive, &node_matrices.morph_weights[node_index])?;
+	let (positions, normals) =
+		transform_positions_and_normals(&positions, &normals, &node_matrices.matrices[node_index]);
+
+	Ok(VertexDataPrepared::Static {
+		positions,
+		normals,
+		tex_coords: read_tex_coords(document, buffers, primitive)?,
+	})
+}
+
+fn make_vertex_animated_vertices(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	primitive: &GltfPrimitive,
+	node_index: usize,
+	frames_node_matrices: &[NodeMatrices],
+) -> GltfResult<VertexDataPrepared>
+{
+	let mut positions = Vec::new();
+	let mut normals = Vec::new();
+	for node_matrices in frames_node_matrices
+	{
+		let (frame_positions, frame_normals) =
+			read_morphed_positions_and_normals(document, buffers, primitive, &node_matrices.morph_weights[node_index])?;
+		let (frame_positions, frame_normals) =
+			transform_positions_and_normals(&frame_positions, &frame_normals, &node_matrices.matrices[node_index]);
+		positions.extend(frame_positions);
+		normals.extend(frame_normals);
+	}
+
+	Ok(VertexDataPrepared::PerFrame {
+		positions,
+		normals,
+		tex_coords: read_tex_coords(document, buffers, primitive)?,
+	})
+}
+
+fn make_skeleton_animated_vertices(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	primitive: &GltfPrimitive,
+	skeleton: &Skeleton,
+) -> GltfResult<VertexDataPrepared>
+{
+	// Transformation of mesh node is ignored for skinned meshes.
+	let (positions, normals) = read_morphed_positions_and_normals(document, buffers, primitive, &[])?;
+	let (positions, normals) =
+		transform_positions_and_normals(&positions, &normals, &get_coordinate_system_conversion_matrix());
+
+	let joints = read_attribute(document, buffers, primitive, "JOINTS_0")?;
+	let weights = read_attribute(document, buffers, primitive, "WEIGHTS_0")?;
+
+	let mut bones_descriptions = Vec::with_capacity(positions.len());
+	for vertex_index in 0 .. positions.len()
+	{
+		let mut vertex_joints = [0; 4];
+		let mut vertex_weights = [0.0; 4];
+		if let (Some(joints), Some(weights)) = (&joints, &weights)
+		{
+			for i in 0 .. 4
+			{
+				vertex_joints[i] = joints.get_component(vertex_index, i) as usize;
+				vertex_weights[i] = weights.get_component(vertex_index, i).max(0.0);
+			}
+		}
+		else
+		{
+			vertex_weights[0] = 1.0;
+		}
+
+		bones_descriptions.push(make_bones_description(
+			&vertex_joints,
+			&vertex_weights,
+			&skeleton.joints_to_bones,
+		));
+	}
+
+	Ok(VertexDataPrepared::Skinned {
+		positions,
+		normals,
+		tex_coords: read_tex_coords(document, buffers, primitive)?,
+		bones_descriptions,
+	})
+}
+
+// Convert weights into integer form with sum equal to 255.
+fn make_bones_description(joints: &[usize; 4], weights: &[f32; 4], joints_to_bones: &[u8])
+	-> [VertexBoneDescription; 4]
+{
+	let weights_sum = weights.iter().sum::<f32>();
+	let scale = if weights_sum > 0.0 { 255.0 / weights_sum } else { 0.0 };
+
+	let mut result = [VertexBoneDescription {
+		bone_index: 0,
+		weight: 0,
+	}; 4];
+	let mut result_weights_sum = 0;
+	for i in 0 .. 4
+	{
+		result[i] = VertexBoneDescription {
+			bone_index: joints_to_bones.get(joints[i]).cloned().unwrap_or(0),
+			weight: (weights[i] * scale).round().clamp(0.0, 255.0) as u8,
+		};
+		result_weights_sum += result[i].weight as i32;
+	}
+
+	// Fix rounding errors - add difference to largest weight.
+	let mut largest = 0;
+	for i in 1 .. 4
+	{
+		if result[i].weight > result[largest].weight
+		{
+			largest = i;
+		}
+	}
+	result[largest].weight = (result[largest].weight as i32 + 255 - result_weights_sum).clamp(0, 255) as u8;
+
+	result
+}
+
+fn read_morphed_positions_and_normals(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	primitive: &GltfPrimitive,
+	morph_weights: &[f32],
+) -> GltfResult<(Vec<Vec3f>, Option<Vec<Vec3f>>)>
+{
+	let mut positions = read_attribute(document, buffers, primitive, "POSITION")?
+		.ok_or_else(|| "No positions".to_string())?
+		.to_vec3_array();
+	let mut normals = read_attribute(document, buffers, primitive, "NORMAL")?.map(|a| a.to_vec3_array());
+
+	for (target, &weight) in primitive.targets.iter().zip(morph_weights)
+	{
+		if weight == 0.0
+		{
+			continue;
+		}
+
+		if let Some(&accessor_index) = target.get("POSITION")
+		{
+			let deltas = read_accessor(document, buffers, accessor_index)?.to_vec3_array();
+			for (position, delta) in positions.iter_mut().zip(deltas)
+			{
+				*position += delta * weight;
+			}
+		}
+		if let (Some(&accessor_index), Some(normals)) = (target.get("NORMAL"), &mut normals)
+		{
+			let deltas = read_accessor(document, buffers, accessor_index)?.to_vec3_array();
+			for (normal, delta) in normals.iter_mut().zip(deltas)
+			{
+				*normal += delta * weight;
+			}
+		}
+	}
+
+	Ok((positions, normals))
+}
+
+// Transform positions and normals. Normals are calculated later if they are absent.
+fn transform_positions_and_normals(
+	positions: &[Vec3f],
+	normals: &Option<Vec<Vec3f>>,
+	matrix: &Mat4f,
+) -> (Vec<Vec3f>, Vec<Vec3f>)
+{
+	let normals_matrix = Mat3f::from_cols(matrix.x.truncate(), matrix.y.truncate(), matrix.z.truncate())
+		.invert()
+		.map(|m| m.transpose())
+		.unwrap_or_else(Mat3f::identity);
+
+	let positions_transformed = positions.iter().map(|p| (matrix * p.extend(1.0)).truncate()).collect();
+	let normals_transformed = if let Some(normals) = normals
+	{
+		normals.iter().map(|n| normalize_or_zero(normals_matrix * n)).collect()
+	}
+	else
+	{
+		Vec::new()
+	};
+
+	(positions_transformed, normals_transformed)
+}
+
+fn read_tex_coords(document: &GltfDocument, buffers: &[Vec<u8>], primitive: &GltfPrimitive)
+	-> GltfResult<Vec<[f32; 2]>>
+{
+	Ok(
+		if let Some(accessor) = read_attribute(document, buffers, primitive, "TEXCOORD_0")?
+		{
+			(0 .. accessor.count())
+				.map(|i| [accessor.get_component(i, 0), accessor.get_component(i, 1)])
+				.collect()
+		}
+		else
+		{
+			Vec::new()
+		},
+	)
+}
+
+fn read_triangles(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	primitive: &GltfPrimitive,
+) -> GltfResult<(Vec<TriangleU32>, usize)>
+{
+	let num_vertices = read_attribute(document, buffers, primitive, "POSITION")?
+		.map(|a| a.count())
+		.unwrap_or(0);
+
+	let indices = if let Some(accessor_index) = primitive.indices
+	{
+		read_accessor_indices(document, buffers, accessor_index)?
+	}
+	else
+	{
+		(0 .. num_vertices).collect()
+	};
+
+	let triangles = indices
+		.chunks_exact(3)
+		.filter(|t| t[0] < num_vertices && t[1] < num_vertices && t[2] < num_vertices)
+		// glTF uses counter-clockwise order for front faces, but clockwise order is used by the engine.
+		.map(|t| [t[0] as u32, t[2] as u32, t[1] as u32])
+		.collect();
+
+	Ok((triangles, num_vertices))
+}
+
+// Calculate missing normals and tangent space and convert prepared data into final form.
+fn finalize_vertex_data(vertex_data: VertexDataPrepared, triangles: &[TriangleU32], num_vertices: usize) -> VertexData
+{
+	match vertex_data
+	{
+		VertexDataPrepared::Static {
+			positions,
+			normals,
+			tex_coords,
+		} =>
+		{
+			let tex_coords = fill_tex_coords(tex_coords, num_vertices);
+			let normals = fill_normals(normals, &positions, triangles);
+			let tangent_space = calculate_tangent_space(&positions, &normals, &tex_coords, triangles);
+			VertexData::NonAnimated(
+				(0 .. num_vertices)
+					.map(|i| VertexNonAnimated {
+						position: positions[i],
+						normal: normals[i],
+						tangent_space: tangent_space[i],
+						tex_coord: tex_coords[i],
+					})
+					.collect(),
+			)
+		},
+		VertexDataPrepared::PerFrame {
+			positions,
+			normals,
+			tex_coords,
+		} =>
+		{
+			let tex_coords = fill_tex_coords(tex_coords, num_vertices);
+			let mut variable = Vec::with_capacity(positions.len());
+			if num_vertices > 0
+			{
+				for (frame, frame_positions) in positions.chunks(num_vertices).enumerate()
+				{
+					let frame_normals = normals
+						.get(frame * num_vertices .. (frame + 1) * num_vertices)
+						.map(|n| n.to_vec())
+						.unwrap_or_default();
+					let frame_normals = fill_normals(frame_normals, frame_positions, triangles);
+					let tangent_space =
+						calculate_tangent_space(frame_positions, &frame_normals, &tex_coords, triangles);
+					for i in 0 .. num_vertices
+					{
+						variable.push(VertexAnimatedVertexVariable {
+							position: frame_positions[i],
+							normal: frame_normals[i],
+							tangent_space: tangent_space[i],
+						});
+					}
+				}
+			}
+
+			VertexData::VertexAnimated {
+				constant: tex_coords
+					.iter()
+					.map(|&tex_coord| VertexAnimatedVertexConstant { tex_coord })
+					.collect(),
+				variable,
+			}
+		},
+		VertexDataPrepared::Skinned {
+			positions,
+			normals,
+			tex_coords,
+			bones_descriptions,
+		} =>
+		{
+			let tex_coords = fill_tex_coords(tex_coords, num_vertices);
+			let normals = fill_normals(normals, &positions, triangles);
+			let tangent_space = calculate_tangent_space(&positions, &normals, &tex_coords, triangles);
+			VertexData::SkeletonAnimated(
+				(0 .. num_vertices)
+					.map(|i| SkeletonAnimatedVertex {
+						tex_coord: tex_coords[i],
+						position: positions[i],
+						normal: normals[i],
+						tangent_space: tangent_space[i],
+						bones_description: bones_descriptions[i],
+					})
+					.collect(),
+			)
+		},
+	}
+}
+
+fn fill_tex_coords(tex_coords: Vec<[f32; 2]>, num_vertices: usize) -> Vec<[f32; 2]>
+{
+	if tex_coords.len() == num_vertices
+	{
+		tex_coords
+	}
+	else
+	{
+		vec![[0.0, 0.0]; num_vertices]
+	}
+}
+
+// Calculate smooth normals if they are absent.
+fn fill_normals(normals: Vec<Vec3f>, positions: &[Vec3f], triangles: &[TriangleU32]) -> Vec<Vec3f>
+{
+	if normals.len() == positions.len()
+	{
+		return normals;
+	}
+
+	let mut result = vec![Vec3f::zero(); positions.len()];
+	for triangle in triangles
+	{
+		let v = triangle.map(|i| positions[i as usize]);
+		// Use clockwise order.
+		let normal = (v[2] - v[0]).cross(v[1] - v[0]);
+		for i in triangle
+		{
+			result[*i as usize] += normal;
+		}
+	}
+
+	result.into_iter().map(normalize_or_zero).collect()
+}
+
+fn normalize_or_zero(v: Vec3f) -> Vec3f
+{
+	let len = v.magnitude();
+	if len > 0.0
+	{
+		v / len
+	}
+	else
+	{
+		v
+	}
+}
+
+fn calculate_frames_info(
+	meshes: &[TriangleModelMesh],
+	num_frames: usize,
+	skeleton: Option<&Skeleton>,
+) -> Vec<TriangleModelFrameInfo>
+{
+	(0 .. num_frames)
+		.map(|frame| {
+			let mut bbox = BBox::from_point(&Vec3f::zero());
+			let mut first_point = true;
+			let mut extend_bbox = |p: &Vec3f| {
+				if first_point
+				{
+					bbox = BBox::from_point(p);
+					first_point = false;
+				}
+				else
+				{
+					bbox.extend_with_point(p);
+				}
+			};
+
+			for mesh in meshes
+			{
+				match &mesh.vertex_data
+				{
+					VertexData::NonAnimated(vertices) =>
+					{
+						for v in vertices
+						{
+							extend_bbox(&v.position);
+						}
+					},
+					VertexData::VertexAnimated { constant, variable } =>
+					{
+						for v in variable.iter().skip(frame * constant.len()).take(constant.len())
+						{
+							extend_bbox(&v.position);
+						}
+					},
+					VertexData::SkeletonAnimated(vertices) =>
+					{
+						let skin_matrices = if let Some(skeleton) = skeleton
+						{
+							let num_bones = skeleton.bones.len();
+							&skeleton.frame_skin_matrices[frame * num_bones .. (frame + 1) * num_bones]
+						}
+						else
+						{
+							continue;
+						};
+
+						for v in vertices
+						{
+							let mut position = Vec3f::zero();
+							for bone_description in &v.bones_description
+							{
+								let weight = (bone_description.weight as f32) / 255.0;
+								if weight > 0.0
+								{
+									position += (skin_matrices[bone_description.bone_index as usize] *
+										v.position.extend(1.0))
+									.truncate() * weight;
+								}
+							}
+							extend_bbox(&position);
+						}
+					},
+				}
+			}
+
+			TriangleModelFrameInfo { bbox }
+		})
+		.collect()
+}
+
+fn read_attribute(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	primitive: &GltfPrimitive,
+	name: &str,
+) -> GltfResult<Option<AccessorData>>
+{
+	if let Some(&accessor_index) = primitive.attributes.get(name)
+	{
+		Ok(Some(read_accessor(document, buffers, accessor_index)?))
+	}
+	else
+	{
+		Ok(None)
+	}
+}
+
+// Accessor data, converted into floats.
+struct AccessorData
+{
+	data: Vec<f32>,
+	num_components: usize,
+}
+
+impl AccessorData
+{
+	fn count(&self) -> usize
+	{
+		self.data.len() / self.num_components
+	}
+
+	fn get_component(&self, element: usize, component: usize) -> f32
+	{
+		if component < self.num_components
+		{
+			self.data
+				.get(element * self.num_components + component)
+				.cloned()
+				.unwrap_or(0.0)
+		}
+		else
+		{
+			0.0
+		}
+	}
+
+	fn to_vec3_array(&self) -> Vec<Vec3f>
+	{
+		(0 .. self.count())
+			.map(|i| {
+				Vec3f::new(
+					self.get_component(i, 0),
+					self.get_component(i, 1),
+					self.get_component(i, 2),
+				)
+			})
+			.collect()
+	}
+}
+
+fn read_accessor(document: &GltfDocument, buffers: &[Vec<u8>], accessor_index: usize) -> GltfResult<AccessorData>
+{
+	let (data, num_components) = read_accessor_components(document, buffers, accessor_index, |accessor, c| {
+		match accessor.component_type
+		{
+			GLTF_COMPONENT_TYPE_BYTE =>
+			{
+				let v = c[0] as i8 as f32;
+				if accessor.normalized
+				{
+					(v / 127.0).max(-1.0)
+				}
+				else
+				{
+					v
+				}
+			},
+			GLTF_COMPONENT_TYPE_UNSIGNED_BYTE =>
+			{
+				let v = c[0] as f32;
+				if accessor.normalized
+				{
+					v / 255.0
+				}
+				else
+				{
+					v
+				}
+			},
+			GLTF_COMPONENT_TYPE_SHORT =>
+			{
+				let v = i16::from_le_bytes([c[0], c[1]]) as f32;
+				if accessor.normalized
+				{
+					(v / 32767.0).max(-1.0)
+				}
+				else
+				{
+					v
+				}
+			},
+			GLTF_COMPONENT_TYPE_UNSIGNED_SHORT =>
+			{
+				let v = u16::from_le_bytes([c[0], c[1]]) as f32;
+				if accessor.normalized
+				{
+					v / 65535.0
+				}
+				else
+				{
+					v
+				}
+			},
+			GLTF_COMPONENT_TYPE_UNSIGNED_INT => u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as f32,
+			_ => f32::from_le_bytes([c[0], c[1], c[2], c[3]]),
+		}
+	})?;
+
+	Ok(AccessorData { data, num_components })
+}
+
+// Read indices directly from integer components, since conversion into floats loses precision for large values.
+fn read_accessor_indices(document: &GltfDocument, buffers: &[Vec<u8>], accessor_index: usize)
+	-> GltfResult<Vec<usize>>
+{
+	let component_type = document
+		.accessors
+		.get(accessor_index)
+		.map(|a| a.component_type)
+		.unwrap_or(0);
+	if !(component_type == GLTF_COMPONENT_TYPE_UNSIGNED_BYTE ||
+		component_type == GLTF_COMPONENT_TYPE_UNSIGNED_SHORT ||
+		component_type == GLTF_COMPONENT_TYPE_UNSIGNED_INT)
+	{
+		return Err(format!("Invalid indices accessor {}", accessor_index));
+	}
+
+	let (data, _num_components) = read_accessor_components(document, buffers, accessor_index, |accessor, c| {
+		match accessor.component_type
+		{
+			GLTF_COMPONENT_TYPE_UNSIGNED_BYTE => c[0] as usize,
+			GLTF_COMPONENT_TYPE_UNSIGNED_SHORT => u16::from_le_bytes([c[0], c[1]]) as usize,
+			_ => u32::from_le_bytes([c[0], c[1], c[2], c[3]]) as usize,
+		}
+	})?;
+
+	Ok(data)
+}
+
+// Read all components of accessor, converting each component (little-endian bytes) with given function.
+// Returns components and number of components per element.
+fn read_accessor_components<T: Clone + Default>(
+	document: &GltfDocument,
+	buffers: &[Vec<u8>],
+	accessor_index: usize,
+	convert_component: impl Fn(&GltfAccessor, &[u8]) -> T,
+) -> GltfResult<(Vec<T>, usize)>
+{
+	let accessor = document
+		.accessors
+		.get(accessor_index)
+		.ok_or_else(|| format!("Invalid accessor {}", accessor_index))?;
+
+	let num_components = match accessor.type_.as_str()
+	{
+		"SCALAR" => 1,
+		"VEC2" => 2,
+		"VEC3" => 3,
+		"VEC4" => 4,
+		"MAT2" => 4,
+		"MAT3" => 9,
+		"MAT4" => 16,
+		t => return Err(format!("Invalid accessor type {}", t)),
+	};
+
+	let component_size = match accessor.component_type
+	{
+		GLTF_COMPONENT_TYPE_BYTE | GLTF_COMPONENT_TYPE_UNSIGNED_BYTE => 1,
+		GLTF_COMPONENT_TYPE_SHORT | GLTF_COMPONENT_TYPE_UNSIGNED_SHORT => 2,
+		GLTF_COMPONENT_TYPE_UNSIGNED_INT | GLTF_COMPONENT_TYPE_FLOAT => 4,
+		t => return Err(format!("Invalid accessor component type {}", t)),
+	};
+
+	let num_values = accessor.count * num_components;
+
+	let buffer_view_index = if let Some(i) = accessor.buffer_view
+	{
+		i
+	}
+	else
+	{
+		// Accessor without buffer view is initialized with zeros.
+		return Ok((vec![T::default(); num_values], num_components));
+	};
+
+	let buffer_view = document
+		.buffer_views
+		.get(buffer_view_index)
+		.ok_or_else(|| format!("Invalid buffer view {}", buffer_view_index))?;
+	let buffer = buffers
+		.get(buffer_view.buffer)
+		.ok_or_else(|| format!("Invalid buffer {}", buffer_view.buffer))?;
+	let view_data = buffer
+		.get(buffer_view.byte_offset .. buffer_view.byte_offset + buffer_view.byte_length)
+		.ok_or_else(|| format!("Invalid buffer view {} range", buffer_view_index))?;
+
+	let element_size = component_size * num_components;
+	let stride = buffer_view.byte_stride.unwrap_or(element_size).max(element_size);
+
+	let mut data = Vec::with_capacity(num_values);
+	for element in 0 .. accessor.count
+	{
+		let element_offset = accessor.byte_offset + element * stride;
+		let element_data = view_data
+			.get(element_offset .. element_offset + element_size)
+			.ok_or_else(|| format!("Accessor {} is out of bounds", accessor_index))?;
+		for c in element_data.chunks_exact(component_size)
+		{
+			data.push(convert_component(accessor, c));
+		}
+	}
+
+	Ok((data, num_components))
+}
+
+// glTF quaternions are stored as x, y, z, w.
+fn make_quaternion(q: &[f32; 4]) -> QuaternionF
+{
+	QuaternionF::new(q[3], q[0], q[1], q[2])
+}
+
+// glTF matrices are stored in column-major order.
+fn make_mat4_array(m: &[f32; 16]) -> [[f32; 4]; 4]
+{
+	[
+		[m[0], m[1], m[2], m[3]],
+		[m[4], m[5], m[6], m[7]],
+		[m[8], m[9], m[10], m[11]],
+		[m[12], m[13], m[14], m[15]],
+	]
+}
+
+fn decode_base64(s: &str) -> Option<Vec<u8>>
+{
+	let mut result = Vec::with_capacity(s.len() * 3 / 4);
+	let mut accumulator = 0u32;
+	let mut num_bits = 0;
+	for c in s.bytes()
+	{
+		let value = match c
+		{
+			b'A' ..= b'Z' => c - b'A',
+			b'a' ..= b'z' => c - b'a' + 26,
+			b'0' ..= b'9' => c - b'0' + 52,
+			b'+' | b'-' => 62,
+			b'/' | b'_' => 63,
+			b'=' => break,
+			b' ' | b'\n' | b'\r' | b'\t' => continue,
+			_ => return None,
+		};
+		accumulator = (accumulator << 6) | (value as u32);
+		num_bits += 6;
+		if num_bits >= 8
+		{
+			num_bits -= 8;
+			result.push((accumulator >> num_bits) as u8);
+		}
+	}
+
+	Some(result)
+}
+
+// Subset of glTF JSON structures, needed for models loading.
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct GltfDocument
+{
+	accessors: Vec<GltfAccessor>,
+	animations: Vec<GltfAnimation>,
+	buffers: Vec<GltfBuffer>,
+	buffer_views: Vec<GltfBufferView>,
+	materials: Vec<GltfMaterial>,
+	meshes: Vec<GltfMesh>,
+	nodes: Vec<GltfNode>,
+	scene: Option<usize>,
+	scenes: Vec<GltfScene>,
+	skins: Vec<GltfSkin>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct GltfAccessor
+{
+	buffer_view: Option<usize>,
+	byte_offset: usize,
+	component_type: u32,
+	normalized: bool,
+	count: usize,
+	#[serde(rename = "type")]
+	type_: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfAnimation
+{
+	name: String,
+	channels: Vec<GltfAnimationChannel>,
+	samplers: Vec<GltfAnimationSampler>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfAnimationChannel
+{
+	sampler: usize,
+	target: GltfAnimationChannelTarget,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfAnimationChannelTarget
+{
+	node: Option<usize>,
+	path: String,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct GltfAnimationSampler
+{
+	input: usize,
+	interpolation: String,
+	output: usize,
+}
+
+impl Default for GltfAnimationSampler
+{
+	fn default() -> Self
+	{
+		Self {
+			input: 0,
+			interpolation: "LINEAR".to_string(),
+			output: 0,
+		}
+	}
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct GltfBuffer
+{
+	uri: Option<String>,
+	byte_length: usize,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct GltfBufferView
+{
+	buffer: usize,
+	byte_offset: usize,
+	byte_length: usize,
+	byte_stride: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfMaterial
+{
+	name: String,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfMesh
+{
+	name: String,
+	primitives: Vec<GltfPrimitive>,
+	weights: Vec<f32>,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct GltfPrimitive
+{
+	attributes: HashMap<String, usize>,
+	indices: Option<usize>,
+	material: Option<usize>,
+	mode: u32,
+	targets: Vec<HashMap<String, usize>>,
+}
+
+impl Default for GltfPrimitive
+{
+	fn default() -> Self
+	{
+		Self {
+			attributes: HashMap::new(),
+			indices: None,
+			material: None,
+			mode: GLTF_MODE_TRIANGLES,
+			targets: Vec::new(),
+		}
+	}
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct GltfNode
+{
+	name: String,
+	children: Vec<usize>,
+	mesh: Option<usize>,
+	skin: Option<usize>,
+	matrix: Option<[f32; 16]>,
+	translation: [f32; 3],
+	rotation: [f32; 4],
+	scale: [f32; 3],
+	weights: Vec<f32>,
+}
+
+impl Default for GltfNode
+{
+	fn default() -> Self
+	{
+		Self {
+			name: String::new(),
+			children: Vec::new(),
+			mesh: None,
+			skin: None,
+			matrix: None,
+			translation: [0.0, 0.0, 0.0],
+			rotation: [0.0, 0.0, 0.0, 1.0],
+			scale: [1.0, 1.0, 1.0],
+			weights: Vec::new(),
+		}
+	}
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct GltfScene
+{
+	nodes: Vec<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct GltfSkin
+{
+	inverse_bind_matrices: Option<usize>,
+	joints: Vec<usize>,
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::{super::triangle_model::get_triangle_model_mesh_num_vertices, *};
+
+	fn load_from_contents(name: &str, contents: &[u8]) -> Option<TriangleModel>
+	{
+		let path = std::env::temp_dir().join(format!("square_wheel_gltf_test_{}_{}", std::process::id(), name));
+		std::fs::write(&path, contents).unwrap();
+		let result = load_model_gltf(&path);
+		std::fs::remove_file(&path).unwrap();
+		result.unwrap()
+	}
+
+	#[test]
+	fn load_embedded_gltf()
+	{
+		// Triangle with positions (0, 0, 0), (1, 0, 0), (0, 1, 0) and 32-bit indices 0, 1, 2.
+		let document = r#"{
+			"asset": { "version": "2.0" },
+			"scene": 0,
+			"scenes": [ { "nodes": [ 0 ] } ],
+			"nodes": [ { "mesh": 0 } ],
+			"meshes": [ { "primitives": [ { "attributes": { "POSITION": 0 }, "indices": 1 } ] } ],
+			"accessors": [
+				{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+				{ "bufferView": 1, "componentType": 5125, "count": 3, "type": "SCALAR" }
+			],
+			"bufferViews": [
+				{ "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+				{ "buffer": 0, "byteOffset": 36, "byteLength": 12 }
+			],
+			"buffers": [
+				{
+					"uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAAAAAAAAEAAAACAAAA",
+					"byteLength": 48
+				}
+			]
+		}"#;
+
+		let model = load_from_contents("embedded.gltf", document.as_bytes()).unwrap();
+		assert_eq!(model.meshes.len(), 1);
+		assert_eq!(model.meshes[0].triangles.len(), 1);
+		assert_eq!(get_triangle_model_mesh_num_vertices(&model.meshes[0]), 3);
+	}
+
+	#[test]
+	fn load_glb()
+	{
+		// Same triangle as in previous test, but with buffer stored in binary chunk.
+		let mut json = r#"{
+			"asset": { "version": "2.0" },
+			"scene": 0,
+			"scenes": [ { "nodes": [ 0 ] } ],
+			"nodes": [ { "mesh": 0 } ],
+			"meshes": [ { "primitives": [ { "attributes": { "POSITION": 0 }, "indices": 1 } ] } ],
+			"accessors": [
+				{ "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" },
+				{ "bufferView": 1, "componentType": 5125, "count": 3, "type": "SCALAR" }
+			],
+			"bufferViews": [
+				{ "buffer": 0, "byteOffset": 0, "byteLength": 36 },
+				{ "buffer": 0, "byteOffset": 36, "byteLength": 12 }
+			],
+			"buffers": [ { "byteLength": 48 } ]
+		}"#
+		.as_bytes()
+		.to_vec();
+		// Chunks are 4-bytes aligned.
+		json.resize((json.len() + 3) & !3, b' ');
+
+		let mut binary = Vec::new();
+		for v in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0]
+		{
+			binary.extend_from_slice(&v.to_le_bytes());
+		}
+		for i in [0u32, 1, 2]
+		{
+			binary.extend_from_slice(&i.to_le_bytes());
+		}
+
+		let mut contents = Vec::new();
+		contents.extend_from_slice(&GLB_MAGIC);
+		contents.extend_from_slice(&2u32.to_le_bytes());
+		contents.extend_from_slice(&((12 + 8 + json.len() + 8 + binary.len()) as u32).to_le_bytes());
+		contents.extend_from_slice(&(json.len() as u32).to_le_bytes());
+		contents.extend_from_slice(&GLB_CHUNK_JSON.to_le_bytes());
+		contents.extend_from_slice(&json);
+		contents.extend_from_slice(&(binary.len() as u32).to_le_bytes());
+		contents.extend_from_slice(&GLB_CHUNK_BIN.to_le_bytes());
+		contents.extend_from_slice(&binary);
+
+		let model = load_from_contents("binary.glb", &contents).unwrap();
+		assert_eq!(model.meshes.len(), 1);
+		assert_eq!(model.meshes[0].triangles.len(), 1);
+		assert_eq!(get_triangle_model_mesh_num_vertices(&model.meshes[0]), 3);
+	}
+
+	#[test]
+	fn invalid_joint_index()
+	{
+		// Skin references node 100, but there is only one node.
+		let document = r#"{
+			"asset": { "version": "2.0" },
+			"scene": 0,
+			"scenes": [ { "nodes": [ 0 ] } ],
+			"nodes": [ { "mesh": 0, "skin": 0 } ],
+			"skins": [ { "joints": [ 0, 100 ] } ],
+			"meshes": [ { "primitives": [ { "attributes": { "POSITION": 0 } } ] } ],
+			"accessors": [ { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" } ],
+			"bufferViews": [ { "buffer": 0, "byteOffset": 0, "byteLength": 36 } ],
+			"buffers": [
+				{
+					"uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA",
+					"byteLength": 36
+				}
+			]
+		}"#;
+
+		assert!(load_from_contents("invalid_joint.gltf", document.as_bytes()).is_none());
+	}
+
+	#[test]
+	fn joints_hierarchy_cycle_outside_scene()
+	{
+		// Joint node 1 is not reachable from scene. Nodes 1 and 2 are children of each other.
+		let document = r#"{
+			"asset": { "version": "2.0" },
+			"scene": 0,
+			"scenes": [ { "nodes": [ 0 ] } ],
+			"nodes": [ { "mesh": 0, "skin": 0 }, { "children": [ 2 ] }, { "children": [ 1 ] } ],
+			"skins": [ { "joints": [ 1 ] } ],
+			"meshes": [ { "primitives": [ { "attributes": { "POSITION": 0 } } ] } ],
+			"accessors": [ { "bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3" } ],
+			"bufferViews": [ { "buffer": 0, "byteOffset": 0, "byteLength": 36 } ],
+			"buffers": [
+				{
+					"uri": "data:application/octet-stream;base64,AAAAAAAAAAAAAAAAAACAPwAAAAAAAAAAAAAAAAAAgD8AAAAA",
+					"byteLength": 36
+				}
+			]
+		}"#;
+
+		assert!(load_from_contents("joints_cycle.gltf", document.as_bytes()).is_none());
+	}
+}
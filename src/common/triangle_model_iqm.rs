@@ -1,5 +1,4 @@
-use super::{triangle_model::*, triangle_model_loading::*};
-use crate::common::{bbox::*, math_types::*};
+use super::{bbox::*, math_types::*, triangle_model::*, triangle_model_loading::*};
 use std::io::{Read, Seek};
 
 pub fn load_model_iqm(file_path: &std::path::Path) -> Result<Option<TriangleModel>, std::io::Error>
@@ -10,30 +9,14 @@ pub fn load_model_iqm(file_path: &std::path::Path) -> Result<Option<TriangleMode
 		.create(false)
 		.open(file_path)?;
 
-	let header_size = std::mem::size_of::<IQMHeader>();
-	let mut header = unsafe { std::mem::zeroed::<IQMHeader>() };
-	let header_bytes =
-		unsafe { std::slice::from_raw_parts_mut((&mut header) as *mut IQMHeader as *mut u8, header_size) };
-
-	if file.read(header_bytes)? != header_size
+	let header = if let Some(h) = load_header(&mut file)?
 	{
-		println!("Can't read IQM header");
-		return Ok(None);
+		h
 	}
-
-	if header.magic != IQM_MAGIC
+	else
 	{
-		println!("File is not a valid IQM model");
-		return Ok(None);
-	}
-	if header.version != IQM_VERSION
-	{
-		println!(
-			"Can't load incompatible IQM model version: {}, expected {}",
-			header.version, IQM_VERSION
-		);
 		return Ok(None);
-	}
+	};
 
 	if header.num_meshes == 0
 	{
@@ -52,21 +35,17 @@ pub fn load_model_iqm(file_path: &std::path::Path) -> Result<Option<TriangleMode
 	let texts = load_texts(&mut file, &header)?;
 	let meshes = load_meshes(&mut file, &header)?;
 	let triangles = load_triangles(&mut file, &header)?;
-	let vertices = load_vertices(&mut file, &header)?;
+	let mut vertices = load_vertices(&mut file, &header)?;
 	let bounds = load_bounds(&mut file, &header)?;
 	let joints = load_joints(&mut file, &header)?;
 	let poses = load_poses(&mut file, &header)?;
 	let animations = load_animations(&mut file, &header)?;
 
-	let bones = joints
-		.iter()
-		.map(|j| TriangleModelBoneInfo {
-			name: get_text_str(&texts, j.name).to_string(),
-			parent: j.parent,
-		})
-		.collect();
+	let base_frame_mats = calculate_base_frame_matrices(&joints);
+
+	let bones = create_bones(&texts, &joints, &base_frame_mats);
 
-	let frame_bones = create_frames(&mut file, &header, &joints, &poses)?;
+	let frame_bones = create_frames(&mut file, &header, &base_frame_mats, &poses)?;
 
 	let mut frames_info: Vec<_> = bounds
 		.iter()
@@ -86,37 +65,33 @@ pub fn load_model_iqm(file_path: &std::path::Path) -> Result<Option<TriangleMode
 		frames_info = vec![TriangleModelFrameInfo { bbox }];
 	}
 
-	let triangles_transformed = triangles
-		.iter()
-		.map(|t| {
-			[
-				t.vertex[0] as VertexIndex,
-				t.vertex[1] as VertexIndex,
-				t.vertex[2] as VertexIndex,
-			]
-		})
-		.collect();
+	let triangles_transformed: Vec<TriangleU32> = triangles.iter().map(|t| t.vertex).collect();
 
-	let animations_transfomed = animations
-		.iter()
-		.map(|a| TriangleModelAnimation {
-			name: get_text_str(&texts, a.name).to_string(),
-			start_frame: a.first_frame,
-			num_frames: a.num_frames,
-			frames_per_second: a.framerate,
-			looped: (a.flags & IQM_LOOP) != 0,
-		})
-		.collect();
+	if !has_tangents(&mut file, &header)?
+	{
+		let tangent_space = calculate_tangent_space(
+			&vertices.iter().map(|v| v.position).collect::<Vec<_>>(),
+			&vertices.iter().map(|v| v.normal).collect::<Vec<_>>(),
+			&vertices.iter().map(|v| v.tex_coord).collect::<Vec<_>>(),
+			&triangles_transformed,
+		);
+		for (v, tangent_space) in vertices.iter_mut().zip(tangent_space)
+		{
+			v.tangent_space = tangent_space;
+		}
+	}
+
+	let animations_transfomed = transform_animations(&texts, &animations);
 
 	// TODO - export all meshes separately.
 	// Now just load single mesh.
-	let single_mesh = TriangleModelMesh {
-		name: get_text_str(&texts, meshes[0].name).to_string(),
-		material_name: get_text_str(&texts, meshes[0].material).to_string(),
-		triangles: triangles_transformed,
-		vertex_data: VertexData::SkeletonAnimated(vertices),
-		num_frames: header.num_frames,
-	};
+	let single_mesh_parts = make_meshes_with_vertex_limit(
+		get_text_str(&texts, meshes[0].name),
+		get_text_str(&texts, meshes[0].material),
+		header.num_frames,
+		VertexData::SkeletonAnimated(vertices),
+		&triangles_transformed,
+	);
 
 	// Add extra shift because we use texture coordinates floor, instead of linear OpenGL interpolation as Quake III does.
 	let tc_shift = -Vec2f::new(0.5, 0.5);
@@ -127,10 +102,116 @@ pub fn load_model_iqm(file_path: &std::path::Path) -> Result<Option<TriangleMode
 		tc_shift,
 		bones,
 		frame_bones,
-		meshes: vec![single_mesh],
+		meshes: single_mesh_parts,
+		lods: Vec::new(),
+	}))
+}
+
+// Load only skeleton and animations, ignore meshes (if they exist).
+pub fn load_skeleton_animation_iqm(
+	file_path: &std::path::Path,
+) -> Result<Option<TriangleModelSkeletonAnimation>, std::io::Error>
+{
+	let mut file = std::fs::OpenOptions::new()
+		.read(true)
+		.write(false)
+		.create(false)
+		.open(file_path)?;
+
+	let header = if let Some(h) = load_header(&mut file)?
+	{
+		h
+	}
+	else
+	{
+		return Ok(None);
+	};
+
+	if header.num_joints != header.num_poses
+	{
+		println!(
+			"Invalid IQM model: joints/poses mismatch ({}/{})",
+			header.num_joints, header.num_poses
+		);
+		return Ok(None);
+	}
+	if header.num_frames == 0
+	{
+		println!("Invalid IQM animation with no frames");
+		return Ok(None);
+	}
+
+	let texts = load_texts(&mut file, &header)?;
+	let joints = load_joints(&mut file, &header)?;
+	let poses = load_poses(&mut file, &header)?;
+	let animations = load_animations(&mut file, &header)?;
+
+	let base_frame_mats = calculate_base_frame_matrices(&joints);
+
+	Ok(Some(TriangleModelSkeletonAnimation {
+		animations: transform_animations(&texts, &animations),
+		bones: create_bones(&texts, &joints, &base_frame_mats),
+		frame_bones: create_frames(&mut file, &header, &base_frame_mats, &poses)?,
 	}))
 }
 
+fn load_header(file: &mut std::fs::File) -> Result<Option<IQMHeader>, std::io::Error>
+{
+	let header_size = std::mem::size_of::<IQMHeader>();
+	let mut header = unsafe { std::mem::zeroed::<IQMHeader>() };
+	let header_bytes =
+		unsafe { std::slice::from_raw_parts_mut((&mut header) as *mut IQMHeader as *mut u8, header_size) };
+
+	if file.read(header_bytes)? != header_size
+	{
+		println!("Can't read IQM header");
+		return Ok(None);
+	}
+
+	if header.magic != IQM_MAGIC
+	{
+		println!("File is not a valid IQM model");
+		return Ok(None);
+	}
+	if header.version != IQM_VERSION
+	{
+		println!(
+			"Can't load incompatible IQM model version: {}, expected {}",
+			header.version, IQM_VERSION
+		);
+		return Ok(None);
+	}
+
+	Ok(Some(header))
+}
+
+fn create_bones(texts: &[u8], joints: &[IQMJoint], base_frame_mats: &[(Mat4f, Mat4f)]) -> Vec<TriangleModelBoneInfo>
+{
+	joints
+		.iter()
+		.zip(base_frame_mats)
+		.map(|(j, base_mats)| TriangleModelBoneInfo {
+			name: get_text_str(texts, j.name).to_string(),
+			parent: j.parent,
+			base_matrix: base_mats.0,
+		})
+		.collect()
+}
+
+fn transform_animations(texts: &[u8], animations: &[IQMAnim]) -> Vec<TriangleModelAnimation>
+{
+	animations
+		.iter()
+		.map(|a| TriangleModelAnimation {
+			name: get_text_str(texts, a.name).to_string(),
+			start_frame: a.first_frame,
+			num_frames: a.num_frames,
+			frames_per_second: a.framerate,
+			looped: (a.flags & IQM_LOOP) != 0,
+		})
+		.collect()
+}
+
 fn load_texts(file: &mut std::fs::File, header: &IQMHeader) -> Result<Vec<u8>, std::io::Error>
 {
 	read_vector(file, header.ofs_text as u64, header.num_text)
@@ -166,6 +247,10 @@ fn load_vertices(file: &mut std::fs::File, header: &IQMHeader) -> Result<Vec<Ske
 			tex_coord: [0.0, 0.0],
 			position: Vec3f::zero(),
 			normal: Vec3f::zero(),
+			tangent_space: VertexTangentSpace {
+				tangent: Vec3f::unit_x(),
+				binormal: Vec3f::unit_y(),
+			},
 			bones_description: [VertexBoneDescription {
 				bone_index: 0,
 				weight: 0
@@ -174,6 +259,8 @@ fn load_vertices(file: &mut std::fs::File, header: &IQMHeader) -> Result<Vec<Ske
 		header.num_vertexes as usize
 	];
 
+	let mut tangents_data: Option<Vec<[f32; 4]>> = None;
+
 	// TODO - improve this, support other attributes and types.
 
 	for vertex_array in &vertex_arrays
@@ -220,7 +307,16 @@ fn load_vertices(file: &mut std::fs::File, header: &IQMHeader) -> Result<Vec<Ske
 				}
 			},
 			IQM_TANGENT =>
-			{},
+			{
+				if vertex_array.size == 4 && vertex_array.format == IQM_FLOAT
+				{
+					// TODO - use uninitialized memory.
+					let mut tangents = vec![[0.0, 0.0, 0.0, 0.0]; vertices.len()];
+					read_chunk(file, vertex_array.offset as u64, &mut tangents)?;
+					// Process tangents later, since normals may be not loaded yet.
+					tangents_data = Some(tangents);
+				}
+			},
 			IQM_BLENDINDEXES =>
 			{
 				if vertex_array.size == 4 && vertex_array.format == IQM_UBYTE
@@ -260,9 +356,29 @@ fn load_vertices(file: &mut std::fs::File, header: &IQMHeader) -> Result<Vec<Ske
 		}
 	}
 
+	if let Some(tangents) = tangents_data
+	{
+		for (v, t) in vertices.iter_mut().zip(tangents.iter())
+		{
+			// Fourth component is a sign of binormal.
+			let tangent = Vec3f::new(t[0], t[1], t[2]);
+			v.tangent_space = VertexTangentSpace {
+				tangent,
+				binormal: v.normal.cross(tangent) * t[3],
+			};
+		}
+	}
+
 	Ok(vertices)
 }
 
+fn has_tangents(file: &mut std::fs::File, header: &IQMHeader) -> Result<bool, std::io::Error>
+{
+	Ok(load_vertex_arrays(file, header)?
+		.iter()
+		.any(|a| a.type_ == IQM_TANGENT && a.size == 4 && a.format == IQM_FLOAT))
+}
+
 fn load_vertex_arrays(file: &mut std::fs::File, header: &IQMHeader) -> Result<Vec<IQMVertexArray>, std::io::Error>
 {
 	read_vector(file, header.ofs_vertexarrays as u64, header.num_vertexarrays)
@@ -288,14 +404,9 @@ fn load_animations(file: &mut std::fs::File, header: &IQMHeader) -> Result<Vec<I
 	read_vector(file, header.ofs_anims as u64, header.num_anims)
 }
 
-fn create_frames(
-	file: &mut std::fs::File,
-	header: &IQMHeader,
-	joints: &[IQMJoint],
-	poses: &[IQMPose],
-) -> Result<Vec<TriangleModelBoneFrame>, std::io::Error>
+// Prepare pairs of base frame and inverted base frame matrices for each joint.
+fn calculate_base_frame_matrices(joints: &[IQMJoint]) -> Vec<(Mat4f, Mat4f)>
 {
-	// Prepare pairs of base frame and inverted base frame matrices for each joint.
 	let mut base_frame_mats = vec![(Mat4f::identity(), Mat4f::identity()); joints.len()];
 	for (index, joint) in joints.iter().enumerate()
 	{
@@ -320,6 +431,16 @@ fn create_frames(
 		}
 	}
 
+	base_frame_mats
+}
+
+fn create_frames(
+	file: &mut std::fs::File,
+	header: &IQMHeader,
+	base_frame_mats: &[(Mat4f, Mat4f)],
+	poses: &[IQMPose],
+) -> Result<Vec<TriangleModelBoneFrame>, std::io::Error>
+{
 	let mut frame_bones = vec![
 		TriangleModelBoneFrame {
 			matrix: Mat4f::identity()
@@ -0,0 +1,273 @@
+use super::{math_types::*, triangle_model::*};
+use std::io::{Read, Seek};
+
+pub fn read_chunk<T: Copy>(file: &mut std::fs::File, offset: u64, dst: &mut [T]) -> Result<(), std::io::Error>
+{
+	file.seek(std::io::SeekFrom::Start(offset as u64))?;
+
+	if dst.is_empty()
+	{
+		return Ok(());
+	}
+
+	let bytes = unsafe {
+		std::slice::from_raw_parts_mut((&mut dst[0]) as *mut T as *mut u8, std::mem::size_of::<T>() * dst.len())
+	};
+
+	file.read_exact(bytes)?;
+
+	Ok(())
+}
+
+pub fn read_vector<T: Copy>(file: &mut std::fs::File, offset: u64, num_elements: u32)
+	-> Result<Vec<T>, std::io::Error>
+{
+	// TODO - use uninitiaized memory instead.
+	let mut result = unsafe { vec![std::mem::zeroed::<T>(); num_elements as usize] };
+
+	read_chunk(file, offset, &mut result)?;
+
+	Ok(result)
+}
+
+// Triangle with large indices, used before splitting meshes into parts.
+pub type TriangleU32 = [u32; 3];
+
+// Create mesh from vertices and triangles with possible indices overflow.
+// Split it into several meshes with same material if number of vertices is greater than maximum number allowed.
+pub fn make_meshes_with_vertex_limit(
+	name: &str,
+	material_name: &str,
+	num_frames: u32,
+	vertex_data: VertexData,
+	triangles: &[TriangleU32],
+) -> Vec<TriangleModelMesh>
+{
+	const MAX_VERTICES: usize = (VertexIndex::MAX as usize) + 1;
+
+	let num_vertices = get_vertex_data_num_vertices(&vertex_data);
+	if num_vertices <= MAX_VERTICES
+	{
+		return vec![TriangleModelMesh {
+			name: name.to_string(),
+			material_name: material_name.to_string(),
+			triangles: triangles.iter().map(|t| t.map(|index| index as VertexIndex)).collect(),
+			num_frames,
+			vertex_data,
+		}];
+	}
+
+	// Add triangles one by one into current part until vertex limit is reached.
+	let mut result = Vec::new();
+	let mut vertex_remap_table = vec![VertexIndex::MAX as u32 + 1; num_vertices];
+	let mut part_vertices = Vec::new();
+	let mut part_triangles = Vec::new();
+	for triangle in triangles
+	{
+		if triangle.iter().any(|&index| index as usize >= num_vertices)
+		{
+			continue;
+		}
+
+		let num_new_vertices = triangle
+			.iter()
+			.filter(|&&index| vertex_remap_table[index as usize] > VertexIndex::MAX as u32)
+			.count();
+		if part_vertices.len() + num_new_vertices > MAX_VERTICES
+		{
+			result.push(make_mesh_part(
+				name,
+				material_name,
+				num_frames,
+				result.len(),
+				&vertex_data,
+				&part_vertices,
+				std::mem::take(&mut part_triangles),
+			));
+			for &index in &part_vertices
+			{
+				vertex_remap_table[index as usize] = VertexIndex::MAX as u32 + 1;
+			}
+			part_vertices.clear();
+		}
+
+		let mut part_triangle = [0; 3];
+		for (&index, part_index) in triangle.iter().zip(part_triangle.iter_mut())
+		{
+			let remapped_index = &mut vertex_remap_table[index as usize];
+			if *remapped_index > VertexIndex::MAX as u32
+			{
+				*remapped_index = part_vertices.len() as u32;
+				part_vertices.push(index);
+			}
+			*part_index = *remapped_index as VertexIndex;
+		}
+		part_triangles.push(part_triangle);
+	}
+
+	if !part_triangles.is_empty()
+	{
+		result.push(make_mesh_part(
+			name,
+			material_name,
+			num_frames,
+			result.len(),
+			&vertex_data,
+			&part_vertices,
+			part_triangles,
+		));
+	}
+
+	result
+}
+
+pub fn get_vertex_data_num_vertices(vertex_data: &VertexData) -> usize
+{
+	match vertex_data
+	{
+		VertexData::NonAnimated(v) => v.len(),
+		VertexData::VertexAnimated { constant, .. } => constant.len(),
+		VertexData::SkeletonAnimated(v) => v.len(),
+	}
+}
+
+// Create new vertex data with given vertices of source vertex data.
+pub fn select_vertices(vertex_data: &VertexData, vertices: &[u32]) -> VertexData
+{
+	match vertex_data
+	{
+		VertexData::NonAnimated(v) => VertexData::NonAnimated(vertices.iter().map(|&i| v[i as usize]).collect()),
+		VertexData::VertexAnimated { constant, variable } =>
+		{
+			let mut variable_selected = Vec::with_capacity(variable.len() / constant.len().max(1) * vertices.len());
+			if !constant.is_empty()
+			{
+				for frame_vertices in variable.chunks(constant.len())
+				{
+					variable_selected.extend(vertices.iter().map(|&i| frame_vertices[i as usize]));
+				}
+			}
+			VertexData::VertexAnimated {
+				constant: vertices.iter().map(|&i| constant[i as usize]).collect(),
+				variable: variable_selected,
+			}
+		},
+		VertexData::SkeletonAnimated(v) =>
+		{
+			VertexData::SkeletonAnimated(vertices.iter().map(|&i| v[i as usize]).collect())
+		},
+	}
+}
+
+fn make_mesh_part(
+	name: &str,
+	material_name: &str,
+	num_frames: u32,
+	part_index: usize,
+	vertex_data: &VertexData,
+	part_vertices: &[u32],
+	part_triangles: Vec<Triangle>,
+) -> TriangleModelMesh
+{
+	TriangleModelMesh {
+		name: format!("{}_part{}", name, part_index),
+		material_name: material_name.to_string(),
+		triangles: part_triangles,
+		num_frames,
+		vertex_data: select_vertices(vertex_data, part_vertices),
+	}
+}
+
+// Calculate tangent space for vertices, using triangles texture coordinates.
+// Result vectors are normalized and orthogonal to normals.
+// Triangles with indices of any integer type are supported.
+pub fn calculate_tangent_space<I: Copy + Into<u64>>(
+	positions: &[Vec3f],
+	normals: &[Vec3f],
+	tex_coords: &[[f32; 2]],
+	triangles: &[[I; 3]],
+) -> Vec<VertexTangentSpace>
+{
+	let mut tangents = vec![Vec3f::zero(); positions.len()];
+	let mut binormals = vec![Vec3f::zero(); positions.len()];
+
+	// Accumulate tangent space vectors of all triangles, adjacent to each vertex.
+	for triangle in triangles
+	{
+		let i = triangle.map(|index| index.into() as usize);
+		if i[0] >= positions.len() || i[1] >= positions.len() || i[2] >= positions.len()
+		{
+			continue;
+		}
+
+		let d_pos1 = positions[i[1]] - positions[i[0]];
+		let d_pos2 = positions[i[2]] - positions[i[0]];
+		let d_tc1 = Vec2f::from(tex_coords[i[1]]) - Vec2f::from(tex_coords[i[0]]);
+		let d_tc2 = Vec2f::from(tex_coords[i[2]]) - Vec2f::from(tex_coords[i[0]]);
+
+		let det = d_tc1.perp_dot(d_tc2);
+		if det.abs() < 1.0e-12
+		{
+			// Degenerate texture coordinates.
+			continue;
+		}
+		let inv_det = 1.0 / det;
+
+		let tangent = (d_pos1 * d_tc2.y - d_pos2 * d_tc1.y) * inv_det;
+		let binormal = (d_pos2 * d_tc1.x - d_pos1 * d_tc2.x) * inv_det;
+		for index in i
+		{
+			tangents[index] += tangent;
+			binormals[index] += binormal;
+		}
+	}
+
+	normals
+		.iter()
+		.zip(tangents.iter().zip(binormals.iter()))
+		.map(|(normal, (tangent, binormal))| {
+			let normal_normalized = normalize_or(*normal, Vec3f::unit_z());
+			// Make vectors orthogonal to normal.
+			// Use arbitrary perpendicular vectors if there is no valid tangent space.
+			let fallback_tangent = get_perpendicular_vector(&normal_normalized);
+			let tangent_corrected = normalize_or(
+				tangent - normal_normalized * normal_normalized.dot(*tangent),
+				fallback_tangent,
+			);
+			let binormal_corrected = normalize_or(
+				binormal - normal_normalized * normal_normalized.dot(*binormal),
+				normal_normalized.cross(tangent_corrected),
+			);
+			VertexTangentSpace {
+				tangent: tangent_corrected,
+				binormal: binormal_corrected,
+			}
+		})
+		.collect()
+}
+
+fn normalize_or(v: Vec3f, fallback: Vec3f) -> Vec3f
+{
+	let len = v.magnitude();
+	if len > 1.0e-6
+	{
+		v / len
+	}
+	else
+	{
+		fallback
+	}
+}
+
+fn get_perpendicular_vector(v: &Vec3f) -> Vec3f
+{
+	let axis = if v.x.abs() < 0.5
+	{
+		Vec3f::unit_x()
+	}
+	else
+	{
+		Vec3f::unit_y()
+	};
+	v.cross(axis).normalize()
+}
@@ -1,5 +1,4 @@
-use super::{triangle_model::*, triangle_model_loading::*};
-use crate::common::{bbox::*, math_types::*};
+use super::{bbox::*, math_types::*, triangle_model::*, triangle_model_loading::*};
 use std::io::{Read, Seek};
 
 pub fn load_model_md3(file_path: &std::path::Path) -> Result<Option<TriangleModel>, std::io::Error>
@@ -52,6 +51,8 @@ pub fn load_model_md3(file_path: &std::path::Path) -> Result<Option<TriangleMode
 		.map(|t| TriangleModelBoneInfo {
 			name: get_str(&t.name).to_string(),
 			parent: 0xFFFFFFFF,
+			// Tag matrices are absolute.
+			base_matrix: Mat4f::identity(),
 		})
 		.collect();
 
@@ -108,6 +109,7 @@ pub fn load_model_md3(file_path: &std::path::Path) -> Result<Option<TriangleMode
 		frames_info,
 		frame_bones,
 		meshes,
+		lods: Vec::new(),
 		bones,
 		tc_shift,
 	}))
@@ -142,7 +144,7 @@ fn load_md3_mesh(
 	)?;
 	let shaders_src = read_vector::<Md3Shader>(file, src_mesh.lump_shaders as u64 + mesh_offset, src_mesh.num_shaders)?;
 
-	let triangles = triangles_src
+	let triangles: Vec<Triangle> = triangles_src
 		.iter()
 		.map(|x| [x[0] as VertexIndex, x[1] as VertexIndex, x[2] as VertexIndex])
 		.collect();
@@ -152,14 +154,33 @@ fn load_md3_mesh(
 		.map(|&tex_coord| VertexAnimatedVertexConstant { tex_coord })
 		.collect();
 
-	let vertex_data_variable: Vec<VertexAnimatedVertexVariable> = frames_src
+	let mut vertex_data_variable: Vec<VertexAnimatedVertexVariable> = frames_src
 		.iter()
 		.map(|v| VertexAnimatedVertexVariable {
 			position: Vec3f::new(v.origin[0] as f32, v.origin[1] as f32, v.origin[2] as f32) * MD3_COORD_SCALE,
 			normal: decompress_normal(v.normal_pitch_yaw),
+			tangent_space: VertexTangentSpace {
+				tangent: Vec3f::unit_x(),
+				binormal: Vec3f::unit_y(),
+			}, // Calculated later.
 		})
 		.collect();
 
+	// MD3 has no tangent space data, so, calculate it for each frame.
+	if !tex_coords_src.is_empty()
+	{
+		for frame_vertices in vertex_data_variable.chunks_mut(tex_coords_src.len())
+		{
+			let positions: Vec<Vec3f> = frame_vertices.iter().map(|v| v.position).collect();
+			let normals: Vec<Vec3f> = frame_vertices.iter().map(|v| v.normal).collect();
+			let tangent_space = calculate_tangent_space(&positions, &normals, &tex_coords_src, &triangles);
+			for (v, tangent_space) in frame_vertices.iter_mut().zip(tangent_space)
+			{
+				v.tangent_space = tangent_space;
+			}
+		}
+	}
+
 	let material_name = if shaders_src.is_empty()
 	{
 		""
@@ -179,6 +200,7 @@ fn load_md3_mesh(
 				.map(|(v_c, v_v)| VertexNonAnimated {
 					position: v_v.position,
 					normal: v_v.normal,
+					tangent_space: v_v.tangent_space,
 					tex_coord: v_c.tex_coord,
 				})
 				.collect(),
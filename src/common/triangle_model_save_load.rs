@@ -0,0 +1,126 @@
+use super::{triangle_model::*, triangle_model_gltf, triangle_model_iqm, triangle_model_md3};
+use std::{
+	io::{Read, Write},
+	path::{Path, PathBuf},
+};
+
+// Native model format, produced by model compiler.
+pub const TRIANGLE_MODEL_FILE_EXTENSION: &str = "sqwtm";
+
+// Get path of compiled model for given model path, if compiled model exists.
+// Compiled model is ignored if it is older than source model - in order to see changes of source model without recompilation.
+pub fn get_compiled_model_path(model_path: &Path) -> Option<PathBuf>
+{
+	let compiled_model_path = model_path.with_extension(TRIANGLE_MODEL_FILE_EXTENSION);
+	let compiled_model_metadata = compiled_model_path.metadata().ok()?;
+	if let (Ok(compiled_model_time), Ok(source_model_time)) = (
+		compiled_model_metadata.modified(),
+		model_path.metadata().and_then(|m| m.modified()),
+	)
+	{
+		if compiled_model_time < source_model_time
+		{
+			return None;
+		}
+	}
+
+	Some(compiled_model_path)
+}
+
+// Load model of any supported format, based on file extension.
+pub fn load_triangle_model(file_path: &Path) -> Result<Option<TriangleModel>, std::io::Error>
+{
+	let extension = file_path
+		.extension()
+		.and_then(|e| e.to_str())
+		.unwrap_or("")
+		.to_lowercase();
+
+	match extension.as_str()
+	{
+		TRIANGLE_MODEL_FILE_EXTENSION => load_model(file_path),
+		"iqm" => triangle_model_iqm::load_model_iqm(file_path),
+		"gltf" | "glb" => triangle_model_gltf::load_model_gltf(file_path),
+		_ => triangle_model_md3::load_model_md3(file_path),
+	}
+}
+
+// Save model in native format - header with serialized model data.
+pub fn save_model(model: &TriangleModel, file_path: &Path) -> Result<(), std::io::Error>
+{
+	let mut file = std::fs::OpenOptions::new()
+		.read(true)
+		.write(true)
+		.create(true)
+		.truncate(true)
+		.open(file_path)?;
+
+	let header = TriangleModelHeader {
+		id: TRIANGLE_MODEL_ID,
+		version: TRIANGLE_MODEL_VERSION,
+	};
+	let header_bytes = unsafe {
+		std::slice::from_raw_parts(
+			(&header) as *const TriangleModelHeader as *const u8,
+			std::mem::size_of::<TriangleModelHeader>(),
+		)
+	};
+	file.write_all(header_bytes)?;
+
+	bincode::serialize_into(std::io::BufWriter::new(file), model).map_err(std::io::Error::other)
+}
+
+pub fn load_model(file_path: &Path) -> Result<Option<TriangleModel>, std::io::Error>
+{
+	let mut file = std::fs::OpenOptions::new()
+		.read(true)
+		.write(false)
+		.create(false)
+		.open(file_path)?;
+
+	let header_size = std::mem::size_of::<TriangleModelHeader>();
+	let mut header = unsafe { std::mem::zeroed::<TriangleModelHeader>() };
+	let header_bytes =
+		unsafe { std::slice::from_raw_parts_mut((&mut header) as *mut TriangleModelHeader as *mut u8, header_size) };
+
+	if file.read(header_bytes)? != header_size
+	{
+		println!("Can't read model header");
+		return Ok(None);
+	}
+
+	if header.id != TRIANGLE_MODEL_ID
+	{
+		println!("File is not a valid model");
+		return Ok(None);
+	}
+	if header.version != TRIANGLE_MODEL_VERSION
+	{
+		println!(
+			"Can't load incompatible model version: {}, expected {}",
+			header.version, TRIANGLE_MODEL_VERSION
+		);
+		return Ok(None);
+	}
+
+	match bincode::deserialize_from(std::io::BufReader::new(file))
+	{
+		Ok(model) => Ok(Some(model)),
+		Err(e) =>
+		{
+			println!("Failed to read model data: {}", e);
+			Ok(None)
+		},
+	}
+}
+
+#[repr(C)]
+struct TriangleModelHeader
+{
+	id: [u8; 4],
+	version: u32,
+}
+
+const TRIANGLE_MODEL_ID: [u8; 4] = *b"SQWT";
+// Change version each time when TriangleModel structure changes.
+const TRIANGLE_MODEL_VERSION: u32 = 2;
@@ -1,6 +1,9 @@
+mod models_baking;
+
 use square_wheel_lib::common::{
-	bsp_builder, bsp_map_compact, bsp_map_compact_conversion, bsp_map_save_load, image, lightmaps_builder, map_csg,
-	map_file_q1, map_file_q4, map_polygonizer, material,
+	bbox::*, bsp_builder, bsp_map_compact, bsp_map_compact_conversion, bsp_map_save_load, detail_objects_builder,
+	image, lightmaps_builder, map_csg, map_file_q1, map_file_q4, map_polygonizer, material, triangle_model,
+	triangle_model_save_load,
 };
 use std::path::PathBuf;
 use structopt::StructOpt;
@@ -45,6 +48,10 @@ struct Opt
 	/// Path to directory containing textures.
 	#[structopt(parse(from_os_str), long)]
 	textures_dir: Option<PathBuf>,
+
+	/// Path to directory containing models. Used for detail objects placement and models baking.
+	#[structopt(parse(from_os_str), long)]
+	models_dir: Option<PathBuf>,
 }
 
 fn main()
@@ -94,7 +101,7 @@ fn main()
 		},
 	};
 
-	let map_csg_processed = if opt.perform_csg
+	let mut map_csg_processed = if opt.perform_csg
 	{
 		println!("Doing CSG for brushes");
 		map_csg::perform_csg_for_map_brushes(&map_polygonized, &materials)
@@ -105,6 +112,19 @@ fn main()
 		map_csg::perform_no_csg_for_map_brushes(&map_polygonized)
 	};
 
+	let models_dir = opt.models_dir;
+
+	if models_baking::has_models_for_baking(&map_csg_processed)
+	{
+		println!("Baking models");
+		let num_baked_models = models_baking::bake_models(
+			&mut map_csg_processed,
+			&mut |model| load_model(models_dir.as_ref(), model),
+			&mut |texture| get_material_texture_size(&materials, textures_dir.as_ref(), texture),
+		);
+		println!("Baked models: {}", num_baked_models);
+	}
+
 	println!("Building BSP tree");
 	let bsp_tree = bsp_builder::build_leaf_bsp_tree(
 		&map_csg_processed,
@@ -126,6 +146,20 @@ fn main()
 		&materials,
 	);
 
+	println!("Building detail objects");
+	let mut models_bbox_cache = std::collections::HashMap::<String, Option<BBox>>::new();
+	detail_objects_builder::build_detail_objects(&mut map_compact, &mut |model| {
+		if let Some(value) = models_bbox_cache.get(model)
+		{
+			return *value;
+		}
+		// Detail objects are not animated - use bbox of first frame.
+		let value = load_model(models_dir.as_ref(), model)
+			.and_then(|model| model.frames_info.first().map(|frame_info| frame_info.bbox));
+		models_bbox_cache.insert(model.to_string(), value);
+		value
+	});
+
 	println!("Creating dummy lightmaps");
 	lightmaps_builder::build_dummy_lightmaps(&materials, &mut map_compact);
 
@@ -193,6 +227,36 @@ fn get_material_texture_size(
 	[128, 128]
 }
 
+fn load_model(models_dir: Option<&PathBuf>, model: &str) -> Option<triangle_model::TriangleModel>
+{
+	let mut model_path = if let Some(dir) = models_dir
+	{
+		let mut p = dir.clone();
+		p.push(model);
+		p
+	}
+	else
+	{
+		PathBuf::from(model)
+	};
+
+	// Prefer compiled model (if it exists and is up to date) over source model, like engine does.
+	if let Some(compiled_model_path) = triangle_model_save_load::get_compiled_model_path(&model_path)
+	{
+		model_path = compiled_model_path;
+	}
+
+	match triangle_model_save_load::load_triangle_model(&model_path)
+	{
+		Ok(Some(model)) => Some(model),
+		Ok(None) | Err(_) =>
+		{
+			println!("Can't load model {:?}", model_path);
+			None
+		},
+	}
+}
+
 fn print_stats(
 	map_polygonized: &map_polygonizer::MapPolygonized,
 	map_csg_processed: &map_csg::MapCSGProcessed,
@@ -231,7 +295,7 @@ fn print_stats(
 
 	println!(
 		"Compact map nodes: {}, leafs: {}, polygons: {}, portals: {}, leafs_portals: {}, vertices: {}, textures: {}, \
-		 submodels: {}, sumbodels nodes: {}, lightmap texels: {}",
+		 submodels: {}, sumbodels nodes: {}, lightmap texels: {}, detail objects: {}, leafs detail objects: {}",
 		map_compact.nodes.len(),
 		map_compact.leafs.len(),
 		map_compact.polygons.len(),
@@ -241,7 +305,9 @@ fn print_stats(
 		map_compact.textures.len(),
 		map_compact.submodels.len(),
 		map_compact.submodels_bsp_nodes.len(),
-		map_compact.lightmaps_data.len()
+		map_compact.lightmaps_data.len(),
+		map_compact.detail_objects.len(),
+		map_compact.leafs_detail_objects.len()
 	);
 }
 
@@ -0,0 +1,235 @@
+use square_wheel_lib::common::{
+	map_csg, map_file_common, map_polygonizer, math_types::*, matrix::*, plane::*, triangle_model::*,
+};
+
+// Convert triangle models of "misc_model" entities with "_bake" key into world polygons.
+// Such polygons are inserted into BSP tree (without CSG), get lightmaps and cast shadows like regular world polygons.
+// Baked entities are removed from the map, so they are not spawned as regular models.
+// Returns number of baked models.
+pub fn bake_models(
+	map: &mut map_csg::MapCSGProcessed,
+	model_loader: &mut dyn FnMut(&str) -> Option<TriangleModel>,
+	texture_size_getter: &mut dyn FnMut(&str) -> [u32; 2],
+) -> usize
+{
+	let mut baked_polygons = Vec::new();
+	let mut num_baked_models = 0;
+
+	let mut entities_left = Vec::with_capacity(map.len());
+	for (entity_index, entity) in map.drain(..).enumerate()
+	{
+		// Skip world entity.
+		if entity_index > 0 && is_baked_model_entity(&entity)
+		{
+			if let Some(model) = entity.keys.get("model").and_then(|m| model_loader(m))
+			{
+				bake_model(&entity, &model, texture_size_getter, &mut baked_polygons);
+				num_baked_models += 1;
+				continue;
+			}
+			println!(
+				"Can't bake model of entity {}, leaving it as regular entity",
+				entity_index
+			);
+		}
+		entities_left.push(entity);
+	}
+	*map = entities_left;
+
+	if let Some(world_entity) = map.first_mut()
+	{
+		world_entity.polygons.extend(baked_polygons);
+	}
+
+	num_baked_models
+}
+
+pub fn has_models_for_baking(map: &map_csg::MapCSGProcessed) -> bool
+{
+	// Skip world entity.
+	map.iter().skip(1).any(is_baked_model_entity)
+}
+
+fn is_baked_model_entity(entity: &map_csg::Entity) -> bool
+{
+	let bake = entity
+		.keys
+		.get("_bake")
+		.and_then(|s| map_file_common::parse_key_value_number(s).ok())
+		.unwrap_or(0.0);
+	entity.keys.get("classname").map(|s| s.as_str()) == Some("misc_model") && bake != 0.0
+}
+
+fn bake_model(
+	entity: &map_csg::Entity,
+	model: &TriangleModel,
+	texture_size_getter: &mut dyn FnMut(&str) -> [u32; 2],
+	out_polygons: &mut Vec<map_polygonizer::Polygon>,
+)
+{
+	let origin = entity
+		.keys
+		.get("origin")
+		.and_then(|s| map_file_common::parse_vec3(s).ok())
+		.unwrap_or_else(Vec3f::zero);
+	let scale = get_entity_scale(entity);
+	let transform_matrix = get_object_matrix_with_scale(origin, get_entity_rotation(entity), scale);
+	// Mirroring scale changes vertices order.
+	let is_mirrored = scale.x * scale.y * scale.z < 0.0;
+
+	// Use only most detailed LOD.
+	for mesh in &model.meshes[get_triangle_model_lod_meshes_range(model, 0)]
+	{
+		// Use first frame for vertex-animated models and bind pose for skeleton-animated models.
+		let vertices: Vec<(Vec3f, [f32; 2])> = match &mesh.vertex_data
+		{
+			VertexData::NonAnimated(v) => v.iter().map(|v| (v.position, v.tex_coord)).collect(),
+			VertexData::VertexAnimated { constant, variable } => constant
+				.iter()
+				.zip(variable.iter())
+				.map(|(v_c, v_v)| (v_v.position, v_c.tex_coord))
+				.collect(),
+			VertexData::SkeletonAnimated(v) => v.iter().map(|v| (v.position, v.tex_coord)).collect(),
+		};
+
+		let texture_size = texture_size_getter(&mesh.material_name);
+		let tc_scale = Vec2f::new(texture_size[0] as f32, texture_size[1] as f32);
+
+		for triangle in &mesh.triangles
+		{
+			if triangle.iter().any(|&index| index as usize >= vertices.len())
+			{
+				continue;
+			}
+
+			let triangle = if is_mirrored
+			{
+				[triangle[0], triangle[2], triangle[1]]
+			}
+			else
+			{
+				*triangle
+			};
+
+			let triangle_vertices =
+				triangle.map(|index| (transform_matrix * vertices[index as usize].0.extend(1.0)).truncate());
+			let triangle_tex_coords = triangle
+				.map(|index| Vec2f::from(vertices[index as usize].1).mul_element_wise(tc_scale) + model.tc_shift);
+
+			// Models use clockwise vertices order for front faces.
+			let normal =
+				(triangle_vertices[2] - triangle_vertices[0]).cross(triangle_vertices[1] - triangle_vertices[0]);
+			let normal_length = normal.magnitude();
+			const MIN_DOUBLE_AREA: f32 = 1.0 / 64.0;
+			if normal_length < MIN_DOUBLE_AREA
+			{
+				// Skip too small triangles in order to avoid problems with BSP tree building.
+				continue;
+			}
+
+			let plane_vec = normal / normal_length;
+			let plane = Plane {
+				vec: plane_vec,
+				dist: plane_vec.dot(triangle_vertices[0]),
+			};
+
+			out_polygons.push(map_polygonizer::Polygon {
+				plane,
+				texture_info: map_polygonizer::TextureInfo {
+					tex_coord_equation: calculate_tex_coord_equation(&triangle_vertices, &triangle_tex_coords)
+						.unwrap_or_else(|| get_fallback_tex_coord_equation(&plane_vec)),
+					texture: mesh.material_name.clone(),
+				},
+				vertices: map_polygonizer::sort_convex_polygon_vertices(triangle_vertices.to_vec(), &plane),
+			});
+		}
+	}
+}
+
+// Calculate texture coordinates equation for triangle plane, which produces given texture coordinates for triangle vertices.
+fn calculate_tex_coord_equation(vertices: &[Vec3f; 3], tex_coords: &[Vec2f; 3]) -> Option<[Plane; 2]>
+{
+	// Search for vector in triangle plane with dot products with triangle edges equal to texture coordinates deltas.
+	let e1 = vertices[1] - vertices[0];
+	let e2 = vertices[2] - vertices[0];
+	let g11 = e1.dot(e1);
+	let g12 = e1.dot(e2);
+	let g22 = e2.dot(e2);
+	let det = g11 * g22 - g12 * g12;
+	if det.abs() < 1.0e-9
+	{
+		return None;
+	}
+
+	let d_tc1 = tex_coords[1] - tex_coords[0];
+	let d_tc2 = tex_coords[2] - tex_coords[0];
+
+	let mut result = [Plane {
+		vec: Vec3f::zero(),
+		dist: 0.0,
+	}; 2];
+	for i in 0 .. 2
+	{
+		let a = (d_tc1[i] * g22 - d_tc2[i] * g12) / det;
+		let b = (d_tc2[i] * g11 - d_tc1[i] * g12) / det;
+		let vec = e1 * a + e2 * b;
+		// Texture coordinates are degenerate - lightmap can't be built for this triangle.
+		if vec.magnitude2() < 1.0e-6
+		{
+			return None;
+		}
+
+		result[i] = Plane {
+			vec,
+			dist: tex_coords[0][i] - vec.dot(vertices[0]),
+		};
+	}
+
+	Some(result)
+}
+
+// Use planar projection with one texel per unit.
+fn get_fallback_tex_coord_equation(normal: &Vec3f) -> [Plane; 2]
+{
+	let axis = if normal.z.abs() < 0.9
+	{
+		Vec3f::unit_z()
+	}
+	else
+	{
+		Vec3f::unit_x()
+	};
+	let u = normal.cross(axis).normalize();
+	let v = normal.cross(u);
+	[Plane { vec: u, dist: 0.0 }, Plane { vec: v, dist: 0.0 }]
+}
+
+// Both uniform and per-axis scale keys are applied.
+fn get_entity_scale(entity: &map_csg::Entity) -> Vec3f
+{
+	let scale = entity
+		.keys
+		.get("modelscale")
+		.and_then(|s| map_file_common::parse_key_value_number(s).ok())
+		.unwrap_or(1.0);
+	let scale_vec = entity
+		.keys
+		.get("modelscale_vec")
+		.and_then(|s| map_file_common::parse_vec3(s).ok())
+		.unwrap_or_else(|| Vec3f::new(1.0, 1.0, 1.0));
+	scale_vec * scale
+}
+
+fn get_entity_rotation(entity: &map_csg::Entity) -> QuaternionF
+{
+	map_file_common::get_entity_rotation_from_angles(
+		entity
+			.keys
+			.get("angles")
+			.and_then(|s| map_file_common::parse_vec3(s).ok()),
+		entity
+			.keys
+			.get("angle")
+			.and_then(|s| map_file_common::parse_key_value_number(s).ok()),
+	)
+}
@@ -0,0 +1,145 @@
+use square_wheel_lib::{
+	common::{triangle_model, triangle_model_loading, triangle_model_save_load},
+	square_wheel::{triangle_model_optimization, triangle_model_simplification},
+};
+use std::path::PathBuf;
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(name = "model_compiler", about = "SquareWheel model compiler.")]
+struct Opt
+{
+	/// Input file (.md3, .iqm, .gltf, .glb)
+	#[structopt(parse(from_os_str), short = "i", required(true))]
+	input: PathBuf,
+
+	/// Output file. If extension is not specified, native model extension is used.
+	#[structopt(parse(from_os_str), short = "o", required(true))]
+	output: PathBuf,
+
+	/// Disable meshes optimization (degenerate triangles removal, triangles and vertices reordering).
+	#[structopt(long)]
+	no_optimize: bool,
+
+	/// Number of LODs (including source model) to generate via mesh simplification.
+	#[structopt(long)]
+	num_lods: Option<u32>,
+
+	/// Number of triangles of each next LOD relative to previous LOD. Default is 0.5.
+	#[structopt(long)]
+	lod_triangles_reduction: Option<f32>,
+
+	/// Maximum screen size (in pixels) of model for usage of second LOD. Each next LOD has this size halved.
+	/// Default is 256.
+	#[structopt(long)]
+	lod_screen_size: Option<f32>,
+
+	/// Hand-made LOD models. They are added after generated LODs.
+	#[structopt(parse(from_os_str), long)]
+	lod_models: Vec<PathBuf>,
+
+	/// Print stats of input/result model
+	#[structopt(long)]
+	print_stats: bool,
+}
+
+fn main()
+{
+	// use "unwrap" in this function. It's fine to abort application if something is wrong.
+
+	let opt = Opt::from_args();
+
+	println!("Loading model {:?}", opt.input);
+	let mut model = triangle_model_save_load::load_triangle_model(&opt.input)
+		.unwrap()
+		.expect("Failed to load model");
+
+	if opt.print_stats
+	{
+		println!("Source model stats:");
+		print_stats(&model);
+	}
+
+	let mut lod_screen_size = opt.lod_screen_size.unwrap_or(256.0);
+	let num_lods = opt.num_lods.unwrap_or(1);
+	if num_lods > 1
+	{
+		println!("Generating LODs");
+		triangle_model_simplification::generate_triangle_model_lods(
+			&mut model,
+			num_lods,
+			opt.lod_triangles_reduction.unwrap_or(0.5),
+			lod_screen_size,
+		);
+		lod_screen_size *= 0.5_f32.powi(num_lods as i32 - 1);
+	}
+
+	for lod_model_path in &opt.lod_models
+	{
+		println!("Loading LOD model {:?}", lod_model_path);
+		let lod_model = triangle_model_save_load::load_triangle_model(lod_model_path)
+			.unwrap()
+			.expect("Failed to load LOD model");
+		assert!(
+			lod_model.frames_info.len() == model.frames_info.len() && lod_model.bones.len() == model.bones.len(),
+			"LOD model frames/bones mismatch"
+		);
+
+		triangle_model_simplification::add_triangle_model_lod(&mut model, lod_model.meshes, lod_screen_size);
+		lod_screen_size *= 0.5;
+	}
+
+	if !opt.no_optimize
+	{
+		println!("Optimizing meshes");
+		triangle_model_optimization::optimize_model(&mut model);
+	}
+
+	if opt.print_stats
+	{
+		println!("Result model stats:");
+		print_stats(&model);
+	}
+
+	let mut output = opt.output;
+	if output.extension().is_none()
+	{
+		output.set_extension(triangle_model_save_load::TRIANGLE_MODEL_FILE_EXTENSION);
+	}
+
+	println!("Saving model {:?}", output);
+	triangle_model_save_load::save_model(&model, &output).unwrap();
+
+	println!("Done!");
+}
+
+fn print_stats(model: &triangle_model::TriangleModel)
+{
+	println!(
+		"Meshes: {}, LODs: {}, frames: {}, animations: {}, bones: {}",
+		model.meshes.len(),
+		model.lods.len().max(1),
+		model.frames_info.len(),
+		model.animations.len(),
+		model.bones.len()
+	);
+	for lod in &model.lods
+	{
+		println!(
+			"LOD meshes: {} - {}, max screen size: {}",
+			lod.first_mesh,
+			lod.first_mesh + lod.num_meshes,
+			lod.max_screen_size
+		);
+	}
+	for mesh in &model.meshes
+	{
+		println!(
+			"Mesh {:?}, material {:?}: triangles: {}, vertices: {}",
+			mesh.name,
+			mesh.material_name,
+			mesh.triangles.len(),
+			triangle_model_loading::get_vertex_data_num_vertices(&mesh.vertex_data)
+		);
+	}
+}
@@ -0,0 +1,165 @@
+use super::fast_math::*;
+use crate::common::{color::*, image};
+
+// 3D lookup table for color grading of LDR image.
+// Output color is calculated using trilinear interpolation of table values.
+pub struct ColorGradingLut
+{
+	size: usize,
+	// Colors in range [0; 255]. Red changes fastest, blue - slowest.
+	data: Vec<ColorVec>,
+}
+
+pub const MIN_COLOR_GRADING_LUT_SIZE: usize = 2;
+pub const MAX_COLOR_GRADING_LUT_SIZE: usize = 64;
+
+impl ColorGradingLut
+{
+	pub fn new_identity(size: usize) -> Self
+	{
+		let size = size.clamp(MIN_COLOR_GRADING_LUT_SIZE, MAX_COLOR_GRADING_LUT_SIZE);
+		let scale = 255.0 / ((size - 1) as f32);
+
+		let mut data = Vec::with_capacity(size * size * size);
+		for b in 0 .. size
+		{
+			for g in 0 .. size
+			{
+				for r in 0 .. size
+				{
+					data.push(ColorVec::from_color_f32x3(&[
+						(r as f32) * scale,
+						(g as f32) * scale,
+						(b as f32) * scale,
+					]));
+				}
+			}
+		}
+
+		Self { size, data }
+	}
+
+	// Load table from horizontal strip image with size (N * N) x N.
+	// Each N x N square is a slice with constant blue, red increases along X axis, green - along Y axis.
+	pub fn from_image(image: &image::Image) -> Option<Self>
+	{
+		let size = image.size[1] as usize;
+		if size < MIN_COLOR_GRADING_LUT_SIZE ||
+			size > MAX_COLOR_GRADING_LUT_SIZE ||
+			image.size[0] as usize != size * size
+		{
+			return None;
+		}
+
+		let mut data = Vec::with_capacity(size * size * size);
+		for b in 0 .. size
+		{
+			for g in 0 .. size
+			{
+				let row_start = b * size + g * size * size;
+				for &c in &image.pixels[row_start .. row_start + size]
+				{
+					let rgb = c.get_rgb();
+					data.push(ColorVec::from_color_f32x3(&[
+						rgb[0] as f32,
+						rgb[1] as f32,
+						rgb[2] as f32,
+					]));
+				}
+			}
+		}
+
+		Some(Self { size, data })
+	}
+
+	// Rebuild this table as blend of two tables (identity table is used if first table is none).
+	// Size of result is maximum size of source tables.
+	pub fn make_blend(&mut self, a: Option<&Self>, b: &Self, b_weight: f32)
+	{
+		let size = a.map(|a| a.size).unwrap_or(MIN_COLOR_GRADING_LUT_SIZE).max(b.size);
+		let scale = 1.0 / ((size - 1) as f32);
+		let b_weight = b_weight.clamp(0.0, 1.0);
+		let a_weight = 1.0 - b_weight;
+
+		self.size = size;
+		self.data.clear();
+		for b_index in 0 .. size
+		{
+			for g_index in 0 .. size
+			{
+				for r_index in 0 .. size
+				{
+					let coord = [
+						(r_index as f32) * scale,
+						(g_index as f32) * scale,
+						(b_index as f32) * scale,
+					];
+					let a_value = if let Some(a) = a
+					{
+						a.sample(&coord)
+					}
+					else
+					{
+						ColorVec::from_color_f32x3(&[coord[0] * 255.0, coord[1] * 255.0, coord[2] * 255.0])
+					};
+					let b_value = b.sample(&coord);
+					self.data.push(ColorVec::mul_scalar_add(
+						&b_value,
+						b_weight,
+						&a_value.scalar_mul(a_weight),
+					));
+				}
+			}
+		}
+	}
+
+	pub fn apply(&self, c: Color32) -> Color32
+	{
+		let rgb = c.get_rgb();
+		let inv_255 = 1.0 / 255.0;
+		self.sample(&[
+			(rgb[0] as f32) * inv_255,
+			(rgb[1] as f32) * inv_255,
+			(rgb[2] as f32) * inv_255,
+		])
+		.into_color32()
+	}
+
+	// Coordinates are in range [0; 1].
+	fn sample(&self, coord: &[f32; 3]) -> ColorVec
+	{
+		let max_coord = (self.size - 1) as f32;
+		let mut base = [0; 3];
+		let mut frac = [0.0; 3];
+		for i in 0 .. 3
+		{
+			let c = (coord[i] * max_coord).max(0.0).min(max_coord);
+			base[i] = (c as usize).min(self.size - 2);
+			frac[i] = c - (base[i] as f32);
+		}
+
+		let stride = [1, self.size, self.size * self.size];
+		let offset = base[0] * stride[0] + base[1] * stride[1] + base[2] * stride[2];
+
+		let mut values_b = [ColorVec::zero(); 2];
+		for (db, value_b) in values_b.iter_mut().enumerate()
+		{
+			let mut values_g = [ColorVec::zero(); 2];
+			for (dg, value_g) in values_g.iter_mut().enumerate()
+			{
+				let o = offset + db * stride[2] + dg * stride[1];
+				let v0 = unsafe { debug_only_checked_fetch(&self.data, o) };
+				let v1 = unsafe { debug_only_checked_fetch(&self.data, o + 1) };
+				*value_g = lerp_color(&v0, &v1, frac[0]);
+			}
+			*value_b = lerp_color(&values_g[0], &values_g[1], frac[1]);
+		}
+
+		lerp_color(&values_b[0], &values_b[1], frac[2])
+	}
+}
+
+fn lerp_color(a: &ColorVec, b: &ColorVec, k: f32) -> ColorVec
+{
+	ColorVec::mul_scalar_add(b, k, &a.scalar_mul(1.0 - k))
+}
@@ -9,6 +9,8 @@ pub struct DynamicObjectsIndex
 	map: Arc<bsp_map_compact::BSPMap>,
 	leafs_info: Vec<LeafInfo>,
 	objects_info: Vec<ObjectInfo>,
+	// Static objects are placed once and are not cleared. They are always at start of objects list.
+	num_static_objects: usize,
 }
 
 // TODO - try to use some sort of vector with small internal storage to reduce heap allocations.
@@ -16,7 +18,9 @@ pub struct DynamicObjectsIndex
 #[derive(Default, Clone)]
 struct LeafInfo
 {
+	// Static objects are placed first.
 	objects: Vec<DynamicObjectId>,
+	num_static_objects: usize,
 }
 
 #[derive(Default, Clone)]
@@ -34,10 +38,45 @@ impl DynamicObjectsIndex
 		Self {
 			leafs_info: vec![LeafInfo::default(); map.leafs.len()],
 			objects_info: Vec::new(),
+			num_static_objects: 0,
 			map,
 		}
 	}
 
+	// Create index for models with map detail objects placed in it.
+	// Detail objects are placed once, using leafs precalculated by map compiler.
+	pub fn new_with_detail_objects(map: Arc<bsp_map_compact::BSPMap>) -> Self
+	{
+		let mut index = Self::new(map.clone());
+		index.num_static_objects = map.detail_objects.len();
+		index.allocate_objects(index.num_static_objects);
+		for (leaf_index, (leaf, leaf_info)) in map.leafs.iter().zip(index.leafs_info.iter_mut()).enumerate()
+		{
+			for &detail_object_index in &map.leafs_detail_objects[leaf.first_leaf_detail_object as usize ..
+				(leaf.first_leaf_detail_object + leaf.num_leaf_detail_objects) as usize]
+			{
+				leaf_info.objects.push(detail_object_index);
+				index.objects_info[detail_object_index as usize]
+					.leafs
+					.push(leaf_index as u32);
+			}
+			leaf_info.num_static_objects = leaf_info.objects.len();
+		}
+		index
+	}
+
+	pub fn get_num_static_objects(&self) -> usize
+	{
+		self.num_static_objects
+	}
+
+	// Get static objects of given leaf. Dynamic objects are not included.
+	pub fn get_leaf_static_objects(&self, leaf_index: u32) -> &[DynamicObjectId]
+	{
+		let leaf_info = &self.leafs_info[leaf_index as usize];
+		&leaf_info.objects[.. leaf_info.num_static_objects]
+	}
+
 	pub fn get_leaf_objects(&self, leaf_index: u32) -> &[DynamicObjectId]
 	{
 		&self.leafs_info[leaf_index as usize].objects
@@ -49,6 +88,7 @@ impl DynamicObjectsIndex
 	}
 
 	// Reset internal state and position new set of models.
+	// First models in list must be static objects (map detail objects), they are not positioned again.
 	pub fn position_models(&mut self, models: &[ModelEntity])
 	{
 		// Clear previous models.
@@ -56,7 +96,7 @@ impl DynamicObjectsIndex
 
 		// Position new models.
 		self.allocate_objects(models.len());
-		for (index, model) in models.iter().enumerate()
+		for (index, model) in models.iter().enumerate().skip(self.num_static_objects)
 		{
 			if model.flags.contains(ModelEntityDrawFlags::VIEW_MODEL)
 			{
@@ -66,7 +106,7 @@ impl DynamicObjectsIndex
 
 			self.position_object_bbox(
 				index as DynamicObjectId,
-				&get_current_triangle_model_bbox(&model.model, &model.animation),
+				&get_model_entity_bbox(model),
 				&get_object_matrix(model.position, model.rotation),
 			);
 		}
@@ -156,6 +196,21 @@ impl DynamicObjectsIndex
 		}
 	}
 
+	// Reset internal state and position new set of fog volumes.
+	pub fn position_fog_volumes(&mut self, fog_volumes: &[FogVolume])
+	{
+		// Clear previous fog volumes.
+		self.clear();
+
+		// Position new fog volumes.
+		self.allocate_objects(fog_volumes.len());
+		let identity_matrix = Mat4f::identity();
+		for (index, fog_volume) in fog_volumes.iter().enumerate()
+		{
+			self.position_object_bbox(index as DynamicObjectId, &fog_volume.bbox, &identity_matrix);
+		}
+	}
+
 	fn position_object_bbox(&mut self, id: DynamicObjectId, bbox: &BBox, transform_matrix: &Mat4f)
 	{
 		// transform bbox vertices.
@@ -252,10 +307,10 @@ impl DynamicObjectsIndex
 	{
 		for leafs_info in &mut self.leafs_info
 		{
-			leafs_info.objects.clear();
+			leafs_info.objects.truncate(leafs_info.num_static_objects);
 		}
 		// Do not clear objects_info vector itself to preserve allocations in interlal vectors and avoid reallocations.
-		for object_info in &mut self.objects_info
+		for object_info in &mut self.objects_info[self.num_static_objects ..]
 		{
 			object_info.leafs.clear();
 		}
@@ -0,0 +1,230 @@
+use super::{fast_math::*, frame_info::*};
+use crate::common::{bbox::*, material::BlendingMode, math_types::*};
+
+// Result of fog application for some point, viewed from some other point.
+// Fogged color = color * visibility + color_add.
+#[derive(Copy, Clone)]
+pub struct FogFactor
+{
+	// 1 - no fog, 0 - fully fogged.
+	pub visibility: f32,
+	// Color in texel units (0-255).
+	pub color_add: [f32; 3],
+}
+
+impl FogFactor
+{
+	pub fn none() -> Self
+	{
+		Self {
+			visibility: 1.0,
+			color_add: [0.0; 3],
+		}
+	}
+
+	pub fn is_none(&self) -> bool
+	{
+		self.visibility >= 1.0
+	}
+
+	pub fn mix(a: &Self, b: &Self, ratio: f32) -> Self
+	{
+		let ratio_inv = 1.0 - ratio;
+		Self {
+			visibility: a.visibility * ratio_inv + b.visibility * ratio,
+			color_add: [
+				a.color_add[0] * ratio_inv + b.color_add[0] * ratio,
+				a.color_add[1] * ratio_inv + b.color_add[1] * ratio,
+				a.color_add[2] * ratio_inv + b.color_add[2] * ratio,
+			],
+		}
+	}
+
+	pub fn average3(a: &Self, b: &Self, c: &Self) -> Self
+	{
+		const SCALE: f32 = 1.0 / 3.0;
+		Self {
+			visibility: (a.visibility + b.visibility + c.visibility) * SCALE,
+			color_add: [
+				(a.color_add[0] + b.color_add[0] + c.color_add[0]) * SCALE,
+				(a.color_add[1] + b.color_add[1] + c.color_add[1]) * SCALE,
+				(a.color_add[2] + b.color_add[2] + c.color_add[2]) * SCALE,
+			],
+		}
+	}
+
+	pub fn apply_to_light(&self, light: &[f32; 3]) -> [f32; 3]
+	{
+		[
+			light[0] * self.visibility,
+			light[1] * self.visibility,
+			light[2] * self.visibility,
+		]
+	}
+
+	// Get color for addition in rasterizer.
+	// Fog color should not be added for additive objects, since they are just fading in fog.
+	pub fn get_color_add_vec(&self, blending_mode: BlendingMode) -> ColorVecI
+	{
+		if blending_mode == BlendingMode::Additive
+		{
+			ColorVecI::zero()
+		}
+		else
+		{
+			ColorVecI::from_color_f32x3(&self.color_add)
+		}
+	}
+}
+
+// Calculate fog for segment from "start" (usually camera position) to "end".
+pub fn calculate_fog_factor<'a>(
+	fog_volumes: impl Iterator<Item = &'a FogVolume>,
+	start: &Vec3f,
+	end: &Vec3f,
+) -> FogFactor
+{
+	let mut total_optical_depth = 0.0;
+	let mut color_sum = [0.0; 3];
+	for fog_volume in fog_volumes
+	{
+		let optical_depth = get_fog_volume_optical_depth(fog_volume, start, end);
+		if optical_depth <= 0.0
+		{
+			continue;
+		}
+		total_optical_depth += optical_depth;
+		for i in 0 .. 3
+		{
+			color_sum[i] += fog_volume.color[i] * optical_depth;
+		}
+	}
+
+	if total_optical_depth <= 0.0
+	{
+		return FogFactor::none();
+	}
+
+	// Use average color of all volumes, weighted by optical depth.
+	let visibility = (-total_optical_depth).exp();
+	let color_scale = (1.0 - visibility) / total_optical_depth;
+	FogFactor {
+		visibility,
+		color_add: [
+			color_sum[0] * color_scale,
+			color_sum[1] * color_scale,
+			color_sum[2] * color_scale,
+		],
+	}
+}
+
+// Calculate integral of fog density along segment.
+pub fn get_fog_volume_optical_depth(fog_volume: &FogVolume, start: &Vec3f, end: &Vec3f) -> f32
+{
+	let bbox = &fog_volume.bbox;
+
+	// Clip segment by bbox.
+	let dir = end - start;
+	let mut t_min = 0.0f32;
+	let mut t_max = 1.0f32;
+	for i in 0 .. 3
+	{
+		if dir[i].abs() < 1.0e-6
+		{
+			if start[i] < bbox.min[i] || start[i] > bbox.max[i]
+			{
+				return 0.0;
+			}
+			continue;
+		}
+
+		let inv_dir = 1.0 / dir[i];
+		let t0 = (bbox.min[i] - start[i]) * inv_dir;
+		let t1 = (bbox.max[i] - start[i]) * inv_dir;
+		t_min = t_min.max(t0.min(t1));
+		t_max = t_max.min(t0.max(t1));
+		if t_min >= t_max
+		{
+			return 0.0;
+		}
+	}
+
+	let length = dir.magnitude() * (t_max - t_min);
+
+	// Calculate average density within clipped segment.
+	let average_density_scale = if fog_volume.height_falloff > 0.0
+	{
+		let k = fog_volume.height_falloff;
+		let h0 = start.z + dir.z * t_min - bbox.min.z;
+		let h1 = start.z + dir.z * t_max - bbox.min.z;
+		let dh_scaled = (h1 - h0) * k;
+		if dh_scaled.abs() > 1.0e-3
+		{
+			((-k * h0).exp() - (-k * h1).exp()) / dh_scaled
+		}
+		else
+		{
+			(-k * (h0 + h1) * 0.5).exp()
+		}
+	}
+	else
+	{
+		1.0
+	};
+
+	length * fog_volume.density * average_density_scale
+}
+
+// Check if something inside given bbox may be fogged by given fog volume, if viewed from given point.
+pub fn fog_volume_may_affect_bbox(fog_volume: &FogVolume, bbox: &BBox, view_position: &Vec3f) -> bool
+{
+	let mut bbox_extended = *bbox;
+	bbox_extended.extend_with_point(view_position);
+	bbox_extended.touches_or_intersects(&fog_volume.bbox)
+}
+
+// Collect fog volumes, which may affect something inside given bbox.
+// Fixed-size storage is used in most cases, extra storage is used only if there are too many fog volumes.
+// Doing so we avoid allocations, but never drop any fog volume.
+pub fn collect_fog_volumes_for_bbox<'a, 'b>(
+	fog_volumes: impl Iterator<Item = &'a FogVolume>,
+	bbox: &BBox,
+	view_position: &Vec3f,
+	fixed_storage: &'b mut [&'a FogVolume],
+	extra_storage: &'b mut Vec<&'a FogVolume>,
+) -> &'b [&'a FogVolume]
+{
+	extra_storage.clear();
+	let mut num_fog_volumes = 0;
+	for fog_volume in fog_volumes
+	{
+		if !fog_volume_may_affect_bbox(fog_volume, bbox, view_position)
+		{
+			continue;
+		}
+
+		if !extra_storage.is_empty()
+		{
+			extra_storage.push(fog_volume);
+		}
+		else if num_fog_volumes < fixed_storage.len()
+		{
+			fixed_storage[num_fog_volumes] = fog_volume;
+			num_fog_volumes += 1;
+		}
+		else
+		{
+			extra_storage.extend_from_slice(fixed_storage);
+			extra_storage.push(fog_volume);
+		}
+	}
+
+	if extra_storage.is_empty()
+	{
+		&fixed_storage[.. num_fog_volumes]
+	}
+	else
+	{
+		extra_storage
+	}
+}
@@ -1,6 +1,7 @@
-use super::{resources_manager::*, textures::*, triangle_model::*};
-use crate::common::{bbox::*, material::BlendingMode, math_types::*, matrix::*, plane::*};
+use super::{color_grading_lut::*, resources_manager::*, textures::*, triangle_model_animation::*};
+use crate::common::{bbox::*, material::BlendingMode, math_types::*, matrix::*, plane::*, triangle_model::*};
 use serde::{Deserialize, Serialize};
+use std::sync::Arc;
 
 // This file contains structs, used to describe world state for Renderer in order to draw a frame properly.
 
@@ -22,9 +23,21 @@ pub struct FrameViewInfo
 	// Use values only greater than zero!
 	pub color_modulate: [f32; 3],
 
+	// Additional color grading, blended with color grading from config.
+	// Use it for underwater, damage or similar looks.
+	pub color_grading_lut_blend: Option<ColorGradingLutBlend>,
+
 	pub is_third_person_view: bool,
 }
 
+#[derive(Clone)]
+pub struct ColorGradingLutBlend
+{
+	pub lut: SharedResourcePtr<ColorGradingLut>,
+	// 0 - use only color grading from config, 1 - use only this LUT.
+	pub weight: f32,
+}
+
 // Frame info, independent on view point.
 // Game code should avoid building this depending on view point, for example, removing objects far from camera,
 // since it is possible to render view from another point via portals/mirrors.
@@ -39,6 +52,8 @@ pub struct FrameWorldInfo
 	pub sprites: Vec<Sprite>,
 	pub lights: Vec<DynamicLight>,
 	pub portals: Vec<ViewPortal>,
+	pub fog_volumes: Vec<FogVolume>,
+	pub lens_flares: Vec<LensFlare>,
 }
 
 pub type SubmodelEntityOpt = Option<SubmodelEntity>;
@@ -57,6 +72,9 @@ pub struct ModelEntity
 	pub position: Vec3f,
 	pub rotation: QuaternionF,
 	pub animation: AnimationPoint,
+	// Pose for skeleton-animated models, built from multiple animations.
+	// If it is present, it is used for vertices animation and bbox calculation instead of "animation" field.
+	pub skeleton_pose: Option<Arc<SkeletonPose>>,
 	pub model: SharedResourcePtr<TriangleModel>,
 	pub texture: SharedResourcePtr<TextureLiteWithMips>,
 	pub blending_mode: BlendingMode,
@@ -106,6 +124,12 @@ pub struct ModelEntityDrawFlags: u8
 	const VIEW_MODEL = 1;
 	// Draw only in portals/mirrors, but not from intial view point.
 	const ONLY_THIRD_PERSON_VIEW = 2;
+	// Calculate dynamic light (with shadows) per-pixel, rather than per-vertex.
+	// This is much slower, so, use it only for large models or models that are important.
+	const PER_PIXEL_LIGHTING = 4;
+	// Use materials (with normal and specular maps), specified for model meshes, instead of model texture.
+	// Lighting for meshes with materials is always calculated per-pixel.
+	const USE_MATERIALS = 8;
 }
 }
 
@@ -167,6 +191,32 @@ pub enum DynamicLightShadowType
 	},
 }
 
+// Lens flare of bright light source.
+// Flare is drawn only if light source is not occluded by map geometry.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct LensFlare
+{
+	pub position: Vec3f,
+	// Color in range [0; 1].
+	pub color: [f32; 3],
+	// Radius of main glow, relative to screen height.
+	pub radius: f32,
+}
+
+// Axis-aligned volume filled with fog.
+// Fog density decreases exponentially with height, starting from bottom of the volume.
+#[derive(Copy, Clone, Serialize, Deserialize)]
+pub struct FogVolume
+{
+	pub bbox: BBox,
+	// Color of fog, in texel units (0-255).
+	pub color: [f32; 3],
+	// Optical density (inverse distance) at the bottom of the volume.
+	pub density: f32,
+	// Inverse height of density decreasing by factor "e". Use zero for uniform fog.
+	pub height_falloff: f32,
+}
+
 #[derive(Clone)]
 pub struct ViewPortal
 {
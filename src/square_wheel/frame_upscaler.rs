@@ -1,34 +1,67 @@
 use super::fast_math::*;
 use crate::common::{color::*, system_window};
+use rayon::prelude::*;
 
 pub const MAX_FRAME_SCALE: usize = 6;
 
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum FrameUpscaleFilter
+{
+	Nearest,
+	Bilinear,
+	// Bilinear filter with reduced blurring of high-contrast edges.
+	// It is purely spatial - there is no accumulation of previous frames,
+	// so, thin high-contrast details may still flicker while camera moves.
+	EdgeAware,
+}
+
 pub struct FrameUpscaler
 {
 	buffer: Vec<Color32>,
+	// Size of last requested draw buffer.
+	buffer_size: [usize; 2],
+	// Reuse memory for columns interpolation data.
+	columns: Vec<InterpolationCoord>,
 }
 
 impl FrameUpscaler
 {
 	pub fn new() -> Self
 	{
-		Self { buffer: Vec::new() }
+		Self {
+			buffer: Vec::new(),
+			buffer_size: [0, 0],
+			columns: Vec::new(),
+		}
 	}
 
+	// Scale may be non-integer.
 	pub fn get_draw_buffer(
 		&mut self,
 		surface_info: &system_window::SurfaceInfo,
-		scale: usize,
+		scale: f32,
 	) -> (&mut [Color32], system_window::SurfaceInfo)
 	{
-		let width = surface_info.width / scale;
-		let height = surface_info.height / scale;
+		let (width, height) = if let Some(integer_scale) = get_integer_scale(scale)
+		{
+			(surface_info.width / integer_scale, surface_info.height / integer_scale)
+		}
+		else
+		{
+			(
+				((surface_info.width as f32) / scale) as usize,
+				((surface_info.height as f32) / scale) as usize,
+			)
+		};
+		let width = width.max(1);
+		let height = height.max(1);
 
 		let target_size = width * height;
 		if self.buffer.len() < target_size
 		{
 			self.buffer.resize(target_size, Color32::black());
 		}
+		self.buffer_size = [width, height];
 		(
 			&mut self.buffer,
 			system_window::SurfaceInfo {
@@ -39,34 +72,120 @@ impl FrameUpscaler
 		)
 	}
 
+	// Call this only after buffer was requested with same scale and surface info.
 	pub fn perform_upscale(
 		&mut self,
 		pixels: &mut [Color32],
 		surface_info: &system_window::SurfaceInfo,
-		scale: usize,
-		interpolate: bool,
+		scale: f32,
+		filter: FrameUpscaleFilter,
 	)
 	{
-		match scale
+		if let Some(integer_scale) = get_integer_scale(scale)
 		{
-			1 => self.perform_upscale_impl::<1>(pixels, surface_info),
-			2 =>
+			// Use fast paths for integer scales.
+			match (integer_scale, filter)
 			{
-				if interpolate
-				{
-					self.perform_upscale_2x_linear(pixels, surface_info)
-				}
-				else
+				(1, _) => return self.perform_upscale_impl::<1>(pixels, surface_info),
+				(2, FrameUpscaleFilter::Nearest) => return self.perform_upscale_impl::<2>(pixels, surface_info),
+				(2, FrameUpscaleFilter::Bilinear) => return self.perform_upscale_2x_linear(pixels, surface_info),
+				(3, FrameUpscaleFilter::Nearest) => return self.perform_upscale_impl::<3>(pixels, surface_info),
+				(4, FrameUpscaleFilter::Nearest) => return self.perform_upscale_impl::<4>(pixels, surface_info),
+				(5, FrameUpscaleFilter::Nearest) => return self.perform_upscale_impl::<5>(pixels, surface_info),
+				(6, FrameUpscaleFilter::Nearest) => return self.perform_upscale_impl::<6>(pixels, surface_info),
+				_ =>
+				{},
+			}
+		}
+
+		match filter
+		{
+			FrameUpscaleFilter::Nearest => self.perform_upscale_generic::<FILTER_NEAREST>(pixels, surface_info),
+			FrameUpscaleFilter::Bilinear => self.perform_upscale_generic::<FILTER_BILINEAR>(pixels, surface_info),
+			FrameUpscaleFilter::EdgeAware => self.perform_upscale_generic::<FILTER_EDGE_AWARE>(pixels, surface_info),
+		}
+	}
+
+	// Upscale with arbitrary (non-integer) scale.
+	fn perform_upscale_generic<const FILTER: usize>(
+		&mut self,
+		pixels: &mut [Color32],
+		surface_info: &system_window::SurfaceInfo,
+	)
+	{
+		let [src_width, src_height] = self.buffer_size;
+		if src_width == 0 || src_height == 0 || self.buffer.len() < src_width * src_height
+		{
+			// Something went wrong.
+			return;
+		}
+
+		// Precalculate interpolation data for columns, since it is the same for all rows.
+		self.columns.clear();
+		self.columns
+			.extend((0 .. surface_info.width).map(|x| InterpolationCoord::new(x, surface_info.width, src_width)));
+
+		let buffer = &self.buffer;
+		let columns = &self.columns;
+
+		// Use multithreading here, since filtering requires some computations.
+		pixels
+			.par_chunks_mut(surface_info.pitch)
+			.take(surface_info.height)
+			.enumerate()
+			.for_each(|(y, dst_line)| {
+				let row = InterpolationCoord::new(y, surface_info.height, src_height);
+				let src_line0 = &buffer[(row.c0 as usize) * src_width .. (row.c0 as usize + 1) * src_width];
+				let src_line1 = &buffer[(row.c1 as usize) * src_width .. (row.c1 as usize + 1) * src_width];
+
+				for (dst_pixel, column) in dst_line[.. surface_info.width].iter_mut().zip(columns.iter())
 				{
-					self.perform_upscale_impl::<2>(pixels, surface_info)
+					if FILTER == FILTER_NEAREST
+					{
+						let src_line = if row.k < INTERPOLATION_K_HALF
+						{
+							src_line0
+						}
+						else
+						{
+							src_line1
+						};
+						let src_x = if column.k < INTERPOLATION_K_HALF
+						{
+							column.c0
+						}
+						else
+						{
+							column.c1
+						};
+						*dst_pixel = unsafe { debug_only_checked_fetch(src_line, src_x as usize) };
+						continue;
+					}
+
+					let p00 = unsafe { debug_only_checked_fetch(src_line0, column.c0 as usize) };
+					let p10 = unsafe { debug_only_checked_fetch(src_line0, column.c1 as usize) };
+					let p01 = unsafe { debug_only_checked_fetch(src_line1, column.c0 as usize) };
+					let p11 = unsafe { debug_only_checked_fetch(src_line1, column.c1 as usize) };
+
+					let (kx, ky) = if FILTER == FILTER_EDGE_AWARE
+					{
+						let b00 = get_brightness(p00);
+						let b10 = get_brightness(p10);
+						let b01 = get_brightness(p01);
+						let b11 = get_brightness(p11);
+						let edge_x = b00.abs_diff(b10).max(b01.abs_diff(b11));
+						let edge_y = b00.abs_diff(b01).max(b10.abs_diff(b11));
+						(sharpen_k(column.k, edge_x), sharpen_k(row.k, edge_y))
+					}
+					else
+					{
+						(column.k, row.k)
+					};
+
+					*dst_pixel =
+						interpolate_colors(interpolate_colors(p00, p10, kx), interpolate_colors(p01, p11, kx), ky);
 				}
-			},
-			3 => self.perform_upscale_impl::<3>(pixels, surface_info),
-			4 => self.perform_upscale_impl::<4>(pixels, surface_info),
-			5 => self.perform_upscale_impl::<5>(pixels, surface_info),
-			6 => self.perform_upscale_impl::<6>(pixels, surface_info),
-			_ => panic!("Unsupported scale {}", scale),
-		}
+			});
 	}
 
 	fn perform_upscale_impl<const SCALE: usize>(
@@ -238,3 +357,78 @@ impl FrameUpscaler
 		}
 	}
 }
+
+const FILTER_NEAREST: usize = 0;
+const FILTER_BILINEAR: usize = 1;
+const FILTER_EDGE_AWARE: usize = 2;
+
+// Returns some if scale is integer.
+fn get_integer_scale(scale: f32) -> Option<usize>
+{
+	let scale_rounded = scale.round();
+	if scale_rounded >= 1.0 && (scale - scale_rounded).abs() < 1.0 / 1024.0
+	{
+		Some(scale_rounded as usize)
+	}
+	else
+	{
+		None
+	}
+}
+
+const INTERPOLATION_K_SHIFT: u32 = 8;
+const INTERPOLATION_K_ONE: u32 = 1 << INTERPOLATION_K_SHIFT;
+const INTERPOLATION_K_HALF: u32 = INTERPOLATION_K_ONE / 2;
+
+// Source coordinates of two pixels and interpolation factor between them.
+#[derive(Copy, Clone)]
+struct InterpolationCoord
+{
+	c0: u32,
+	c1: u32,
+	k: u32,
+}
+
+impl InterpolationCoord
+{
+	fn new(dst_coord: usize, dst_size: usize, src_size: usize) -> Self
+	{
+		// Map centers of destination pixels into source coordinates.
+		let src_coord = (((dst_coord as f32) + 0.5) * (src_size as f32) / (dst_size as f32) - 0.5)
+			.clamp(0.0, (src_size - 1) as f32);
+		let c0 = src_coord.floor();
+		Self {
+			c0: c0 as u32,
+			c1: ((c0 as usize) + 1).min(src_size - 1) as u32,
+			k: ((src_coord - c0) * (INTERPOLATION_K_ONE as f32)) as u32,
+		}
+	}
+}
+
+// Interpolate all components. k is in range [0; INTERPOLATION_K_ONE].
+fn interpolate_colors(a: Color32, b: Color32, k: u32) -> Color32
+{
+	// Process two components at once.
+	let (a, b) = (a.get_raw(), b.get_raw());
+	let k_inv = INTERPOLATION_K_ONE - k;
+	let rb = ((a & 0x00FF00FF) * k_inv + (b & 0x00FF00FF) * k) >> INTERPOLATION_K_SHIFT;
+	let ag = ((a >> 8) & 0x00FF00FF) * k_inv + ((b >> 8) & 0x00FF00FF) * k;
+	Color32::from_raw((rb & 0x00FF00FF) | (ag & 0xFF00FF00))
+}
+
+fn get_brightness(c: Color32) -> u32
+{
+	let rgb = c.get_rgb();
+	((rgb[0] as u32) + 2 * (rgb[1] as u32) + (rgb[2] as u32)) >> 2
+}
+
+// Make interpolation steeper for high-contrast edges.
+fn sharpen_k(k: u32, edge_contrast: u32) -> u32
+{
+	// Fully sharpen edges with brightness difference greater than 1/4.
+	let sharpness = (edge_contrast * 4).min(INTERPOLATION_K_ONE) as i32;
+	let k = k as i32;
+	let k_steep =
+		((k - (INTERPOLATION_K_HALF as i32)) * 3 + (INTERPOLATION_K_HALF as i32)).clamp(0, INTERPOLATION_K_ONE as i32);
+	(k + (((k_steep - k) * sharpness) >> INTERPOLATION_K_SHIFT)) as u32
+}
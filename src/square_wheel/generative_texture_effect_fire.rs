@@ -8,6 +8,8 @@ pub struct GenerativeTextureEffectFire
 	fire_effect: FireEffect,
 	heat_map: Vec<HeatMapElemement>,
 	palette: Palette,
+	// Used only if diffuse texture is generated.
+	diffuse_palette: Palette,
 	update_step: u32,
 	prev_update_time_s: f32,
 	// Use random for some heat sources.
@@ -32,17 +34,14 @@ impl GenerativeTextureEffectFire
 		}
 
 		// Fill dummy/backup palette.
-		let mut palette = [Color32::black(); 256];
-		for i in 0 .. 256
-		{
-			palette[i] = Color32::from_rgb(i as u8, i as u8, i as u8);
-		}
+		let palette = make_gradient_palette(None, None);
 
 		let mut result = Self {
 			update_frequency: fire_effect.update_frequency.max(1.0).min(200.0),
 			fire_effect,
 			heat_map: vec![0; area],
 			palette,
+			diffuse_palette: palette,
 			update_step: 0,
 			prev_update_time_s: 0.0,
 			rand_engine: create_rand_engine(),
@@ -325,7 +324,12 @@ impl GenerativeTextureEffect for GenerativeTextureEffectFire
 	{
 		// Count emissive texels as half-texels and heat buffer as quarter texel.
 		let area = 1 << (self.fire_effect.resolution_log2[0] + self.fire_effect.resolution_log2[1]);
-		area / 2 + area / 4
+		let mut s = area / 2 + area / 4;
+		if self.fire_effect.diffuse.is_some()
+		{
+			s += area;
+		}
+		s
 	}
 
 	fn update(
@@ -358,31 +362,29 @@ impl GenerativeTextureEffect for GenerativeTextureEffectFire
 
 		if out_texture_data.emissive_texture[0].pixels.is_empty()
 		{
-			// First update - generate palette.
-			if let Some(color) = self.fire_effect.color
+			// First update - generate palette, using specified color or emissive layer texture.
+			self.palette = make_gradient_palette(
+				self.fire_effect.color,
+				texture_data.emissive_texture.as_ref().map(|t| &t[0]),
+			);
+		}
+
+		if let Some(diffuse) = &self.fire_effect.diffuse
+		{
+			if out_texture_data.texture[0].pixels.is_empty()
 			{
-				// Use specified color for palette generation.
-				for i in 0 .. 256
+				// First update - generate palette for diffuse texture.
+				self.diffuse_palette = if diffuse.color.is_some()
 				{
-					let scale = i as f32;
-					self.palette[i] = Color32::from_rgb(
-						(color[0] * scale).max(0.0).min(255.0) as u8,
-						(color[1] * scale).max(0.0).min(255.0) as u8,
-						(color[2] * scale).max(0.0).min(255.0) as u8,
-					);
+					make_gradient_palette(diffuse.color, None)
 				}
-			}
-			else if let Some(emissive_texture) = &texture_data.emissive_texture
-			{
-				if !emissive_texture[0].pixels.is_empty()
+				else
 				{
-					// Use emissive layer texture as palette.
-					// Assume it contains gradient along U axis.
-					for i in 0 .. 256
-					{
-						self.palette[i as usize] =
-							emissive_texture[0].pixels[((i * emissive_texture[0].size[0]) >> 8) as usize];
-					}
+					self.palette
+				};
+				for (i, color) in self.diffuse_palette.iter_mut().enumerate()
+				{
+					color.set_alpha(if diffuse.alpha { i as u8 } else { 255 });
 				}
 			}
 		}
@@ -405,6 +407,26 @@ impl GenerativeTextureEffect for GenerativeTextureEffectFire
 			let (s0, s1) = out_texture_data.emissive_texture.split_at_mut(i);
 			build_texture_lite_mip(&mut s1[0], &s0[i - 1]);
 		}
+
+		if self.fire_effect.diffuse.is_some()
+		{
+			generate_diffuse_texture_based_on_heat_map(
+				[
+					1 << self.fire_effect.resolution_log2[0],
+					1 << self.fire_effect.resolution_log2[1],
+				],
+				&self.heat_map,
+				&self.diffuse_palette,
+				texture_data.material.roughness.clamp(MIN_VALID_ROUGHNESS, 1.0),
+				&mut out_texture_data.texture[0],
+			);
+
+			for i in 1 .. NUM_MIPS
+			{
+				let (s0, s1) = out_texture_data.texture.split_at_mut(i);
+				build_texture_mip(&mut s1[0], &s0[i - 1]);
+			}
+		}
 	}
 }
 
@@ -547,8 +569,6 @@ fn update_heat_map(size: [u32; 2], heat_map: &mut [HeatMapElemement], attenuatio
 	}
 }
 
-type Palette = [Color32; 256];
-
 fn generate_emissive_texture_based_on_heat_map(
 	size: [u32; 2],
 	heat_map: &[HeatMapElemement],
@@ -564,3 +584,31 @@ fn generate_emissive_texture_based_on_heat_map(
 		*dst_texel = unsafe { debug_only_checked_fetch(palette, heat_value as usize) };
 	}
 }
+
+fn generate_diffuse_texture_based_on_heat_map(
+	size: [u32; 2],
+	heat_map: &[HeatMapElemement],
+	palette: &Palette,
+	roughness: f32,
+	texture: &mut Texture,
+)
+{
+	texture.size = size;
+	texture.has_normal_map = false;
+	texture.has_non_one_roughness = roughness < 1.0;
+	texture.is_metal = false;
+
+	let packed_normal_roughness = PackedNormalRoughness::pack(&Vec3f::unit_z(), roughness);
+	texture.pixels.resize(
+		(size[0] * size[1]) as usize,
+		TextureElement {
+			diffuse: Color32::black(),
+			packed_normal_roughness,
+		},
+	);
+
+	for (dst_texel, &heat_value) in texture.pixels.iter_mut().zip(heat_map)
+	{
+		dst_texel.diffuse = unsafe { debug_only_checked_fetch(palette, heat_value as usize) };
+	}
+}
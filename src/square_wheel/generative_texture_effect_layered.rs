@@ -1,5 +1,5 @@
 use super::{abstract_color::*, fast_math::*, map_materials_processor_structs::*, surfaces, textures::*};
-use crate::common::{color::*, material::*};
+use crate::common::{color::*, material::*, math_types::*};
 
 pub struct GenerativeTextureEffectLayered
 {
@@ -125,7 +125,15 @@ impl GenerativeTextureEffect for GenerativeTextureEffectLayered
 
 					if !adding_zero
 					{
-						apply_texture_layer(dst_mip.size, &mut dst_mip.pixels, src_mip, shift, light, blending_mode);
+						apply_texture_layer(
+							dst_mip.size,
+							&mut dst_mip.pixels,
+							src_mip,
+							shift,
+							light,
+							blending_mode,
+							animation_layer.normals_blending_mode,
+						);
 					}
 
 					dst_mip.has_normal_map |= src_mip.has_normal_map;
@@ -168,9 +176,11 @@ fn apply_texture_layer(
 	layer_texture_offset: [i32; 2],
 	light: [f32; 3],
 	blending_mode: BlendingMode,
+	normals_blending_mode: Option<LayerNormalsBlendingMode>,
 )
 {
 	if blending_mode == BlendingMode::None &&
+		normals_blending_mode != Some(LayerNormalsBlendingMode::Reoriented) &&
 		texture_size == layer_texture.size &&
 		layer_texture_offset == [0, 0] &&
 		light == [1.0, 1.0, 1.0]
@@ -218,6 +228,16 @@ fn apply_texture_layer(
 			light,
 		),
 	}
+
+	apply_texture_layer_normals_roughness(
+		texture_size,
+		texture_data,
+		layer_texture,
+		layer_texture_offset,
+		light,
+		blending_mode,
+		normals_blending_mode,
+	);
 }
 
 fn apply_texture_layer_impl_1<const BLENDING_MODE: usize>(
@@ -293,11 +313,9 @@ fn apply_texture_layer_impl_2<const BLENDING_MODE: usize, const MODULATE: bool>(
 				if BLENDING_MODE == BLENDING_MODE_NONE
 				{
 					dst_texel.diffuse = texel_value_modulated.into();
-					dst_texel.packed_normal_roughness = texel_value.packed_normal_roughness;
 				}
 				else if BLENDING_MODE == BLENDING_MODE_AVERAGE
 				{
-					// TODO - support normals/roughness blending.
 					dst_texel.diffuse = ColorVecI::shift_right::<1>(&ColorVecI::add(
 						&texel_value_modulated,
 						&ColorVecI::from_color32(dst_texel.diffuse),
@@ -306,7 +324,6 @@ fn apply_texture_layer_impl_2<const BLENDING_MODE: usize, const MODULATE: bool>(
 				}
 				else if BLENDING_MODE == BLENDING_MODE_ADDITIVE
 				{
-					// TODO - support normals/roughness blending.
 					dst_texel.diffuse =
 						ColorVecI::add(&texel_value_modulated, &ColorVecI::from_color32(dst_texel.diffuse)).into();
 				}
@@ -315,12 +332,10 @@ fn apply_texture_layer_impl_2<const BLENDING_MODE: usize, const MODULATE: bool>(
 					if texel_value.diffuse.test_alpha()
 					{
 						dst_texel.diffuse = texel_value_modulated.into();
-						dst_texel.packed_normal_roughness = texel_value.packed_normal_roughness;
 					}
 				}
 				else if BLENDING_MODE == BLENDING_MODE_ALPHA_BLEND
 				{
-					// TODO - support normals/roughness blending.
 					let alpha = texel_value.diffuse.get_alpha();
 					dst_texel.diffuse = ColorVecI::shift_right::<8>(&ColorVecI::add(
 						&ColorVecI::mul_scalar(&texel_value_modulated, alpha),
@@ -334,17 +349,14 @@ fn apply_texture_layer_impl_2<const BLENDING_MODE: usize, const MODULATE: bool>(
 				// Mix with initial texture (without modulation).
 				if BLENDING_MODE == BLENDING_MODE_NONE
 				{
-					*dst_texel = texel_value;
-					dst_texel.packed_normal_roughness = texel_value.packed_normal_roughness;
+					dst_texel.diffuse = texel_value.diffuse;
 				}
 				else if BLENDING_MODE == BLENDING_MODE_AVERAGE
 				{
-					// TODO - support normals/roughness blending.
 					dst_texel.diffuse = Color32::get_average(dst_texel.diffuse, texel_value.diffuse);
 				}
 				else if BLENDING_MODE == BLENDING_MODE_ADDITIVE
 				{
-					// TODO - support normals/roughness blending.
 					dst_texel.diffuse = ColorVecI::add(
 						&ColorVecI::from_color32(texel_value.diffuse),
 						&ColorVecI::from_color32(dst_texel.diffuse),
@@ -355,13 +367,11 @@ fn apply_texture_layer_impl_2<const BLENDING_MODE: usize, const MODULATE: bool>(
 				{
 					if texel_value.diffuse.test_alpha()
 					{
-						*dst_texel = texel_value;
-						dst_texel.packed_normal_roughness = texel_value.packed_normal_roughness;
+						dst_texel.diffuse = texel_value.diffuse;
 					}
 				}
 				else if BLENDING_MODE == BLENDING_MODE_ALPHA_BLEND
 				{
-					// TODO - support normals/roughness blending.
 					let alpha = texel_value.diffuse.get_alpha();
 					dst_texel.diffuse = ColorVecI::shift_right::<8>(&ColorVecI::add(
 						&ColorVecI::mul_scalar(&ColorVecI::from_color32(texel_value.diffuse), alpha),
@@ -379,3 +389,134 @@ fn apply_texture_layer_impl_2<const BLENDING_MODE: usize, const MODULATE: bool>(
 		}
 	}
 }
+
+// Normals and roughness are mixed separately from color, since such mixing requires floating point calculations.
+fn apply_texture_layer_normals_roughness(
+	texture_size: [u32; 2],
+	texture_data: &mut [TextureElement],
+	layer_texture: &Texture,
+	layer_texture_offset: [i32; 2],
+	light: [f32; 3],
+	blending_mode: BlendingMode,
+	normals_blending_mode: Option<LayerNormalsBlendingMode>,
+)
+{
+	let (normals_blending_mode, modulation) = if let Some(m) = normals_blending_mode
+	{
+		(m, light[0].max(light[1]).max(light[2]).clamp(0.0, 1.0))
+	}
+	else
+	{
+		// Legacy behavior - replace normals and roughness only for none and alpha test blending, ignore modulation.
+		if blending_mode != BlendingMode::None && blending_mode != BlendingMode::AlphaTest
+		{
+			return;
+		}
+		(LayerNormalsBlendingMode::Replace, 1.0)
+	};
+
+	if modulation <= 0.0
+	{
+		return;
+	}
+
+	for dst_v in 0 .. texture_size[1]
+	{
+		let dst_line_start = (dst_v * texture_size[0]) as usize;
+		let dst_line = &mut texture_data[dst_line_start .. dst_line_start + (texture_size[0] as usize)];
+
+		let src_v = (layer_texture_offset[1] + (dst_v as i32)).rem_euclid(layer_texture.size[1] as i32);
+		let src_line_start = ((src_v as u32) * layer_texture.size[0]) as usize;
+		let src_line = &layer_texture.pixels[src_line_start .. src_line_start + (layer_texture.size[0] as usize)];
+		let mut src_u = layer_texture_offset[0].rem_euclid(layer_texture.size[0] as i32);
+
+		for dst_texel in dst_line.iter_mut()
+		{
+			let texel_value = unsafe { debug_only_checked_fetch(src_line, src_u as usize) };
+
+			let blend_factor = modulation *
+				match blending_mode
+				{
+					BlendingMode::None | BlendingMode::Additive => 1.0,
+					BlendingMode::Average => 0.5,
+					BlendingMode::AlphaTest =>
+					{
+						if texel_value.diffuse.test_alpha()
+						{
+							1.0
+						}
+						else
+						{
+							0.0
+						}
+					},
+					BlendingMode::AlphaBlend => (texel_value.diffuse.get_alpha() as f32) * (1.0 / 255.0),
+				};
+
+			if blend_factor > 0.0
+			{
+				dst_texel.packed_normal_roughness = blend_normal_roughness(
+					dst_texel.packed_normal_roughness,
+					texel_value.packed_normal_roughness,
+					blend_factor,
+					normals_blending_mode,
+				);
+			}
+
+			src_u += 1;
+			if src_u == (layer_texture.size[0] as i32)
+			{
+				src_u = 0;
+			}
+		}
+	}
+}
+
+fn blend_normal_roughness(
+	dst: PackedNormalRoughness,
+	src: PackedNormalRoughness,
+	blend_factor: f32,
+	normals_blending_mode: LayerNormalsBlendingMode,
+) -> PackedNormalRoughness
+{
+	if normals_blending_mode == LayerNormalsBlendingMode::Replace
+	{
+		return if blend_factor >= 0.5 { src } else { dst };
+	}
+
+	let (dst_normal, dst_roughness) = dst.unpack();
+	let (src_normal, src_roughness) = src.unpack();
+
+	let target_normal = if normals_blending_mode == LayerNormalsBlendingMode::Reoriented
+	{
+		// See "Blending in Detail" by Colin Barré-Brisebois and Stephen Hill.
+		let t = dst_normal + Vec3f::unit_z();
+		let u = Vec3f::new(-src_normal.x, -src_normal.y, src_normal.z);
+		normalize_or_z(t * (t.dot(u) / t.z.max(MIN_NORMAL_Z)) - u)
+	}
+	else
+	{
+		src_normal
+	};
+
+	let k = blend_factor.min(1.0);
+	PackedNormalRoughness::pack(
+		&normalize_or_z(dst_normal * (1.0 - k) + target_normal * k),
+		(dst_roughness * (1.0 - k) + src_roughness * k).max(MIN_VALID_ROUGHNESS),
+	)
+}
+
+fn normalize_or_z(v: Vec3f) -> Vec3f
+{
+	let square_len = v.magnitude2();
+	if square_len > 0.0
+	{
+		v * inv_sqrt_fast(square_len)
+	}
+	else
+	{
+		Vec3f::unit_z()
+	}
+}
+
+const MIN_NORMAL_Z: f32 = 1.0 / 64.0;
@@ -0,0 +1,526 @@
+use super::{abstract_color::*, fast_math::*, map_materials_processor_structs::*, textures::*};
+use crate::common::{color::*, material_function::*, material_procedural::*, math_types::*};
+
+// Expression graph is compiled into list of operations, that are evaluated for whole texture row at once.
+// Operations, that don't depend on vertical texture coordinate, are evaluated only once per update.
+pub struct GenerativeTextureEffectProcedural
+{
+	procedural_effect: ProceduralTextureEffect,
+	// Corrected config value.
+	update_frequency: f32,
+	operations: Vec<Operation>,
+	// Values of operations for current row.
+	rows: Vec<Vec<f32>>,
+	prev_update_step: i32,
+}
+
+impl GenerativeTextureEffectProcedural
+{
+	pub fn new<MaterialLoadFunction: FnMut(&str) -> TextureIndex>(
+		procedural_effect: ProceduralTextureEffect,
+		material_load_function: &mut MaterialLoadFunction,
+	) -> Self
+	{
+		if procedural_effect.resolution_log2[0] < MAX_MIP as u32 ||
+			procedural_effect.resolution_log2[1] < MAX_MIP as u32
+		{
+			panic!("Procedural texture must have size at least {}x{}!", MAX_MIP, MAX_MIP);
+		}
+		if procedural_effect.resolution_log2[0] + procedural_effect.resolution_log2[1] >= 22
+		{
+			panic!("Procedural texture is too big!");
+		}
+
+		let mut operations = Vec::with_capacity(procedural_effect.nodes.len());
+		for (node_index, node) in procedural_effect.nodes.iter().enumerate()
+		{
+			let operation = compile_node(node, &operations, material_load_function).unwrap_or_else(|| {
+				println!("Invalid procedural texture node {}: {:?}", node_index, node);
+				Operation {
+					kind: OperationKind::Constant(0.0),
+					depends_on_v: false,
+				}
+			});
+			operations.push(operation);
+		}
+
+		let width = 1 << procedural_effect.resolution_log2[0];
+
+		Self {
+			update_frequency: procedural_effect.update_frequency.clamp(1.0, 200.0),
+			procedural_effect,
+			rows: vec![vec![0.0; width]; operations.len()],
+			operations,
+			prev_update_step: -1,
+		}
+	}
+
+	fn get_output_row<'a>(&'a self, node_index: u32, zero_row: &'a [f32]) -> &'a [f32]
+	{
+		if let Some(row) = self.rows.get(node_index as usize)
+		{
+			row
+		}
+		else
+		{
+			zero_row
+		}
+	}
+}
+
+impl GenerativeTextureEffect for GenerativeTextureEffectProcedural
+{
+	fn get_estimated_texel_count(&self, _texture_data: &MapTextureData, _all_textures_data: &[MapTextureData]) -> u32
+	{
+		let area = 1 << (self.procedural_effect.resolution_log2[0] + self.procedural_effect.resolution_log2[1]);
+		let mut s = 0;
+		if self.procedural_effect.color.is_some()
+		{
+			s += area;
+		}
+		if self.procedural_effect.emissive.is_some()
+		{
+			// Count emisive texels as half-texels.
+			s += area / 2;
+		}
+		s
+	}
+
+	fn update(
+		&mut self,
+		out_texture_data: &mut GenerativeTextureData,
+		texture_data: &MapTextureData,
+		all_textures_data: &[MapTextureData],
+		_textures_mapping_table: &[TextureMappingElement],
+		current_time_s: f32,
+	)
+	{
+		// Update texture with fixed frequency, in order to avoid regenerating it too often.
+		let update_step = (current_time_s * self.update_frequency) as i32;
+		if update_step == self.prev_update_step
+		{
+			return;
+		}
+		self.prev_update_step = update_step;
+
+		let time_s = (update_step as f32) / self.update_frequency;
+
+		let size = [
+			1 << self.procedural_effect.resolution_log2[0],
+			1 << self.procedural_effect.resolution_log2[1],
+		];
+		let texel_count = (size[0] * size[1]) as usize;
+
+		let color = self.procedural_effect.color;
+		let emissive = self.procedural_effect.emissive;
+
+		if color.is_some()
+		{
+			let texture = &mut out_texture_data.texture[0];
+			let roughness = texture_data.material.roughness.clamp(MIN_VALID_ROUGHNESS, 1.0);
+			texture.size = size;
+			texture.has_normal_map = false;
+			texture.has_non_one_roughness = roughness < 1.0;
+			texture.is_metal = false;
+			texture.pixels.resize(
+				texel_count,
+				TextureElement {
+					diffuse: Color32::black(),
+					packed_normal_roughness: PackedNormalRoughness::pack(&Vec3f::unit_z(), roughness),
+				},
+			);
+		}
+		if emissive.is_some()
+		{
+			let texture = &mut out_texture_data.emissive_texture[0];
+			texture.size = size;
+			texture.pixels.resize(texel_count, Color32::black());
+		}
+
+		// Used for output nodes with invalid indices.
+		let zero_row = vec![0.0; size[0] as usize];
+
+		let inv_size = [1.0 / (size[0] as f32), 1.0 / (size[1] as f32)];
+		for y in 0 .. size[1]
+		{
+			let v = (y as f32) * inv_size[1];
+			for (operation_index, operation) in self.operations.iter().enumerate()
+			{
+				if y > 0 && !operation.depends_on_v
+				{
+					// Result does not depend on row - reuse values, calculated for first row.
+					continue;
+				}
+
+				let (prev_rows, rows_left) = self.rows.split_at_mut(operation_index);
+				evaluate_operation(
+					&operation.kind,
+					prev_rows,
+					v,
+					inv_size[0],
+					time_s,
+					all_textures_data,
+					&mut rows_left[0],
+				);
+			}
+
+			let row_start = (y * size[0]) as usize;
+			let row_end = row_start + (size[0] as usize);
+
+			if let Some(color) = color
+			{
+				let rgb = color.map(|c| self.get_output_row(c, &zero_row));
+				let alpha = self.procedural_effect.alpha.map(|a| self.get_output_row(a, &zero_row));
+				let dst_row = &mut out_texture_data.texture[0].pixels[row_start .. row_end];
+				for (x, dst_texel) in dst_row.iter_mut().enumerate()
+				{
+					let a = alpha.map(|a| convert_component(a[x])).unwrap_or(255);
+					dst_texel.diffuse = Color32::from_rgba(
+						convert_component(rgb[0][x]),
+						convert_component(rgb[1][x]),
+						convert_component(rgb[2][x]),
+						a,
+					);
+				}
+			}
+
+			if let Some(emissive) = emissive
+			{
+				let rgb = emissive.map(|c| self.get_output_row(c, &zero_row));
+				let dst_row = &mut out_texture_data.emissive_texture[0].pixels[row_start .. row_end];
+				for (x, dst_texel) in dst_row.iter_mut().enumerate()
+				{
+					*dst_texel = Color32::from_rgb(
+						convert_component(rgb[0][x]),
+						convert_component(rgb[1][x]),
+						convert_component(rgb[2][x]),
+					);
+				}
+			}
+		}
+
+		// Generate mips.
+		if color.is_some()
+		{
+			for i in 1 .. NUM_MIPS
+			{
+				let (s0, s1) = out_texture_data.texture.split_at_mut(i);
+				build_texture_mip(&mut s1[0], &s0[i - 1]);
+			}
+		}
+		if emissive.is_some()
+		{
+			for i in 1 .. NUM_MIPS
+			{
+				let (s0, s1) = out_texture_data.emissive_texture.split_at_mut(i);
+				build_texture_lite_mip(&mut s1[0], &s0[i - 1]);
+			}
+		}
+	}
+}
+
+struct Operation
+{
+	kind: OperationKind,
+	// If false - result is the same for all rows.
+	depends_on_v: bool,
+}
+
+enum OperationKind
+{
+	Constant(f32),
+	U,
+	V,
+	Time,
+	Function(SingleArgumentFunction, usize),
+	Add(usize, usize),
+	Sub(usize, usize),
+	Mul(usize, usize),
+	Min(usize, usize),
+	Max(usize, usize),
+	Fract(usize),
+	Noise
+	{
+		u: usize,
+		v: usize,
+		cells: [u32; 2],
+		seed: u32,
+	},
+	TextureSample
+	{
+		texture_index: TextureIndex,
+		u: usize,
+		v: usize,
+		channel: ProceduralTextureChannel,
+	},
+}
+
+// Returns None if node references invalid nodes.
+fn compile_node<MaterialLoadFunction: FnMut(&str) -> TextureIndex>(
+	node: &ProceduralTextureNode,
+	prev_operations: &[Operation],
+	material_load_function: &mut MaterialLoadFunction,
+) -> Option<Operation>
+{
+	let arg = |index: u32| -> Option<usize> {
+		if (index as usize) < prev_operations.len()
+		{
+			Some(index as usize)
+		}
+		else
+		{
+			None
+		}
+	};
+	let depends_on_v = |args: &[usize]| args.iter().any(|&index| prev_operations[index].depends_on_v);
+
+	let operation = match node
+	{
+		ProceduralTextureNode::Constant(c) => Operation {
+			kind: OperationKind::Constant(*c),
+			depends_on_v: false,
+		},
+		ProceduralTextureNode::U => Operation {
+			kind: OperationKind::U,
+			depends_on_v: false,
+		},
+		ProceduralTextureNode::V => Operation {
+			kind: OperationKind::V,
+			depends_on_v: true,
+		},
+		ProceduralTextureNode::Time => Operation {
+			kind: OperationKind::Time,
+			depends_on_v: false,
+		},
+		ProceduralTextureNode::Function { function, arg: a } =>
+		{
+			let a = arg(*a)?;
+			Operation {
+				kind: OperationKind::Function(*function, a),
+				depends_on_v: depends_on_v(&[a]),
+			}
+		},
+		ProceduralTextureNode::Add(a, b) =>
+		{
+			let (a, b) = (arg(*a)?, arg(*b)?);
+			Operation {
+				kind: OperationKind::Add(a, b),
+				depends_on_v: depends_on_v(&[a, b]),
+			}
+		},
+		ProceduralTextureNode::Sub(a, b) =>
+		{
+			let (a, b) = (arg(*a)?, arg(*b)?);
+			Operation {
+				kind: OperationKind::Sub(a, b),
+				depends_on_v: depends_on_v(&[a, b]),
+			}
+		},
+		ProceduralTextureNode::Mul(a, b) =>
+		{
+			let (a, b) = (arg(*a)?, arg(*b)?);
+			Operation {
+				kind: OperationKind::Mul(a, b),
+				depends_on_v: depends_on_v(&[a, b]),
+			}
+		},
+		ProceduralTextureNode::Min(a, b) =>
+		{
+			let (a, b) = (arg(*a)?, arg(*b)?);
+			Operation {
+				kind: OperationKind::Min(a, b),
+				depends_on_v: depends_on_v(&[a, b]),
+			}
+		},
+		ProceduralTextureNode::Max(a, b) =>
+		{
+			let (a, b) = (arg(*a)?, arg(*b)?);
+			Operation {
+				kind: OperationKind::Max(a, b),
+				depends_on_v: depends_on_v(&[a, b]),
+			}
+		},
+		ProceduralTextureNode::Fract(a) =>
+		{
+			let a = arg(*a)?;
+			Operation {
+				kind: OperationKind::Fract(a),
+				depends_on_v: depends_on_v(&[a]),
+			}
+		},
+		ProceduralTextureNode::Noise { u, v, cells, seed } =>
+		{
+			let (u, v) = (arg(*u)?, arg(*v)?);
+			Operation {
+				kind: OperationKind::Noise {
+					u,
+					v,
+					cells: cells.map(|c| c.max(1)),
+					seed: *seed,
+				},
+				depends_on_v: depends_on_v(&[u, v]),
+			}
+		},
+		ProceduralTextureNode::TextureSample {
+			material_name,
+			u,
+			v,
+			channel,
+		} =>
+		{
+			let (u, v) = (arg(*u)?, arg(*v)?);
+			Operation {
+				kind: OperationKind::TextureSample {
+					texture_index: material_load_function(material_name),
+					u,
+					v,
+					channel: *channel,
+				},
+				depends_on_v: depends_on_v(&[u, v]),
+			}
+		},
+	};
+
+	Some(operation)
+}
+
+fn evaluate_operation(
+	kind: &OperationKind,
+	prev_rows: &[Vec<f32>],
+	v: f32,
+	inv_width: f32,
+	time_s: f32,
+	all_textures_data: &[MapTextureData],
+	dst: &mut [f32],
+)
+{
+	let unary = |dst: &mut [f32], a: usize, func: &dyn Fn(f32) -> f32| {
+		for (dst_value, &a_value) in dst.iter_mut().zip(prev_rows[a].iter())
+		{
+			*dst_value = func(a_value);
+		}
+	};
+	let binary = |dst: &mut [f32], a: usize, b: usize, func: &dyn Fn(f32, f32) -> f32| {
+		for ((dst_value, &a_value), &b_value) in dst.iter_mut().zip(prev_rows[a].iter()).zip(prev_rows[b].iter())
+		{
+			*dst_value = func(a_value, b_value);
+		}
+	};
+
+	match kind
+	{
+		OperationKind::Constant(c) => dst.fill(*c),
+		OperationKind::U =>
+		{
+			for (x, dst_value) in dst.iter_mut().enumerate()
+			{
+				*dst_value = (x as f32) * inv_width;
+			}
+		},
+		OperationKind::V => dst.fill(v),
+		OperationKind::Time => dst.fill(time_s),
+		OperationKind::Function(function, a) => unary(dst, *a, &|x| function.evaluate(x)),
+		OperationKind::Add(a, b) => binary(dst, *a, *b, &|x, y| x + y),
+		OperationKind::Sub(a, b) => binary(dst, *a, *b, &|x, y| x - y),
+		OperationKind::Mul(a, b) => binary(dst, *a, *b, &|x, y| x * y),
+		OperationKind::Min(a, b) => binary(dst, *a, *b, &|x, y| x.min(y)),
+		OperationKind::Max(a, b) => binary(dst, *a, *b, &|x, y| x.max(y)),
+		OperationKind::Fract(a) => unary(dst, *a, &|x| x - x.floor()),
+		OperationKind::Noise { u, v, cells, seed } =>
+		{
+			binary(dst, *u, *v, &|x, y| value_noise([x, y], *cells, *seed));
+		},
+		OperationKind::TextureSample {
+			texture_index,
+			u,
+			v,
+			channel,
+		} =>
+		{
+			let texture = &all_textures_data[*texture_index as usize].texture[0];
+			binary(dst, *u, *v, &|x, y| sample_texture(texture, [x, y], *channel));
+		},
+	}
+}
+
+fn convert_component(value: f32) -> u8
+{
+	(value * 255.0).clamp(0.0, 255.0) as u8
+}
+
+// Tileable noise with smooth interpolation between random values in grid cells corners.
+fn value_noise(coord: [f32; 2], cells: [u32; 2], seed: u32) -> f32
+{
+	let coord_scaled = [
+		(coord[0] - coord[0].floor()) * (cells[0] as f32),
+		(coord[1] - coord[1].floor()) * (cells[1] as f32),
+	];
+	let coord_floor = [coord_scaled[0].floor(), coord_scaled[1].floor()];
+	let k = [
+		smooth_step(coord_scaled[0] - coord_floor[0]),
+		smooth_step(coord_scaled[1] - coord_floor[1]),
+	];
+
+	// Wrap cell coordinates in order to make noise tileable.
+	let x0 = (coord_floor[0] as u32) % cells[0];
+	let y0 = (coord_floor[1] as u32) % cells[1];
+	let x1 = (x0 + 1) % cells[0];
+	let y1 = (y0 + 1) % cells[1];
+
+	let v00 = hash_to_unit(x0, y0, seed);
+	let v10 = hash_to_unit(x1, y0, seed);
+	let v01 = hash_to_unit(x0, y1, seed);
+	let v11 = hash_to_unit(x1, y1, seed);
+
+	let v0 = v00 + (v10 - v00) * k[0];
+	let v1 = v01 + (v11 - v01) * k[0];
+	v0 + (v1 - v0) * k[1]
+}
+
+fn smooth_step(x: f32) -> f32
+{
+	x * x * (3.0 - 2.0 * x)
+}
+
+// Returns pseudo-random value in range [0; 1].
+fn hash_to_unit(x: u32, y: u32, seed: u32) -> f32
+{
+	let mut h = x.wrapping_mul(0x8da6b343) ^ y.wrapping_mul(0xd8163841) ^ seed.wrapping_mul(0xcb1ab31f);
+	h ^= h >> 15;
+	h = h.wrapping_mul(0x2c1b3c6d);
+	h ^= h >> 12;
+	h = h.wrapping_mul(0x297a2d39);
+	h ^= h >> 15;
+	((h >> 8) as f32) * (1.0 / ((1 << 24) - 1) as f32)
+}
+
+fn sample_texture(texture: &Texture, coord: [f32; 2], channel: ProceduralTextureChannel) -> f32
+{
+	if texture.pixels.is_empty()
+	{
+		return 0.0;
+	}
+
+	let x = ((coord[0] * (texture.size[0] as f32)).floor() as i32).rem_euclid(texture.size[0] as i32);
+	let y = ((coord[1] * (texture.size[1] as f32)).floor() as i32).rem_euclid(texture.size[1] as i32);
+	let texel = unsafe {
+		debug_only_checked_fetch(
+			&texture.pixels,
+			(x as usize) + (y as usize) * (texture.size[0] as usize),
+		)
+	};
+
+	const SCALE: f32 = 1.0 / 255.0;
+	let rgb = texel.diffuse.get_rgb();
+	match channel
+	{
+		ProceduralTextureChannel::Red => (rgb[0] as f32) * SCALE,
+		ProceduralTextureChannel::Green => (rgb[1] as f32) * SCALE,
+		ProceduralTextureChannel::Blue => (rgb[2] as f32) * SCALE,
+		ProceduralTextureChannel::Alpha => (texel.diffuse.get_alpha() as f32) * SCALE,
+		ProceduralTextureChannel::Brightness =>
+		{
+			((rgb[0] as u32) + (rgb[1] as u32) + (rgb[2] as u32)) as f32 * (SCALE / 3.0)
+		},
+	}
+}
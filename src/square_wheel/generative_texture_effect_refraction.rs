@@ -0,0 +1,249 @@
+use super::{fast_math::*, map_materials_processor_structs::*, textures::*};
+use crate::common::material_refraction::*;
+
+pub struct GenerativeTextureEffectRefraction
+{
+	refraction_effect: RefractionEffect,
+	// Corrected config value.
+	update_frequency: f32,
+	distortion_texture_index: TextureIndex,
+	// Precalculated shifts for each mip of distortion layer. Calculated during first update.
+	distortion_shifts: Vec<DistortionShifts>,
+	prev_update_step: i32,
+}
+
+impl GenerativeTextureEffectRefraction
+{
+	pub fn new<MaterialLoadFunction: FnMut(&str) -> TextureIndex>(
+		refraction_effect: RefractionEffect,
+		material_load_function: &mut MaterialLoadFunction,
+	) -> Self
+	{
+		Self {
+			update_frequency: refraction_effect.update_frequency.clamp(1.0, 200.0),
+			distortion_texture_index: material_load_function(&refraction_effect.distortion_material_name),
+			refraction_effect,
+			distortion_shifts: Vec::new(),
+			prev_update_step: -1,
+		}
+	}
+}
+
+impl GenerativeTextureEffect for GenerativeTextureEffectRefraction
+{
+	fn get_estimated_texel_count(&self, texture_data: &MapTextureData, _all_textures_data: &[MapTextureData]) -> u32
+	{
+		texture_data.texture[0].size[0] * texture_data.texture[0].size[1]
+	}
+
+	fn update(
+		&mut self,
+		out_texture_data: &mut GenerativeTextureData,
+		texture_data: &MapTextureData,
+		all_textures_data: &[MapTextureData],
+		_textures_mapping_table: &[TextureMappingElement],
+		current_time_s: f32,
+	)
+	{
+		// Update texture with fixed frequency, in order to avoid regenerating it too often.
+		let update_step = (current_time_s * self.update_frequency) as i32;
+		if update_step == self.prev_update_step && !out_texture_data.texture[0].pixels.is_empty()
+		{
+			return;
+		}
+		self.prev_update_step = update_step;
+
+		let time_s = (update_step as f32) / self.update_frequency;
+
+		let distortion_texture = &all_textures_data[self.distortion_texture_index as usize].texture;
+		if self.distortion_shifts.is_empty()
+		{
+			let distortion_source = self.refraction_effect.distortion_source;
+			let strength = self.refraction_effect.strength;
+			self.distortion_shifts = distortion_texture
+				.iter()
+				.enumerate()
+				.map(|(mip_index, mip)| {
+					make_power_of_two_distortion_shifts(
+						calculate_distortion_shifts(mip, mip_index, distortion_source, strength),
+						mip.size,
+					)
+				})
+				.collect();
+		}
+
+		for mip_index in 0 .. NUM_MIPS
+		{
+			let src_mip = &texture_data.texture[mip_index];
+			let dst_mip = &mut out_texture_data.texture[mip_index];
+			if dst_mip.pixels.is_empty()
+			{
+				*dst_mip = src_mip.clone();
+			}
+
+			let base_shift = self
+				.refraction_effect
+				.base_tex_coord_shift
+				.map(|f| (f.evaluate(time_s) as i32) >> mip_index);
+			let distortion_shift = self
+				.refraction_effect
+				.distortion_tex_coord_shift
+				.map(|f| (f.evaluate(time_s) as i32) >> mip_index);
+
+			let distortion_shifts = &self.distortion_shifts[mip_index];
+			make_refraction(
+				src_mip,
+				base_shift,
+				distortion_shifts.size,
+				&distortion_shifts.shifts,
+				distortion_shift,
+				&mut dst_mip.pixels,
+			);
+		}
+	}
+}
+
+type DistortionShift = [i32; 2];
+
+struct DistortionShifts
+{
+	// Always power of two.
+	size: [u32; 2],
+	shifts: Vec<DistortionShift>,
+}
+
+// Distortion layer is tiled using bit masks, so, its size should be power of two.
+// Resize (using nearest sampling) shifts of distortion layer with other size.
+fn make_power_of_two_distortion_shifts(shifts: Vec<DistortionShift>, size: [u32; 2]) -> DistortionShifts
+{
+	let size_corrected = size.map(|s| s.max(1).next_power_of_two());
+	if size_corrected == size
+	{
+		return DistortionShifts { size, shifts };
+	}
+
+	let mut shifts_corrected = Vec::with_capacity((size_corrected[0] * size_corrected[1]) as usize);
+	for y in 0 .. size_corrected[1]
+	{
+		let src_y = y * size[1] / size_corrected[1];
+		for x in 0 .. size_corrected[0]
+		{
+			let src_x = x * size[0] / size_corrected[0];
+			shifts_corrected.push(
+				shifts
+					.get((src_x + src_y * size[0]) as usize)
+					.copied()
+					.unwrap_or([0, 0]),
+			);
+		}
+	}
+
+	DistortionShifts {
+		size: size_corrected,
+		shifts: shifts_corrected,
+	}
+}
+
+fn calculate_distortion_shifts(
+	texture: &Texture,
+	mip_index: usize,
+	distortion_source: RefractionDistortionSource,
+	strength: f32,
+) -> Vec<DistortionShift>
+{
+	let mip_scale = 1.0 / ((1 << mip_index) as f32);
+	match distortion_source
+	{
+		RefractionDistortionSource::Normals =>
+		{
+			let strength_corrected = strength * mip_scale;
+			texture
+				.pixels
+				.iter()
+				.map(|texel| {
+					let (normal, _roughness) = texel.packed_normal_roughness.unpack();
+					[
+						(normal.x * strength_corrected).round() as i32,
+						(normal.y * strength_corrected).round() as i32,
+					]
+				})
+				.collect()
+		},
+		RefractionDistortionSource::Heights =>
+		{
+			let heights: Vec<f32> = texture
+				.pixels
+				.iter()
+				.map(|texel| {
+					let rgb = texel.diffuse.get_rgb();
+					((rgb[0] as u32) + (rgb[1] as u32) + (rgb[2] as u32)) as f32 * (1.0 / (3.0 * 255.0))
+				})
+				.collect();
+
+			// Height difference between adjacent texels grows for lower mips, so, compensate it.
+			let strength_corrected = strength * mip_scale * mip_scale * 0.5;
+
+			let size = [texture.size[0] as usize, texture.size[1] as usize];
+			let mut result = Vec::with_capacity(heights.len());
+			for y in 0 .. size[1]
+			{
+				let y_minus_one = (y + size[1] - 1) % size[1];
+				let y_plus_one = (y + 1) % size[1];
+				for x in 0 .. size[0]
+				{
+					let x_minus_one = (x + size[0] - 1) % size[0];
+					let x_plus_one = (x + 1) % size[0];
+					let dx = heights[x_plus_one + y * size[0]] - heights[x_minus_one + y * size[0]];
+					let dy = heights[x + y_plus_one * size[0]] - heights[x + y_minus_one * size[0]];
+					result.push([
+						(dx * strength_corrected).round() as i32,
+						(dy * strength_corrected).round() as i32,
+					]);
+				}
+			}
+			result
+		},
+	}
+}
+
+fn make_refraction(
+	src_texture: &Texture,
+	base_shift: [i32; 2],
+	distortion_size: [u32; 2],
+	distortion_shifts: &[DistortionShift],
+	distortion_shift: [i32; 2],
+	dst_pixels: &mut [TextureElement],
+)
+{
+	let size = [src_texture.size[0] as i32, src_texture.size[1] as i32];
+	let distortion_size = [distortion_size[0] as i32, distortion_size[1] as i32];
+
+	debug_assert!((size[0] & (size[0] - 1)) == 0);
+	debug_assert!((size[1] & (size[1] - 1)) == 0);
+	debug_assert!((distortion_size[0] & (distortion_size[0] - 1)) == 0);
+	debug_assert!((distortion_size[1] & (distortion_size[1] - 1)) == 0);
+	let mask = [size[0] - 1, size[1] - 1];
+	let distortion_mask = [distortion_size[0] - 1, distortion_size[1] - 1];
+
+	for y in 0 .. size[1]
+	{
+		let dst_row = &mut dst_pixels[(y * size[0]) as usize .. ((y + 1) * size[0]) as usize];
+		let distortion_row_offset = ((y + distortion_shift[1]) & distortion_mask[1]) * distortion_size[0];
+		for x in 0 .. size[0]
+		{
+			unsafe {
+				let shift = debug_only_checked_fetch(
+					distortion_shifts,
+					(((x + distortion_shift[0]) & distortion_mask[0]) + distortion_row_offset) as usize,
+				);
+				let u = (x + base_shift[0] + shift[0]) & mask[0];
+				let v = (y + base_shift[1] + shift[1]) & mask[1];
+				debug_only_checked_write(
+					dst_row,
+					x as usize,
+					debug_only_checked_fetch(&src_texture.pixels, (u + v * size[0]) as usize),
+				);
+			}
+		}
+	}
+}
@@ -10,6 +10,8 @@ pub struct GenerativeTextureEffectWater
 	wave_field_old: Vec<WaveFieldElement>,
 	// Used for setting color of result texture.
 	color_image: Image,
+	// Used for emissive texture generation. Empty if emissive texture is not generated.
+	emissive_palette: Vec<Color32>,
 	// Use random for some wave sources.
 	rand_engine: RandEngine,
 	update_step: u32,
@@ -38,6 +40,7 @@ impl GenerativeTextureEffectWater
 			wave_field: vec![0.0; area],
 			wave_field_old: vec![0.0; area],
 			color_image: Image::default(),
+			emissive_palette: Vec::new(),
 			rand_engine: create_rand_engine(),
 			update_step: 0,
 			prev_update_time_s: 0.0,
@@ -229,7 +232,13 @@ impl GenerativeTextureEffect for GenerativeTextureEffectWater
 	{
 		let base_size = 1 << (self.water_effect.resolution_log2[0] + self.water_effect.resolution_log2[1]);
 		// Count result texture and wave field buffers.
-		base_size * 2
+		let mut s = base_size * 2;
+		if self.water_effect.emissive.is_some()
+		{
+			// Count emisive texels as half-texels.
+			s += base_size / 2;
+		}
+		s
 	}
 
 	fn update(
@@ -286,6 +295,7 @@ impl GenerativeTextureEffect for GenerativeTextureEffectWater
 			&mut out_texture_data.texture[0],
 			last_mip_texel_color,
 			texture_data.material.roughness.max(MIN_VALID_ROUGHNESS).min(1.0),
+			self.water_effect.color_modulation_scale,
 			&self.color_image.pixels,
 		);
 
@@ -298,6 +308,31 @@ impl GenerativeTextureEffect for GenerativeTextureEffectWater
 			let (s0, s1) = out_texture_data.texture.split_at_mut(i);
 			build_texture_mip(&mut s1[0], &s0[i - 1]);
 		}
+
+		if let Some(emissive) = &self.water_effect.emissive
+		{
+			if self.emissive_palette.is_empty()
+			{
+				self.emissive_palette =
+					make_gradient_palette(emissive.color, texture_data.emissive_texture.as_ref().map(|t| &t[0]))
+						.to_vec();
+			}
+
+			make_wavy_emissive_texture(
+				size,
+				&self.wave_field,
+				&self.emissive_palette,
+				emissive.height_min,
+				emissive.height_max,
+				&mut out_texture_data.emissive_texture[0],
+			);
+
+			for i in 1 .. NUM_MIPS
+			{
+				let (s0, s1) = out_texture_data.emissive_texture.split_at_mut(i);
+				build_texture_lite_mip(&mut s1[0], &s0[i - 1]);
+			}
+		}
 	}
 }
 
@@ -424,6 +459,7 @@ fn make_wavy_texture(
 	out_texture: &mut Texture,
 	base_color: Color32,
 	roughness: f32,
+	color_modulation_scale: f32,
 	color_image_pixels: &[Color32],
 )
 {
@@ -464,6 +500,7 @@ fn make_wavy_texture(
 				wave_field,
 				out_texture,
 				roughness,
+				color_modulation_scale,
 				color_image_pixels,
 			)
 		},
@@ -474,6 +511,7 @@ fn make_wavy_texture(
 				wave_field,
 				out_texture,
 				roughness,
+				color_modulation_scale,
 				color_image_pixels,
 			)
 		},
@@ -484,6 +522,29 @@ fn make_wavy_texture(
 				wave_field,
 				out_texture,
 				roughness,
+				color_modulation_scale,
+				color_image_pixels,
+			)
+		},
+		ColorTextureApplyMode::SourceTextureModulatedByHeight =>
+		{
+			make_wavy_texture_impl::<WAVY_TEXTURE_COLOR_MODE_SOURCE_TEXTURE_MODULATED_BY_HEIGHT>(
+				size,
+				wave_field,
+				out_texture,
+				roughness,
+				color_modulation_scale,
+				color_image_pixels,
+			)
+		},
+		ColorTextureApplyMode::SourceTextureModulatedBySlope =>
+		{
+			make_wavy_texture_impl::<WAVY_TEXTURE_COLOR_MODE_SOURCE_TEXTURE_MODULATED_BY_SLOPE>(
+				size,
+				wave_field,
+				out_texture,
+				roughness,
+				color_modulation_scale,
 				color_image_pixels,
 			)
 		},
@@ -493,12 +554,15 @@ fn make_wavy_texture(
 const WAVY_TEXTURE_COLOR_MODE_NONE: u32 = 0;
 const WAVY_TEXTURE_COLOR_MODE_SOURCE_TEXTURE_NORMAL_DEFORMED: u32 = 1;
 const WAVY_TEXTURE_COLOR_MODE_SOURCE_TEXTURE_NORMAL_DEFORMED_X: u32 = 2;
+const WAVY_TEXTURE_COLOR_MODE_SOURCE_TEXTURE_MODULATED_BY_HEIGHT: u32 = 3;
+const WAVY_TEXTURE_COLOR_MODE_SOURCE_TEXTURE_MODULATED_BY_SLOPE: u32 = 4;
 
 fn make_wavy_texture_impl<const COLOR_MODE: u32>(
 	size: [u32; 2],
 	wave_field: &[WaveFieldElement],
 	out_texture: &mut Texture,
 	roughness: f32,
+	color_modulation_scale: f32,
 	color_image_pixels: &[Color32],
 )
 {
@@ -548,6 +612,20 @@ fn make_wavy_texture_impl<const COLOR_MODE: u32>(
 				let u = (((x as i32) + du) as u32) & size_mask[0];
 				out_texel.diffuse = debug_only_checked_fetch(color_image_pixels, (u + y * size[0]) as usize)
 			},
+			WAVY_TEXTURE_COLOR_MODE_SOURCE_TEXTURE_MODULATED_BY_HEIGHT =>
+			{
+				let val = debug_only_checked_fetch(wave_field, offset as usize);
+				let scale = f32_mul_add(val, color_modulation_scale, 1.0).max(0.0);
+				let src_color = debug_only_checked_fetch(color_image_pixels, offset as usize);
+				out_texel.diffuse = ColorVec::scalar_mul(&ColorVec::from_color32(src_color), scale).into();
+			},
+			WAVY_TEXTURE_COLOR_MODE_SOURCE_TEXTURE_MODULATED_BY_SLOPE =>
+			{
+				let slope = (dx * dx + dy * dy).sqrt();
+				let scale = f32_mul_add(-slope, color_modulation_scale, 1.0).max(0.0);
+				let src_color = debug_only_checked_fetch(color_image_pixels, offset as usize);
+				out_texel.diffuse = ColorVec::scalar_mul(&ColorVec::from_color32(src_color), scale).into();
+			},
 			_ =>
 			{},
 		};
@@ -681,6 +759,26 @@ fn make_wavy_texture_impl<const COLOR_MODE: u32>(
 	}
 }
 
+fn make_wavy_emissive_texture(
+	size: [u32; 2],
+	wave_field: &[WaveFieldElement],
+	palette: &[Color32],
+	height_min: f32,
+	height_max: f32,
+	texture: &mut TextureLite,
+)
+{
+	texture.size = size;
+	texture.pixels.resize((size[0] * size[1]) as usize, Color32::black());
+
+	let scale = 255.0 / (height_max - height_min).max(MIN_POSITIVE_VALUE);
+	for (dst_texel, &val) in texture.pixels.iter_mut().zip(wave_field)
+	{
+		let index = ((val - height_min) * scale).clamp(0.0, 255.0) as usize;
+		*dst_texel = unsafe { debug_only_checked_fetch(palette, index) };
+	}
+}
+
 fn extract_color_image_from_texture(texture: &Texture) -> Image
 {
 	Image {
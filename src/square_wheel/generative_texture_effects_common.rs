@@ -1,3 +1,5 @@
+use super::textures::*;
+use crate::common::color::*;
 pub use rand::{Rng, RngCore, SeedableRng};
 
 // Use simple and fast random engine for texture effects.
@@ -8,3 +10,40 @@ pub fn create_rand_engine() -> RandEngine
 	// Initialize random engine generator with good, but deterministic value.
 	RandEngine::seed_from_u64(0b1001100000111010100101010101010111000111010110100101111001010101)
 }
+
+pub type Palette = [Color32; 256];
+
+// Create gradient palette - from black to given color or from given texture (assuming it contains gradient along U axis).
+// Create grayscale palette if neither color nor non-empty texture is specified.
+pub fn make_gradient_palette(color: Option<[f32; 3]>, gradient_texture: Option<&TextureLite>) -> Palette
+{
+	let mut palette = [Color32::black(); 256];
+	if let Some(color) = color
+	{
+		for (i, dst) in palette.iter_mut().enumerate()
+		{
+			let scale = i as f32;
+			*dst = Color32::from_rgb(
+				(color[0] * scale).clamp(0.0, 255.0) as u8,
+				(color[1] * scale).clamp(0.0, 255.0) as u8,
+				(color[2] * scale).clamp(0.0, 255.0) as u8,
+			);
+		}
+	}
+	else if let Some(texture) = gradient_texture.filter(|t| !t.pixels.is_empty())
+	{
+		for (i, dst) in palette.iter_mut().enumerate()
+		{
+			*dst = texture.pixels[(i * (texture.size[0] as usize)) >> 8];
+		}
+	}
+	else
+	{
+		for (i, dst) in palette.iter_mut().enumerate()
+		{
+			*dst = Color32::from_rgb(i as u8, i as u8, i as u8);
+		}
+	}
+
+	palette
+}
@@ -26,6 +26,10 @@ pub struct Host
 	prev_frame_end_time: std::time::Instant,
 	fps_counter: TicksCounter,
 	frame_duration_counter: PerformanceCounter,
+	// Current scale, used for rendering (may be changed by dynamic resolution).
+	frame_scale: f32,
+	// Smoothed frame duration, used for dynamic resolution.
+	dynamic_resolution_frame_time_s: f32,
 	game_perfromance_counter: PerformanceCounter,
 	quit_requested: bool,
 	screenshot_requested: bool,
@@ -111,7 +115,7 @@ impl Host
 			commands_processor: commands_processor.clone(),
 			console: console.clone(),
 			window: system_window::SystemWindow::new(),
-			postprocessor: Postprocessor::new(app_config.clone()),
+			postprocessor: Postprocessor::new(app_config.clone(), resources_manager.clone()),
 			frame_upscaler: FrameUpscaler::new(),
 			resources_manager: resources_manager.clone(),
 			game: game_creation_function(app_config, commands_processor, console, resources_manager),
@@ -120,6 +124,8 @@ impl Host
 			prev_frame_end_time: cur_time,
 			fps_counter: TicksCounter::new(),
 			frame_duration_counter: PerformanceCounter::new(200),
+			frame_scale: 1.0,
+			dynamic_resolution_frame_time_s: 0.0,
 			game_perfromance_counter: PerformanceCounter::new(200),
 			quit_requested: false,
 			screenshot_requested: false,
@@ -233,14 +239,34 @@ impl Host
 			config_is_dirty = true;
 		}
 
-		if self.config.frame_scale > MAX_FRAME_SCALE as u32
+		if self.config.frame_scale > MAX_FRAME_SCALE as f32
 		{
-			self.config.frame_scale = MAX_FRAME_SCALE as u32;
+			self.config.frame_scale = MAX_FRAME_SCALE as f32;
 			config_is_dirty = true;
 		}
-		if self.config.frame_scale < 1
+		if self.config.frame_scale < 1.0
 		{
-			self.config.frame_scale = 1;
+			self.config.frame_scale = 1.0;
+			config_is_dirty = true;
+		}
+		if self.config.frame_upscale_filter > 2
+		{
+			self.config.frame_upscale_filter = 0;
+			config_is_dirty = true;
+		}
+		if self.config.dynamic_resolution_target_frame_time_ms < 1.0
+		{
+			self.config.dynamic_resolution_target_frame_time_ms = 1.0;
+			config_is_dirty = true;
+		}
+		if self.config.dynamic_resolution_max_scale > MAX_FRAME_SCALE as f32
+		{
+			self.config.dynamic_resolution_max_scale = MAX_FRAME_SCALE as f32;
+			config_is_dirty = true;
+		}
+		if self.config.dynamic_resolution_max_scale < self.config.frame_scale
+		{
+			self.config.dynamic_resolution_max_scale = self.config.frame_scale;
 			config_is_dirty = true;
 		}
 
@@ -283,6 +309,8 @@ impl Host
 
 		self.frame_duration_counter.add_value(time_delta_s);
 
+		self.update_frame_scale(time_delta_s);
+
 		let parallel_swap_buffers = self.config.parallel_swap_buffers;
 
 		let window = &mut self.window;
@@ -293,8 +321,14 @@ impl Host
 		let console = self.console.clone();
 		let fps_counter = &mut self.fps_counter;
 		let max_fps = self.config.max_fps;
-		let frame_scale = self.config.frame_scale;
-		let frame_resize_interpolate = self.config.frame_resize_interpolate;
+		let frame_scale = self.frame_scale;
+		let frame_upscale_filter = match self.config.frame_upscale_filter
+		{
+			1 => FrameUpscaleFilter::Bilinear,
+			2 => FrameUpscaleFilter::EdgeAware,
+			_ => FrameUpscaleFilter::Nearest,
+		};
+		let dynamic_resolution = self.config.dynamic_resolution;
 		let frame_duration_counter = &self.frame_duration_counter;
 		let prev_frame_end_time = &mut self.prev_frame_end_time;
 		let game_perfromance_counter = &mut self.game_perfromance_counter;
@@ -320,12 +354,12 @@ impl Host
 			}
 
 			// Perform rendering frame preparation.
-			if frame_scale > 1
+			if frame_scale > 1.0
 			{
 				// In case of scaled buffer perform frame preparation, rendering and postprocessing now.
 
 				let (pixels_scaled, surface_info_scaled) =
-					frame_upscaler.get_draw_buffer(&surface_info_initial, frame_scale as usize);
+					frame_upscaler.get_draw_buffer(&surface_info_initial, frame_scale);
 
 				// Get frame info from game code.
 				frame_info = game.get_frame_info(&surface_info_scaled);
@@ -356,7 +390,7 @@ impl Host
 						postprocessor.perform_postprocessing(
 							pixels_scaled,
 							&surface_info_scaled,
-							&frame_info_ref.view.color_modulate,
+							&frame_info_ref.view,
 							time_delta_s,
 							&mut active_map.debug_stats_printer,
 						);
@@ -375,8 +409,6 @@ impl Host
 						);
 					}
 				}
-
-				game.draw_frame_overlay(pixels_scaled, &surface_info_scaled);
 			}
 			else
 			{
@@ -461,10 +493,10 @@ impl Host
 				return;
 			}
 
-			if frame_scale > 1
+			if frame_scale > 1.0
 			{
 				// In case of scaled buffer just perform upscaling into screen buffer.
-				frame_upscaler.perform_upscale(pixels, surface_info, frame_scale as usize, frame_resize_interpolate);
+				frame_upscaler.perform_upscale(pixels, surface_info, frame_scale, frame_upscale_filter);
 			}
 			else
 			{
@@ -476,14 +508,14 @@ impl Host
 						postprocessor.perform_postprocessing(
 							pixels,
 							surface_info,
-							&frame_info_ref.view.color_modulate,
+							&frame_info_ref.view,
 							time_delta_s,
 							&mut active_map.debug_stats_printer,
 						);
 					}
 					else
 					{
-						// No intermediate buffer or postprocessing - perform rendering directly into screen buffer.
+						// No intermediate buffer - perform rendering directly into screen buffer.
 						active_map.renderer.draw_frame(
 							pixels,
 							surface_info,
@@ -492,10 +524,22 @@ impl Host
 						);
 					}
 				}
+			}
 
-				game.draw_frame_overlay(pixels, surface_info);
+			// Apply LDR effects in screen resolution (after upscaling), in order to avoid smearing them.
+			if let (Some(active_map), Some(frame_info_ref)) = (active_map.as_mut(), frame_info.as_ref())
+			{
+				postprocessor.perform_ldr_postprocessing(
+					pixels,
+					surface_info,
+					&frame_info_ref.view,
+					active_map.renderer.get_screen_lens_flares(),
+					&mut active_map.debug_stats_printer,
+				);
 			}
 
+			game.draw_frame_overlay(pixels, surface_info);
+
 			if let Some(active_map) = active_map
 			{
 				if active_map.debug_stats_printer.show_debug_stats()
@@ -508,14 +552,19 @@ impl Host
 
 			if show_fps
 			{
+				let mut text = format!(
+					"fps {:04.2}\n{:04.2}ms",
+					fps_counter.get_frequency(),
+					frame_duration_counter.get_average_value() * 1000.0
+				);
+				if dynamic_resolution
+				{
+					text += &format!("\nscale {:01.3}", frame_scale);
+				}
 				text_printer::print(
 					pixels,
 					surface_info,
-					&format!(
-						"fps {:04.2}\n{:04.2}ms",
-						fps_counter.get_frequency(),
-						frame_duration_counter.get_average_value() * 1000.0
-					),
+					&text,
 					(surface_info.width - 96) as i32,
 					1,
 					Color32::from_rgb(255, 255, 255),
@@ -541,6 +590,46 @@ impl Host
 		!self.quit_requested
 	}
 
+	fn update_frame_scale(&mut self, time_delta_s: f32)
+	{
+		if !self.config.dynamic_resolution
+		{
+			self.frame_scale = self.config.frame_scale;
+			self.dynamic_resolution_frame_time_s = 0.0;
+			return;
+		}
+
+		let mut target_frame_time_s = self.config.dynamic_resolution_target_frame_time_ms * 0.001;
+		if self.config.max_fps > 0.0
+		{
+			// Measured frame time includes FPS limiting, so, it is not possible to reach frame time less than limit.
+			target_frame_time_s = target_frame_time_s.max(1.1 / self.config.max_fps);
+		}
+
+		// Smooth frame time in order to avoid reacting on single slow frames.
+		if self.dynamic_resolution_frame_time_s <= 0.0
+		{
+			self.dynamic_resolution_frame_time_s = target_frame_time_s;
+		}
+		self.dynamic_resolution_frame_time_s += (time_delta_s - self.dynamic_resolution_frame_time_s) * 0.1;
+
+		// Use hysteresis in order to avoid constant scale changes.
+		let ratio = self.dynamic_resolution_frame_time_s / target_frame_time_s;
+		if !(0.8 ..= 1.0).contains(&ratio)
+		{
+			// Rendering time is roughly proportional to number of pixels, which is inversely proportional to square of scale.
+			let desired_scale = self.frame_scale * ratio.sqrt();
+			// Change scale slowly, using small fixed steps, in order to make result temporally stable.
+			const SCALE_STEP: f32 = 1.0 / 16.0;
+			self.frame_scale += (desired_scale - self.frame_scale).clamp(-SCALE_STEP, SCALE_STEP);
+			self.frame_scale = (self.frame_scale / SCALE_STEP).round() * SCALE_STEP;
+		}
+
+		self.frame_scale = self
+			.frame_scale
+			.clamp(self.config.frame_scale, self.config.dynamic_resolution_max_scale);
+	}
+
 	fn command_map(&mut self, args: commands_queue::CommandArgs)
 	{
 		if args.is_empty()
@@ -22,18 +22,32 @@ pub struct HostConfig
 	#[serde(default)]
 	pub fullscreen_mode: u32,
 
+	// May be non-integer.
 	#[serde(default)]
-	pub frame_scale: u32,
+	pub frame_scale: f32,
 
+	// 0 - nearest, 1 - bilinear, 2 - edge-aware.
 	#[serde(default)]
-	pub frame_resize_interpolate: bool,
+	pub frame_upscale_filter: u32,
+
+	// If true - frame scale is adjusted in range [frame_scale; dynamic_resolution_max_scale] to reach target frame time.
+	#[serde(default)]
+	pub dynamic_resolution: bool,
+
+	#[serde(default = "default_dynamic_resolution_target_frame_time_ms")]
+	pub dynamic_resolution_target_frame_time_ms: f32,
+
+	#[serde(default = "default_dynamic_resolution_max_scale")]
+	pub dynamic_resolution_max_scale: f32,
 }
 
 impl HostConfig
 {
 	pub fn from_app_config(app_config: &config::ConfigSharedPtr) -> Self
 	{
-		serde_json::from_value(app_config.read().unwrap()["host"].clone()).unwrap_or_default()
+		let mut json = app_config.read().unwrap()["host"].clone();
+		migrate_old_keys(&mut json);
+		serde_json::from_value(json).unwrap_or_default()
 	}
 
 	pub fn update_app_config(&self, app_config: &config::ConfigSharedPtr)
@@ -45,11 +59,46 @@ impl HostConfig
 	}
 }
 
+// Convert values of keys from older versions into actual values.
+// Old keys are removed from config on its update.
+fn migrate_old_keys(json: &mut serde_json::Value)
+{
+	if let Some(obj) = json.as_object_mut()
+	{
+		// "frame_resize_interpolate" was replaced with "frame_upscale_filter".
+		if let Some(frame_resize_interpolate) = obj.remove("frame_resize_interpolate")
+		{
+			if !obj.contains_key("frame_upscale_filter")
+			{
+				let filter = if frame_resize_interpolate.as_bool().unwrap_or(false)
+				{
+					1
+				}
+				else
+				{
+					0
+				};
+				obj.insert("frame_upscale_filter".to_string(), serde_json::Value::from(filter));
+			}
+		}
+	}
+}
+
 fn max_fps_default() -> f32
 {
 	120.0
 }
 
+fn default_dynamic_resolution_target_frame_time_ms() -> f32
+{
+	1000.0 / 60.0
+}
+
+fn default_dynamic_resolution_max_scale() -> f32
+{
+	3.0
+}
+
 fn default_true() -> bool
 {
 	true
@@ -0,0 +1,402 @@
+use super::{fast_math::*, frame_info::*};
+use crate::common::{
+	bsp_map_compact, color::*, light_trace::*, map_lights::*, material::*, math_types::*, system_window,
+};
+use std::sync::Arc;
+
+// Lens flare, projected into screen.
+#[derive(Copy, Clone)]
+pub struct ScreenLensFlare
+{
+	// Position relative to screen size (in range [0; 1] for flares inside screen).
+	// Relative coordinates are used in order to draw flares in images with size different from rendering size.
+	pub position: [f32; 2],
+	// Color in range [0; 1].
+	pub color: [f32; 3],
+	// Radius of main glow relative to screen height.
+	pub radius: f32,
+	// Fraction of light, passed from light source towards camera. Zero for fully occluded light source.
+	pub visibility: f32,
+	// Sun flares are used as source for light shafts.
+	pub is_sun: bool,
+	// Scale for light shafts strength. Less than 1 for sun outside screen.
+	pub light_shafts_scale: f32,
+}
+
+// Builds list of screen lens flares for current frame - for map sun lights, lights from frame info.
+// Performs visibility test for each light source using map BSP tree.
+pub struct LensFlaresBuilder
+{
+	map: Arc<bsp_map_compact::BSPMap>,
+	opacity_table: MaterialsOpacityTable,
+	sky_flag_table: MaterialsSkyFlagTable,
+	sun_lights: Vec<SunLight>,
+	screen_lens_flares: Vec<ScreenLensFlare>,
+}
+
+impl LensFlaresBuilder
+{
+	pub fn new(map: Arc<bsp_map_compact::BSPMap>, materials: &MaterialsMap) -> Self
+	{
+		let map_bbox = bsp_map_compact::get_map_bbox(&map);
+		Self {
+			opacity_table: build_materials_opacity_table(&map, materials),
+			sky_flag_table: build_materials_sky_flag_table(&map, materials),
+			sun_lights: extract_sun_lights(&map, &map_bbox),
+			map,
+			screen_lens_flares: Vec::new(),
+		}
+	}
+
+	pub fn update(
+		&mut self,
+		surface_info: &system_window::SurfaceInfo,
+		frame_info: &FrameInfo,
+		use_dynamic_lights: bool,
+	)
+	{
+		self.screen_lens_flares.clear();
+
+		let camera_matrices = &frame_info.view.camera_matrices;
+		let camera_position = camera_matrices.position;
+		let screen_size = [surface_info.width as f32, surface_info.height as f32];
+
+		for sun_light in &self.sun_lights
+		{
+			// Project direction, not position.
+			let projected = (camera_matrices.view_matrix * sun_light.dir.extend(0.0)).truncate();
+			if let Some(position) = get_screen_position_unclipped(&projected, &screen_size)
+			{
+				// Keep sun outside screen, since it is still needed for light shafts, which are faded out smoothly.
+				let distance_outside_screen = get_distance_outside_screen(&position);
+				let light_shafts_scale = 1.0 - distance_outside_screen / SUN_LIGHT_SHAFTS_FADE_DISTANCE;
+				if light_shafts_scale <= 0.0
+				{
+					continue;
+				}
+
+				// Add sun flare even if sun is occluded, since it is still needed for light shafts.
+				// Flare itself is not drawn for sun outside screen.
+				let visibility = if distance_outside_screen > 0.0
+				{
+					0.0
+				}
+				else
+				{
+					get_sun_shadow_factor(
+						&camera_position,
+						&sun_light.dir,
+						&self.map,
+						&self.opacity_table,
+						&self.sky_flag_table,
+					)
+				};
+
+				self.screen_lens_flares.push(ScreenLensFlare {
+					position,
+					color: normalize_flare_color(&sun_light.color),
+					radius: SUN_LENS_FLARE_RADIUS,
+					visibility,
+					is_sun: true,
+					light_shafts_scale,
+				});
+			}
+		}
+
+		let dynamic_lights_flares = frame_info
+			.world
+			.lights
+			.iter()
+			.filter(|_| use_dynamic_lights)
+			.map(|light| LensFlare {
+				position: light.position,
+				color: normalize_flare_color(&light.color),
+				radius: DYNAMIC_LIGHT_LENS_FLARE_RADIUS,
+			});
+
+		for lens_flare in frame_info
+			.world
+			.lens_flares
+			.iter()
+			.cloned()
+			.chain(dynamic_lights_flares)
+		{
+			if self.screen_lens_flares.len() >= MAX_LENS_FLARES
+			{
+				break;
+			}
+
+			let projected = (camera_matrices.view_matrix * lens_flare.position.extend(1.0)).truncate();
+			if let Some(position) = get_screen_position(&projected, &screen_size)
+			{
+				let visibility =
+					get_shadow_factor(&camera_position, &lens_flare.position, &self.map, &self.opacity_table);
+				// TODO - check also intersection with submodels and dynamic models?
+				if visibility > 0.0
+				{
+					self.screen_lens_flares.push(ScreenLensFlare {
+						position,
+						color: lens_flare.color,
+						radius: lens_flare.radius,
+						visibility,
+						is_sun: false,
+						light_shafts_scale: 0.0,
+					});
+				}
+			}
+		}
+	}
+
+	pub fn get_screen_lens_flares(&self) -> &[ScreenLensFlare]
+	{
+		&self.screen_lens_flares
+	}
+}
+
+// Sprites, used for drawing of flare chains.
+pub struct LensFlareSprites
+{
+	sprites: [LensFlareSprite; 3],
+}
+
+impl LensFlareSprites
+{
+	pub fn new() -> Self
+	{
+		Self {
+			sprites: [
+				LensFlareSprite::new(|r| (1.0 - r).max(0.0).powi(3)),
+				LensFlareSprite::new(|r| (1.0 - (r - 0.85).abs() * 8.0).max(0.0)),
+				LensFlareSprite::new(|r| ((1.0 - r) * 6.0).clamp(0.0, 1.0) * (0.6 + 0.4 * r)),
+			],
+		}
+	}
+}
+
+impl Default for LensFlareSprites
+{
+	fn default() -> Self
+	{
+		Self::new()
+	}
+}
+
+// Add flare chains of all flares to given line of image.
+pub fn draw_lens_flares_line(
+	line: &mut [Color32],
+	y: usize,
+	image_size: [usize; 2],
+	lens_flares: &[ScreenLensFlare],
+	sprites: &LensFlareSprites,
+	scale: f32,
+)
+{
+	let screen_center = [image_size[0] as f32 * 0.5, image_size[1] as f32 * 0.5];
+	let y_center = y as f32 + 0.5;
+
+	for lens_flare in lens_flares
+	{
+		if lens_flare.visibility <= 0.0
+		{
+			continue;
+		}
+
+		let position = [
+			lens_flare.position[0] * (image_size[0] as f32),
+			lens_flare.position[1] * (image_size[1] as f32),
+		];
+		let flare_radius = lens_flare.radius * (image_size[1] as f32);
+
+		let intensity = lens_flare.visibility * scale * 255.0;
+		for element in &LENS_FLARE_CHAIN
+		{
+			let center = [
+				screen_center[0] + (position[0] - screen_center[0]) * element.position,
+				screen_center[1] + (position[1] - screen_center[1]) * element.position,
+			];
+			let radius = flare_radius * element.radius;
+			let dy = y_center - center[1];
+			if radius <= 0.0 || dy.abs() >= radius
+			{
+				continue;
+			}
+
+			let x_start = ((center[0] - radius).floor().max(0.0) as usize).min(image_size[0]);
+			let x_end = ((center[0] + radius).ceil().max(0.0) as usize).min(image_size[0]);
+			if x_start >= x_end
+			{
+				continue;
+			}
+
+			let color = ColorVec::from_color_f32x3(&[
+				lens_flare.color[0] * element.color[0] * intensity,
+				lens_flare.color[1] * element.color[1] * intensity,
+				lens_flare.color[2] * element.color[2] * intensity,
+			]);
+
+			let sprite = &sprites.sprites[element.sprite as usize];
+			let sprite_scale = (LENS_FLARE_SPRITE_SIZE as f32) * 0.5 / radius;
+			let v = ((dy * sprite_scale) as i32 + (LENS_FLARE_SPRITE_SIZE as i32) / 2)
+				.clamp(0, LENS_FLARE_SPRITE_SIZE as i32 - 1) as usize;
+			let sprite_line = &sprite.data[v * LENS_FLARE_SPRITE_SIZE .. (v + 1) * LENS_FLARE_SPRITE_SIZE];
+
+			for (x, dst) in line[x_start .. x_end].iter_mut().enumerate()
+			{
+				let dx = (x + x_start) as f32 + 0.5 - center[0];
+				let u = ((dx * sprite_scale) as i32 + (LENS_FLARE_SPRITE_SIZE as i32) / 2)
+					.clamp(0, LENS_FLARE_SPRITE_SIZE as i32 - 1) as usize;
+				let sprite_value = unsafe { debug_only_checked_fetch(sprite_line, u) };
+				if sprite_value > 0.0
+				{
+					*dst = ColorVec::mul_scalar_add(&color, sprite_value, &ColorVec::from_color32(*dst)).into_color32();
+				}
+			}
+		}
+	}
+}
+
+#[derive(Copy, Clone)]
+enum LensFlareSpriteKind
+{
+	Glow,
+	Ring,
+	Ghost,
+}
+
+struct LensFlareChainElement
+{
+	// Position along line from screen center to light source. 1 - at light source, 0 - in screen center.
+	position: f32,
+	// Radius relative to flare radius.
+	radius: f32,
+	sprite: LensFlareSpriteKind,
+	color: [f32; 3],
+}
+
+const LENS_FLARE_CHAIN: [LensFlareChainElement; 7] = [
+	LensFlareChainElement {
+		position: 1.0,
+		radius: 1.0,
+		sprite: LensFlareSpriteKind::Glow,
+		color: [1.0, 1.0, 1.0],
+	},
+	LensFlareChainElement {
+		position: 1.0,
+		radius: 1.5,
+		sprite: LensFlareSpriteKind::Ring,
+		color: [0.15, 0.12, 0.1],
+	},
+	LensFlareChainElement {
+		position: 0.6,
+		radius: 0.2,
+		sprite: LensFlareSpriteKind::Ghost,
+		color: [0.1, 0.2, 0.35],
+	},
+	LensFlareChainElement {
+		position: 0.25,
+		radius: 0.12,
+		sprite: LensFlareSpriteKind::Ghost,
+		color: [0.3, 0.2, 0.1],
+	},
+	LensFlareChainElement {
+		position: -0.2,
+		radius: 0.35,
+		sprite: LensFlareSpriteKind::Ring,
+		color: [0.1, 0.25, 0.15],
+	},
+	LensFlareChainElement {
+		position: -0.5,
+		radius: 0.25,
+		sprite: LensFlareSpriteKind::Ghost,
+		color: [0.25, 0.1, 0.3],
+	},
+	LensFlareChainElement {
+		position: -0.9,
+		radius: 0.5,
+		sprite: LensFlareSpriteKind::Ghost,
+		color: [0.08, 0.12, 0.2],
+	},
+];
+
+struct LensFlareSprite
+{
+	// Intensity values.
+	data: Vec<f32>,
+}
+
+impl LensFlareSprite
+{
+	// Create radially-symmetric sprite using given function of distance to center (in range [0; 1]).
+	fn new<F: Fn(f32) -> f32>(func: F) -> Self
+	{
+		let half_size = (LENS_FLARE_SPRITE_SIZE as f32) * 0.5;
+		let mut data = Vec::with_capacity(LENS_FLARE_SPRITE_SIZE * LENS_FLARE_SPRITE_SIZE);
+		for y in 0 .. LENS_FLARE_SPRITE_SIZE
+		{
+			let dy = (y as f32 + 0.5 - half_size) / half_size;
+			for x in 0 .. LENS_FLARE_SPRITE_SIZE
+			{
+				let dx = (x as f32 + 0.5 - half_size) / half_size;
+				let r = (dx * dx + dy * dy).sqrt();
+				data.push(if r < 1.0 { func(r).max(0.0) } else { 0.0 });
+			}
+		}
+
+		Self { data }
+	}
+}
+
+const LENS_FLARE_SPRITE_SIZE: usize = 64;
+
+// Radius (relative to screen height) of sun flare.
+const SUN_LENS_FLARE_RADIUS: f32 = 0.25;
+// Radius (relative to screen height) of dynamic light flare.
+const DYNAMIC_LIGHT_LENS_FLARE_RADIUS: f32 = 0.08;
+
+// Distance (relative to screen size) outside screen, at which light shafts of sun fade out completely.
+const SUN_LIGHT_SHAFTS_FADE_DISTANCE: f32 = 0.5;
+
+const MAX_LENS_FLARES: usize = 64;
+
+// Returns position if projected point is in front of camera and inside screen.
+fn get_screen_position(projected: &Vec3f, screen_size: &[f32; 2]) -> Option<[f32; 2]>
+{
+	get_screen_position_unclipped(projected, screen_size).filter(|p| get_distance_outside_screen(p) <= 0.0)
+}
+
+// Returns relative position if projected point is in front of camera.
+fn get_screen_position_unclipped(projected: &Vec3f, screen_size: &[f32; 2]) -> Option<[f32; 2]>
+{
+	if projected.z <= 0.0
+	{
+		return None;
+	}
+
+	Some([
+		projected.x / (projected.z * screen_size[0]),
+		projected.y / (projected.z * screen_size[1]),
+	])
+}
+
+// Returns zero for points inside screen.
+fn get_distance_outside_screen(position: &[f32; 2]) -> f32
+{
+	let dx = (-position[0]).max(position[0] - 1.0).max(0.0);
+	let dy = (-position[1]).max(position[1] - 1.0).max(0.0);
+	(dx * dx + dy * dy).sqrt()
+}
+
+// Scale color in order to make maximum component equal to 1.
+fn normalize_flare_color(color: &[f32; 3]) -> [f32; 3]
+{
+	let max_component = color[0].max(color[1]).max(color[2]);
+	if max_component <= 0.0
+	{
+		return [0.0; 3];
+	}
+	[
+		color[0] / max_component,
+		color[1] / max_component,
+		color[2] / max_component,
+	]
+}
@@ -0,0 +1,338 @@
+use super::{
+	frame_info::*, map_materials_processor::*, map_materials_processor_structs::*, resources_manager::*, textures::*,
+};
+use crate::common::material::*;
+use rayon::prelude::*;
+use std::collections::HashMap;
+
+// Processor for animated lite textures, used by models, sprites and decals.
+// Lite texture is animated if it was obtained via "get_material_texture_lite" for material with framed animation or generative effect.
+// Textures are updated once per frame and shared across all renderers.
+// Only textures used in current frame are updated.
+pub struct LiteTexturesProcessor
+{
+	resources_manager: ResourcesManagerSharedPtr,
+	current_frame: u64,
+	// Use lite texture pointer as key. Contains only textures used in current frame.
+	textures_map: HashMap<usize, LiteTextureMapEntry>,
+	// All loaded materials - animated textures, their animation frames and layers.
+	textures: Vec<MapTextureData>,
+	textures_internal: Vec<LiteTextureDataInternal>,
+	textures_mapping_table: Vec<TextureMappingElement>,
+	material_name_to_texture_index: HashMap<String, TextureIndex>,
+}
+
+struct LiteTextureMapEntry
+{
+	// Hold texture in order to prevent reusing of its address by another texture.
+	_texture: SharedResourcePtr<TextureLiteWithMips>,
+	// None for static textures.
+	texture_index: Option<TextureIndex>,
+	last_used_frame: u64,
+}
+
+struct LiteTextureDataInternal
+{
+	// Lite texture of material itself.
+	lite_texture: SharedResourcePtr<TextureLiteWithMips>,
+	// Invalid index if has no framed animation.
+	next_frame_texture_index: TextureIndex,
+	// Effect instance itself (if exists).
+	generative_effect: OptDynGenerativeTextureEffect,
+	// Data for generative texture effects.
+	generative_texture_data: GenerativeTextureData,
+	// Result of generative effect, converted into lite texture. Empty if has no generative effect.
+	generated_lite_texture: TextureLiteWithMips,
+	last_used_frame: u64,
+}
+
+impl LiteTexturesProcessor
+{
+	pub fn new(resources_manager: ResourcesManagerSharedPtr) -> Self
+	{
+		Self {
+			resources_manager,
+			current_frame: 0,
+			textures_map: HashMap::new(),
+			textures: Vec::new(),
+			textures_internal: Vec::new(),
+			textures_mapping_table: Vec::new(),
+			material_name_to_texture_index: HashMap::new(),
+		}
+	}
+
+	// Models list is passed separately, since it includes also map detail objects.
+	pub fn update(&mut self, frame_info: &FrameWorldInfo, model_entities: &[ModelEntity])
+	{
+		self.current_frame += 1;
+
+		for model in model_entities
+		{
+			self.use_texture(&model.texture);
+		}
+		for sprite in &frame_info.sprites
+		{
+			self.use_texture(&sprite.texture);
+		}
+		for decal in &frame_info.decals
+		{
+			self.use_texture(&decal.texture);
+		}
+
+		// Remove entries for textures that are not used anymore, in order to free them.
+		let current_frame = self.current_frame;
+		self.textures_map
+			.retain(|_k, entry| entry.last_used_frame == current_frame);
+
+		self.update_framed_animations(frame_info.game_time_s);
+		self.mark_used_animated_textures();
+		self.update_animations(frame_info.game_time_s);
+	}
+
+	// Get current frame of given texture (or texture itself, if it is not animated).
+	pub fn get_texture<'a>(&'a self, texture: &'a SharedResourcePtr<TextureLiteWithMips>) -> &'a TextureLiteWithMips
+	{
+		if let Some(LiteTextureMapEntry {
+			texture_index: Some(texture_index),
+			..
+		}) = self.textures_map.get(&get_texture_key(texture))
+		{
+			let current_texture_index = self.textures_mapping_table[*texture_index as usize].index as usize;
+			let texture_data_internal = &self.textures_internal[current_texture_index];
+			if !texture_data_internal.generated_lite_texture[0].pixels.is_empty()
+			{
+				&texture_data_internal.generated_lite_texture
+			}
+			else
+			{
+				&texture_data_internal.lite_texture
+			}
+		}
+		else
+		{
+			texture
+		}
+	}
+
+	fn use_texture(&mut self, texture: &SharedResourcePtr<TextureLiteWithMips>)
+	{
+		let key = get_texture_key(texture);
+		if let Some(entry) = self.textures_map.get_mut(&key)
+		{
+			entry.last_used_frame = self.current_frame;
+			return;
+		}
+
+		let animated_material_name = {
+			let mut r = self.resources_manager.lock().unwrap();
+			let materials = r.get_materials();
+			r.get_material_texture_lite_name(texture).and_then(|name| {
+				materials.get(name).and_then(|material| {
+					if material_is_animated(material)
+					{
+						Some(name.to_string())
+					}
+					else
+					{
+						None
+					}
+				})
+			})
+		};
+
+		let texture_index = animated_material_name.map(|name| self.load_material(&name));
+
+		self.textures_map.insert(
+			key,
+			LiteTextureMapEntry {
+				_texture: texture.clone(),
+				texture_index,
+				last_used_frame: self.current_frame,
+			},
+		);
+	}
+
+	fn load_material(&mut self, material_name: &str) -> TextureIndex
+	{
+		if let Some(texture_index) = self.material_name_to_texture_index.get(material_name)
+		{
+			return *texture_index;
+		}
+
+		let texture_index = self.textures.len() as TextureIndex;
+		self.material_name_to_texture_index
+			.insert(material_name.to_string(), texture_index);
+
+		let (material, texture, emissive_texture, lite_texture) = {
+			let mut r = self.resources_manager.lock().unwrap();
+			// Resources manager prints message about missing material.
+			let texture = r.get_material_texture(material_name);
+			let lite_texture = r.get_material_texture_lite(material_name);
+			let material = r.get_materials().get(material_name).cloned().unwrap_or_default();
+			let emissive_texture = material.emissive_layer.as_ref().map(|l| r.get_texture_lite(&l.image));
+			(material, texture, emissive_texture, lite_texture)
+		};
+
+		let framed_animation = material.framed_animation.clone();
+		let special_effect = material.special_effect.clone();
+
+		self.textures.push(MapTextureData {
+			material,
+			texture,
+			emissive_texture,
+		});
+		self.textures_internal.push(LiteTextureDataInternal {
+			lite_texture,
+			next_frame_texture_index: !0, // Set later.
+			generative_effect: None,      // Set later.
+			generative_texture_data: GenerativeTextureData::default(),
+			generated_lite_texture: TextureLiteWithMips::default(),
+			last_used_frame: 0,
+		});
+		// Initially texture is mapped to itself.
+		self.textures_mapping_table.push(TextureMappingElement {
+			index: texture_index,
+			frame_change_time_point: 0.0,
+		});
+
+		// Load animation frames and materials of generative effects after insertion of this material.
+		// This is needed in order to handle animation loops.
+		let next_frame_texture_index = framed_animation
+			.map(|a| self.load_material(&a.next_material_name))
+			.unwrap_or(!0);
+		let generative_effect =
+			create_generative_texture_effect(special_effect, &mut |name: &str| self.load_material(name));
+
+		let texture_data_internal = &mut self.textures_internal[texture_index as usize];
+		texture_data_internal.next_frame_texture_index = next_frame_texture_index;
+		texture_data_internal.generative_effect = generative_effect;
+
+		texture_index
+	}
+
+	fn update_framed_animations(&mut self, current_time_s: f32)
+	{
+		// Assume time never goes backwards.
+		for mapping_element in &mut self.textures_mapping_table
+		{
+			let next_frame_texture_index =
+				self.textures_internal[mapping_element.index as usize].next_frame_texture_index;
+			if (next_frame_texture_index as usize) < self.textures.len() &&
+				current_time_s >= mapping_element.frame_change_time_point
+			{
+				mapping_element.index = next_frame_texture_index;
+
+				// Use duration of current frame for calculation of next frame change time point.
+				let duration = self.textures[next_frame_texture_index as usize]
+					.material
+					.framed_animation
+					.as_ref()
+					.map(|a| a.duration)
+					.unwrap_or(0.5);
+
+				// Make sure change time point is not less, than current time.
+				mapping_element.frame_change_time_point =
+					(mapping_element.frame_change_time_point + duration).max(current_time_s);
+			}
+		}
+	}
+
+	// Mark current frames of used textures, in order to update only them.
+	fn mark_used_animated_textures(&mut self)
+	{
+		for entry in self.textures_map.values()
+		{
+			if let Some(texture_index) = entry.texture_index
+			{
+				let current_texture_index = self.textures_mapping_table[texture_index as usize].index;
+				self.textures_internal[current_texture_index as usize].last_used_frame = self.current_frame;
+			}
+		}
+	}
+
+	fn update_animations(&mut self, current_time_s: f32)
+	{
+		let current_frame = self.current_frame;
+		let textures = &self.textures;
+		let textures_mapping_table = &self.textures_mapping_table;
+
+		let animate_func = |(texture_data_internal, texture_data): (&mut LiteTextureDataInternal, &MapTextureData)| {
+			if texture_data_internal.last_used_frame != current_frame
+			{
+				return;
+			}
+			if let Some(generative_effect) = &mut texture_data_internal.generative_effect
+			{
+				generative_effect.update(
+					&mut texture_data_internal.generative_texture_data,
+					texture_data,
+					textures,
+					textures_mapping_table,
+					current_time_s,
+				);
+
+				convert_generative_texture_data_into_lite_texture(
+					&texture_data_internal.generative_texture_data,
+					&mut texture_data_internal.generated_lite_texture,
+				);
+			}
+		};
+
+		// Perform animation in parallel (if has enough threads).
+		if rayon::current_num_threads() == 1
+		{
+			self.textures_internal.iter_mut().zip(textures).for_each(animate_func);
+		}
+		else
+		{
+			self.textures_internal
+				.par_iter_mut()
+				.zip_eq(textures)
+				.for_each(animate_func);
+		}
+	}
+}
+
+fn material_is_animated(material: &Material) -> bool
+{
+	material.framed_animation.is_some() ||
+		!matches!(
+			material.special_effect,
+			SpecialMaterialEffect::None | SpecialMaterialEffect::Skybox(..)
+		)
+}
+
+// Extract color from generated texture.
+// If effect produces only emissive texture (fire without diffuse output, for example) - use it as color.
+fn convert_generative_texture_data_into_lite_texture(
+	generative_texture_data: &GenerativeTextureData,
+	out_texture: &mut TextureLiteWithMips,
+)
+{
+	if !generative_texture_data.texture[0].pixels.is_empty()
+	{
+		for (src_mip, dst_mip) in generative_texture_data.texture.iter().zip(out_texture.iter_mut())
+		{
+			dst_mip.size = src_mip.size;
+			dst_mip.pixels.clear();
+			dst_mip.pixels.extend(src_mip.pixels.iter().map(|t| t.diffuse));
+		}
+	}
+	else if !generative_texture_data.emissive_texture[0].pixels.is_empty()
+	{
+		for (src_mip, dst_mip) in generative_texture_data
+			.emissive_texture
+			.iter()
+			.zip(out_texture.iter_mut())
+		{
+			dst_mip.size = src_mip.size;
+			dst_mip.pixels.clear();
+			dst_mip.pixels.extend_from_slice(&src_mip.pixels);
+		}
+	}
+}
+
+fn get_texture_key(texture: &SharedResourcePtr<TextureLiteWithMips>) -> usize
+{
+	SharedResourcePtr::as_ptr(texture) as *const TextureLite as usize
+}
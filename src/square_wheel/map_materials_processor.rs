@@ -1,5 +1,6 @@
 use super::{
 	abstract_color::*, config, generative_texture_effect_fire::*, generative_texture_effect_layered::*,
+	generative_texture_effect_procedural::*, generative_texture_effect_refraction::*,
 	generative_texture_effect_turb::*, generative_texture_effect_water::*, map_materials_processor_config::*,
 	map_materials_processor_structs::*, resources_manager::*, textures::*,
 };
@@ -482,7 +483,7 @@ struct MapTextureDataInternal
 const ANIMATIONS_UPDATE_PERIOD_MIN: u32 = 1;
 const ANIMATIONS_UPDATE_PERIOD_MAX: u32 = 16;
 
-fn create_generative_texture_effect<MaterialLoadFunction: FnMut(&str) -> TextureIndex>(
+pub fn create_generative_texture_effect<MaterialLoadFunction: FnMut(&str) -> TextureIndex>(
 	special_effect: SpecialMaterialEffect,
 	material_load_function: &mut MaterialLoadFunction,
 ) -> OptDynGenerativeTextureEffect
@@ -496,6 +497,14 @@ fn create_generative_texture_effect<MaterialLoadFunction: FnMut(&str) -> Texture
 		)),
 		SpecialMaterialEffect::Water(water_effect) => Some(Box::new(GenerativeTextureEffectWater::new(water_effect))),
 		SpecialMaterialEffect::Fire(fire_effect) => Some(Box::new(GenerativeTextureEffectFire::new(fire_effect))),
+		SpecialMaterialEffect::Refraction(refraction_effect) => Some(Box::new(GenerativeTextureEffectRefraction::new(
+			refraction_effect,
+			material_load_function,
+		))),
+		SpecialMaterialEffect::Procedural(procedural_effect) => Some(Box::new(GenerativeTextureEffectProcedural::new(
+			procedural_effect,
+			material_load_function,
+		))),
 		SpecialMaterialEffect::Skybox(..) => None,
 	}
 }
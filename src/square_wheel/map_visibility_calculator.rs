@@ -214,8 +214,7 @@ impl MapVisibilityCalculator
 					if portal_data.visible_frame != self.current_frame
 					{
 						portal_data.visible_frame = self.current_frame;
-						portal_data.current_frame_projection =
-							project_portal(portal_value, &self.map, &camera_matrices.view_matrix);
+						portal_data.current_frame_projection = project_portal(portal_value, &self.map, camera_matrices);
 					}
 
 					let mut bounds_intersection = if let Some(b) = portal_data.current_frame_projection
@@ -286,10 +285,14 @@ impl MapVisibilityCalculator
 fn project_portal(
 	portal: &bsp_map_compact::Portal,
 	map: &bsp_map_compact::BSPMap,
-	view_matrix: &Mat4f,
+	camera_matrices: &CameraMatrices,
 ) -> Option<ClippingPolygon>
 {
-	let mut vertex_count = std::cmp::min(portal.num_vertices as usize, MAX_VERTICES);
+	let vertex_count = std::cmp::min(portal.num_vertices as usize, MAX_VERTICES);
+	if vertex_count < 3
+	{
+		return None;
+	}
 
 	// Perform initial matrix tranformation, obtain 3d vertices in camera-aligned space.
 	let mut vertices_transformed = [Vec3f::zero(); MAX_VERTICES]; // TODO - use uninitialized memory
@@ -298,29 +301,296 @@ fn project_portal(
 		.iter()
 		.zip(vertices_transformed.iter_mut())
 	{
-		*out_vertex = view_matrix_transform_vertex(view_matrix, in_vertex);
+		*out_vertex = view_matrix_transform_vertex(&camera_matrices.view_matrix, in_vertex);
 	}
+	let vertices_transformed = &vertices_transformed[.. vertex_count];
+
+	let mut portal_polygon_bounds = None;
 
-	// Perform z_near clipping. Use very small z_near to avoid clipping portals.
+	// Perform z_near clipping and project part of the portal polygon, that lies in front of z_near plane.
+	// Use regular z_near here - projection of clipped vertices has reasonable magnitude and thus is precise enough.
 	let mut vertices_transformed_z_clipped = [Vec3f::zero(); MAX_VERTICES]; // TODO - use uninitialized memory
-	const Z_NEAR: f32 = 1.0 / 4096.0;
-	vertex_count = clip_3d_polygon_by_z_plane(
-		&vertices_transformed[.. vertex_count],
-		Z_NEAR,
-		&mut vertices_transformed_z_clipped,
-	);
-	if vertex_count < 3
+	let vertex_count_z_clipped =
+		clip_3d_polygon_by_z_plane(vertices_transformed, Z_NEAR, &mut vertices_transformed_z_clipped);
+	if vertex_count_z_clipped >= 3
 	{
-		return None;
+		for vertex_transformed in &vertices_transformed_z_clipped[.. vertex_count_z_clipped]
+		{
+			extend_portal_bounds(
+				&mut portal_polygon_bounds,
+				&(vertex_transformed.truncate() / vertex_transformed.z),
+			);
+		}
+	}
+
+	// Process part of the portal polygon between camera plane and z_near plane.
+	// It is not possible just to clip portal polygon by some very small z_near -
+	// intersection points calculation is too imprecise in such case and projected points may be totally wrong.
+	// Instead use the fact that projection of each line is a ray, starting at line point at z_near plane
+	// and directed towards projection of line point at camera plane (it has zero z, so it is just a direction).
+	// Extend bounds with starts of such rays and with points on these rays far enough outside any viewport.
+	const FAR_POINT_DISTANCE: f32 = (1 << 20) as f32;
+
+	// Ray directions lie on line of intersection of portal plane with camera plane.
+	// Calculate direction from camera to the closest point of this line.
+	// If rays directions are located on both sides of this direction, it is also necessary to add far point in this direction.
+	// Doing so we avoid wrong bounds for rays, that are almost opposite.
+	let plane_transformed = camera_matrices.planes_matrix * portal.plane.vec.extend(-portal.plane.dist);
+	let closest_direction = Vec2f::new(plane_transformed.x, plane_transformed.y) * (-plane_transformed.w);
+	let mut has_direction_on_side = [false, false];
+	let mut last_ray_start = None;
+
+	let mut prev_vertex = &vertices_transformed[vertex_count - 1];
+	for vertex in vertices_transformed
+	{
+		let (v_near, v_far) = if vertex.z <= prev_vertex.z
+		{
+			(vertex, prev_vertex)
+		}
+		else
+		{
+			(prev_vertex, vertex)
+		};
+		prev_vertex = vertex;
+
+		if v_near.z >= Z_NEAR || v_far.z <= 0.0
+		{
+			// This edge is fully in front of z_near plane (and is already processed) or is fully behind camera.
+			continue;
+		}
+
+		if v_near.z == v_far.z
+		{
+			// This edge is parallel to camera plane. Project it directly.
+			extend_portal_bounds(&mut portal_polygon_bounds, &(v_near.truncate() / v_near.z));
+			extend_portal_bounds(&mut portal_polygon_bounds, &(v_far.truncate() / v_far.z));
+			continue;
+		}
+
+		// Use line point at z_near plane even if whole edge is behind z_near plane.
+		// This produces slightly bigger bounds, but it is fine.
+		let ray_start = get_line_z_intersection(v_near, v_far, Z_NEAR).truncate() / Z_NEAR;
+		extend_portal_bounds(&mut portal_polygon_bounds, &ray_start);
+		last_ray_start = Some(ray_start);
+
+		let ray_direction = get_line_z_intersection(v_near, v_far, 0.0).truncate();
+		let ray_direction_len = ray_direction.magnitude();
+		if ray_direction_len <= 0.0
+		{
+			// Camera lies on this line. Can't determine projection direction.
+			continue;
+		}
+		extend_portal_bounds(
+			&mut portal_polygon_bounds,
+			&(ray_start + ray_direction * (FAR_POINT_DISTANCE / ray_direction_len)),
+		);
+
+		let side = closest_direction.perp_dot(ray_direction);
+		if side > 0.0
+		{
+			has_direction_on_side[0] = true;
+		}
+		else if side < 0.0
+		{
+			has_direction_on_side[1] = true;
+		}
+	}
+
+	if has_direction_on_side[0] && has_direction_on_side[1]
+	{
+		let closest_direction_len = closest_direction.magnitude();
+		if let (Some(ray_start), true) = (last_ray_start, closest_direction_len > 0.0)
+		{
+			// Any ray start is fine here.
+			extend_portal_bounds(
+				&mut portal_polygon_bounds,
+				&(ray_start + closest_direction * (FAR_POINT_DISTANCE / closest_direction_len)),
+			);
+		}
+	}
+
+	portal_polygon_bounds
+}
+
+fn extend_portal_bounds(bounds: &mut Option<ClippingPolygon>, point: &Vec2f)
+{
+	if let Some(b) = bounds
+	{
+		b.extend_with_point(point);
+	}
+	else
+	{
+		*bounds = Some(ClippingPolygon::from_point(point));
+	}
+}
+
+#[cfg(test)]
+mod tests
+{
+	use super::*;
+	use crate::common::plane::Plane;
+
+	const VIEWPORT_SIZE: f32 = 512.0;
+	const FOV: f32 = std::f32::consts::PI * 0.5;
+
+	// Build map with two leafs, separated by plane x = 0 and connected via single huge portal.
+	// Leaf 0 is in front of the plane, leaf 1 is behind it.
+	// Portal is big enough to produce imprecise results, when it is clipped by tiny z_near.
+	fn make_two_leafs_map() -> Arc<bsp_map_compact::BSPMap>
+	{
+		const PORTAL_HALF_SIZE: f32 = 65536.0;
+		let plane = Plane {
+			vec: Vec3f::unit_x(),
+			dist: 0.0,
+		};
+
+		let mut map = bsp_map_compact::BSPMap::default();
+		map.nodes.push(bsp_map_compact::BSPNode {
+			children: [bsp_map_compact::FIRST_LEAF_INDEX, bsp_map_compact::FIRST_LEAF_INDEX + 1],
+			plane,
+		});
+		for leaf_index in 0 .. 2
+		{
+			map.leafs.push(bsp_map_compact::BSPLeaf {
+				first_polygon: 0,
+				num_polygons: 0,
+				first_leaf_portal: leaf_index,
+				num_leaf_portals: 1,
+				first_leaf_detail_object: 0,
+				num_leaf_detail_objects: 0,
+			});
+			map.leafs_portals.push(0);
+		}
+		map.vertices = vec![
+			Vec3f::new(0.0, -PORTAL_HALF_SIZE, -PORTAL_HALF_SIZE),
+			Vec3f::new(0.0, PORTAL_HALF_SIZE, -PORTAL_HALF_SIZE),
+			Vec3f::new(0.0, PORTAL_HALF_SIZE, PORTAL_HALF_SIZE),
+			Vec3f::new(0.0, -PORTAL_HALF_SIZE, PORTAL_HALF_SIZE),
+		];
+		map.portals.push(bsp_map_compact::Portal {
+			leafs: [0, 1],
+			plane,
+			first_vertex: 0,
+			num_vertices: 4,
+		});
+
+		Arc::new(map)
+	}
+
+	fn get_camera_rotation(angle_deg: f32, elevation_deg: f32) -> QuaternionF
+	{
+		QuaternionF::from_angle_z(Rad(angle_deg.to_radians())) *
+			QuaternionF::from_angle_y(Rad(-elevation_deg.to_radians()))
+	}
+
+	// Minimum X component of normalized view frustum corner rays.
+	// View frustum is convex, so portal plane x = 0 is visible from camera in front of it if some corner ray has negative X.
+	fn get_view_frustum_min_x(rotation: QuaternionF) -> f32
+	{
+		let forward = rotation.rotate_vector(Vec3f::unit_x());
+		let left = rotation.rotate_vector(Vec3f::unit_y());
+		let up = rotation.rotate_vector(Vec3f::unit_z());
+		let half_fov_tan = (FOV * 0.5).tan();
+
+		let mut min_x = f32::MAX;
+		for left_scale in [-half_fov_tan, half_fov_tan]
+		{
+			for up_scale in [-half_fov_tan, half_fov_tan]
+			{
+				min_x = min_x.min((forward + left * left_scale + up * up_scale).normalize().x);
+			}
+		}
+		min_x
 	}
 
-	let mut portal_polygon_bounds = ClippingPolygon::from_point(
-		&(vertices_transformed_z_clipped[0].truncate() / vertices_transformed_z_clipped[0].z),
-	);
-	for vertex_transformed in &vertices_transformed_z_clipped[1 .. vertex_count]
+	fn is_leaf_behind_portal_visible(
+		map: &Arc<bsp_map_compact::BSPMap>,
+		camera_dist: f32,
+		rotation: QuaternionF,
+	) -> bool
 	{
-		portal_polygon_bounds.extend_with_point(&(vertex_transformed.truncate() / vertex_transformed.z));
+		let camera_matrices = build_view_matrix_with_full_rotation(
+			Vec3f::new(camera_dist, 0.0, 0.0),
+			rotation,
+			FOV,
+			VIEWPORT_SIZE,
+			VIEWPORT_SIZE,
+		);
+
+		let mut visibility_calculator = MapVisibilityCalculator::new(map.clone());
+		visibility_calculator.update_visibility(
+			&camera_matrices,
+			&ClippingPolygon::from_box(0.0, 0.0, VIEWPORT_SIZE, VIEWPORT_SIZE),
+		);
+		assert!(visibility_calculator.get_current_frame_leaf_bounds(0).is_some());
+
+		visibility_calculator.get_current_frame_leaf_bounds(1).is_some()
 	}
 
-	Some(portal_polygon_bounds)
+	#[test]
+	fn test_portal_crossing_z_near_plane()
+	{
+		// Camera is close to the portal, so portal crosses both z_near and camera planes for most view directions.
+		// Previously such portals were clipped by tiny z_near and projected totally wrong,
+		// which culled leaf behind them.
+		let map = make_two_leafs_map();
+		let mut num_checks = 0;
+		for camera_dist in [2.5, 3.0, 10.0]
+		{
+			for angle in 0 .. 72
+			{
+				for elevation in [-80.0, -60.0, -30.0, 0.0, 30.0, 60.0, 80.0]
+				{
+					let rotation = get_camera_rotation(angle as f32 * 5.0, elevation);
+					if get_view_frustum_min_x(rotation) > -0.1
+					{
+						// Portal is not visible or is barely visible.
+						continue;
+					}
+
+					num_checks += 1;
+					assert!(
+						is_leaf_behind_portal_visible(&map, camera_dist, rotation),
+						"camera dist {}, angle {}, elevation {}",
+						camera_dist,
+						angle * 5,
+						elevation
+					);
+				}
+			}
+		}
+		assert!(num_checks > 0);
+	}
+
+	#[test]
+	fn test_portal_outside_view()
+	{
+		// Portal is fully behind camera plane or outside view frustum.
+		let map = make_two_leafs_map();
+		for angle in [-30.0, -15.0, 0.0, 15.0, 30.0]
+		{
+			let rotation = get_camera_rotation(angle, 0.0);
+			assert!(get_view_frustum_min_x(rotation) > 0.0);
+			assert!(!is_leaf_behind_portal_visible(&map, 3.0, rotation), "angle {}", angle);
+		}
+	}
+
+	#[test]
+	fn test_portal_projection_clipped_by_z_near()
+	{
+		// Camera looks along portal plane.
+		// Projection must cover whole viewport half, where portal is visible.
+		let map = make_two_leafs_map();
+		let camera_matrices = build_view_matrix_with_full_rotation(
+			Vec3f::new(3.0, 0.0, 0.0),
+			get_camera_rotation(90.0, 0.0),
+			FOV,
+			VIEWPORT_SIZE,
+			VIEWPORT_SIZE,
+		);
+
+		let projection = project_portal(&map.portals[0], &map, &camera_matrices).unwrap();
+		let half_viewport = ClippingPolygon::from_box(0.0, 0.0, VIEWPORT_SIZE * 0.5 - 16.0, VIEWPORT_SIZE);
+		assert!(projection.contains(&half_viewport));
+	}
 }
@@ -1,6 +1,7 @@
 #![cfg_attr(feature = "rasterizer_unchecked_div", feature(core_intrinsics))]
 
 pub mod abstract_color;
+pub mod color_grading_lut;
 pub mod commands_processor;
 pub mod commands_queue;
 pub mod config;
@@ -11,19 +12,24 @@ pub mod draw_ordering;
 pub mod dynamic_objects_index;
 pub mod equations;
 pub mod fast_math;
+pub mod fog;
 pub mod frame_info;
 pub mod frame_number;
 pub mod frame_upscaler;
 pub mod game_interface;
 pub mod generative_texture_effect_fire;
 pub mod generative_texture_effect_layered;
+pub mod generative_texture_effect_procedural;
+pub mod generative_texture_effect_refraction;
 pub mod generative_texture_effect_turb;
 pub mod generative_texture_effect_water;
 pub mod generative_texture_effects_common;
 pub mod host;
 pub mod host_config;
 pub mod inline_models_index;
+pub mod lens_flares;
 pub mod light;
+pub mod lite_textures_processor;
 pub mod map_materials_processor;
 pub mod map_materials_processor_config;
 pub mod map_materials_processor_structs;
@@ -32,6 +38,7 @@ pub mod partial_renderer;
 pub mod performance_counter;
 pub mod postprocessor;
 pub mod postprocessor_config;
+pub mod postprocessor_ldr_effects;
 pub mod rasterizer;
 pub mod rect_splitting;
 pub mod renderer;
@@ -44,8 +51,8 @@ pub mod surfaces;
 pub mod text_printer;
 pub mod textures;
 pub mod ticks_counter;
-pub mod triangle_model;
-pub mod triangle_model_iqm;
-pub mod triangle_model_loading;
-pub mod triangle_model_md3;
+pub mod triangle_model_animation;
+pub mod triangle_model_optimization;
+pub mod triangle_model_simplification;
+pub mod triangle_models_materials;
 pub mod triangle_models_rendering;
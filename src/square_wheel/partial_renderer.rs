@@ -1,12 +1,13 @@
 use super::{
-	abstract_color::*, depth_renderer::*, draw_ordering, dynamic_objects_index::*, equations::*, fast_math::*,
-	frame_info::*, frame_number::*, inline_models_index::*, light::*, map_materials_processor::*,
-	map_visibility_calculator::*, rasterizer::*, rect_splitting, renderer_config::*, renderer_structs::*,
-	renderer_utils::*, resources_manager::*, surfaces::*, textures::*, triangle_model::*, triangle_models_rendering::*,
+	abstract_color::*, depth_renderer::*, draw_ordering, dynamic_objects_index::*, equations::*, fast_math::*, fog::*,
+	frame_info::*, frame_number::*, inline_models_index::*, light::*, lite_textures_processor::*,
+	map_materials_processor::*, map_visibility_calculator::*, rasterizer::*, rect_splitting, renderer_config::*,
+	renderer_structs::*, renderer_utils::*, resources_manager::*, surfaces::*, textures::*,
+	triangle_models_materials::*, triangle_models_rendering::*,
 };
 use crate::common::{
-	bbox::*, bsp_map_compact, clipping::*, clipping_polygon::*, fixed_math::*, light_cube::*, lightmap, material::*,
-	math_types::*, matrix::*, plane::*, shared_mut_slice::*, system_window,
+	bbox::*, bsp_map_compact, clipping::*, clipping_polygon::*, color::*, fixed_math::*, light_cube::*, lightmap,
+	material::*, math_types::*, matrix::*, plane::*, shared_mut_slice::*, system_window, triangle_model::*,
 };
 use rayon::prelude::*;
 use std::sync::{Arc, Mutex};
@@ -31,6 +32,10 @@ pub struct PartialRenderer
 	current_frame_visible_polygons: Vec<u32>,
 	mip_bias: f32,
 	submodels_info: Vec<VisibleSubmodelInfo>,
+	// Visible leafs of current frame in drawing order.
+	leafs_draw_list: Vec<u32>,
+	// Index of each leaf in draw list. Valid only for visible leafs.
+	leafs_draw_order: Vec<u32>,
 	// Material index and clipping polygon of current frame sky.
 	current_sky: Option<(u32, ClippingPolygon)>,
 	performance_counters: PerformanceCountersPtr,
@@ -43,8 +48,19 @@ pub struct PartialRenderer
 	// This is needed to avoid transforming/sorting model's vertices/triangles in each BSP leaf where this model is located.
 	visible_dynamic_meshes_list: Vec<VisibleDynamicMeshInfo>,
 	dynamic_model_to_dynamic_meshes_index: Vec<DynamicModelInfo>,
+	// Static models (detail objects), located in visible leafs.
+	current_frame_visible_static_models: Vec<u32>,
 	dynamic_meshes_vertices: Vec<ModelVertex3d>,
+	// Normals are calculated only for meshes with per-pixel lighting.
+	dynamic_meshes_normals: Vec<ModelVertexNormals>,
+	dynamic_meshes_lit_textures_data: Vec<Color32>,
 	dynamic_meshes_triangles: Vec<Triangle>,
+	// Fog factors are calculated only for meshes affected by fog.
+	dynamic_meshes_vertices_fog: Vec<FogFactor>,
+	// Indices of fog volumes, located in visible leafs.
+	current_frame_visible_fog_volumes: Vec<u32>,
+	// Used only if depth buffer mode is enabled.
+	depth_buffer: Vec<f32>,
 
 	portals_rendering_data: PortalsRenderingData,
 }
@@ -104,6 +120,8 @@ impl PartialRenderer
 			current_frame_visible_polygons: Vec::with_capacity(map.polygons.len()),
 			mip_bias: 0.0,
 			submodels_info: vec![VisibleSubmodelInfo::default(); map.submodels.len()],
+			leafs_draw_list: Vec::with_capacity(map.leafs.len()),
+			leafs_draw_order: vec![0; map.leafs.len()],
 			current_sky: None,
 			visibility_calculator: MapVisibilityCalculator::new(map.clone()),
 			shadows_maps_renderer: DepthRenderer::new(resources_manager.clone(), map.clone()),
@@ -115,8 +133,14 @@ impl PartialRenderer
 			sprites_info: Vec::new(),
 			visible_dynamic_meshes_list: Vec::new(),
 			dynamic_model_to_dynamic_meshes_index: Vec::new(),
+			current_frame_visible_static_models: Vec::new(),
 			dynamic_meshes_vertices: Vec::new(),
+			dynamic_meshes_normals: Vec::new(),
+			dynamic_meshes_lit_textures_data: Vec::new(),
 			dynamic_meshes_triangles: Vec::new(),
+			dynamic_meshes_vertices_fog: Vec::new(),
+			current_frame_visible_fog_volumes: Vec::new(),
+			depth_buffer: Vec::new(),
 			portals_rendering_data: PortalsRenderingData {
 				renderer: if depth > 0
 				{
@@ -187,8 +211,11 @@ impl PartialRenderer
 				self.visibility_calculator
 					.update_visibility(camera_matrices, &frame_bounds);
 			}
+			self.build_leafs_draw_list(camera_matrices);
 		});
 
+		self.prepare_fog_volumes(frame_info, &renderers_common_data.fog_volumes_index);
+
 		self.prepare_dynamic_lights(frame_info, camera_matrices, &renderers_common_data.dynamic_lights_index);
 		performance_counters.shadow_maps_building.run_with_measure(|| {
 			self.build_shadow_maps(&frame_info.lights, &renderers_common_data.inline_models_index);
@@ -200,15 +227,29 @@ impl PartialRenderer
 			self.prepare_dynamic_models(
 				camera_matrices,
 				is_third_person_view,
-				&frame_info.model_entities,
+				&renderers_common_data.model_entities,
 				&renderers_common_data.dynamic_models_index,
+				&renderers_common_data.triangle_models_materials,
+				&renderers_common_data.lite_textures_processor,
+			);
+			self.build_dynamic_models_buffers(
+				camera_matrices,
+				&frame_info.lights,
+				&frame_info.fog_volumes,
+				&renderers_common_data.model_entities,
+				&renderers_common_data.lite_textures_processor,
 			);
-			self.build_dynamic_models_buffers(&frame_info.lights, &frame_info.model_entities);
+			self.prepare_models_single_draw(renderers_common_data);
 		});
 
 		self.prepare_decals(frame_info, camera_matrices);
 
-		self.prepare_sprites(frame_info, camera_matrices, &renderers_common_data.sprites_index);
+		self.prepare_sprites(
+			frame_info,
+			camera_matrices,
+			&renderers_common_data.sprites_index,
+			&renderers_common_data.lite_textures_processor,
+		);
 
 		performance_counters.surfaces_preparation.run_with_measure(|| {
 			self.prepare_polygons_surfaces(
@@ -223,6 +264,7 @@ impl PartialRenderer
 				&renderers_common_data.dynamic_lights_index,
 				&renderers_common_data.materials_processor,
 				&frame_info.lights,
+				&frame_info.fog_volumes,
 			);
 		});
 
@@ -256,10 +298,42 @@ impl PartialRenderer
 			}
 		});
 
+		// Take depth buffer in order to pass it mutably into rasterization code.
+		let mut depth_buffer = std::mem::take(&mut self.depth_buffer);
+		if !self.config.use_depth_buffer
+		{
+			// Free memory.
+			depth_buffer = Vec::new();
+		}
+
+		// Allocate depth buffer and write polygons depth only if there are some objects which need depth test.
+		let depth_buffer_needed =
+			self.config.use_depth_buffer && self.has_depth_tested_objects(frame_info, renderers_common_data);
+		if depth_buffer_needed
+		{
+			depth_buffer.resize(surface_info.pitch * surface_info.height, 0.0);
+		}
+
 		performance_counters.rasterization.run_with_measure(|| {
-			self.perform_rasterization(pixels, surface_info, frame_info, camera_matrices, renderers_common_data)
+			self.perform_rasterization(
+				pixels,
+				if depth_buffer_needed
+				{
+					&mut depth_buffer
+				}
+				else
+				{
+					&mut []
+				},
+				surface_info,
+				frame_info,
+				camera_matrices,
+				renderers_common_data,
+			)
 		});
 
+		self.depth_buffer = depth_buffer;
+
 		self.populate_debug_stats(frame_info, renderers_common_data, debug_stats);
 
 		if self.config.debug_draw_depth
@@ -284,6 +358,28 @@ impl PartialRenderer
 		}
 	}
 
+	// Only dynamic objects (triangle models and sprites) use depth test.
+	fn has_depth_tested_objects(&self, frame_info: &FrameWorldInfo, renderers_common_data: &RenderersCommonData)
+		-> bool
+	{
+		if !self.visible_dynamic_meshes_list.is_empty()
+		{
+			return true;
+		}
+
+		(0 .. frame_info.sprites.len()).any(|sprite_index| {
+			renderers_common_data
+				.sprites_index
+				.get_object_leafs(sprite_index)
+				.iter()
+				.any(|leaf_index| {
+					self.visibility_calculator
+						.get_current_frame_leaf_bounds(*leaf_index)
+						.is_some()
+				})
+		})
+	}
+
 	fn populate_debug_stats(
 		&self,
 		frame_info: &FrameWorldInfo,
@@ -303,17 +399,25 @@ impl PartialRenderer
 					.inline_models_index
 					.get_leaf_models(leaf_index as u32)
 					.len();
+				// Models drawn only once are counted only in leaf where they are drawn.
 				debug_stats.num_visible_meshes_parts += renderers_common_data
 					.dynamic_models_index
 					.get_leaf_objects(leaf_index as u32)
-					.len();
+					.iter()
+					.filter(|&&model_index| {
+						self.dynamic_model_to_dynamic_meshes_index[model_index as usize]
+							.single_draw_leaf
+							.map(|l| l == leaf_index)
+							.unwrap_or(true)
+					})
+					.count();
 			}
 		}
 
 		for visible_dynamic_mesh in &self.visible_dynamic_meshes_list
 		{
 			debug_stats.num_triangles += visible_dynamic_mesh.num_visible_triangles;
-			debug_stats.num_triangle_vertices += match &frame_info.model_entities
+			debug_stats.num_triangle_vertices += match &renderers_common_data.model_entities
 				[visible_dynamic_mesh.entity_index as usize]
 				.model
 				.meshes[visible_dynamic_mesh.mesh_index as usize]
@@ -395,6 +499,37 @@ impl PartialRenderer
 		debug_stats.num_surfaces_pixels += self.num_visible_surfaces_pixels;
 	}
 
+	// Call this after visible leafs search.
+	fn prepare_fog_volumes(&mut self, frame_info: &FrameWorldInfo, fog_volumes_index: &DynamicObjectsIndex)
+	{
+		self.current_frame_visible_fog_volumes.clear();
+		for fog_volume_index in 0 .. frame_info.fog_volumes.len()
+		{
+			for leaf_index in fog_volumes_index.get_object_leafs(fog_volume_index)
+			{
+				if self
+					.visibility_calculator
+					.get_current_frame_leaf_bounds(*leaf_index)
+					.is_some()
+				{
+					self.current_frame_visible_fog_volumes.push(fog_volume_index as u32);
+					break;
+				}
+			}
+		}
+	}
+
+	fn calculate_object_fog(&self, fog_volumes: &[FogVolume], camera_position: &Vec3f, position: &Vec3f) -> FogFactor
+	{
+		calculate_fog_factor(
+			self.current_frame_visible_fog_volumes
+				.iter()
+				.map(|index| &fog_volumes[*index as usize]),
+			camera_position,
+			position,
+		)
+	}
+
 	fn prepare_dynamic_lights(
 		&mut self,
 		frame_info: &FrameWorldInfo,
@@ -729,6 +864,7 @@ impl PartialRenderer
 			let decal_info = DecalInfo {
 				camera_planes_matrix,
 				dynamic_light: light_cube.convert_into_light_grid_sample(),
+				fog: self.calculate_object_fog(&frame_info.fog_volumes, &camera_matrices.position, &decal.position),
 			};
 			self.decals_info.push(decal_info);
 		}
@@ -742,6 +878,7 @@ impl PartialRenderer
 		frame_info: &FrameWorldInfo,
 		camera_matrices: &CameraMatrices,
 		sprites_index: &DynamicObjectsIndex,
+		lite_textures_processor: &LiteTexturesProcessor,
 	)
 	{
 		let view_matrix_inverse = camera_matrices.view_matrix.invert().unwrap();
@@ -759,6 +896,7 @@ impl PartialRenderer
 			light: [0.0; 3],
 			mip: 0,
 			tesselation_level: 0,
+			fog: FogFactor::none(),
 		};
 
 		self.sprites_info.clear();
@@ -843,7 +981,7 @@ impl PartialRenderer
 				);
 			}
 
-			let texture_mip0 = &sprite.texture[0];
+			let texture_mip0 = &lite_textures_processor.get_texture(&sprite.texture)[0];
 			let ratio_h_w = (texture_mip0.size[1] as f32) / (texture_mip0.size[0] as f32);
 			let step_u = sprite.radius * inv_sqrt_fast(1.0 + ratio_h_w * ratio_h_w);
 			let ratio_w_h = (texture_mip0.size[0] as f32) / (texture_mip0.size[1] as f32);
@@ -979,11 +1117,14 @@ impl PartialRenderer
 				} // for ynamic lights
 			}
 
+			let fog = self.calculate_object_fog(&frame_info.fog_volumes, &camera_matrices.position, &sprite.position);
+
 			let sprite_info = SpriteInfo {
 				vertices_projected,
-				light: sprite_light,
+				light: fog.apply_to_light(&sprite_light),
 				mip,
 				tesselation_level,
+				fog,
 			};
 			self.sprites_info.push(sprite_info);
 		}
@@ -998,30 +1139,58 @@ impl PartialRenderer
 		is_third_person_view: bool,
 		models: &[ModelEntity],
 		dynamic_models_index: &DynamicObjectsIndex,
+		triangle_models_materials: &TriangleModelsMaterials,
+		lite_textures_processor: &LiteTexturesProcessor,
 	)
 	{
 		self.visible_dynamic_meshes_list.clear();
 
 		self.dynamic_model_to_dynamic_meshes_index
 			.resize(models.len(), DynamicModelInfo::default());
+		for dynamic_model_info in &mut self.dynamic_model_to_dynamic_meshes_index
+		{
+			dynamic_model_info.first_visible_mesh = 0;
+			dynamic_model_info.num_visible_meshes = 0;
+		}
+
+		// Check only static models in visible leafs, instead of checking all static models.
+		self.current_frame_visible_static_models.clear();
+		for leaf_index in 0 .. self.map.leafs.len() as u32
+		{
+			if self
+				.visibility_calculator
+				.get_current_frame_leaf_bounds(leaf_index)
+				.is_some()
+			{
+				self.current_frame_visible_static_models
+					.extend_from_slice(dynamic_models_index.get_leaf_static_objects(leaf_index));
+			}
+		}
+		// Static model may be located in multiple leafs.
+		self.current_frame_visible_static_models.sort_unstable();
+		self.current_frame_visible_static_models.dedup();
+
+		let lod_screen_size_scale = (-self.config.models_lod_bias).exp2();
 
 		// Reserve place in vertex/triangle buffers for each visible mesh.
 		let mut vertices_offset = 0;
 		let mut triangles_offset = 0;
-		for (entity_index, (model, dynamic_model_info)) in models
+		let mut lit_textures_data_offset = 0;
+		for entity_index in self
+			.current_frame_visible_static_models
 			.iter()
-			.zip(self.dynamic_model_to_dynamic_meshes_index.iter_mut())
-			.enumerate()
+			.map(|&index| index as usize)
+			.chain(dynamic_models_index.get_num_static_objects() .. models.len())
 		{
-			dynamic_model_info.first_visible_mesh = 0;
-			dynamic_model_info.num_visible_meshes = 0;
+			let model = &models[entity_index];
+			let dynamic_model_info = &mut self.dynamic_model_to_dynamic_meshes_index[entity_index];
 
 			// Calculate matrices.
 			let model_matrix = get_object_matrix(model.position, model.rotation);
 			let model_view_matrix = camera_matrices.view_matrix * model_matrix;
 
 			// Transform bbox.
-			let bbox = get_current_triangle_model_bbox(&model.model, &model.animation);
+			let bbox = get_model_entity_bbox(model);
 			let bbox_vertices_transformed = bbox
 				.get_corners_vertices()
 				.map(|pos| view_matrix_transform_vertex(&model_view_matrix, &pos));
@@ -1073,8 +1242,61 @@ impl PartialRenderer
 				position: Vec3f::zero(),
 			};
 
-			for (mesh_index, mesh) in model.model.meshes.iter().enumerate()
+			let use_per_pixel_lighting = model.flags.contains(ModelEntityDrawFlags::PER_PIXEL_LIGHTING);
+			let use_materials = model.flags.contains(ModelEntityDrawFlags::USE_MATERIALS);
+
+			// Select LOD, using LOD of previous frame for hysteresis (if model is the same).
+			let model_key = SharedResourcePtr::as_ptr(&model.model) as usize;
+			let screen_size = calculate_triangle_model_screen_size(&model_view_matrix, &bbox).unwrap_or(f32::MAX);
+			let lod = select_triangle_model_lod(
+				&model.model,
+				screen_size * lod_screen_size_scale,
+				if dynamic_model_info.lod_model_key == model_key
+				{
+					Some(dynamic_model_info.lod)
+				}
+				else
+				{
+					None
+				},
+			);
+			dynamic_model_info.lod = lod;
+			dynamic_model_info.lod_model_key = model_key;
+
+			let meshes_range = get_triangle_model_lod_meshes_range(&model.model, lod);
+			for mesh_index in meshes_range.clone()
 			{
+				let mesh = &model.model.meshes[mesh_index];
+				let material = if use_materials
+				{
+					triangle_models_materials
+						.get_mesh_material(&model.model, mesh_index)
+						.cloned()
+				}
+				else
+				{
+					None
+				};
+
+				// Reserve space for lit texture of each mesh.
+				// Use size of first mip, since actual mip is not known yet.
+				let lit_texture_offset = if let Some(material) = &material
+				{
+					let offset = lit_textures_data_offset;
+					lit_textures_data_offset += material[0].pixels.len();
+					Some(offset)
+				}
+				else if use_per_pixel_lighting
+				{
+					let offset = lit_textures_data_offset;
+					lit_textures_data_offset += lite_textures_processor.get_texture(&model.texture)[0].pixels.len();
+					Some(offset)
+				}
+				else
+				{
+					None
+				};
+
 				self.visible_dynamic_meshes_list.push(VisibleDynamicMeshInfo {
 					entity_index: entity_index as u32,
 					mesh_index: mesh_index as u32,
@@ -1085,21 +1307,17 @@ impl PartialRenderer
 					clipping_polygon,
 					model_matrix,
 					camera_matrices: model_camera_matrices,
-					mip: 0, // Set later.
+					mip: 0,         // Set later.
+					has_fog: false, // Set later.
+					lit_texture_offset,
+					material,
 				});
 
-				let num_vertices = match &mesh.vertex_data
-				{
-					VertexData::NonAnimated(v) => v.len(),
-					VertexData::VertexAnimated { constant, .. } => constant.len(),
-					VertexData::SkeletonAnimated(v) => v.len(),
-				};
-
-				vertices_offset += num_vertices;
+				vertices_offset += get_triangle_model_mesh_num_vertices(mesh);
 				triangles_offset += mesh.triangles.len();
 			}
 
-			dynamic_model_info.num_visible_meshes = model.model.meshes.len() as u32;
+			dynamic_model_info.num_visible_meshes = meshes_range.len() as u32;
 		}
 
 		if vertices_offset > self.dynamic_meshes_vertices.len()
@@ -1117,9 +1335,179 @@ impl PartialRenderer
 		{
 			self.dynamic_meshes_triangles.resize(triangles_offset, [0, 0, 0]);
 		}
+		if lit_textures_data_offset > 0 && vertices_offset > self.dynamic_meshes_normals.len()
+		{
+			self.dynamic_meshes_normals.resize(
+				vertices_offset,
+				ModelVertexNormals {
+					normal: Vec3f::zero(),
+					tangent: Vec3f::zero(),
+					binormal: Vec3f::zero(),
+				},
+			);
+		}
+		if lit_textures_data_offset > self.dynamic_meshes_lit_textures_data.len()
+		{
+			self.dynamic_meshes_lit_textures_data
+				.resize(lit_textures_data_offset, Color32::black());
+		}
+		if !self.current_frame_visible_fog_volumes.is_empty() &&
+			vertices_offset > self.dynamic_meshes_vertices_fog.len()
+		{
+			self.dynamic_meshes_vertices_fog
+				.resize(vertices_offset, FogFactor::none());
+		}
 	}
 
-	fn build_dynamic_models_buffers(&mut self, dynamic_lights: &[DynamicLight], models: &[ModelEntity])
+	// Find models which may be drawn only once, instead of drawing model parts in each leaf where model is located.
+	// This is possible only if no geometry, drawn after first model leaf, may be located in front of this model.
+	fn prepare_models_single_draw(&mut self, renderers_common_data: &RenderersCommonData)
+	{
+		for entity_index in 0 .. self.dynamic_model_to_dynamic_meshes_index.len()
+		{
+			let single_draw_leaf = if self.config.invert_polygons_order
+			{
+				None
+			}
+			else
+			{
+				self.find_model_single_draw_leaf(entity_index, renderers_common_data)
+			};
+			self.dynamic_model_to_dynamic_meshes_index[entity_index].single_draw_leaf = single_draw_leaf;
+		}
+	}
+
+	fn find_model_single_draw_leaf(
+		&self,
+		entity_index: usize,
+		renderers_common_data: &RenderersCommonData,
+	) -> Option<u32>
+	{
+		let model_info = &self.dynamic_model_to_dynamic_meshes_index[entity_index];
+		if model_info.num_visible_meshes == 0
+		{
+			return None;
+		}
+
+		let model_leafs = renderers_common_data
+			.dynamic_models_index
+			.get_object_leafs(entity_index);
+		if model_leafs.len() <= 1
+		{
+			// Nothing to optimize.
+			return None;
+		}
+
+		// Find range of model leafs in draw list.
+		let mut first_draw_order = u32::MAX;
+		let mut last_draw_order = 0;
+		for &leaf_index in model_leafs
+		{
+			if self
+				.visibility_calculator
+				.get_current_frame_leaf_bounds(leaf_index)
+				.is_some()
+			{
+				let draw_order = self.leafs_draw_order[leaf_index as usize];
+				first_draw_order = std::cmp::min(first_draw_order, draw_order);
+				last_draw_order = std::cmp::max(last_draw_order, draw_order);
+			}
+		}
+		if first_draw_order >= last_draw_order
+		{
+			// Model is visible in less than two leafs.
+			return None;
+		}
+
+		let model = &renderers_common_data.model_entities[entity_index];
+		let model_matrix = get_object_matrix(model.position, model.rotation);
+		let bbox_vertices = get_model_entity_bbox(model)
+			.get_corners_vertices()
+			.map(|v| (model_matrix * v.extend(1.0)).truncate());
+
+		// Polygons of model leafs are drawn before model.
+		// Model may be partially located behind such polygons, if its bbox crosses polygon plane.
+		for &leaf_index in model_leafs
+		{
+			let leaf = &self.map.leafs[leaf_index as usize];
+			for polygon in
+				&self.map.polygons[leaf.first_polygon as usize .. (leaf.first_polygon + leaf.num_polygons) as usize]
+			{
+				const EPS: f32 = 1.0 / 16.0;
+				let dist_threshold = polygon.plane.dist - EPS * polygon.plane.vec.magnitude();
+				if bbox_vertices.iter().any(|v| polygon.plane.vec.dot(*v) < dist_threshold)
+				{
+					return None;
+				}
+			}
+		}
+
+		let clipping_polygon =
+			self.visible_dynamic_meshes_list[model_info.first_visible_mesh as usize].clipping_polygon;
+
+		// Leafs drawn before first model leaf can't be in front of it.
+		// Leafs drawn after last model leaf are drawn over model properly.
+		// Objects of last model leaf are sorted together with model.
+		// So, check only leafs in between.
+		for &leaf_index in &self.leafs_draw_list[first_draw_order as usize .. last_draw_order as usize]
+		{
+			if model_leafs.contains(&leaf_index)
+			{
+				// Other objects of these leafs may be located in front of the model.
+				// Do not try to sort them, just disable optimization.
+				if !renderers_common_data
+					.inline_models_index
+					.get_leaf_models(leaf_index)
+					.is_empty() || !renderers_common_data
+					.sprites_index
+					.get_leaf_objects(leaf_index)
+					.is_empty() || !renderers_common_data
+					.portals_index
+					.get_leaf_objects(leaf_index)
+					.is_empty()
+				{
+					return None;
+				}
+
+				for &other_entity_index in renderers_common_data.dynamic_models_index.get_leaf_objects(leaf_index)
+				{
+					let other_model_info = &self.dynamic_model_to_dynamic_meshes_index[other_entity_index as usize];
+					if other_entity_index as usize == entity_index || other_model_info.num_visible_meshes == 0
+					{
+						continue;
+					}
+
+					let mut other_clipping_polygon =
+						self.visible_dynamic_meshes_list[other_model_info.first_visible_mesh as usize].clipping_polygon;
+					other_clipping_polygon.intersect(&clipping_polygon);
+					if other_clipping_polygon.is_valid_and_non_empty()
+					{
+						return None;
+					}
+				}
+			}
+			else if let Some(mut leaf_bounds) = self.visibility_calculator.get_current_frame_leaf_bounds(leaf_index)
+			{
+				// Everything inside this leaf is drawn within its portal bounds.
+				leaf_bounds.intersect(&clipping_polygon);
+				if leaf_bounds.is_valid_and_non_empty()
+				{
+					return None;
+				}
+			}
+		}
+
+		Some(self.leafs_draw_list[last_draw_order as usize])
+	}
+
+	fn build_dynamic_models_buffers(
+		&mut self,
+		camera_matrices: &CameraMatrices,
+		dynamic_lights: &[DynamicLight],
+		fog_volumes: &[FogVolume],
+		models: &[ModelEntity],
+		lite_textures_processor: &LiteTexturesProcessor,
+	)
 	{
 		// Prepare array of dynamic lights with shadowmaps.
 		// TODO - avoid allocation.
@@ -1131,40 +1519,91 @@ impl PartialRenderer
 			.map(|(light, light_info)| create_dynamic_light_with_shadow(light, light_info, shadow_maps_data))
 			.collect();
 
+		// Used only to initialize references.
+		let dummy_light = DynamicLightWithShadow {
+			position: Vec3f::zero(),
+			radius: 1.0,
+			inv_square_radius: 1.0,
+			color: [0.0; 3],
+			shadow_map: ShadowMap::None,
+		};
+		const MAX_MODEL_LIGHTS: usize = 6;
+
+		let dummy_fog_volume = FogVolume {
+			bbox: BBox::zero(),
+			color: [0.0; 3],
+			density: 0.0,
+			height_falloff: 0.0,
+		};
+		const MAX_MODEL_FOG_VOLUMES: usize = 4;
+
+		let view_matrix_inverse = camera_matrices.view_matrix.invert().unwrap_or_else(Mat4f::identity);
+
 		// It is safe to share vertices and triangle buffers since each mesh uses its own region.
 		let dst_vertices_shared = SharedMutSlice::new(&mut self.dynamic_meshes_vertices);
+		let dst_normals_shared = SharedMutSlice::new(&mut self.dynamic_meshes_normals);
 		let dst_triangles_shared = SharedMutSlice::new(&mut self.dynamic_meshes_triangles);
+		let dst_lit_textures_data_shared = SharedMutSlice::new(&mut self.dynamic_meshes_lit_textures_data);
+		let dst_vertices_fog_shared = SharedMutSlice::new(&mut self.dynamic_meshes_vertices_fog);
 
 		let map = &self.map;
 		let mip_bias = self.mip_bias;
+		let visible_fog_volumes = &self.current_frame_visible_fog_volumes;
 
 		let func = |visible_dynamic_mesh: &mut VisibleDynamicMeshInfo| {
 			let model = &models[visible_dynamic_mesh.entity_index as usize];
 			let animation = &model.animation;
 
+			let material = visible_dynamic_mesh.material.clone();
+			let model_texture = lite_textures_processor.get_texture(&model.texture);
+
 			visible_dynamic_mesh.mip = calculate_triangle_model_texture_mip(
 				&visible_dynamic_mesh.camera_matrices.view_matrix,
-				&get_current_triangle_model_bbox(&model.model, animation),
-				model.texture[0].size,
+				&get_model_entity_bbox(model),
+				material.as_ref().map(|m| m[0].size).unwrap_or(model_texture[0].size),
 				mip_bias,
 			);
 
-			let texture = &model.texture[visible_dynamic_mesh.mip as usize];
+			let texture = &model_texture[visible_dynamic_mesh.mip as usize];
+			let material_texture = material.as_ref().map(|m| &m[visible_dynamic_mesh.mip as usize]);
+			let texture_size = material_texture.map(|t| t.size).unwrap_or(texture.size);
 			let mesh = &model.model.meshes[visible_dynamic_mesh.mesh_index as usize];
 
 			// Perform vertices transformation.
 			let dst_mesh_vertices = unsafe { &mut dst_vertices_shared.get()[visible_dynamic_mesh.vertices_offset ..] };
+			let dst_mesh_normals: &mut [ModelVertexNormals] = if visible_dynamic_mesh.lit_texture_offset.is_some()
+			{
+				unsafe { &mut dst_normals_shared.get()[visible_dynamic_mesh.vertices_offset ..] }
+			}
+			else
+			{
+				&mut []
+			};
+
+			// Dynamic light is calculated later for models with per-pixel lighting.
+			let vertex_dynamic_lights: &[DynamicLightWithShadow] = if visible_dynamic_mesh.lit_texture_offset.is_some()
+			{
+				&[]
+			}
+			else
+			{
+				&lights
+			};
+
+			let model_light = get_model_light(map, vertex_dynamic_lights, model, &visible_dynamic_mesh.model_matrix);
 
 			animate_and_transform_triangle_mesh_vertices(
 				&model.model,
 				mesh,
 				animation,
-				&get_model_light(map, &lights, model, &visible_dynamic_mesh.model_matrix),
+				model.skeleton_pose.as_deref(),
+				&model_light,
 				&visible_dynamic_mesh.model_matrix,
 				&visible_dynamic_mesh.camera_matrices.view_matrix,
-				&Vec2f::new(texture.size[0] as f32, texture.size[1] as f32),
+				&Vec2f::new(texture_size[0] as f32, texture_size[1] as f32),
 				&model.model.tc_shift,
 				dst_mesh_vertices,
+				dst_mesh_normals,
 			);
 
 			// Copy, filter and sort triangles.
@@ -1176,6 +1615,110 @@ impl PartialRenderer
 				dst_mesh_vertices,
 				&mut dst_triangles[.. visible_dynamic_mesh.num_visible_triangles],
 			);
+
+			if let Some(lit_texture_offset) = visible_dynamic_mesh.lit_texture_offset
+			{
+				// Collect lights, affecting this model.
+				let model_bbox = get_model_entity_bbox(model);
+				let mut model_lights = [&dummy_light; MAX_MODEL_LIGHTS];
+				let mut num_model_lights = 0;
+				for light in &lights
+				{
+					if model_is_affected_by_light(&model_bbox, &visible_dynamic_mesh.model_matrix, light)
+					{
+						model_lights[num_model_lights] = light;
+						num_model_lights += 1;
+						if num_model_lights == MAX_MODEL_LIGHTS
+						{
+							break;
+						}
+					}
+				}
+
+				let num_vertices = get_triangle_model_mesh_num_vertices(mesh);
+				let dst_lit_texture_data = unsafe {
+					&mut dst_lit_textures_data_shared.get()
+						[lit_texture_offset .. lit_texture_offset + ((texture_size[0] * texture_size[1]) as usize)]
+				};
+
+				if let Some(material_texture) = material_texture
+				{
+					build_triangle_model_material_lit_texture(
+						material_texture,
+						&dst_mesh_vertices[.. num_vertices],
+						&dst_mesh_normals[.. num_vertices],
+						&dst_triangles[.. visible_dynamic_mesh.num_visible_triangles],
+						&view_matrix_inverse,
+						&camera_matrices.position,
+						&model_light,
+						&model_lights[.. num_model_lights],
+						get_model_dynamic_light_scale(model),
+						dst_lit_texture_data,
+					);
+				}
+				else
+				{
+					build_triangle_model_lit_texture(
+						texture,
+						&dst_mesh_vertices[.. num_vertices],
+						&dst_mesh_normals[.. num_vertices],
+						&dst_triangles[.. visible_dynamic_mesh.num_visible_triangles],
+						&view_matrix_inverse,
+						&model_lights[.. num_model_lights],
+						get_model_dynamic_light_scale(model),
+						dst_lit_texture_data,
+					);
+				}
+
+				// Light is already applied to lit texture.
+				for v in &mut dst_mesh_vertices[.. num_vertices]
+				{
+					v.light = [MODEL_LIT_TEXTURE_LIGHT_SCALE; 3];
+				}
+			}
+
+			// Collect fog volumes, affecting this model.
+			let bbox_vertices_world = get_model_entity_bbox(model)
+				.get_corners_vertices()
+				.map(|v| (visible_dynamic_mesh.model_matrix * v.extend(1.0)).truncate());
+			let mut model_bbox_world = BBox::from_point(&bbox_vertices_world[0]);
+			for v in &bbox_vertices_world[1 ..]
+			{
+				model_bbox_world.extend_with_point(v);
+			}
+
+			let mut model_fog_volumes_storage = [&dummy_fog_volume; MAX_MODEL_FOG_VOLUMES];
+			let mut model_fog_volumes_extra_storage = Vec::new();
+			let model_fog_volumes = collect_fog_volumes_for_bbox(
+				visible_fog_volumes.iter().map(|index| &fog_volumes[*index as usize]),
+				&model_bbox_world,
+				&camera_matrices.position,
+				&mut model_fog_volumes_storage,
+				&mut model_fog_volumes_extra_storage,
+			);
+
+			// Calculate fog for each vertex, since model may be big enough to cross fog volume boundary.
+			// Apply fog visibility to vertex light. Fog color is added later, for each triangle.
+			visible_dynamic_mesh.has_fog = !model_fog_volumes.is_empty();
+			if visible_dynamic_mesh.has_fog
+			{
+				let num_vertices = get_triangle_model_mesh_num_vertices(mesh);
+				let dst_vertices_fog = unsafe {
+					&mut dst_vertices_fog_shared.get()
+						[visible_dynamic_mesh.vertices_offset .. visible_dynamic_mesh.vertices_offset + num_vertices]
+				};
+				for (v, dst_fog) in dst_mesh_vertices[.. num_vertices]
+					.iter_mut()
+					.zip(dst_vertices_fog.iter_mut())
+				{
+					*dst_fog = calculate_fog_factor(
+						model_fog_volumes.iter().copied(),
+						&camera_matrices.position,
+						&view_matrix_transform_vertex(&view_matrix_inverse, &v.pos),
+					);
+					v.light = dst_fog.apply_to_light(&v.light);
+				}
+			}
 		};
 
 		let num_threads = rayon::current_num_threads();
@@ -1192,6 +1735,7 @@ impl PartialRenderer
 	fn perform_rasterization<ColorT: AbstractColor>(
 		&self,
 		pixels: &mut [ColorT],
+		depth_buffer: &mut [f32],
 		surface_info: &system_window::SurfaceInfo,
 		frame_info: &FrameWorldInfo,
 		camera_matrices: &CameraMatrices,
@@ -1208,6 +1752,7 @@ impl PartialRenderer
 		{
 			let mut rasterizer = Rasterizer::new(
 				pixels,
+				depth_buffer,
 				surface_info,
 				ClipRect {
 					min_x: 0,
@@ -1235,6 +1780,7 @@ impl PartialRenderer
 		else
 		{
 			let pixels_shared = SharedMutSlice::new(pixels);
+			let depth_buffer_shared = SharedMutSlice::new(depth_buffer);
 
 			// Split viewport rect into several rects for each thread.
 			// Use tricky splitting method that avoid creation of thin rects.
@@ -1244,11 +1790,13 @@ impl PartialRenderer
 
 			rects[.. num_threads].par_iter().for_each(|rect| {
 				let pixels_cur = unsafe { pixels_shared.get() };
+				let depth_buffer_cur = unsafe { depth_buffer_shared.get() };
 
 				// Create rasterizer with custom clip rect in order to perform pixel-perfect clipping.
 				// TODO - change this. Just create rasterizer with shifted raster and shift vertex coordinates instead.
 				let mut rasterizer = Rasterizer::new(
 					pixels_cur,
+					depth_buffer_cur,
 					surface_info,
 					ClipRect {
 						min_x: rect.min.x as i32,
@@ -1291,6 +1839,8 @@ impl PartialRenderer
 		viewport_clipping_polygon: &ClippingPolygon,
 	)
 	{
+		rasterizer.clear_depth_buffer();
+
 		if !self.config.invert_polygons_order
 		{
 			self.draw_skybox(
@@ -1321,7 +1871,12 @@ impl PartialRenderer
 			);
 		}
 
-		self.draw_view_models(rasterizer, viewport_clipping_polygon, &frame_info.model_entities);
+		self.draw_view_models(
+			rasterizer,
+			&renderers_common_data.lite_textures_processor,
+			viewport_clipping_polygon,
+			&renderers_common_data.model_entities,
+		);
 	}
 
 	fn prepare_polygons_surfaces(
@@ -1643,6 +2198,7 @@ impl PartialRenderer
 		dynamic_lights_index: &DynamicObjectsIndex,
 		materials_processor: &MapMaterialsProcessor,
 		dynamic_lights: &[DynamicLight],
+		fog_volumes: &[FogVolume],
 	)
 	{
 		// Prepare array of dynamic lights with shadowmaps.
@@ -1665,6 +2221,15 @@ impl PartialRenderer
 		};
 		const MAX_POLYGON_LIGHTS: usize = 6;
 
+		let dummy_fog_volume = FogVolume {
+			bbox: BBox::zero(),
+			color: [0.0; 3],
+			density: 0.0,
+			height_falloff: 0.0,
+		};
+		const MAX_POLYGON_FOG_VOLUMES: usize = 4;
+		let visible_fog_volumes = &self.current_frame_visible_fog_volumes;
+
 		// Perform parallel surfaces building.
 		// Use "unsafe" to write into surfaces data concurrently.
 		// It is fine since each surface uses its own region.
@@ -1700,6 +2265,11 @@ impl PartialRenderer
 			let material = materials_processor.get_material(polygon.texture);
 			let apply_diffuse_layer = material.light && material.diffuse.is_some();
 
+			let mut basis_vecs_scaled_and_corrected = polygon_data.basis_vecs.get_basis_vecs_for_mip(polygon_data.mip);
+			basis_vecs_scaled_and_corrected.start -= (extra_shift_mip_compensated[0] as f32) *
+				basis_vecs_scaled_and_corrected.u +
+				(extra_shift_mip_compensated[1] as f32) * basis_vecs_scaled_and_corrected.v;
+
 			if apply_diffuse_layer
 			{
 				// Collect lights, affecting this polygon.
@@ -1733,12 +2303,6 @@ impl PartialRenderer
 					}
 				}
 
-				let mut basis_vecs_scaled_and_corrected =
-					polygon_data.basis_vecs.get_basis_vecs_for_mip(polygon_data.mip);
-				basis_vecs_scaled_and_corrected.start -= (extra_shift_mip_compensated[0] as f32) *
-					basis_vecs_scaled_and_corrected.u +
-					(extra_shift_mip_compensated[1] as f32) * basis_vecs_scaled_and_corrected.v;
-
 				let texture = &materials_processor.get_texture(polygon.texture)[polygon_data.mip as usize];
 
 				let mut lightmap_tc_shift: [u32; 2] = [0, 0];
@@ -1817,6 +2381,35 @@ impl PartialRenderer
 					surface_data,
 				);
 			}
+
+			if !visible_fog_volumes.is_empty()
+			{
+				// Collect fog volumes, affecting this polygon.
+				let surface_bbox = get_surface_bbox(&basis_vecs_scaled_and_corrected, tc_start, surface_size);
+
+				let mut polygon_fog_volumes_storage = [&dummy_fog_volume; MAX_POLYGON_FOG_VOLUMES];
+				let mut polygon_fog_volumes_extra_storage = Vec::new();
+				let polygon_fog_volumes = collect_fog_volumes_for_bbox(
+					visible_fog_volumes.iter().map(|index| &fog_volumes[*index as usize]),
+					&surface_bbox,
+					&camera_matrices.position,
+					&mut polygon_fog_volumes_storage,
+					&mut polygon_fog_volumes_extra_storage,
+				);
+
+				if !polygon_fog_volumes.is_empty()
+				{
+					apply_fog_to_surface(
+						&basis_vecs_scaled_and_corrected,
+						surface_size,
+						tc_start,
+						polygon_fog_volumes,
+						material.blending_mode != BlendingMode::Additive,
+						&camera_matrices.position,
+						surface_data,
+					);
+				}
+			}
 		};
 
 		if rayon::current_num_threads() == 1
@@ -2284,6 +2877,7 @@ impl PartialRenderer
 	fn draw_view_models<'a, ColorT: AbstractColor>(
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
+		lite_textures_processor: &LiteTexturesProcessor,
 		viewport_clipping_polygon: &ClippingPolygon,
 		models: &[ModelEntity],
 	)
@@ -2299,6 +2893,7 @@ impl PartialRenderer
 			{
 				self.draw_mesh(
 					rasterizer,
+					lite_textures_processor,
 					viewport_clipping_polygon,
 					&[], // No 3d clip planes.
 					models,
@@ -2447,21 +3042,15 @@ impl PartialRenderer
 		}
 	}
 
-	fn draw_tree<'a, ColorT: AbstractColor>(
-		&self,
-		rasterizer: &mut Rasterizer<'a, ColorT>,
-		frame_info: &FrameWorldInfo,
-		camera_matrices: &CameraMatrices,
-		renderers_common_data: &RenderersCommonData,
-		viewport_clipping_polygon: &ClippingPolygon,
-	)
+	// Build list of visible leafs in order of their drawing (back to front or front to back).
+	fn build_leafs_draw_list(&mut self, camera_matrices: &CameraMatrices)
 	{
 		// Use iterative approach of BSP tree traverse.
 		// This is more effective way to do this,
 		// because compiler can't properly optimize recursive calls and saves all arguments on stack,
 		// which is unnecessary, since all arguments except one are the same.
 
-		let mut objects_sorter = draw_ordering::LeafObjectsSorter::new();
+		self.leafs_draw_list.clear();
 
 		let planes_matrix_w_row = camera_matrices.planes_matrix.row(3);
 
@@ -2481,21 +3070,13 @@ impl PartialRenderer
 				num_nodes_on_stack -= 1;
 
 				let leaf_index = node_index - bsp_map_compact::FIRST_LEAF_INDEX;
-				if let Some(mut leaf_bounds) = self.visibility_calculator.get_current_frame_leaf_bounds(leaf_index)
+				if self
+					.visibility_calculator
+					.get_current_frame_leaf_bounds(leaf_index)
+					.is_some()
 				{
-					leaf_bounds.intersect(viewport_clipping_polygon);
-					if leaf_bounds.is_valid_and_non_empty()
-					{
-						self.draw_leaf(
-							rasterizer,
-							frame_info,
-							camera_matrices,
-							renderers_common_data,
-							&mut objects_sorter,
-							&leaf_bounds,
-							leaf_index,
-						);
-					}
+					self.leafs_draw_order[leaf_index as usize] = self.leafs_draw_list.len() as u32;
+					self.leafs_draw_list.push(leaf_index);
 				}
 			}
 			else if num_nodes_on_stack < MAX_STACK_SIZE
@@ -2516,6 +3097,103 @@ impl PartialRenderer
 		}
 	}
 
+	fn draw_tree<'a, ColorT: AbstractColor>(
+		&self,
+		rasterizer: &mut Rasterizer<'a, ColorT>,
+		frame_info: &FrameWorldInfo,
+		camera_matrices: &CameraMatrices,
+		renderers_common_data: &RenderersCommonData,
+		viewport_clipping_polygon: &ClippingPolygon,
+	)
+	{
+		let mut objects_sorter = draw_ordering::LeafObjectsSorter::new();
+
+		for &leaf_index in &self.leafs_draw_list
+		{
+			if let Some(mut leaf_bounds) = self.visibility_calculator.get_current_frame_leaf_bounds(leaf_index)
+			{
+				leaf_bounds.intersect(viewport_clipping_polygon);
+				if leaf_bounds.is_valid_and_non_empty()
+				{
+					self.draw_leaf(
+						rasterizer,
+						frame_info,
+						camera_matrices,
+						renderers_common_data,
+						&mut objects_sorter,
+						&leaf_bounds,
+						viewport_clipping_polygon,
+						leaf_index,
+					);
+				}
+				else
+				{
+					// Leaf is outside viewport, but models drawn only once in this leaf may be still inside it.
+					self.draw_leaf_single_draw_models(
+						rasterizer,
+						renderers_common_data,
+						&mut objects_sorter,
+						viewport_clipping_polygon,
+						leaf_index,
+					);
+				}
+			}
+		}
+	}
+
+	fn draw_leaf_single_draw_models<'a, ColorT: AbstractColor>(
+		&self,
+		rasterizer: &mut Rasterizer<'a, ColorT>,
+		renderers_common_data: &RenderersCommonData,
+		objects_sorter: &mut draw_ordering::LeafObjectsSorter,
+		viewport_clipping_polygon: &ClippingPolygon,
+		leaf_index: u32,
+	)
+	{
+		objects_sorter.clear();
+		for dynamic_model_index in renderers_common_data.dynamic_models_index.get_leaf_objects(leaf_index)
+		{
+			let entry = self.dynamic_model_to_dynamic_meshes_index[*dynamic_model_index as usize];
+			if entry.single_draw_leaf != Some(leaf_index)
+			{
+				continue;
+			}
+
+			let model = &renderers_common_data.model_entities[*dynamic_model_index as usize];
+			let bbox = if let Some(bb) = model.ordering_custom_bbox
+			{
+				bb
+			}
+			else
+			{
+				get_model_entity_bbox(model)
+			};
+
+			for visible_mesh_index in entry.first_visible_mesh .. entry.first_visible_mesh + entry.num_visible_meshes
+			{
+				let mesh = &self.visible_dynamic_meshes_list[visible_mesh_index as usize];
+				objects_sorter.add_object((
+					visible_mesh_index,
+					draw_ordering::project_bbox(&bbox, &mesh.camera_matrices),
+				));
+			}
+		}
+
+		objects_sorter.sort_objects();
+
+		for (visible_mesh_index, _bbox) in objects_sorter.get_objects()
+		{
+			self.draw_mesh(
+				rasterizer,
+				&renderers_common_data.lite_textures_processor,
+				viewport_clipping_polygon,
+				&[],
+				&renderers_common_data.model_entities,
+				&self.visible_dynamic_meshes_list[*visible_mesh_index as usize],
+			);
+		}
+	}
+
 	fn draw_leaf<'a, ColorT: AbstractColor>(
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
@@ -2524,6 +3202,7 @@ impl PartialRenderer
 		renderers_common_data: &RenderersCommonData,
 		objects_sorter: &mut draw_ordering::LeafObjectsSorter,
 		bounds: &ClippingPolygon,
+		viewport_clipping_polygon: &ClippingPolygon,
 		leaf_index: u32,
 	)
 	{
@@ -2567,6 +3246,7 @@ impl PartialRenderer
 				self.draw_polygon_decals(
 					rasterizer,
 					&renderers_common_data.materials_processor,
+					&renderers_common_data.lite_textures_processor,
 					&clip_planes,
 					polygon_index,
 					&frame_info.decals,
@@ -2617,6 +3297,7 @@ impl PartialRenderer
 				self.draw_submodel_in_leaf(
 					rasterizer,
 					&renderers_common_data.materials_processor,
+					&renderers_common_data.lite_textures_processor,
 					frame_info,
 					&clip_planes,
 					used_leaf_clip_planes,
@@ -2628,22 +3309,33 @@ impl PartialRenderer
 			else if leaf_dynamic_models.len() == 1
 			{
 				let entry = self.dynamic_model_to_dynamic_meshes_index[leaf_dynamic_models[0] as usize];
-				for visible_mesh_index in
-					entry.first_visible_mesh .. entry.first_visible_mesh + entry.num_visible_meshes
+				if let Some((mesh_bounds, mesh_leaf_clip_planes)) = get_model_draw_bounds(
+					&entry,
+					leaf_index,
+					bounds,
+					viewport_clipping_polygon,
+					used_leaf_clip_planes,
+				)
 				{
-					self.draw_mesh(
-						rasterizer,
-						bounds,
-						used_leaf_clip_planes,
-						&frame_info.model_entities,
-						&self.visible_dynamic_meshes_list[visible_mesh_index as usize],
-					);
+					for visible_mesh_index in
+						entry.first_visible_mesh .. entry.first_visible_mesh + entry.num_visible_meshes
+					{
+						self.draw_mesh(
+							rasterizer,
+							&renderers_common_data.lite_textures_processor,
+							mesh_bounds,
+							mesh_leaf_clip_planes,
+							&renderers_common_data.model_entities,
+							&self.visible_dynamic_meshes_list[visible_mesh_index as usize],
+						);
+					}
 				}
 			}
 			else if leaf_sprites.len() == 1
 			{
 				self.draw_sprite(
 					rasterizer,
+					&renderers_common_data.lite_textures_processor,
 					&clip_planes,
 					used_leaf_clip_planes,
 					&frame_info.sprites,
@@ -2689,17 +3381,23 @@ impl PartialRenderer
 
 		for dynamic_model_index in leaf_dynamic_models
 		{
-			let model = &frame_info.model_entities[*dynamic_model_index as usize];
+			let entry = self.dynamic_model_to_dynamic_meshes_index[*dynamic_model_index as usize];
+			if entry.single_draw_leaf.map(|l| l != leaf_index).unwrap_or(false)
+			{
+				// This model is drawn in another leaf.
+				continue;
+			}
+
+			let model = &renderers_common_data.model_entities[*dynamic_model_index as usize];
 			let bbox = if let Some(bb) = model.ordering_custom_bbox
 			{
 				bb
 			}
 			else
 			{
-				get_current_triangle_model_bbox(&model.model, &model.animation)
+				get_model_entity_bbox(model)
 			};
 
-			let entry = self.dynamic_model_to_dynamic_meshes_index[*dynamic_model_index as usize];
 			for visible_mesh_index in entry.first_visible_mesh .. entry.first_visible_mesh + entry.num_visible_meshes
 			{
 				let mesh = &self.visible_dynamic_meshes_list[visible_mesh_index as usize];
@@ -2758,6 +3456,7 @@ impl PartialRenderer
 			{
 				self.draw_sprite(
 					rasterizer,
+					&renderers_common_data.lite_textures_processor,
 					&clip_planes,
 					used_leaf_clip_planes,
 					&frame_info.sprites,
@@ -2766,20 +3465,32 @@ impl PartialRenderer
 			}
 			else if *object_index >= DYNAMIC_MESH_INDEX_ADD
 			{
-				let visible_mesh_index = *object_index - DYNAMIC_MESH_INDEX_ADD;
-				self.draw_mesh(
-					rasterizer,
+				let visible_mesh = &self.visible_dynamic_meshes_list[(*object_index - DYNAMIC_MESH_INDEX_ADD) as usize];
+				let entry = self.dynamic_model_to_dynamic_meshes_index[visible_mesh.entity_index as usize];
+				if let Some((mesh_bounds, mesh_leaf_clip_planes)) = get_model_draw_bounds(
+					&entry,
+					leaf_index,
 					bounds,
+					viewport_clipping_polygon,
 					used_leaf_clip_planes,
-					&frame_info.model_entities,
-					&self.visible_dynamic_meshes_list[visible_mesh_index as usize],
-				);
+				)
+				{
+					self.draw_mesh(
+						rasterizer,
+						&renderers_common_data.lite_textures_processor,
+						mesh_bounds,
+						mesh_leaf_clip_planes,
+						&renderers_common_data.model_entities,
+						visible_mesh,
+					);
+				}
 			}
 			else if *object_index >= SUBMODEL_INDEX_ADD
 			{
 				self.draw_submodel_in_leaf(
 					rasterizer,
 					&renderers_common_data.materials_processor,
+					&renderers_common_data.lite_textures_processor,
 					frame_info,
 					&clip_planes,
 					used_leaf_clip_planes,
@@ -2795,6 +3506,7 @@ impl PartialRenderer
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
 		materials_processor: &MapMaterialsProcessor,
+		lite_textures_processor: &LiteTexturesProcessor,
 		clip_planes: &ClippingPolygonPlanes,
 		polygon_index: u32,
 		decals: &[Decal],
@@ -2889,7 +3601,8 @@ impl PartialRenderer
 			}
 
 			// Calculate texture coordinates equation.
-			let texture = &decal.texture[0];
+			let decal_texture = lite_textures_processor.get_texture(&decal.texture);
+			let texture = &decal_texture[0];
 
 			let tc_basis_transformed = [
 				decal_planes_matrix * (Vec4f::from(DECAL_TEXTURE_BASIS[0]) * (texture.size[0] as f32)),
@@ -2907,7 +3620,7 @@ impl PartialRenderer
 				&tc_equation,
 				self.mip_bias,
 			);
-			let mip_texture = &decal.texture[mip as usize];
+			let mip_texture = &decal_texture[mip as usize];
 			let tc_equation_scaled = tc_equation * (1.0 / ((1 << mip) as f32));
 
 			// Use projected polygon texture coordinates equation in order to get lightmap coordinates for decal points.
@@ -2950,6 +3663,7 @@ impl PartialRenderer
 					],
 					mip_texture,
 					&dynamic_light,
+					&decal_info.fog,
 					0,
 				);
 			}
@@ -2968,6 +3682,7 @@ impl PartialRenderer
 		points: &[Vec2f; 3],
 		texture: &TextureLite,
 		dynamic_light: &[f32; 3],
+		fog: &FogFactor,
 		recursion_depth: usize,
 	)
 	{
@@ -3049,9 +3764,9 @@ impl PartialRenderer
 						),
 					],
 					light: [
-						f32_to_fixed16(light[0]),
-						f32_to_fixed16(light[1]),
-						f32_to_fixed16(light[2]),
+						f32_to_fixed16(light[0] * fog.visibility),
+						f32_to_fixed16(light[1] * fog.visibility),
+						f32_to_fixed16(light[2] * fog.visibility),
 					],
 				};
 			}
@@ -3064,7 +3779,14 @@ impl PartialRenderer
 			let texture_data = &texture.pixels;
 			let blending_mode = decal.blending_mode;
 
-			rasterizer.fill_triangle(&vertices_fixed, &texture_info, texture_data, blending_mode);
+			rasterizer.fill_triangle(
+				&vertices_fixed,
+				&texture_info,
+				texture_data,
+				blending_mode,
+				fog.get_color_add_vec(blending_mode),
+				None, // Decals are drawn atop of polygons, so, depth test is not needed.
+			);
 		}
 		else
 		{
@@ -3089,6 +3811,7 @@ impl PartialRenderer
 					triangle,
 					texture,
 					dynamic_light,
+					fog,
 					recursion_depth + 1,
 				);
 			}
@@ -3099,6 +3822,7 @@ impl PartialRenderer
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
 		materials_processor: &MapMaterialsProcessor,
+		lite_textures_processor: &LiteTexturesProcessor,
 		frame_info: &FrameWorldInfo,
 		clip_planes: &ClippingPolygonPlanes,
 		leaf_clip_planes: &[Plane],
@@ -3113,6 +3837,7 @@ impl PartialRenderer
 			self.draw_submodel_bsp_node_r(
 				rasterizer,
 				materials_processor,
+				lite_textures_processor,
 				frame_info,
 				&submodel_matrices.camera_matrices.planes_matrix.row(3),
 				clip_planes,
@@ -3128,6 +3853,7 @@ impl PartialRenderer
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
 		materials_processor: &MapMaterialsProcessor,
+		lite_textures_processor: &LiteTexturesProcessor,
 		frame_info: &FrameWorldInfo,
 		submodel_planes_matrix_w: &Vec4f,
 		clip_planes: &ClippingPolygonPlanes,
@@ -3155,6 +3881,7 @@ impl PartialRenderer
 			self.draw_submodel_bsp_node_r(
 				rasterizer,
 				materials_processor,
+				lite_textures_processor,
 				frame_info,
 				submodel_planes_matrix_w,
 				clip_planes,
@@ -3181,6 +3908,7 @@ impl PartialRenderer
 				self.draw_polygon_decals(
 					rasterizer,
 					materials_processor,
+					lite_textures_processor,
 					clip_planes,
 					polygon_index,
 					&frame_info.decals,
@@ -3194,6 +3922,7 @@ impl PartialRenderer
 			self.draw_submodel_bsp_node_r(
 				rasterizer,
 				materials_processor,
+				lite_textures_processor,
 				frame_info,
 				submodel_planes_matrix_w,
 				clip_planes,
@@ -3290,6 +4019,7 @@ impl PartialRenderer
 	fn draw_sprite<'a, ColorT: AbstractColor>(
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
+		lite_textures_processor: &LiteTexturesProcessor,
 		clip_planes: &ClippingPolygonPlanes,
 		leaf_clip_planes: &[Plane],
 		sprites: &[Sprite],
@@ -3299,7 +4029,7 @@ impl PartialRenderer
 		let sprite = &sprites[sprite_index as usize];
 		let sprite_info = &self.sprites_info[sprite_index as usize];
 
-		let texture_mip = &sprite.texture[sprite_info.mip as usize];
+		let texture_mip = &lite_textures_processor.get_texture(&sprite.texture)[sprite_info.mip as usize];
 		let texture_size = [texture_mip.size[0] as f32, texture_mip.size[1] as f32];
 
 		let mut vertices_clipped0 = unsafe { std::mem::zeroed::<[ModelVertex3d; MAX_VERTICES]>() };
@@ -3392,6 +4122,19 @@ impl PartialRenderer
 			};
 			let texture_data = &texture_mip.pixels;
 			let blending_mode = sprite.blending_mode;
+			let color_add = sprite_info.fog.get_color_add_vec(blending_mode);
+			let depth_equation = if rasterizer.has_depth_buffer()
+			{
+				calculate_triangle_depth_equation(&[
+					sprite_info.vertices_projected[0],
+					sprite_info.vertices_projected[1],
+					sprite_info.vertices_projected[2],
+				])
+			}
+			else
+			{
+				None
+			};
 
 			for t in 0 .. num_vertices - 2
 			{
@@ -3400,6 +4143,8 @@ impl PartialRenderer
 					&texture_info,
 					texture_data,
 					blending_mode,
+					color_add,
+					depth_equation.as_ref(),
 				);
 			} // for subtriangles
 		}
@@ -3451,6 +4196,19 @@ impl PartialRenderer
 			};
 			let texture_data = &texture_mip.pixels;
 			let blending_mode = sprite.blending_mode;
+			let color_add = sprite_info.fog.get_color_add_vec(blending_mode);
+			let depth_equation = if rasterizer.has_depth_buffer()
+			{
+				calculate_triangle_depth_equation(&[
+					sprite_info.vertices_projected[0],
+					sprite_info.vertices_projected[1],
+					sprite_info.vertices_projected[2],
+				])
+			}
+			else
+			{
+				None
+			};
 
 			// Process subquads.
 			for x in 0 .. sprite_info.tesselation_level
@@ -3515,6 +4273,8 @@ impl PartialRenderer
 							&texture_info,
 							texture_data,
 							blending_mode,
+							color_add,
+							depth_equation.as_ref(),
 						);
 					} // for subtriangles
 				} // for y
@@ -3525,6 +4285,7 @@ impl PartialRenderer
 	fn draw_mesh<'a, ColorT: AbstractColor>(
 		&self,
 		rasterizer: &mut Rasterizer<'a, ColorT>,
+		lite_textures_processor: &LiteTexturesProcessor,
 		clipping_polygon: &ClippingPolygon,
 		leaf_clip_planes: &[Plane],
 		models: &[ModelEntity],
@@ -3603,14 +4364,54 @@ impl PartialRenderer
 			}
 		}
 
-		let texture = &model.texture[visible_dynamic_mesh.mip as usize];
+		let texture = &lite_textures_processor.get_texture(&model.texture)[visible_dynamic_mesh.mip as usize];
+		let texture_size = if let Some(material) = &visible_dynamic_mesh.material
+		{
+			material[visible_dynamic_mesh.mip as usize].size
+		}
+		else
+		{
+			texture.size
+		};
 
-		// TODO - use individual texture for each mesh.
 		let texture_info = TextureInfo {
-			size: [texture.size[0] as i32, texture.size[1] as i32],
+			size: [texture_size[0] as i32, texture_size[1] as i32],
+		};
+
+		let texture_data = if let Some(lit_texture_offset) = visible_dynamic_mesh.lit_texture_offset
+		{
+			&self.dynamic_meshes_lit_textures_data
+				[lit_texture_offset .. lit_texture_offset + ((texture_size[0] * texture_size[1]) as usize)]
+		}
+		else
+		{
+			&texture.pixels
 		};
 
-		let texture_data = &texture.pixels;
+		// Fog visibility is already applied to vertices light. Add fog color for each triangle.
+		let vertices_fog = if visible_dynamic_mesh.has_fog
+		{
+			&self.dynamic_meshes_vertices_fog[visible_dynamic_mesh.vertices_offset ..]
+		}
+		else
+		{
+			&[]
+		};
+		let get_triangle_color_add = |triangle: &Triangle| {
+			if vertices_fog.is_empty()
+			{
+				ColorVecI::zero()
+			}
+			else
+			{
+				FogFactor::average3(
+					&vertices_fog[triangle[0] as usize],
+					&vertices_fog[triangle[1] as usize],
+					&vertices_fog[triangle[2] as usize],
+				)
+				.get_color_add_vec(blending_mode)
+			}
+		};
 
 		let vertices_combined = &self.dynamic_meshes_vertices[visible_dynamic_mesh.vertices_offset ..];
 		let triangles = &self.dynamic_meshes_triangles[visible_dynamic_mesh.triangles_offset ..
@@ -3621,6 +4422,7 @@ impl PartialRenderer
 			// Special case - perform no clipping at all, just draw source triangles.
 			for triangle in triangles
 			{
+				let color_add = get_triangle_color_add(triangle);
 				let vertices_projected = triangle.map(|index| {
 					let v = triangle_vertex_debug_checked_fetch(vertices_combined, index);
 					let point = v.pos.truncate() / v.pos.z;
@@ -3636,7 +4438,25 @@ impl PartialRenderer
 					}
 				});
 
-				rasterizer.fill_triangle(&vertices_projected, &texture_info, texture_data, blending_mode);
+				let depth_equation = if rasterizer.has_depth_buffer()
+				{
+					calculate_triangle_depth_equation(
+						&triangle.map(|index| triangle_vertex_debug_checked_fetch(vertices_combined, index).pos),
+					)
+				}
+				else
+				{
+					None
+				};
+
+				rasterizer.fill_triangle(
+					&vertices_projected,
+					&texture_info,
+					texture_data,
+					blending_mode,
+					color_add,
+					depth_equation.as_ref(),
+				);
 			}
 		}
 		else
@@ -3649,6 +4469,8 @@ impl PartialRenderer
 
 			'triangles_loop: for triangle in triangles
 			{
+				let color_add = get_triangle_color_add(triangle);
+
 				let mut vc_src = &mut vertices_clipped0;
 				let mut vc_dst = &mut vertices_clipped1;
 
@@ -3657,6 +4479,16 @@ impl PartialRenderer
 					*dst_vertex = triangle_vertex_debug_checked_fetch(vertices_combined, index);
 				}
 
+				// Calculate depth equation before clipping, using source triangle vertices.
+				let depth_equation = if rasterizer.has_depth_buffer()
+				{
+					calculate_triangle_depth_equation(&[vc_src[0].pos, vc_src[1].pos, vc_src[2].pos])
+				}
+				else
+				{
+					None
+				};
+
 				let mut num_vertices = 3;
 				for clip_plane in &clip_planes_3d[.. num_clip_planes_3d]
 				{
@@ -3711,6 +4543,8 @@ impl PartialRenderer
 						&texture_info,
 						texture_data,
 						blending_mode,
+						color_add,
+						depth_equation.as_ref(),
 					);
 				} // for subtriangles
 			} // For triangles
@@ -3984,6 +4818,23 @@ fn polygon_is_affected_by_light(
 	}
 }
 
+// Returns clipping polygon and leaf clip planes for model drawing in given leaf or none, if model is drawn in another leaf.
+fn get_model_draw_bounds<'a>(
+	model_info: &DynamicModelInfo,
+	leaf_index: u32,
+	leaf_bounds: &'a ClippingPolygon,
+	viewport_clipping_polygon: &'a ClippingPolygon,
+	leaf_clip_planes: &'a [Plane],
+) -> Option<(&'a ClippingPolygon, &'a [Plane])>
+{
+	match model_info.single_draw_leaf
+	{
+		None => Some((leaf_bounds, leaf_clip_planes)),
+		Some(l) if l == leaf_index => Some((viewport_clipping_polygon, &[])),
+		Some(_) => None,
+	}
+}
+
 fn draw_background<ColorT: Copy + Send + Sync>(pixels: &mut [ColorT], color: ColorT)
 {
 	let num_threads = rayon::current_num_threads();
@@ -4139,4 +4990,10 @@ fn draw_polygon<'a, ColorT: AbstractColor>(
 			);
 		}
 	}
+
+	if blending_mode == BlendingMode::None
+	{
+		// Write depth in order to perform depth test for dynamic objects later (if depth buffer is used).
+		rasterizer.fill_polygon_depth(&vertices_for_rasterizer[0 .. vertex_count], depth_equation);
+	}
 }
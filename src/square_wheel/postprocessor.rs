@@ -1,4 +1,7 @@
-use super::{config, debug_stats_printer::*, fast_math::*, performance_counter::*, postprocessor_config::*};
+use super::{
+	color_grading_lut::*, config, debug_stats_printer::*, fast_math::*, frame_info::*, lens_flares::*,
+	performance_counter::*, postprocessor_config::*, postprocessor_ldr_effects::*, resources_manager::*,
+};
 use crate::common::{color::*, shared_mut_slice::*, system_window};
 use rayon::prelude::*;
 
@@ -6,6 +9,7 @@ pub struct Postprocessor
 {
 	app_config: config::ConfigSharedPtr,
 	config: PostprocessorConfig,
+	resources_manager: ResourcesManagerSharedPtr,
 
 	hdr_buffer_size: [usize; 2],
 	hdr_buffer: Vec<Color64>,
@@ -13,12 +17,25 @@ pub struct Postprocessor
 	bloom_buffer_size: [usize; 2],
 	bloom_buffers: [Vec<Color64>; 2],
 	current_exposure: f32,
+	// Color grading LUT from config and its name.
+	color_grading_lut: Option<(String, SharedResourcePtr<ColorGradingLut>)>,
+	// Result of blending of config LUT and LUT from frame info.
+	blended_color_grading_lut: ColorGradingLut,
+	// Parameters of current blended LUT. It is rebuilt only if they are changed.
+	blended_color_grading_lut_params: Option<ColorGradingLutBlendParams>,
+	// Effects chain for current frame.
+	ldr_effects: Vec<LdrEffect>,
+	// Copy of source image for effects, that require access to neighbor pixels.
+	ldr_source_buffer: Vec<Color32>,
+	ldr_frame_index: u32,
+	lens_flare_sprites: LensFlareSprites,
 }
 
 struct PostprocessorPerformanceCounters
 {
 	tonemapping_duration: PerformanceCounter,
 	bloom_duration: PerformanceCounter,
+	ldr_effects_duration: PerformanceCounter,
 }
 
 impl PostprocessorPerformanceCounters
@@ -29,6 +46,7 @@ impl PostprocessorPerformanceCounters
 		Self {
 			tonemapping_duration: PerformanceCounter::new(window_size),
 			bloom_duration: PerformanceCounter::new(window_size),
+			ldr_effects_duration: PerformanceCounter::new(window_size),
 		}
 	}
 }
@@ -36,21 +54,31 @@ type Clock = std::time::Instant;
 
 impl Postprocessor
 {
-	pub fn new(app_config: config::ConfigSharedPtr) -> Self
+	pub fn new(app_config: config::ConfigSharedPtr, resources_manager: ResourcesManagerSharedPtr) -> Self
 	{
 		let config_parsed = PostprocessorConfig::from_app_config(&app_config);
 		config_parsed.update_app_config(&app_config); // Update JSON with struct fields.
 
-		Self {
+		let mut result = Self {
 			app_config,
 			config: config_parsed,
+			resources_manager,
 			hdr_buffer_size: [0, 0],
 			hdr_buffer: Vec::new(),
 			performance_counters: PostprocessorPerformanceCounters::new(),
 			bloom_buffer_size: [0, 0],
 			bloom_buffers: [Vec::new(), Vec::new()],
 			current_exposure: 1.0,
-		}
+			color_grading_lut: None,
+			blended_color_grading_lut: ColorGradingLut::new_identity(MIN_COLOR_GRADING_LUT_SIZE),
+			blended_color_grading_lut_params: None,
+			ldr_effects: Vec::new(),
+			ldr_source_buffer: Vec::new(),
+			ldr_frame_index: 0,
+			lens_flare_sprites: LensFlareSprites::new(),
+		};
+		result.update_color_grading_lut();
+		result
 	}
 
 	pub fn get_hdr_buffer(&mut self, size: [usize; 2]) -> &mut [Color64]
@@ -65,11 +93,13 @@ impl Postprocessor
 		&mut self.hdr_buffer[.. required_size]
 	}
 
+	// Perform bloom and tonemapping.
+	// LDR effects should be applied separately (after upscaling, if it is needed).
 	pub fn perform_postprocessing(
 		&mut self,
 		pixels: &mut [Color32],
 		surface_info: &system_window::SurfaceInfo,
-		color_modulate: &[f32; 3],
+		view_info: &FrameViewInfo,
 		frame_duration_s: f32,
 		debug_stats_printer: &mut DebugStatsPrinter,
 	)
@@ -102,7 +132,11 @@ impl Postprocessor
 
 		let tonemapping_start_time = Clock::now();
 
-		let tonemapping_function = TonemappingFunction::new(self.current_exposure, color_modulate);
+		let tonemapping_function = TonemappingFunction::new(
+			self.config.tonemapping_operator,
+			self.current_exposure,
+			&view_info.color_modulate,
+		);
 
 		let num_threads = rayon::current_num_threads();
 
@@ -203,6 +237,93 @@ impl Postprocessor
 		}
 	}
 
+	// Apply chain of effects to LDR image.
+	// It should be called after tonemapping in HDR mode or directly after rendering in non-HDR mode.
+	// If frame is rendered with reduced resolution, it should be called after upscaling.
+	pub fn perform_ldr_postprocessing(
+		&mut self,
+		pixels: &mut [Color32],
+		surface_info: &system_window::SurfaceInfo,
+		view_info: &FrameViewInfo,
+		lens_flares: &[ScreenLensFlare],
+		debug_stats_printer: &mut DebugStatsPrinter,
+	)
+	{
+		let ldr_effects_start_time = Clock::now();
+
+		self.ldr_frame_index = self.ldr_frame_index.wrapping_add(1);
+
+		let color_grading_lut = self.prepare_color_grading_lut(view_info);
+		self.build_ldr_effects_chain(color_grading_lut.is_some(), lens_flares);
+
+		if self.ldr_effects.is_empty()
+		{
+			return;
+		}
+
+		let color_grading_lut = match color_grading_lut
+		{
+			Some(ColorGradingLutSource::Config) => self.color_grading_lut.as_ref().map(|(_name, lut)| lut.as_ref()),
+			Some(ColorGradingLutSource::Blended) => Some(&self.blended_color_grading_lut),
+			None => None,
+		};
+
+		let image_size = [surface_info.width, surface_info.height];
+		let use_multithreading = self.config.use_multithreading;
+
+		// Split chain into passes. Each effect, that reads neighbor pixels, starts new pass.
+		let mut pass_start = 0;
+		while pass_start < self.ldr_effects.len()
+		{
+			let pass_end = self.ldr_effects[pass_start + 1 ..]
+				.iter()
+				.position(LdrEffect::reads_neighbor_pixels)
+				.map(|p| pass_start + 1 + p)
+				.unwrap_or(self.ldr_effects.len());
+
+			if self.ldr_effects[pass_start].reads_neighbor_pixels()
+			{
+				// Copy result of previous passes in order to read it from any line.
+				self.ldr_source_buffer.clear();
+				for y in 0 .. image_size[1]
+				{
+					self.ldr_source_buffer
+						.extend_from_slice(&pixels[y * surface_info.pitch .. y * surface_info.pitch + image_size[0]]);
+				}
+			}
+
+			let data = LdrEffectsData {
+				color_grading_lut,
+				source_image: &self.ldr_source_buffer,
+				lens_flares,
+				lens_flare_sprites: &self.lens_flare_sprites,
+			};
+			perform_ldr_effects_pass(
+				pixels,
+				surface_info,
+				&self.ldr_effects[pass_start .. pass_end],
+				&data,
+				use_multithreading,
+			);
+
+			pass_start = pass_end;
+		}
+
+		let ldr_effects_end_time = Clock::now();
+		let ldr_effects_duration_s = (ldr_effects_end_time - ldr_effects_start_time).as_secs_f32();
+		self.performance_counters
+			.ldr_effects_duration
+			.add_value(ldr_effects_duration_s);
+
+		if debug_stats_printer.show_debug_stats()
+		{
+			debug_stats_printer.add_line(format!(
+				"LDR effects time: {:04.2}ms",
+				self.performance_counters.ldr_effects_duration.get_average_value() * 1000.0,
+			));
+		}
+	}
+
 	fn update_exposure(&mut self, average_color: &ColorVec, frame_duration_s: f32)
 	{
 		let brightness = get_color_brightness(average_color).max(1.0 / 1024.0).min(65536.0) / 255.0;
@@ -222,6 +343,105 @@ impl Postprocessor
 			(self.current_exposure.ln() * mix_factor + taget_exposure_clamped.ln() * (1.0 - mix_factor)).exp();
 	}
 
+	// Returns which LUT should be used for color grading (if any).
+	fn prepare_color_grading_lut(&mut self, view_info: &FrameViewInfo) -> Option<ColorGradingLutSource>
+	{
+		let config_lut = self.color_grading_lut.as_ref().map(|(_name, lut)| lut);
+		match &view_info.color_grading_lut_blend
+		{
+			Some(blend) if blend.weight > 0.0 =>
+			{
+				let params = ColorGradingLutBlendParams {
+					a: config_lut.cloned(),
+					b: blend.lut.clone(),
+					b_weight: blend.weight,
+				};
+				if !self
+					.blended_color_grading_lut_params
+					.as_ref()
+					.map(|p| p.is_same(&params))
+					.unwrap_or(false)
+				{
+					self.blended_color_grading_lut
+						.make_blend(config_lut.map(|l| l.as_ref()), &blend.lut, blend.weight);
+					self.blended_color_grading_lut_params = Some(params);
+				}
+				Some(ColorGradingLutSource::Blended)
+			},
+			_ => config_lut.map(|_| ColorGradingLutSource::Config),
+		}
+	}
+
+	fn build_ldr_effects_chain(&mut self, use_color_grading: bool, lens_flares: &[ScreenLensFlare])
+	{
+		let config = &self.config;
+		let effects = &mut self.ldr_effects;
+		effects.clear();
+
+		if config.chromatic_aberration > 0.0
+		{
+			effects.push(LdrEffect::ChromaticAberration {
+				strength: config.chromatic_aberration,
+			});
+		}
+		if config.light_shafts_strength > 0.0
+		{
+			// Use first sun for light shafts.
+			if let Some(sun) = lens_flares.iter().find(|f| f.is_sun)
+			{
+				effects.push(LdrEffect::LightShafts {
+					center: sun.position,
+					color: sun.color,
+					strength: config.light_shafts_strength * sun.light_shafts_scale,
+				});
+			}
+		}
+		if config.lens_flares_scale > 0.0 && lens_flares.iter().any(|f| f.visibility > 0.0)
+		{
+			effects.push(LdrEffect::LensFlares {
+				scale: config.lens_flares_scale,
+			});
+		}
+		if use_color_grading
+		{
+			effects.push(LdrEffect::ColorGrading);
+		}
+		if config.vignette_strength > 0.0
+		{
+			effects.push(LdrEffect::Vignette {
+				strength: config.vignette_strength,
+			});
+		}
+		if config.crt_scanlines_strength > 0.0 || config.crt_mask_strength > 0.0
+		{
+			effects.push(LdrEffect::CrtEmulation {
+				scanlines_strength: config.crt_scanlines_strength,
+				mask_strength: config.crt_mask_strength,
+			});
+		}
+		if config.film_grain_strength > 0.0
+		{
+			effects.push(LdrEffect::FilmGrain {
+				strength: config.film_grain_strength,
+				seed: self.ldr_frame_index,
+			});
+		}
+		let palette_bits = match config.palette_reduction
+		{
+			1 => Some([3, 3, 2]),
+			2 => Some([4, 4, 4]),
+			3 => Some([5, 5, 5]),
+			_ => None,
+		};
+		if let Some(bits) = palette_bits
+		{
+			effects.push(LdrEffect::PaletteReduction {
+				bits,
+				dithering: config.palette_reduction_dithering,
+			});
+		}
+	}
+
 	// Returns sum of colors.
 	fn perform_tonemapping(
 		&self,
@@ -972,8 +1192,39 @@ impl Postprocessor
 			.max(MIN_BLOOM_BUFFER_SCALE_LOG2 as u32)
 			.min(MAX_BLOOM_BUFFER_SCALE_LOG2 as u32);
 		self.config.bloom_scale = self.config.bloom_scale.max(1.0 / 16.0).min(1.0 / 2.0);
+		self.config.tonemapping_operator = self.config.tonemapping_operator.min(2);
+		self.config.vignette_strength = self.config.vignette_strength.clamp(0.0, 1.0);
+		self.config.film_grain_strength = self.config.film_grain_strength.clamp(0.0, 1.0);
+		self.config.chromatic_aberration = self.config.chromatic_aberration.clamp(0.0, 16.0);
+		self.config.crt_scanlines_strength = self.config.crt_scanlines_strength.clamp(0.0, 1.0);
+		self.config.crt_mask_strength = self.config.crt_mask_strength.clamp(0.0, 1.0);
+		self.config.palette_reduction = self.config.palette_reduction.min(3);
+		self.config.lens_flares_scale = self.config.lens_flares_scale.clamp(0.0, 4.0);
+		self.config.light_shafts_strength = self.config.light_shafts_strength.clamp(0.0, 4.0);
 
 		self.config.update_app_config(&self.app_config);
+
+		self.update_color_grading_lut();
+	}
+
+	fn update_color_grading_lut(&mut self)
+	{
+		let lut_name = &self.config.color_grading_lut;
+		if lut_name.is_empty()
+		{
+			self.color_grading_lut = None;
+			return;
+		}
+		if let Some((prev_lut_name, _)) = &self.color_grading_lut
+		{
+			if prev_lut_name == lut_name
+			{
+				return;
+			}
+		}
+
+		let lut = self.resources_manager.lock().unwrap().get_color_grading_lut(lut_name);
+		self.color_grading_lut = Some((lut_name.clone(), lut));
 	}
 
 	pub fn use_hdr_rendering(&self) -> bool
@@ -993,36 +1244,189 @@ const MAX_GAUSSIAN_KERNEL_SIZE: usize = 1 + 2 * MAX_GAUSSIAN_KERNEL_RADIUS;
 
 const MAX_THREADS: usize = 64;
 
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum ColorGradingLutSource
+{
+	Config,
+	Blended,
+}
+
+// Hold source tables in order to prevent reusing of their addresses.
+struct ColorGradingLutBlendParams
+{
+	a: Option<SharedResourcePtr<ColorGradingLut>>,
+	b: SharedResourcePtr<ColorGradingLut>,
+	b_weight: f32,
+}
+
+impl ColorGradingLutBlendParams
+{
+	fn is_same(&self, other: &Self) -> bool
+	{
+		let a_is_same = match (&self.a, &other.a)
+		{
+			(Some(l), Some(r)) => SharedResourcePtr::ptr_eq(l, r),
+			(None, None) => true,
+			_ => false,
+		};
+		a_is_same && SharedResourcePtr::ptr_eq(&self.b, &other.b) && self.b_weight == other.b_weight
+	}
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum TonemappingOperator
+{
+	Reinhard,
+	Aces,
+	Hable,
+}
+
 #[derive(Copy, Clone)]
 struct TonemappingFunction
 {
+	operator: TonemappingOperator,
 	inv_scale_vec: ColorVec,
 	inv_255_vec: ColorVec,
+	// Scale for conversion of source color into range, where 1 is white.
+	scale_vec: ColorVec,
 }
 
 impl TonemappingFunction
 {
-	fn new(exposure: f32, color_modulate: &[f32; 3]) -> Self
+	fn new(operator: u32, exposure: f32, color_modulate: &[f32; 3]) -> Self
 	{
 		let inv_255 = 1.0 / 255.0;
 
 		Self {
+			operator: match operator
+			{
+				1 => TonemappingOperator::Aces,
+				2 => TonemappingOperator::Hable,
+				_ => TonemappingOperator::Reinhard,
+			},
 			inv_scale_vec: ColorVec::from_color_f32x3(&[
 				1.0 / (exposure * color_modulate[0]),
 				1.0 / (exposure * color_modulate[1]),
 				1.0 / (exposure * color_modulate[2]),
 			]),
 			inv_255_vec: ColorVec::from_color_f32x3(&[inv_255, inv_255, inv_255]),
+			scale_vec: ColorVec::from_color_f32x3(&[
+				exposure * color_modulate[0] * inv_255,
+				exposure * color_modulate[1] * inv_255,
+				exposure * color_modulate[2] * inv_255,
+			]),
 		}
 	}
 
 	fn do_it(&self, c: &ColorVec) -> ColorVec
 	{
-		// Use Reinhard formula for tonemapping.
-		ColorVec::div(c, &ColorVec::mul_add(c, &self.inv_255_vec, &self.inv_scale_vec))
+		match self.operator
+		{
+			TonemappingOperator::Reinhard =>
+			{
+				// Use Reinhard formula for tonemapping.
+				ColorVec::div(c, &ColorVec::mul_add(c, &self.inv_255_vec, &self.inv_scale_vec))
+			},
+			TonemappingOperator::Aces =>
+			{
+				// Use fitted ACES curve (by Krzysztof Narkowicz).
+				// Scale input in order to make result brightness close to brightness of Reinhard operator.
+				let x = ColorVec::mul(c, &self.scale_vec).scalar_mul(0.6);
+				let numerator = ColorVec::mul(
+					&x,
+					&ColorVec::mul_add(&x, &color_vec_splat(2.51), &color_vec_splat(0.03)),
+				);
+				let denominator = ColorVec::mul_add(
+					&x,
+					&ColorVec::mul_add(&x, &color_vec_splat(2.43), &color_vec_splat(0.59)),
+					&color_vec_splat(0.14),
+				);
+				ColorVec::div(&numerator, &denominator).scalar_mul(255.0)
+			},
+			TonemappingOperator::Hable =>
+			{
+				// Use filmic curve from Uncharted 2 (by John Hable).
+				let x = ColorVec::mul(c, &self.scale_vec).scalar_mul(HABLE_EXPOSURE_BIAS);
+				let scale = 255.0 / hable_curve(HABLE_WHITE_POINT);
+				let numerator = ColorVec::mul_add(
+					&x,
+					&ColorVec::mul_add(&x, &color_vec_splat(HABLE_A), &color_vec_splat(HABLE_C * HABLE_B)),
+					&color_vec_splat(HABLE_D * HABLE_E),
+				);
+				let denominator = ColorVec::mul_add(
+					&x,
+					&ColorVec::mul_add(&x, &color_vec_splat(HABLE_A), &color_vec_splat(HABLE_B)),
+					&color_vec_splat(HABLE_D * HABLE_F),
+				);
+				ColorVec::mul_scalar_add(
+					&ColorVec::div(&numerator, &denominator),
+					scale,
+					&color_vec_splat(-HABLE_E / HABLE_F * scale),
+				)
+			},
+		}
 	}
 }
 
+fn perform_ldr_effects_pass(
+	pixels: &mut [Color32],
+	surface_info: &system_window::SurfaceInfo,
+	effects: &[LdrEffect],
+	data: &LdrEffectsData,
+	use_multithreading: bool,
+)
+{
+	let image_size = [surface_info.width, surface_info.height];
+	let process_lines = |pixels: &mut [Color32], y_start: usize, y_end: usize| {
+		for y in y_start .. y_end
+		{
+			let line = &mut pixels[y * surface_info.pitch .. y * surface_info.pitch + image_size[0]];
+			apply_ldr_effects_to_line(line, y, image_size, effects, data);
+		}
+	};
+
+	let num_threads = rayon::current_num_threads();
+	if num_threads == 1 || !use_multithreading
+	{
+		process_lines(pixels, 0, image_size[1]);
+	}
+	else
+	{
+		// It is safe to share destination buffer since each thead writes into its own regon.
+		let pixels_shared = SharedMutSlice::new(pixels);
+
+		let mut ranges = [[0, 0]; MAX_THREADS];
+		for i in 0 .. num_threads
+		{
+			ranges[i] = [image_size[1] * i / num_threads, image_size[1] * (i + 1) / num_threads];
+		}
+		ranges[.. num_threads].par_iter().for_each(|range| {
+			let pixels = unsafe { pixels_shared.get() };
+			process_lines(pixels, range[0], range[1]);
+		});
+	}
+}
+
+const HABLE_A: f32 = 0.15;
+const HABLE_B: f32 = 0.50;
+const HABLE_C: f32 = 0.10;
+const HABLE_D: f32 = 0.20;
+const HABLE_E: f32 = 0.02;
+const HABLE_F: f32 = 0.30;
+const HABLE_WHITE_POINT: f32 = 11.2;
+const HABLE_EXPOSURE_BIAS: f32 = 2.0;
+
+fn hable_curve(x: f32) -> f32
+{
+	(x * (HABLE_A * x + HABLE_C * HABLE_B) + HABLE_D * HABLE_E) / (x * (HABLE_A * x + HABLE_B) + HABLE_D * HABLE_F) -
+		HABLE_E / HABLE_F
+}
+
+fn color_vec_splat(v: f32) -> ColorVec
+{
+	ColorVec::from_color_f32x3(&[v, v, v])
+}
+
 fn compute_gaussian_kernel(sigma: f32, radius: usize) -> [f32; MAX_GAUSSIAN_KERNEL_SIZE]
 {
 	let mut result = [0.0; MAX_GAUSSIAN_KERNEL_SIZE];
@@ -39,6 +39,47 @@ pub struct PostprocessorConfig
 
 	#[serde(default)]
 	pub linear_bloom_filter: bool,
+
+	// 0 - Reinhard, 1 - ACES (fitted), 2 - Hable (filmic).
+	#[serde(default)]
+	pub tonemapping_operator: u32,
+
+	// Image (relative to textures path) with color grading LUT, applied after tonemapping.
+	// Empty string - no color grading.
+	#[serde(default)]
+	pub color_grading_lut: String,
+
+	// LDR effects. They are applied both in HDR and non-HDR modes. Zero strength - effect is disabled.
+	#[serde(default)]
+	pub vignette_strength: f32,
+
+	#[serde(default)]
+	pub film_grain_strength: f32,
+
+	// Shift (in pixels) of red and blue components in image corners.
+	#[serde(default)]
+	pub chromatic_aberration: f32,
+
+	#[serde(default)]
+	pub crt_scanlines_strength: f32,
+
+	#[serde(default)]
+	pub crt_mask_strength: f32,
+
+	// 0 - none, 1 - 8 bit (RGB 332), 2 - 12 bit (RGB 444), 3 - 15 bit (RGB 555).
+	#[serde(default)]
+	pub palette_reduction: u32,
+
+	#[serde(default)]
+	pub palette_reduction_dithering: bool,
+
+	// Brightness of lens flares of sun and bright lights.
+	#[serde(default)]
+	pub lens_flares_scale: f32,
+
+	// Brightness of radial light shafts from sun.
+	#[serde(default)]
+	pub light_shafts_strength: f32,
 }
 
 impl PostprocessorConfig
@@ -0,0 +1,309 @@
+use super::{color_grading_lut::*, fast_math::*, lens_flares::*};
+use crate::common::color::*;
+
+// Effects, applied to LDR image (after tonemapping or directly after rendering without HDR).
+// Effects are applied line by line in order of the chain.
+#[derive(Copy, Clone)]
+pub enum LdrEffect
+{
+	// Shift red and blue components in opposite directions. Strength is shift (in pixels) in image corners.
+	ChromaticAberration
+	{
+		strength: f32,
+	},
+	// Radial light shafts from bright pixels towards given point (relative to image size).
+	LightShafts
+	{
+		center: [f32; 2],
+		color: [f32; 3],
+		strength: f32,
+	},
+	// Flare chains of screen lens flares.
+	LensFlares
+	{
+		scale: f32,
+	},
+	// Color grading with given LUT.
+	ColorGrading,
+	// Darken image corners.
+	Vignette
+	{
+		strength: f32,
+	},
+	// Scanlines and aperture grille mask.
+	CrtEmulation
+	{
+		scanlines_strength: f32,
+		mask_strength: f32,
+	},
+	// Monochrome noise, different for each frame.
+	FilmGrain
+	{
+		strength: f32,
+		seed: u32,
+	},
+	// Reduce number of bits per component, with optional ordered dithering.
+	PaletteReduction
+	{
+		bits: [u32; 3],
+		dithering: bool,
+	},
+}
+
+impl LdrEffect
+{
+	// Effects, that read neighbor pixels, require copy of image, produced by previous effects.
+	pub fn reads_neighbor_pixels(&self) -> bool
+	{
+		matches!(
+			self,
+			LdrEffect::ChromaticAberration { .. } | LdrEffect::LightShafts { .. }
+		)
+	}
+}
+
+// Data, used by effects of chain.
+pub struct LdrEffectsData<'a>
+{
+	pub color_grading_lut: Option<&'a ColorGradingLut>,
+	// Copy of image, produced by previous effects. Used only by effects, that read neighbor pixels.
+	pub source_image: &'a [Color32],
+	pub lens_flares: &'a [ScreenLensFlare],
+	pub lens_flare_sprites: &'a LensFlareSprites,
+}
+
+// Apply effects chain to single line of image.
+pub fn apply_ldr_effects_to_line(
+	line: &mut [Color32],
+	y: usize,
+	image_size: [usize; 2],
+	effects: &[LdrEffect],
+	data: &LdrEffectsData,
+)
+{
+	for effect in effects
+	{
+		match effect
+		{
+			LdrEffect::ChromaticAberration { strength } =>
+			{
+				apply_chromatic_aberration_to_line(data.source_image, image_size, y, *strength, line)
+			},
+			LdrEffect::LightShafts {
+				center,
+				color,
+				strength,
+			} => apply_light_shafts_to_line(data.source_image, image_size, y, *center, *color, *strength, line),
+			LdrEffect::LensFlares { scale } =>
+			{
+				draw_lens_flares_line(line, y, image_size, data.lens_flares, data.lens_flare_sprites, *scale)
+			},
+			LdrEffect::ColorGrading =>
+			{
+				if let Some(lut) = data.color_grading_lut
+				{
+					apply_color_grading(line, lut);
+				}
+			},
+			LdrEffect::Vignette { strength } => apply_vignette(line, y, image_size, *strength),
+			LdrEffect::CrtEmulation {
+				scanlines_strength,
+				mask_strength,
+			} => apply_crt_emulation(line, y, *scanlines_strength, *mask_strength),
+			LdrEffect::FilmGrain { strength, seed } => apply_film_grain(line, y, *strength, *seed),
+			LdrEffect::PaletteReduction { bits, dithering } => apply_palette_reduction(line, y, bits, *dithering),
+		}
+	}
+}
+
+// Shift red and blue components in opposite directions, proportionally to distance to image center.
+// Strength is shift (in pixels) in image corners.
+fn apply_chromatic_aberration_to_line(
+	src: &[Color32],
+	image_size: [usize; 2],
+	y: usize,
+	strength: f32,
+	dst_line: &mut [Color32],
+)
+{
+	let half_size = [image_size[0] as f32 * 0.5, image_size[1] as f32 * 0.5];
+	let scale = strength / (half_size[0] * half_size[0] + half_size[1] * half_size[1]).sqrt();
+	let max_coord = [image_size[0] as i32 - 1, image_size[1] as i32 - 1];
+
+	let dy = (y as f32 + 0.5 - half_size[1]) * scale;
+	let src_line = &src[y * image_size[0] .. (y + 1) * image_size[0]];
+	for (x, dst) in dst_line.iter_mut().enumerate()
+	{
+		let dx = (x as f32 + 0.5 - half_size[0]) * scale;
+		let shift = [dx.round() as i32, dy.round() as i32];
+
+		let r_x = (x as i32 + shift[0]).clamp(0, max_coord[0]) as usize;
+		let r_y = (y as i32 + shift[1]).clamp(0, max_coord[1]) as usize;
+		let b_x = (x as i32 - shift[0]).clamp(0, max_coord[0]) as usize;
+		let b_y = (y as i32 - shift[1]).clamp(0, max_coord[1]) as usize;
+
+		let r = unsafe { debug_only_checked_fetch(src, r_x + r_y * image_size[0]) }.get_rgb()[0];
+		let g = unsafe { debug_only_checked_fetch(src_line, x) }.get_rgb()[1];
+		let b = unsafe { debug_only_checked_fetch(src, b_x + b_y * image_size[0]) }.get_rgb()[2];
+		*dst = Color32::from_rgb(r, g, b);
+	}
+}
+
+// Add radial light shafts from bright pixels towards given screen point (sun position, usually).
+// Center is relative to image size.
+fn apply_light_shafts_to_line(
+	src: &[Color32],
+	image_size: [usize; 2],
+	y: usize,
+	center: [f32; 2],
+	color: [f32; 3],
+	strength: f32,
+	dst_line: &mut [Color32],
+)
+{
+	const NUM_SAMPLES: usize = 16;
+	const DECAY: f32 = 0.9;
+	// Fraction of distance to center, covered by samples.
+	const LENGTH: f32 = 0.5;
+	// Pixels with brightness below this value do not produce shafts.
+	const THRESHOLD: f32 = 192.0;
+
+	let scale = strength * (1.0 - DECAY) / (255.0 - THRESHOLD);
+	let color_vec = ColorVec::from_color_f32x3(&[color[0] * 255.0, color[1] * 255.0, color[2] * 255.0]);
+	let max_coord = [image_size[0] as f32 - 1.0, image_size[1] as f32 - 1.0];
+	let center = [center[0] * (image_size[0] as f32), center[1] * (image_size[1] as f32)];
+
+	let y_f = y as f32 + 0.5;
+	for (x, dst) in dst_line.iter_mut().enumerate()
+	{
+		let x_f = x as f32 + 0.5;
+		let step = [
+			(center[0] - x_f) * (LENGTH / (NUM_SAMPLES as f32)),
+			(center[1] - y_f) * (LENGTH / (NUM_SAMPLES as f32)),
+		];
+
+		let mut sum = 0.0;
+		let mut weight = 1.0;
+		for i in 0 .. NUM_SAMPLES
+		{
+			let sample_x = (x_f + step[0] * (i as f32)).clamp(0.0, max_coord[0]) as usize;
+			let sample_y = (y_f + step[1] * (i as f32)).clamp(0.0, max_coord[1]) as usize;
+			let rgb = unsafe { debug_only_checked_fetch(src, sample_x + sample_y * image_size[0]) }.get_rgb();
+			let brightness = rgb[0].max(rgb[1]).max(rgb[2]) as f32;
+			sum += (brightness - THRESHOLD).max(0.0) * weight;
+			weight *= DECAY;
+		}
+
+		if sum > 0.0
+		{
+			*dst = ColorVec::mul_scalar_add(&color_vec, sum * scale, &ColorVec::from_color32(*dst)).into_color32();
+		}
+	}
+}
+
+fn apply_color_grading(line: &mut [Color32], lut: &ColorGradingLut)
+{
+	for c in line
+	{
+		*c = lut.apply(*c);
+	}
+}
+
+fn apply_vignette(line: &mut [Color32], y: usize, image_size: [usize; 2], strength: f32)
+{
+	let half_size = [image_size[0] as f32 * 0.5, image_size[1] as f32 * 0.5];
+	let inv_square_radius = 1.0 / (half_size[0] * half_size[0] + half_size[1] * half_size[1]);
+
+	let dy = y as f32 + 0.5 - half_size[1];
+	let dy2 = dy * dy;
+	for (x, c) in line.iter_mut().enumerate()
+	{
+		let dx = x as f32 + 0.5 - half_size[0];
+		// Square distance is 1 in corners.
+		let square_distance = (dx * dx + dy2) * inv_square_radius;
+		let scale = (1.0 - strength * square_distance * square_distance).max(0.0);
+		*c = ColorVec::from_color32(*c).scalar_mul(scale).into_color32();
+	}
+}
+
+fn apply_crt_emulation(line: &mut [Color32], y: usize, scanlines_strength: f32, mask_strength: f32)
+{
+	let line_scale = if (y & 1) == 0 { 1.0 } else { 1.0 - scanlines_strength };
+	let other_scale = line_scale * (1.0 - mask_strength);
+
+	// Each triple of pixels emphasizes red, green and blue components.
+	let masks = [
+		ColorVec::from_color_f32x3_with_one(&[line_scale, other_scale, other_scale]),
+		ColorVec::from_color_f32x3_with_one(&[other_scale, line_scale, other_scale]),
+		ColorVec::from_color_f32x3_with_one(&[other_scale, other_scale, line_scale]),
+	];
+
+	for (x, c) in line.iter_mut().enumerate()
+	{
+		*c = ColorVec::mul(&ColorVec::from_color32(*c), &masks[x % 3]).into_color32();
+	}
+}
+
+fn apply_film_grain(line: &mut [Color32], y: usize, strength: f32, seed: u32)
+{
+	let scale = strength * 255.0 / (u16::MAX as f32);
+	for (x, c) in line.iter_mut().enumerate()
+	{
+		let noise = (hash_coords(x as u32, y as u32, seed) & 0xFFFF) as f32 * scale;
+		let offset = noise - 0.5 * strength * 255.0;
+		let rgb = c.get_rgb();
+		*c = Color32::from_rgb(
+			(rgb[0] as f32 + offset).clamp(0.0, 255.0) as u8,
+			(rgb[1] as f32 + offset).clamp(0.0, 255.0) as u8,
+			(rgb[2] as f32 + offset).clamp(0.0, 255.0) as u8,
+		);
+	}
+}
+
+fn apply_palette_reduction(line: &mut [Color32], y: usize, bits: &[u32; 3], dithering: bool)
+{
+	let levels = [
+		((1 << bits[0]) - 1) as f32,
+		((1 << bits[1]) - 1) as f32,
+		((1 << bits[2]) - 1) as f32,
+	];
+	let inv_levels = [255.0 / levels[0], 255.0 / levels[1], 255.0 / levels[2]];
+
+	let dither_row = &BAYER_MATRIX_4X4[y & 3];
+	for (x, c) in line.iter_mut().enumerate()
+	{
+		let threshold = if dithering
+		{
+			(dither_row[x & 3] as f32 + 0.5) * (1.0 / 16.0)
+		}
+		else
+		{
+			0.5
+		};
+
+		let rgb = c.get_rgb();
+		let mut result = [0; 3];
+		for i in 0 .. 3
+		{
+			let value = (rgb[i] as f32) * (levels[i] / 255.0) + threshold;
+			result[i] = (value.floor().min(levels[i]) * inv_levels[i]) as u8;
+		}
+		*c = Color32::from_rgb(result[0], result[1], result[2]);
+	}
+}
+
+const BAYER_MATRIX_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+fn hash_coords(x: u32, y: u32, seed: u32) -> u32
+{
+	let mut h = x
+		.wrapping_mul(0x8DA6B343)
+		.wrapping_add(y.wrapping_mul(0xD8163841))
+		.wrapping_add(seed.wrapping_mul(0xCB1AB31F));
+	h ^= h >> 16;
+	h = h.wrapping_mul(0x7FEB352D);
+	h ^= h >> 15;
+	h = h.wrapping_mul(0x846CA68B);
+	h ^= h >> 16;
+	h
+}
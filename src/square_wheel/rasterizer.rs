@@ -16,21 +16,74 @@ const TEXTURE_COORDINATES_INTERPOLATION_MODE_FULL_AFFINE: usize = 2;
 pub struct Rasterizer<'a, ColorT: AbstractColor>
 {
 	color_buffer: &'a mut [ColorT],
+	// Buffer of inverse depth values with same layout as color buffer.
+	// May be empty - in such case no depth test/write is performed.
+	depth_buffer: &'a mut [f32],
 	row_size: i32,
 	clip_rect: ClipRect,
 }
 
 impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 {
-	pub fn new(color_buffer: &'a mut [ColorT], surface_info: &system_window::SurfaceInfo, clip_rect: ClipRect) -> Self
+	pub fn new(
+		color_buffer: &'a mut [ColorT],
+		depth_buffer: &'a mut [f32],
+		surface_info: &system_window::SurfaceInfo,
+		clip_rect: ClipRect,
+	) -> Self
 	{
+		debug_assert!(depth_buffer.is_empty() || depth_buffer.len() >= color_buffer.len());
 		Rasterizer {
 			color_buffer,
+			depth_buffer,
 			row_size: (surface_info.pitch) as i32,
 			clip_rect,
 		}
 	}
 
+	pub fn has_depth_buffer(&self) -> bool
+	{
+		!self.depth_buffer.is_empty()
+	}
+
+	// Fill depth buffer within clip rect with infinitely far depth.
+	pub fn clear_depth_buffer(&mut self)
+	{
+		if self.depth_buffer.is_empty()
+		{
+			return;
+		}
+
+		for y in self.clip_rect.min_y .. self.clip_rect.max_y
+		{
+			let line_buffer_offset = y * self.row_size;
+			unchecked_slice_range_mut(
+				self.depth_buffer,
+				(self.clip_rect.min_x + line_buffer_offset) as usize,
+				(self.clip_rect.max_x + line_buffer_offset) as usize,
+			)
+			.fill(0.0);
+		}
+	}
+
+	// Write depth of convex clockwise polygon into depth buffer (if it exists).
+	pub fn fill_polygon_depth(&mut self, vertices: &[PolygonPointProjected], depth_equation: &DepthEquation)
+	{
+		if self.depth_buffer.is_empty()
+		{
+			return;
+		}
+
+		self.fill_polygon_with_part_func(
+			vertices,
+			depth_equation,
+			&TexCoordEquation::default(),
+			&TextureInfo { size: [0, 0] },
+			&[],
+			Self::fill_polygon_part_depth,
+		);
+	}
+
 	// Fill convex clockwise polygon.
 	pub fn fill_polygon(
 		&mut self,
@@ -157,6 +210,38 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 			_ => Self::fill_polygon_part::<BLENDING_MODE>,
 		};
 
+		self.fill_polygon_with_part_func(
+			vertices,
+			depth_equation,
+			tex_coord_equation,
+			texture_info,
+			texture_data,
+			draw_func,
+		);
+	}
+
+	fn fill_polygon_with_part_func<
+		DrawFunc: Fn(
+			&mut Self,
+			Fixed16,
+			Fixed16,
+			PolygonSide,
+			PolygonSide,
+			&DepthEquation,
+			&TexCoordEquation,
+			&TextureInfo,
+			&[ColorT],
+		),
+	>(
+		&mut self,
+		vertices: &[PolygonPointProjected],
+		depth_equation: &DepthEquation,
+		tex_coord_equation: &TexCoordEquation,
+		texture_info: &TextureInfo,
+		texture_data: &[ColorT],
+		draw_func: DrawFunc,
+	)
+	{
 		// Search for start vertex (with min y).
 		let mut lower_vertex_index = 0;
 		let mut min_y = vertices[0].y;
@@ -728,45 +813,146 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		} // for lines
 	}
 
+	fn fill_polygon_part_depth(
+		&mut self,
+		y_start: Fixed16,
+		y_end: Fixed16,
+		left_side: PolygonSide,
+		right_side: PolygonSide,
+		depth_equation: &DepthEquation,
+		_tex_coord_equation: &TexCoordEquation,
+		_texture_info: &TextureInfo,
+		_texture_data: &[ColorT],
+	)
+	{
+		let y_start_int = fixed16_round_to_int(y_start).max(self.clip_rect.min_y);
+		let y_end_int = fixed16_round_to_int(y_end).min(self.clip_rect.max_y);
+		let y_start_delta = int_to_fixed16(y_start_int) + FIXED16_HALF - y_start;
+		let mut x_left = left_side.x_start + fixed16_mul(y_start_delta, left_side.dx_dy) + FIXED16_HALF;
+		let mut x_right = right_side.x_start + fixed16_mul(y_start_delta, right_side.dx_dy) + FIXED16_HALF;
+		// Add extra 0.5 to shift to pixel center.
+		let mut line_inv_z = (y_start_int as f32) * depth_equation.d_inv_z_dy +
+			0.5 * (depth_equation.d_inv_z_dx + depth_equation.d_inv_z_dy) +
+			depth_equation.k;
+
+		for y_int in y_start_int .. y_end_int
+		{
+			let x_start_int = fixed16_floor_to_int(x_left).max(self.clip_rect.min_x);
+			let x_end_int = fixed16_floor_to_int(x_right).min(self.clip_rect.max_x);
+			if x_start_int < x_end_int
+			{
+				let line_buffer_offset = y_int * self.row_size;
+				let line_dst = unchecked_slice_range_mut(
+					self.depth_buffer,
+					(x_start_int + line_buffer_offset) as usize,
+					(x_end_int + line_buffer_offset) as usize,
+				);
+
+				let mut inv_z = line_inv_z + (x_start_int as f32) * depth_equation.d_inv_z_dx;
+				for dst_pixel in line_dst
+				{
+					*dst_pixel = inv_z;
+					inv_z += depth_equation.d_inv_z_dx;
+				} // for span pixels
+			} // if span is non-empty
+
+			x_left += left_side.dx_dy;
+			x_right += right_side.dx_dy;
+			line_inv_z += depth_equation.d_inv_z_dy;
+		} // for lines
+	}
+
+	// "color_add" is added to lighted texel (may be used for fog).
+	// If "depth_equation" is specified and depth buffer exists, depth test is performed.
+	// Depth write is performed only for non-transparent blending modes.
 	pub fn fill_triangle<TextureColorT: AbstractColor>(
 		&mut self,
 		vertices: &[TrianglePointProjected; 3],
 		texture_info: &TextureInfo,
 		texture_data: &[TextureColorT],
 		blending_mode: BlendingMode,
+		color_add: ColorVecI,
+		depth_equation: Option<&DepthEquation>,
 	)
 	{
 		match blending_mode
 		{
-			BlendingMode::None =>
-			{
-				self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_NONE>(vertices, texture_info, texture_data)
-			},
-			BlendingMode::Average =>
-			{
-				self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_AVERAGE>(vertices, texture_info, texture_data)
-			},
-			BlendingMode::Additive =>
-			{
-				self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_ADDITIVE>(vertices, texture_info, texture_data)
-			},
-			BlendingMode::AlphaTest =>
-			{
-				self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_ALPHA_TEST>(vertices, texture_info, texture_data)
-			},
-			BlendingMode::AlphaBlend => self.fill_triangle_impl::<TextureColorT, BLENDING_MODE_ALPHA_BLEND>(
+			BlendingMode::None => self.fill_triangle_impl_1_static_params::<TextureColorT, BLENDING_MODE_NONE>(
 				vertices,
 				texture_info,
 				texture_data,
+				color_add,
+				depth_equation,
 			),
+			BlendingMode::Average => self.fill_triangle_impl_1_static_params::<TextureColorT, BLENDING_MODE_AVERAGE>(
+				vertices,
+				texture_info,
+				texture_data,
+				color_add,
+				depth_equation,
+			),
+			BlendingMode::Additive => self.fill_triangle_impl_1_static_params::<TextureColorT, BLENDING_MODE_ADDITIVE>(
+				vertices,
+				texture_info,
+				texture_data,
+				color_add,
+				depth_equation,
+			),
+			BlendingMode::AlphaTest => self
+				.fill_triangle_impl_1_static_params::<TextureColorT, BLENDING_MODE_ALPHA_TEST>(
+					vertices,
+					texture_info,
+					texture_data,
+					color_add,
+					depth_equation,
+				),
+			BlendingMode::AlphaBlend => self
+				.fill_triangle_impl_1_static_params::<TextureColorT, BLENDING_MODE_ALPHA_BLEND>(
+					vertices,
+					texture_info,
+					texture_data,
+					color_add,
+					depth_equation,
+				),
 		}
 	}
 
-	pub fn fill_triangle_impl<TextureColorT: AbstractColor, const BLENDING_MODE: usize>(
+	fn fill_triangle_impl_1_static_params<TextureColorT: AbstractColor, const BLENDING_MODE: usize>(
 		&mut self,
 		vertices: &[TrianglePointProjected; 3],
 		texture_info: &TextureInfo,
 		texture_data: &[TextureColorT],
+		color_add: ColorVecI,
+		depth_equation: Option<&DepthEquation>,
+	)
+	{
+		match depth_equation
+		{
+			Some(depth_equation) if !self.depth_buffer.is_empty() => self
+				.fill_triangle_impl::<TextureColorT, BLENDING_MODE, true>(
+					vertices,
+					texture_info,
+					texture_data,
+					color_add,
+					depth_equation,
+				),
+			_ => self.fill_triangle_impl::<TextureColorT, BLENDING_MODE, false>(
+				vertices,
+				texture_info,
+				texture_data,
+				color_add,
+				&DepthEquation::default(),
+			),
+		}
+	}
+
+	fn fill_triangle_impl<TextureColorT: AbstractColor, const BLENDING_MODE: usize, const DEPTH_TEST: bool>(
+		&mut self,
+		vertices: &[TrianglePointProjected; 3],
+		texture_info: &TextureInfo,
+		texture_data: &[TextureColorT],
+		color_add: ColorVecI,
+		depth_equation: &DepthEquation,
 	)
 	{
 		// Sort triangle vertices.
@@ -856,7 +1042,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				d_light_dx[i] = fixed16_div(long_edge_light_in_middle[i] - middle_vertex.light[i], middle_dx);
 			}
 
-			self.fill_triangle_part::<TextureColorT, BLENDING_MODE>(
+			self.fill_triangle_part::<TextureColorT, BLENDING_MODE, DEPTH_TEST>(
 				lower_vertex.y,
 				middle_vertex.y,
 				PolygonSide {
@@ -875,8 +1061,10 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				d_light_dx,
 				texture_info,
 				texture_data,
+				color_add,
+				depth_equation,
 			);
-			self.fill_triangle_part::<TextureColorT, BLENDING_MODE>(
+			self.fill_triangle_part::<TextureColorT, BLENDING_MODE, DEPTH_TEST>(
 				middle_vertex.y,
 				upper_vertex.y,
 				PolygonSide {
@@ -895,6 +1083,8 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				d_light_dx,
 				texture_info,
 				texture_data,
+				color_add,
+				depth_equation,
 			);
 		}
 		else
@@ -923,7 +1113,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				d_light_dx[i] = fixed16_div(middle_vertex.light[i] - long_edge_light_in_middle[i], middle_dx);
 			}
 
-			self.fill_triangle_part::<TextureColorT, BLENDING_MODE>(
+			self.fill_triangle_part::<TextureColorT, BLENDING_MODE, DEPTH_TEST>(
 				lower_vertex.y,
 				middle_vertex.y,
 				PolygonSide {
@@ -942,8 +1132,10 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				d_light_dx,
 				texture_info,
 				texture_data,
+				color_add,
+				depth_equation,
 			);
-			self.fill_triangle_part::<TextureColorT, BLENDING_MODE>(
+			self.fill_triangle_part::<TextureColorT, BLENDING_MODE, DEPTH_TEST>(
 				middle_vertex.y,
 				upper_vertex.y,
 				PolygonSide {
@@ -962,6 +1154,8 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 				d_light_dx,
 				texture_info,
 				texture_data,
+				color_add,
+				depth_equation,
 			);
 		}
 
@@ -981,7 +1175,7 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		}
 	}
 
-	fn fill_triangle_part<TextureColorT: AbstractColor, const BLENDING_MODE: usize>(
+	fn fill_triangle_part<TextureColorT: AbstractColor, const BLENDING_MODE: usize, const DEPTH_TEST: bool>(
 		&mut self,
 		y_start: Fixed16,
 		y_end: Fixed16,
@@ -995,6 +1189,8 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 		mut d_light_dx: [Fixed16; 3],
 		texture_info: &TextureInfo,
 		texture_data: &[TextureColorT],
+		color_add: ColorVecI,
+		depth_equation: &DepthEquation,
 	)
 	{
 		let y_start_int = fixed16_round_to_int(y_start).max(self.clip_rect.min_y);
@@ -1220,14 +1416,46 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 					(x_end_int + line_buffer_offset) as usize,
 				);
 
-				for dst_pixel in line_dst
+				let line_depth: &mut [f32] = if DEPTH_TEST
 				{
-					debug_assert!(line_light.extract::<0>() >= 0);
-					debug_assert!(line_light.extract::<1>() >= 0);
-					debug_assert!(line_light.extract::<2>() >= 0);
+					unchecked_slice_range_mut(
+						self.depth_buffer,
+						(x_start_int + line_buffer_offset) as usize,
+						(x_end_int + line_buffer_offset) as usize,
+					)
+				}
+				else
+				{
+					&mut []
+				};
+				// Add extra 0.5 to shift to pixel center.
+				let mut inv_z = depth_equation.d_inv_z_dx * ((x_start_int as f32) + 0.5) +
+					depth_equation.d_inv_z_dy * ((y_int as f32) + 0.5) +
+					depth_equation.k;
+
+				for (pixel_index, dst_pixel) in line_dst.iter_mut().enumerate()
+				{
+					let pixel_tc = line_tc;
+					let mut pixel_light = line_light;
+					let pixel_inv_z = inv_z;
+					for i in 0 .. 2
+					{
+						line_tc[i] += d_tc_dx[i];
+					}
+					line_light = ColorVecI::add(&line_light, &d_light_dx_vec);
+					inv_z += depth_equation.d_inv_z_dx;
+
+					if DEPTH_TEST && pixel_inv_z < unsafe { debug_only_checked_fetch(line_depth, pixel_index) }
+					{
+						continue;
+					}
+
+					debug_assert!(pixel_light.extract::<0>() >= 0);
+					debug_assert!(pixel_light.extract::<1>() >= 0);
+					debug_assert!(pixel_light.extract::<2>() >= 0);
 
-					let u = fixed16_floor_to_int(line_tc[0]);
-					let v = fixed16_floor_to_int(line_tc[1]);
+					let u = fixed16_floor_to_int(pixel_tc[0]);
+					let v = fixed16_floor_to_int(pixel_tc[1]);
 					debug_assert!(u >= 0);
 					debug_assert!(u < texture_info.size[0]);
 					debug_assert!(v >= 0);
@@ -1237,12 +1465,19 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 					let texel = unsafe { debug_only_checked_fetch(texture_data, texel_address) };
 
 					let texel_vec = texel.into();
-					let texel_vec_lighted = ColorVecI::shift_right::<16>(&ColorVecI::mul(&texel_vec, &line_light));
+					let texel_vec_lighted = ColorVecI::add(
+						&ColorVecI::shift_right::<16>(&ColorVecI::mul(&texel_vec, &pixel_light)),
+						&color_add,
+					);
 
 					// TODO - fix this, remove unnecessary conversions of ColorVecI
 					if BLENDING_MODE == BLENDING_MODE_NONE
 					{
 						*dst_pixel = texel_vec_lighted.into();
+						if DEPTH_TEST
+						{
+							unsafe { debug_only_checked_write(line_depth, pixel_index, pixel_inv_z) };
+						}
 					}
 					else if BLENDING_MODE == BLENDING_MODE_AVERAGE
 					{
@@ -1258,6 +1493,10 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 						if texel.test_alpha()
 						{
 							*dst_pixel = texel_converted;
+							if DEPTH_TEST
+							{
+								unsafe { debug_only_checked_write(line_depth, pixel_index, pixel_inv_z) };
+							}
 						}
 					}
 					else if BLENDING_MODE == BLENDING_MODE_ALPHA_BLEND
@@ -1270,12 +1509,6 @@ impl<'a, ColorT: AbstractColor> Rasterizer<'a, ColorT>
 						));
 						*dst_pixel = blend_result.into();
 					}
-
-					for i in 0 .. 2
-					{
-						line_tc[i] += d_tc_dx[i];
-					}
-					line_light = ColorVecI::add(&line_light, &d_light_dx_vec);
 				}
 			}
 			x_left += left_side.dx_dy;
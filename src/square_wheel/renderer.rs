@@ -1,9 +1,10 @@
 use super::{
 	abstract_color::*, config, console::*, debug_stats_printer::*, dynamic_objects_index::*, frame_info::*,
-	inline_models_index::*, map_materials_processor::*, partial_renderer::PartialRenderer, performance_counter::*,
-	renderer_config::*, renderer_structs::*, resources_manager::*,
+	inline_models_index::*, lens_flares::*, lite_textures_processor::*, map_materials_processor::*,
+	partial_renderer::PartialRenderer, performance_counter::*, renderer_config::*, renderer_structs::*,
+	resources_manager::*, triangle_models_materials::*,
 };
-use crate::common::{bsp_map_compact, system_window};
+use crate::common::{bsp_map_compact, material, system_window};
 use std::sync::Arc;
 
 pub struct Renderer
@@ -14,6 +15,7 @@ pub struct Renderer
 	common_data: RenderersCommonData,
 	map: Arc<bsp_map_compact::BSPMap>,
 	root_renderer: PartialRenderer,
+	lens_flares_builder: LensFlaresBuilder,
 	materials_update_performance_counter: PerformanceCounter,
 	object_index_build_performance_counter: PerformanceCounter,
 	debug_stats: RendererDebugStats,
@@ -34,6 +36,10 @@ impl Renderer
 
 		config_parsed.update_app_config(&app_config); // Update JSON with struct fields.
 
+		let materials = resources_manager.lock().unwrap().get_materials();
+
+		let detail_objects = create_detail_objects_models(&map, &mut resources_manager.lock().unwrap());
+
 		Self {
 			app_config: app_config.clone(),
 			config: config_parsed,
@@ -41,15 +47,20 @@ impl Renderer
 			common_data: RenderersCommonData {
 				materials_processor: MapMaterialsProcessor::new(resources_manager.clone(), app_config, &*map),
 				inline_models_index: InlineModelsIndex::new(map.clone()),
-				dynamic_models_index: DynamicObjectsIndex::new(map.clone()),
+				dynamic_models_index: DynamicObjectsIndex::new_with_detail_objects(map.clone()),
 				decals_index: DynamicObjectsIndex::new(map.clone()),
 				sprites_index: DynamicObjectsIndex::new(map.clone()),
 				dynamic_lights_index: DynamicObjectsIndex::new(map.clone()),
 				portals_index: DynamicObjectsIndex::new(map.clone()),
+				fog_volumes_index: DynamicObjectsIndex::new(map.clone()),
+				triangle_models_materials: TriangleModelsMaterials::new(resources_manager.clone()),
+				lite_textures_processor: LiteTexturesProcessor::new(resources_manager.clone()),
 				leafs_planes: (0 .. map.leafs.len())
 					.map(|leaf_index| get_leaf_clip_planes(&map, leaf_index as u32))
 					.collect(),
+				model_entities: detail_objects,
 			},
+			lens_flares_builder: LensFlaresBuilder::new(map.clone(), &materials),
 			map: map.clone(),
 			root_renderer: PartialRenderer::new(resources_manager, config_parsed, map, config_parsed.portals_depth),
 			materials_update_performance_counter: PerformanceCounter::new(100),
@@ -74,8 +85,23 @@ impl Renderer
 
 		let frame_world_info = &frame_info.world;
 
-		materials_update_performance_counter
-			.run_with_measure(|| common_data.materials_processor.update(frame_world_info.game_time_s));
+		// Detail objects are created (and placed in models index) once and are always located at start of models list.
+		// Replace only models of current frame after them.
+		common_data.model_entities.truncate(self.map.detail_objects.len());
+		common_data
+			.model_entities
+			.extend_from_slice(&frame_world_info.model_entities);
+
+		materials_update_performance_counter.run_with_measure(|| {
+			common_data.materials_processor.update(frame_world_info.game_time_s);
+			common_data
+				.lite_textures_processor
+				.update(frame_world_info, &common_data.model_entities);
+		});
+
+		common_data
+			.triangle_models_materials
+			.update(&common_data.model_entities);
 
 		object_index_build_performance_counter.run_with_measure(|| {
 			common_data
@@ -83,15 +109,21 @@ impl Renderer
 				.position_models(&frame_world_info.submodel_entities);
 			common_data
 				.dynamic_models_index
-				.position_models(&frame_world_info.model_entities);
+				.position_models(&common_data.model_entities);
 			common_data.decals_index.position_decals(&frame_world_info.decals);
 			common_data.sprites_index.position_sprites(&frame_world_info.sprites);
 			common_data
 				.dynamic_lights_index
 				.position_dynamic_lights(&frame_world_info.lights);
 			common_data.portals_index.position_portals(&frame_world_info.portals);
+			common_data
+				.fog_volumes_index
+				.position_fog_volumes(&frame_world_info.fog_volumes);
 		});
 
+		self.lens_flares_builder
+			.update(surface_info, frame_info, self.config.dynamic_lights_lens_flares);
+
 		self.root_renderer.prepare_frame::<ColorT>(
 			surface_info,
 			frame_world_info,
@@ -123,6 +155,12 @@ impl Renderer
 		self.print_debug_stats(debug_stats_printer);
 	}
 
+	// Get lens flares for last prepared frame.
+	pub fn get_screen_lens_flares(&self) -> &[ScreenLensFlare]
+	{
+		self.lens_flares_builder.get_screen_lens_flares()
+	}
+
 	fn print_debug_stats(&self, debug_stats_printer: &mut DebugStatsPrinter)
 	{
 		let performance_counters_ptr = self.root_renderer.get_performance_counters();
@@ -251,3 +289,53 @@ impl Renderer
 		self.root_renderer.set_config(self.config);
 	}
 }
+
+// Create models for map detail objects. Result models are mapped 1 to 1 to map detail objects.
+fn create_detail_objects_models(
+	map: &bsp_map_compact::BSPMap,
+	resources_manager: &mut ResourcesManager,
+) -> Vec<ModelEntity>
+{
+	map.detail_objects
+		.iter()
+		.map(|detail_object| {
+			let model = resources_manager.get_model(bsp_map_compact::get_map_string(detail_object.model, map));
+
+			// Use texture of first mesh with material, like for regular models.
+			let texture_file_name = model
+				.meshes
+				.iter()
+				.map(|mesh| mesh.material_name.as_str())
+				.find(|material_name| !material_name.is_empty())
+				.unwrap_or("");
+			let texture = resources_manager.get_texture_lite(texture_file_name);
+
+			let mut flags = ModelEntityDrawFlags::empty();
+			if (detail_object.flags & bsp_map_compact::DETAIL_OBJECT_FLAG_PER_PIXEL_LIGHTING) != 0
+			{
+				flags |= ModelEntityDrawFlags::PER_PIXEL_LIGHTING;
+			}
+			if (detail_object.flags & bsp_map_compact::DETAIL_OBJECT_FLAG_USE_MATERIALS) != 0
+			{
+				flags |= ModelEntityDrawFlags::USE_MATERIALS;
+			}
+
+			ModelEntity {
+				position: detail_object.position,
+				rotation: detail_object.rotation,
+				animation: AnimationPoint {
+					frames: [0, 0],
+					lerp: 0.0,
+				},
+				skeleton_pose: None,
+				model,
+				texture,
+				blending_mode: material::BlendingMode::None,
+				// Light grid contains precalculated static light for detail objects positions.
+				lighting: ModelLighting::Default,
+				flags,
+				ordering_custom_bbox: None,
+			}
+		})
+		.collect()
+}
@@ -28,6 +28,18 @@ pub struct RendererConfig
 
 	#[serde(default = "default_true")]
 	pub use_directional_lightmaps: bool,
+
+	#[serde(default)]
+	// Use depth buffer in order to fix ordering of intersecting dynamic objects (models and sprites).
+	pub use_depth_buffer: bool,
+
+	#[serde(default)]
+	// Positive values force usage of less detailed triangle models LODs, negative - more detailed.
+	pub models_lod_bias: f32,
+
+	#[serde(default)]
+	// Draw lens flares for all dynamic lights (in addition to lens flares from frame info and sun).
+	pub dynamic_lights_lens_flares: bool,
 }
 
 impl RendererConfig
@@ -1,6 +1,7 @@
 use super::{
-	dynamic_objects_index::*, equations::*, frame_info::*, frame_number::*, inline_models_index::*, light::*,
-	map_materials_processor::*, partial_renderer::PartialRenderer, performance_counter::*, surfaces::*,
+	dynamic_objects_index::*, equations::*, fog::*, frame_info::*, frame_number::*, inline_models_index::*, light::*,
+	lite_textures_processor::*, map_materials_processor::*, partial_renderer::PartialRenderer, performance_counter::*,
+	resources_manager::*, surfaces::*, textures::*, triangle_models_materials::*,
 };
 use crate::common::{bsp_map_compact, clipping_polygon::*, math_types::*, matrix::*, plane::*};
 
@@ -64,8 +65,13 @@ pub struct RenderersCommonData
 	pub dynamic_lights_index: DynamicObjectsIndex,
 	// Index of drawable portals polygons (not view point polygons).
 	pub portals_index: DynamicObjectsIndex,
+	pub fog_volumes_index: DynamicObjectsIndex,
+	pub triangle_models_materials: TriangleModelsMaterials,
+	pub lite_textures_processor: LiteTexturesProcessor,
 	// Store precalculated list of clip planes for each leaf in order to clip dynamic objects with these planes.
 	pub leafs_planes: Vec<LeafClipPlanes>,
+	// Map detail objects followed by models of current frame.
+	pub model_entities: Vec<ModelEntity>,
 }
 
 // Plane vector is normalized.
@@ -126,6 +132,12 @@ pub struct VisibleDynamicMeshInfo
 	pub model_matrix: Mat4f,
 	pub camera_matrices: CameraMatrices,
 	pub mip: u32,
+	// If true - fog factors are calculated for each vertex of this mesh.
+	pub has_fog: bool,
+	// Offset of texture with per-pixel light (for models with per-pixel lighting).
+	pub lit_texture_offset: Option<usize>,
+	// Material of mesh (if it has one). Meshes with material are always drawn with per-pixel light.
+	pub material: Option<SharedResourcePtr<TextureWithMips>>,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -133,6 +145,14 @@ pub struct DynamicModelInfo
 {
 	pub first_visible_mesh: u32,
 	pub num_visible_meshes: u32,
+	// LOD, selected in previous frame, and key of model for which it was selected.
+	// Used for LOD switching hysteresis.
+	pub lod: u32,
+	pub lod_model_key: usize,
+	// If some - model is drawn only once, in this leaf (last drawn leaf where model is located).
+	// Such model is not clipped by leaf planes.
+	// Otherwise model is drawn in each leaf where it is located.
+	pub single_draw_leaf: Option<u32>,
 }
 
 #[derive(Default, Copy, Clone)]
@@ -150,6 +170,7 @@ pub struct DecalInfo
 {
 	pub camera_planes_matrix: Mat4f,
 	pub dynamic_light: bsp_map_compact::LightGridElement,
+	pub fog: FogFactor,
 }
 
 #[derive(Copy, Clone)]
@@ -159,6 +180,7 @@ pub struct SpriteInfo
 	pub light: [f32; 3],
 	pub mip: u32,
 	pub tesselation_level: u32,
+	pub fog: FogFactor,
 }
 
 pub const MAX_SPRITE_TESSELATION_LEVEL: u32 = 4;
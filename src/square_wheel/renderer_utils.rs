@@ -240,3 +240,19 @@ pub fn calculate_mip(
 
 	mip as u32
 }
+
+// Calculate depth equation for triangle with given vertices in view space.
+// Returns None if triangle plane passes through camera position.
+pub fn calculate_triangle_depth_equation(vertices: &[Vec3f; 3]) -> Option<DepthEquation>
+{
+	let normal = (vertices[1] - vertices[0]).cross(vertices[2] - vertices[0]);
+	let plane = normal.extend(-normal.dot(vertices[0]));
+	if plane.w.abs() <= normal.magnitude() * MIN_PLANE_DISTANCE
+	{
+		return None;
+	}
+
+	Some(DepthEquation::from_transformed_plane_equation(&plane))
+}
+
+const MIN_PLANE_DISTANCE: f32 = 1.0 / 256.0;
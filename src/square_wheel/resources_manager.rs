@@ -1,8 +1,10 @@
 use super::{
-	config, console::*, resources_manager_config::*, textures::*, triangle_model, triangle_model_iqm,
-	triangle_model_md3,
+	color_grading_lut::*, config, console::*, resources_manager_config::*, textures::*, triangle_model_animation::*,
+};
+use crate::common::{
+	bbox::*, bsp_map_compact::*, bsp_map_save_load::*, color::*, image, material::*, math_types::*, triangle_model,
+	triangle_model_iqm, triangle_model_save_load::*,
 };
-use crate::common::{bbox::*, bsp_map_compact::*, bsp_map_save_load::*, color::*, image, material::*, math_types::*};
 use rayon::prelude::*;
 use std::{
 	collections::HashMap,
@@ -26,6 +28,10 @@ pub struct ResourcesManager
 	stub_model: SharedResourcePtr<triangle_model::TriangleModel>,
 	models_names: ResourcesNameMap,
 
+	skeleton_animations: ResourcesMap<triangle_model::TriangleModelSkeletonAnimation>,
+	// Key is combination of model name and animation name.
+	retargeted_skeleton_animations: ResourcesMap<RetargetedSkeletonAnimation>,
+
 	images: ResourcesMap<image::Image>,
 	stub_image: SharedResourcePtr<image::Image>,
 	images_names: ResourcesNameMap,
@@ -35,8 +41,14 @@ pub struct ResourcesManager
 	lite_textures: ResourcesMap<TextureLiteWithMips>,
 	lite_textures_names: ResourcesNameMap,
 
+	// Lite textures, created from diffuse images of materials. Key is material name.
+	material_lite_textures: ResourcesMap<TextureLiteWithMips>,
+	material_lite_textures_names: ResourcesNameMap,
+
 	skybox_textures_32: ResourcesMap<SkyboxTextures<Color32>>,
 	skybox_textures_64: ResourcesMap<SkyboxTextures<Color64>>,
+
+	color_grading_luts: ResourcesMap<ColorGradingLut>,
 }
 
 pub type ResourcesManagerSharedPtr = Arc<Mutex<ResourcesManager>>;
@@ -78,14 +90,19 @@ impl ResourcesManager
 			models: ResourcesMap::new(),
 			stub_model: SharedResourcePtr::new(make_stub_model()),
 			models_names: ResourcesNameMap::new(),
+			skeleton_animations: ResourcesMap::new(),
+			retargeted_skeleton_animations: ResourcesMap::new(),
 			images: ResourcesMap::new(),
 			images_names: ResourcesNameMap::new(),
 			stub_image: SharedResourcePtr::new(image::make_stub()),
 			material_textures: ResourcesMap::new(),
 			lite_textures: ResourcesMap::new(),
 			lite_textures_names: ResourcesNameMap::new(),
+			material_lite_textures: ResourcesMap::new(),
+			material_lite_textures_names: ResourcesNameMap::new(),
 			skybox_textures_32: ResourcesMap::new(),
 			skybox_textures_64: ResourcesMap::new(),
+			color_grading_luts: ResourcesMap::new(),
 		}))
 	}
 
@@ -103,6 +120,8 @@ impl ResourcesManager
 		// Clear lite textures, since they are used in some materials as emissive layer.
 		self.lite_textures.clear();
 		self.lite_textures_names.clear();
+		self.material_lite_textures.clear();
+		self.material_lite_textures_names.clear();
 	}
 
 	pub fn get_materials(&mut self) -> SharedResourcePtr<MaterialsMap>
@@ -161,14 +180,13 @@ impl ResourcesManager
 		let mut model_path = PathBuf::from(self.config.models_path.clone());
 		model_path.push(key);
 
-		let load_result = if key.ends_with(".iqm")
+		// Prefer compiled model (if it exists and is up to date) over source model.
+		if let Some(compiled_model_path) = get_compiled_model_path(&model_path)
 		{
-			triangle_model_iqm::load_model_iqm(&model_path)
+			model_path = compiled_model_path;
 		}
-		else
-		{
-			triangle_model_md3::load_model_md3(&model_path)
-		};
+
+		let load_result = load_triangle_model(&model_path);
 
 		let ptr = match load_result
 		{
@@ -194,6 +212,69 @@ impl ResourcesManager
 		self.models_names.get(&ResourcePtrInt::new(model)).map(|s| s.as_str())
 	}
 
+	// Load animation-only file. Only IQM format is supported.
+	pub fn get_skeleton_animation(
+		&mut self,
+		key: &str,
+	) -> SharedResourcePtr<triangle_model::TriangleModelSkeletonAnimation>
+	{
+		if let Some(p) = self.skeleton_animations.get(key)
+		{
+			return p.clone();
+		}
+
+		let mut animation_path = PathBuf::from(self.config.models_path.clone());
+		animation_path.push(key);
+
+		let animation = match triangle_model_iqm::load_skeleton_animation_iqm(&animation_path)
+		{
+			Ok(Some(animation)) => animation,
+			Ok(None) => make_stub_skeleton_animation(),
+			Err(e) =>
+			{
+				self.console
+					.lock()
+					.unwrap()
+					.add_text(format!("Failed to load skeleton animation {:?}: {}", animation_path, e));
+				make_stub_skeleton_animation()
+			},
+		};
+
+		let ptr = SharedResourcePtr::new(animation);
+		self.skeleton_animations.insert(key.to_string(), ptr.clone());
+		ptr
+	}
+
+	// Get animation from separate file, converted for usage with given model.
+	pub fn get_retargeted_skeleton_animation(
+		&mut self,
+		model: &SharedResourcePtr<triangle_model::TriangleModel>,
+		animation_key: &str,
+	) -> SharedResourcePtr<RetargetedSkeletonAnimation>
+	{
+		let animation = self.get_skeleton_animation(animation_key);
+
+		let model_name = if let Some(n) = self.get_model_name(model)
+		{
+			n.to_string()
+		}
+		else
+		{
+			// Model is not managed by this class - do not cache result.
+			return SharedResourcePtr::new(retarget_skeleton_animation(model, &animation));
+		};
+
+		let key = format!("{}|{}", model_name, animation_key);
+		if let Some(p) = self.retargeted_skeleton_animations.get(&key)
+		{
+			return p.clone();
+		}
+
+		let ptr = SharedResourcePtr::new(retarget_skeleton_animation(model, &animation));
+		self.retargeted_skeleton_animations.insert(key, ptr.clone());
+		ptr
+	}
+
 	pub fn get_image(&mut self, key: &str) -> SharedResourcePtr<image::Image>
 	{
 		if let Some(p) = self.images.get(key)
@@ -243,6 +324,19 @@ impl ResourcesManager
 		ptr
 	}
 
+	// Returns None if there is no such material (without printing error messages).
+	pub fn try_get_material_texture(&mut self, key: &str) -> Option<SharedResourcePtr<TextureWithMips>>
+	{
+		if self.materials.contains_key(key)
+		{
+			Some(self.get_material_texture(key))
+		}
+		else
+		{
+			None
+		}
+	}
+
 	// Load all textures for giving map in parallel.
 	pub fn get_map_material_textures(&mut self, map: &BSPMap) -> Vec<SharedResourcePtr<TextureWithMips>>
 	{
@@ -318,6 +412,46 @@ impl ResourcesManager
 			.map(|s| s.as_str())
 	}
 
+	// Get lite texture for diffuse image of given material.
+	// Such texture is animated by renderer if material has framed animation or generative effect.
+	pub fn get_material_texture_lite(&mut self, key: &str) -> SharedResourcePtr<TextureLiteWithMips>
+	{
+		if let Some(p) = self.material_lite_textures.get(key)
+		{
+			return p.clone();
+		}
+
+		let material = self.materials.get(key).unwrap_or_else(|| {
+			self.console
+				.lock()
+				.unwrap()
+				.add_text(format!("Failed to find material {:?}", key));
+			&self.default_material
+		});
+
+		let mip0 = material
+			.diffuse
+			.as_ref()
+			.and_then(|diffuse| load_image(diffuse, &self.config.textures_path))
+			.unwrap_or_else(|| (*self.stub_image).clone());
+		let ptr = SharedResourcePtr::new(make_texture_lite_mips(mip0));
+
+		self.material_lite_textures.insert(key.to_string(), ptr.clone());
+		self.material_lite_textures_names
+			.insert(ResourcePtrInt::new(&ptr), key.to_string());
+		ptr
+	}
+
+	pub fn get_material_texture_lite_name<'a>(
+		&'a self,
+		texture: &SharedResourcePtr<TextureLiteWithMips>,
+	) -> Option<&'a str>
+	{
+		self.material_lite_textures_names
+			.get(&ResourcePtrInt::new(texture))
+			.map(|s| s.as_str())
+	}
+
 	pub fn get_skybox_textures_32(&mut self, key: &str) -> SharedResourcePtr<SkyboxTextures<Color32>>
 	{
 		if let Some(p) = self.skybox_textures_32.get(key)
@@ -390,15 +524,50 @@ impl ResourcesManager
 		}
 	}
 
+	pub fn get_color_grading_lut(&mut self, key: &str) -> SharedResourcePtr<ColorGradingLut>
+	{
+		if let Some(p) = self.color_grading_luts.get(key)
+		{
+			return p.clone();
+		}
+
+		let lut = if let Some(image) = load_image(key, &self.config.textures_path)
+		{
+			ColorGradingLut::from_image(&image).unwrap_or_else(|| {
+				self.console.lock().unwrap().add_text(format!(
+					"Color grading LUT {:?} has invalid size {}x{}",
+					key, image.size[0], image.size[1]
+				));
+				ColorGradingLut::new_identity(MIN_COLOR_GRADING_LUT_SIZE)
+			})
+		}
+		else
+		{
+			self.console
+				.lock()
+				.unwrap()
+				.add_text(format!("Failed to load color grading LUT {:?}", key));
+			ColorGradingLut::new_identity(MIN_COLOR_GRADING_LUT_SIZE)
+		};
+
+		let ptr = SharedResourcePtr::new(lut);
+		self.color_grading_luts.insert(key.to_string(), ptr.clone());
+		ptr
+	}
+
 	pub fn clear_cache(&mut self)
 	{
 		// Remove all resources that are stored only inside cache.
+		remove_unused_resource_map_entries(&mut self.retargeted_skeleton_animations);
+		remove_unused_resource_map_entries(&mut self.skeleton_animations);
 		remove_unused_resource_map_entries(&mut self.models);
 		remove_unused_resource_map_entries(&mut self.images);
 		remove_unused_resource_map_entries(&mut self.material_textures);
 		remove_unused_resource_map_entries(&mut self.lite_textures);
+		remove_unused_resource_map_entries(&mut self.material_lite_textures);
 		remove_unused_resource_map_entries(&mut self.skybox_textures_32);
 		remove_unused_resource_map_entries(&mut self.skybox_textures_64);
+		remove_unused_resource_map_entries(&mut self.color_grading_luts);
 	}
 }
 
@@ -420,11 +589,21 @@ fn make_stub_model() -> triangle_model::TriangleModel
 			num_frames: 1,
 			vertex_data: triangle_model::VertexData::SkeletonAnimated(Vec::new()),
 		}],
+		lods: Vec::new(),
 		bones: Vec::new(),
 		tc_shift: Vec2f::zero(),
 	}
 }
 
+fn make_stub_skeleton_animation() -> triangle_model::TriangleModelSkeletonAnimation
+{
+	triangle_model::TriangleModelSkeletonAnimation {
+		animations: Vec::new(),
+		bones: Vec::new(),
+		frame_bones: Vec::new(),
+	}
+}
+
 fn load_texture(material: &Material, textures_path: &str) -> TextureWithMips
 {
 	let diffuse = if let Some(diffuse_texture) = &material.diffuse
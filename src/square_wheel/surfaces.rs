@@ -1,5 +1,5 @@
-use super::{abstract_color::*, fast_math::*, light::*, textures};
-use crate::common::{bsp_map_compact, lightmap, material::*, math_types::*, plane::*};
+use super::{abstract_color::*, fast_math::*, fog::*, frame_info::FogVolume, light::*, textures};
+use crate::common::{bbox::*, bsp_map_compact, lightmap, material::*, math_types::*, plane::*};
 
 // Basis vecs, that are used to reconstruct world position of surface texel.
 // This also includes normalized normal, that is used for dynamic lighting.
@@ -970,6 +970,97 @@ fn mix_surface_with_texture_impl<ColorT: AbstractColor, const BLENDING_MODE: usi
 	}
 }
 
+// Get world-space bbox of surface with given size and texture coordinates.
+pub fn get_surface_bbox(basis_vecs: &PolygonBasisVecs, surface_tc_min: [i32; 2], surface_size: [u32; 2]) -> BBox
+{
+	let tc_max = [
+		surface_tc_min[0] + (surface_size[0] as i32),
+		surface_tc_min[1] + (surface_size[1] as i32),
+	];
+
+	let corners = [
+		[surface_tc_min[0], surface_tc_min[1]],
+		[surface_tc_min[0], tc_max[1]],
+		[tc_max[0], surface_tc_min[1]],
+		[tc_max[0], tc_max[1]],
+	]
+	.map(|tc| basis_vecs.start + basis_vecs.u * (tc[0] as f32) + basis_vecs.v * (tc[1] as f32));
+
+	let mut bbox = BBox::from_point(&corners[0]);
+	for corner in &corners[1 ..]
+	{
+		bbox.extend_with_point(corner);
+	}
+	bbox
+}
+
+// Apply fog to already built surface.
+// Fog is calculated for sparse samples along each surface line, linear interpolation is used in between.
+pub fn apply_fog_to_surface<ColorT: AbstractColor>(
+	basis_vecs: &PolygonBasisVecs,
+	surface_size: [u32; 2],
+	surface_tc_min: [i32; 2],
+	fog_volumes: &[&FogVolume],
+	add_fog_color: bool,
+	cam_pos: &Vec3f,
+	surface_data: &mut [ColorT],
+)
+{
+	const SAMPLE_STEP_LOG2: u32 = 3;
+	const SAMPLE_STEP: u32 = 1 << SAMPLE_STEP_LOG2;
+	const MAX_LINE_SAMPLES: usize =
+		((((lightmap::MAX_LIGHTMAP_SIZE + 1) * lightmap::LIGHTMAP_SCALE) >> SAMPLE_STEP_LOG2) + 2) as usize;
+
+	// TODO - use uninitialized memory instead.
+	let mut line_samples = [FogFactor::none(); MAX_LINE_SAMPLES];
+	let num_line_samples =
+		((((surface_size[0] + SAMPLE_STEP - 1) >> SAMPLE_STEP_LOG2) + 1) as usize).clamp(2, MAX_LINE_SAMPLES);
+	let inv_sample_step = 1.0 / (SAMPLE_STEP as f32);
+
+	let u_vec = &basis_vecs.u;
+	let v_vec = &basis_vecs.v;
+	// Add 0.5 to get position for pixel centers.
+	let start_pos =
+		basis_vecs.start + u_vec * ((surface_tc_min[0]) as f32 + 0.5) + v_vec * ((surface_tc_min[1]) as f32 + 0.5);
+
+	// Fog visibility is stored with 8 bits of fractional part.
+	// Use 1.0 for alpha in order to keep it unchanged.
+	let alpha_one = ColorVecI::shift_left::<8>(&ColorVecI::from_color_i32x3_with_one(&[0, 0, 0]));
+
+	for (dst_v, dst_line) in surface_data
+		.chunks_mut(surface_size[0] as usize)
+		.take(surface_size[1] as usize)
+		.enumerate()
+	{
+		let line_start_pos = start_pos + v_vec * (dst_v as f32);
+		for (sample_index, sample) in line_samples[.. num_line_samples].iter_mut().enumerate()
+		{
+			let pos = line_start_pos + u_vec * ((sample_index << SAMPLE_STEP_LOG2) as f32);
+			*sample = calculate_fog_factor(fog_volumes.iter().copied(), cam_pos, &pos);
+		}
+
+		for (dst_u, dst_texel) in dst_line.iter_mut().enumerate()
+		{
+			let sample_index = (dst_u >> SAMPLE_STEP_LOG2).min(num_line_samples - 2);
+			let k = ((dst_u - (sample_index << SAMPLE_STEP_LOG2)) as f32) * inv_sample_step;
+			let fog = FogFactor::mix(&line_samples[sample_index], &line_samples[sample_index + 1], k);
+
+			let visibility = ((fog.visibility * 256.0) as i32).clamp(0, 256);
+			let scale_vec = ColorVecI::add(
+				&ColorVecI::from_color_i32x3(&[visibility, visibility, visibility]),
+				&alpha_one,
+			);
+			let texel_vec: ColorVecI = (*dst_texel).into();
+			let mut result = ColorVecI::shift_right::<8>(&ColorVecI::mul(&texel_vec, &scale_vec));
+			if add_fog_color
+			{
+				result = ColorVecI::add(&result, &ColorVecI::from_color_f32x3(&fog.color_add));
+			}
+			*dst_texel = result.into();
+		}
+	}
+}
+
 trait LightmapElementOps
 {
 	type LightmapElement: Copy;
@@ -1085,7 +1176,7 @@ impl LightmapElementOps for LightmapElementOpsDirectional
 	}
 }
 
-fn get_specular_intensity(vec_to_camera_reflected_light_angle_cos: f32, inv_roughness: f32) -> f32
+pub fn get_specular_intensity(vec_to_camera_reflected_light_angle_cos: f32, inv_roughness: f32) -> f32
 {
 	if false
 	{
@@ -1113,7 +1204,7 @@ fn get_specular_intensity(vec_to_camera_reflected_light_angle_cos: f32, inv_roug
 	}
 }
 
-fn get_fresnel_factor_base(vec_to_camera_normal_angle_cos: f32) -> f32
+pub fn get_fresnel_factor_base(vec_to_camera_normal_angle_cos: f32) -> f32
 {
 	// Schlick's approximation of Fresnel factor.
 	// See https://en.wikipedia.org/wiki/Schlick%27s_approximation.
@@ -1122,7 +1213,7 @@ fn get_fresnel_factor_base(vec_to_camera_normal_angle_cos: f32) -> f32
 	one_minus_angle_cos2 * one_minus_angle_cos2 * one_minus_angle_cos
 }
 
-fn get_specular_k_dielectric(fresnel_factor_base: f32, roughness: f32) -> f32
+pub fn get_specular_k_dielectric(fresnel_factor_base: f32, roughness: f32) -> f32
 {
 	let fresnel_factor = f32_mul_add(
 		fresnel_factor_base,
@@ -1140,7 +1231,7 @@ fn get_specular_k_dielectric(fresnel_factor_base: f32, roughness: f32) -> f32
 	)
 }
 
-fn get_specular_k_metal(fresnel_factor_base: f32, roughness: f32) -> f32
+pub fn get_specular_k_metal(fresnel_factor_base: f32, roughness: f32) -> f32
 {
 	f32_mul_add(
 		fresnel_factor_base,
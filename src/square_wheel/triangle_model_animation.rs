@@ -0,0 +1,628 @@
+use super::{frame_info::*, resources_manager::*};
+use crate::common::{bbox::*, math_types::*, matrix::*, triangle_model::*};
+
+// Final bone matrices of skeleton-animated model.
+// Each matrix transforms vertices from bind pose into animated pose (in model space).
+#[derive(Clone, Default)]
+pub struct SkeletonPose
+{
+	pub bone_matrices: Vec<Mat4f>,
+	// Bbox (in model space) of all vertices in this pose. None if model has no skeleton-animated vertices.
+	pub bbox: Option<BBox>,
+}
+
+// Animation clip (frames pair of some animation) with weight.
+#[derive(Clone)]
+pub struct SkeletonAnimationClip
+{
+	// Source of frames. If it is none - frames of model itself are used.
+	pub source: Option<SharedResourcePtr<RetargetedSkeletonAnimation>>,
+	pub animation: AnimationPoint,
+	pub weight: f32,
+}
+
+// External skeleton animation, converted for specific model.
+// Frames are stored in the same form as model frames and have model bones count.
+pub struct RetargetedSkeletonAnimation
+{
+	pub animations: Vec<TriangleModelAnimation>,
+	pub frame_bones: Vec<TriangleModelBoneFrame>,
+}
+
+// Layer of skeleton animation.
+// Clips of layer are blended together (with normalized weights),
+// than result is blended with result of previous layers, using layer weight and per-bone mask.
+#[derive(Clone)]
+pub struct SkeletonAnimationLayer
+{
+	pub clips: Vec<SkeletonAnimationClip>,
+	// Weight of each bone. Empty mask means weight 1 for all bones.
+	pub bone_mask: BoneMask,
+	pub weight: f32,
+}
+
+pub type BoneMask = Vec<f32>;
+
+// Override of specific bone transformation. Children bones are affected too.
+#[derive(Copy, Clone)]
+pub struct SkeletonBoneOverride
+{
+	pub bone_index: u32,
+	pub kind: SkeletonBoneOverrideKind,
+}
+
+#[derive(Copy, Clone)]
+pub enum SkeletonBoneOverrideKind
+{
+	// Rotation in model space around bone origin, applied after animation.
+	// Use it for head look-at, weapon aiming, etc.
+	Rotation(QuaternionF),
+	// Absolute bone transformation (from bone space into model space). Animation of this bone is ignored.
+	// Use it for ragdolls.
+	Transform(Mat4f),
+}
+
+// Build skeleton pose by combining animation layers and applying bone overrides.
+// Layers are applied in order. First layer usually is full-body layer with empty mask.
+// Result is empty for models without skeleton animation.
+pub fn build_skeleton_pose(
+	model: &TriangleModel,
+	layers: &[SkeletonAnimationLayer],
+	overrides: &[SkeletonBoneOverride],
+	out_pose: &mut SkeletonPose,
+)
+{
+	out_pose.bone_matrices.clear();
+	out_pose.bbox = None;
+
+	let num_bones = model.bones.len();
+	if num_bones == 0
+	{
+		return;
+	}
+
+	let bones_base_matrices = get_bones_base_matrices(&model.bones);
+
+	// Calculate bone transformations relative to parent.
+	// Blend decomposed transformations, since linear blending of matrices produces shear and shrinking.
+	// Start with bind pose in case if there is no layer affecting some bones.
+	let mut local_transforms = bones_base_matrices
+		.iter()
+		.map(|m| BoneTransform::from_matrix(&m.get_local_matrix(&Mat4f::identity())))
+		.collect::<Vec<_>>();
+	let mut layer_transforms = vec![BoneTransform::zero(); num_bones];
+	for (layer_index, layer) in layers.iter().enumerate()
+	{
+		let weights_sum = layer.clips.iter().map(|c| c.weight.max(0.0)).sum::<f32>();
+		if weights_sum <= 0.0
+		{
+			continue;
+		}
+
+		for t in &mut layer_transforms
+		{
+			*t = BoneTransform::zero();
+		}
+		for clip in &layer.clips
+		{
+			let weight = clip.weight.max(0.0) / weights_sum;
+			if weight <= 0.0
+			{
+				continue;
+			}
+
+			let all_frame_bones = if let Some(source) = &clip.source
+			{
+				&source.frame_bones
+			}
+			else
+			{
+				&model.frame_bones
+			};
+			let num_frames = all_frame_bones.len() / num_bones;
+			if num_frames == 0
+			{
+				continue;
+			}
+
+			let frame0 = (clip.animation.frames[0] as usize).min(num_frames - 1);
+			let frame1 = (clip.animation.frames[1] as usize).min(num_frames - 1);
+			let lerp0 = clip.animation.lerp.clamp(0.0, 1.0);
+			let weight0 = weight * lerp0;
+			let weight1 = weight * (1.0 - lerp0);
+
+			let frame_bones0 = &all_frame_bones[frame0 * num_bones .. (frame0 + 1) * num_bones];
+			let frame_bones1 = &all_frame_bones[frame1 * num_bones .. (frame1 + 1) * num_bones];
+			for (((t, base_matrices), frame_bone0), frame_bone1) in layer_transforms
+				.iter_mut()
+				.zip(&bones_base_matrices)
+				.zip(frame_bones0)
+				.zip(frame_bones1)
+			{
+				if weight0 > 0.0
+				{
+					t.add_weighted(
+						&BoneTransform::from_matrix(&base_matrices.get_local_matrix(&frame_bone0.matrix)),
+						weight0,
+					);
+				}
+				if weight1 > 0.0
+				{
+					t.add_weighted(
+						&BoneTransform::from_matrix(&base_matrices.get_local_matrix(&frame_bone1.matrix)),
+						weight1,
+					);
+				}
+			}
+		}
+
+		let layer_weight = layer.weight.clamp(0.0, 1.0);
+		for (bone_index, (dst, src)) in local_transforms.iter_mut().zip(&mut layer_transforms).enumerate()
+		{
+			src.normalize_rotation();
+
+			let bone_weight = if layer_index == 0
+			{
+				// Use first layer as base.
+				1.0
+			}
+			else
+			{
+				layer_weight * layer.bone_mask.get(bone_index).cloned().unwrap_or(1.0)
+			};
+			if bone_weight >= 1.0
+			{
+				*dst = *src;
+			}
+			else if bone_weight > 0.0
+			{
+				*dst = dst.blend(src, bone_weight);
+			}
+		}
+	}
+
+	// Calculate final matrices. This code relies on fact that all bones are sorted in hierarchy order.
+	out_pose.bone_matrices.reserve(num_bones);
+	for (bone_index, ((bone, local_transform), base_matrices)) in model
+		.bones
+		.iter()
+		.zip(&local_transforms)
+		.zip(&bones_base_matrices)
+		.enumerate()
+	{
+		let local_matrix = base_matrices.get_frame_matrix(&local_transform.to_matrix());
+		let parent = bone.parent as usize;
+		let mut mat = if parent < num_bones
+		{
+			debug_assert!(parent < bone_index);
+			out_pose.bone_matrices[parent] * local_matrix
+		}
+		else
+		{
+			local_matrix
+		};
+
+		for bone_override in overrides
+		{
+			if bone_override.bone_index as usize != bone_index
+			{
+				continue;
+			}
+			match &bone_override.kind
+			{
+				SkeletonBoneOverrideKind::Rotation(rotation) =>
+				{
+					// Rotate around current bone origin.
+					let origin = (mat * bone.base_matrix).w.truncate();
+					mat = Mat4f::from_translation(origin) *
+						Mat4f::from(*rotation) *
+						Mat4f::from_translation(-origin) *
+						mat;
+				},
+				SkeletonBoneOverrideKind::Transform(transform) =>
+				{
+					if let Some(inverse_base_matrix) = bone.base_matrix.invert()
+					{
+						mat = transform * inverse_base_matrix;
+					}
+				},
+			}
+		}
+
+		out_pose.bone_matrices.push(mat);
+	}
+
+	out_pose.bbox = calculate_skeleton_pose_bbox(model, &out_pose.bone_matrices);
+}
+
+// Each vertex is weighted sum of its position, transformed by matrices of several bones.
+// So, it is always inside bbox of all bones bboxes (in bind pose) transformed by bone matrices.
+fn calculate_skeleton_pose_bbox(model: &TriangleModel, bone_matrices: &[Mat4f]) -> Option<BBox>
+{
+	let mut bones_bboxes: Vec<Option<BBox>> = vec![None; bone_matrices.len()];
+	for mesh in &model.meshes
+	{
+		if let VertexData::SkeletonAnimated(vertices) = &mesh.vertex_data
+		{
+			for v in vertices
+			{
+				for bone_description in &v.bones_description
+				{
+					if bone_description.weight == 0
+					{
+						continue;
+					}
+					if let Some(bbox) = bones_bboxes.get_mut(bone_description.bone_index as usize)
+					{
+						if let Some(bbox) = bbox
+						{
+							bbox.extend_with_point(&v.position);
+						}
+						else
+						{
+							*bbox = Some(BBox::from_point(&v.position));
+						}
+					}
+				}
+			}
+		}
+	}
+
+	let mut result: Option<BBox> = None;
+	for (bone_matrix, bone_bbox) in bone_matrices.iter().zip(&bones_bboxes)
+	{
+		if let Some(bone_bbox) = bone_bbox
+		{
+			for v in bone_bbox.get_corners_vertices()
+			{
+				let v_transformed = (bone_matrix * v.extend(1.0)).truncate();
+				if let Some(result) = &mut result
+				{
+					result.extend_with_point(&v_transformed);
+				}
+				else
+				{
+					result = Some(BBox::from_point(&v_transformed));
+				}
+			}
+		}
+	}
+
+	result
+}
+
+// Base matrices of bone and its parent, used for conversion between frame matrices and local bone matrices.
+struct BoneBaseMatrices
+{
+	parent_base_matrix: Mat4f,
+	inverse_parent_base_matrix: Mat4f,
+	base_matrix: Mat4f,
+	inverse_base_matrix: Mat4f,
+}
+
+impl BoneBaseMatrices
+{
+	// Frame matrix is parent_base * local * inverse_base.
+	fn get_local_matrix(&self, frame_matrix: &Mat4f) -> Mat4f
+	{
+		self.inverse_parent_base_matrix * frame_matrix * self.base_matrix
+	}
+
+	fn get_frame_matrix(&self, local_matrix: &Mat4f) -> Mat4f
+	{
+		self.parent_base_matrix * local_matrix * self.inverse_base_matrix
+	}
+}
+
+fn get_bones_base_matrices(bones: &[TriangleModelBoneInfo]) -> Vec<BoneBaseMatrices>
+{
+	let inverse_base_matrices = bones
+		.iter()
+		.map(|bone| bone.base_matrix.invert().unwrap_or_else(Mat4f::identity))
+		.collect::<Vec<_>>();
+
+	bones
+		.iter()
+		.zip(&inverse_base_matrices)
+		.map(|(bone, inverse_base_matrix)| {
+			let parent = bone.parent as usize;
+			BoneBaseMatrices {
+				parent_base_matrix: bones.get(parent).map(|b| b.base_matrix).unwrap_or_else(Mat4f::identity),
+				inverse_parent_base_matrix: inverse_base_matrices
+					.get(parent)
+					.cloned()
+					.unwrap_or_else(Mat4f::identity),
+				base_matrix: bone.base_matrix,
+				inverse_base_matrix: *inverse_base_matrix,
+			}
+		})
+		.collect()
+}
+
+// Bone transformation, decomposed into translation, rotation and scale.
+// Shear is not supported.
+#[derive(Copy, Clone)]
+struct BoneTransform
+{
+	translation: Vec3f,
+	rotation: QuaternionF,
+	scale: Vec3f,
+}
+
+impl BoneTransform
+{
+	fn zero() -> Self
+	{
+		Self {
+			translation: Vec3f::zero(),
+			rotation: QuaternionF::zero(),
+			scale: Vec3f::zero(),
+		}
+	}
+
+	fn from_matrix(mat: &Mat4f) -> Self
+	{
+		let x = mat.x.truncate();
+		let y = mat.y.truncate();
+		let z = mat.z.truncate();
+		let mut scale = Vec3f::new(x.magnitude(), y.magnitude(), z.magnitude());
+		if Mat3f::from_cols(x, y, z).determinant() < 0.0
+		{
+			// Mirroring.
+			scale.x = -scale.x;
+		}
+
+		let rotation = if scale.x.abs() > MIN_SCALE && scale.y.abs() > MIN_SCALE && scale.z.abs() > MIN_SCALE
+		{
+			QuaternionF::from(Mat3f::from_cols(x / scale.x, y / scale.y, z / scale.z)).normalize()
+		}
+		else
+		{
+			// Degenerate matrix - rotation can't be extracted.
+			QuaternionF::one()
+		};
+
+		Self {
+			translation: mat.w.truncate(),
+			rotation,
+			scale,
+		}
+	}
+
+	fn to_matrix(self) -> Mat4f
+	{
+		get_object_matrix_with_scale(self.translation, self.rotation, self.scale)
+	}
+
+	// Accumulate weighted transformation. Call "normalize_rotation" after accumulating all transformations.
+	fn add_weighted(&mut self, other: &BoneTransform, weight: f32)
+	{
+		self.translation += other.translation * weight;
+		self.scale += other.scale * weight;
+		// Use shortest path for rotations blending.
+		if self.rotation.dot(other.rotation) >= 0.0
+		{
+			self.rotation += other.rotation * weight;
+		}
+		else
+		{
+			self.rotation -= other.rotation * weight;
+		}
+	}
+
+	fn normalize_rotation(&mut self)
+	{
+		if self.rotation.magnitude2() > 0.0
+		{
+			self.rotation = self.rotation.normalize();
+		}
+		else
+		{
+			self.rotation = QuaternionF::one();
+		}
+	}
+
+	fn blend(&self, other: &BoneTransform, other_weight: f32) -> Self
+	{
+		let mut result = Self::zero();
+		result.add_weighted(self, 1.0 - other_weight);
+		result.add_weighted(other, other_weight);
+		result.normalize_rotation();
+		result
+	}
+}
+
+const MIN_SCALE: f32 = 1.0e-12;
+
+// Convert external animation into form suitable for given model.
+// Bones are matched by names. Model bones without pair in animation stay in bind pose.
+// Only rotations (relative to bind pose of source skeleton) are transferred,
+// translations and scales are taken from bind pose of the model, in order to preserve its proportions.
+// The only exception is translation of root bones - its change relative to bind pose is transferred too.
+pub fn retarget_skeleton_animation(
+	model: &TriangleModel,
+	animation: &TriangleModelSkeletonAnimation,
+) -> RetargetedSkeletonAnimation
+{
+	let num_bones = model.bones.len();
+	let num_src_bones = animation.bones.len();
+	let num_frames = animation.frame_bones.len() / num_src_bones.max(1);
+
+	let bones_base_matrices = get_bones_base_matrices(&model.bones);
+	let src_bones_base_matrices = get_bones_base_matrices(&animation.bones);
+
+	// For each model bone find source bone and bind transformations (relative to parent) of both bones.
+	let mapping: Vec<Option<(usize, BoneTransform, BoneTransform)>> = model
+		.bones
+		.iter()
+		.zip(&bones_base_matrices)
+		.map(|(bone, base_matrices)| {
+			let src_index = animation.bones.iter().position(|b| b.name == bone.name)?;
+			Some((
+				src_index,
+				BoneTransform::from_matrix(&base_matrices.get_local_matrix(&Mat4f::identity())),
+				BoneTransform::from_matrix(&src_bones_base_matrices[src_index].get_local_matrix(&Mat4f::identity())),
+			))
+		})
+		.collect();
+
+	let mut frame_bones = Vec::with_capacity(num_frames * num_bones);
+	for frame in 0 .. num_frames
+	{
+		let src_frame_bones = &animation.frame_bones[frame * num_src_bones .. (frame + 1) * num_src_bones];
+		for ((bone, base_matrices), bone_mapping) in model.bones.iter().zip(&bones_base_matrices).zip(&mapping)
+		{
+			let matrix = if let Some((src_index, bind_transform, src_bind_transform)) = bone_mapping
+			{
+				let src_transform = BoneTransform::from_matrix(
+					&src_bones_base_matrices[*src_index].get_local_matrix(&src_frame_bones[*src_index].matrix),
+				);
+
+				// Apply source rotation change (in parent space) to model bind rotation.
+				let mut transform = *bind_transform;
+				transform.rotation =
+					(src_transform.rotation * src_bind_transform.rotation.invert() * bind_transform.rotation)
+						.normalize();
+				if bone.parent as usize >= num_bones
+				{
+					transform.translation += src_transform.translation - src_bind_transform.translation;
+				}
+
+				base_matrices.get_frame_matrix(&transform.to_matrix())
+			}
+			else
+			{
+				Mat4f::identity()
+			};
+			frame_bones.push(TriangleModelBoneFrame { matrix });
+		}
+	}
+
+	RetargetedSkeletonAnimation {
+		animations: animation.animations.clone(),
+		frame_bones,
+	}
+}
+
+// Get transformation of bone (from bone space into model space) for given animation point.
+// Frames are interpolated in the same way as in rendering code.
+// For MD3 models result is tag transformation.
+pub fn get_bone_matrix(model: &TriangleModel, animation: &AnimationPoint, bone_index: u32) -> Option<Mat4f>
+{
+	let num_bones = model.bones.len();
+	let bone = model.bones.get(bone_index as usize)?;
+	let num_frames = model.frame_bones.len() / num_bones;
+	if num_frames == 0
+	{
+		// No animation - use bind pose.
+		return Some(bone.base_matrix);
+	}
+
+	let frame0 = (animation.frames[0] as usize).min(num_frames - 1);
+	let frame1 = (animation.frames[1] as usize).min(num_frames - 1);
+	let lerp0 = animation.lerp.clamp(0.0, 1.0);
+	let lerp1 = 1.0 - lerp0;
+	let get_frame_matrix = |index: usize| {
+		model.frame_bones[frame0 * num_bones + index].matrix * lerp0 +
+			model.frame_bones[frame1 * num_bones + index].matrix * lerp1
+	};
+
+	// Multiply matrices of all bones in chain up to root bone.
+	let mut mat = get_frame_matrix(bone_index as usize);
+	let mut current_index = bone_index as usize;
+	loop
+	{
+		let parent = model.bones[current_index].parent as usize;
+		if parent >= current_index
+		{
+			// Root or invalid parent.
+			break;
+		}
+		mat = get_frame_matrix(parent) * mat;
+		current_index = parent;
+	}
+
+	Some(mat * bone.base_matrix)
+}
+
+// Get transformation of bone (from bone space into model space) for given skeleton pose.
+pub fn get_skeleton_pose_bone_matrix(model: &TriangleModel, pose: &SkeletonPose, bone_index: u32) -> Option<Mat4f>
+{
+	let bone = model.bones.get(bone_index as usize)?;
+	let pose_matrix = pose.bone_matrices.get(bone_index as usize)?;
+	Some(pose_matrix * bone.base_matrix)
+}
+
+// Get transformation of bone (from bone space into model space) of given model entity.
+// Skeleton pose is used if it is present, animation point otherwise.
+pub fn get_model_entity_bone_model_space_matrix(model_entity: &ModelEntity, bone_index: u32) -> Option<Mat4f>
+{
+	let model = &model_entity.model;
+	if let Some(pose) = model_entity
+		.skeleton_pose
+		.as_ref()
+		.filter(|p| p.bone_matrices.len() == model.bones.len())
+	{
+		get_skeleton_pose_bone_matrix(model, pose, bone_index)
+	}
+	else
+	{
+		get_bone_matrix(model, &model_entity.animation, bone_index)
+	}
+}
+
+// Get transformation of bone (from bone space into world space) of given model entity.
+pub fn get_model_entity_bone_matrix(model_entity: &ModelEntity, bone_index: u32) -> Option<Mat4f>
+{
+	Some(
+		get_object_matrix(model_entity.position, model_entity.rotation) *
+			get_model_entity_bone_model_space_matrix(model_entity, bone_index)?,
+	)
+}
+
+// Extract position and rotation from bone matrix. Scale is ignored.
+// Use it to place attached objects (models, sprites, lights) on bones.
+pub fn get_bone_matrix_position_and_rotation(mat: &Mat4f) -> (Vec3f, QuaternionF)
+{
+	let rotation_matrix = Mat3f::from_cols(
+		mat.x.truncate().normalize(),
+		mat.y.truncate().normalize(),
+		mat.z.truncate().normalize(),
+	);
+	(mat.w.truncate(), QuaternionF::from(rotation_matrix).normalize())
+}
+
+pub fn find_animation(animations: &[TriangleModelAnimation], name: &str) -> Option<u32>
+{
+	animations.iter().position(|a| a.name == name).map(|index| index as u32)
+}
+
+pub fn find_bone(model: &TriangleModel, bone_name: &str) -> Option<u32>
+{
+	model
+		.bones
+		.iter()
+		.position(|b| b.name == bone_name)
+		.map(|index| index as u32)
+}
+
+// Make mask with weight 1 for given bone and all its children and 0 for all other bones.
+// Use it to create upper-body or lower-body masks.
+pub fn make_bone_subtree_mask(model: &TriangleModel, root_bone_index: u32) -> BoneMask
+{
+	let mut mask = vec![0.0; model.bones.len()];
+	for (bone_index, bone) in model.bones.iter().enumerate()
+	{
+		if bone_index == root_bone_index as usize ||
+			(bone.parent as usize) < bone_index && mask[bone.parent as usize] > 0.0
+		{
+			mask[bone_index] = 1.0;
+		}
+	}
+	mask
+}
+
+pub fn invert_bone_mask(mask: &[f32]) -> BoneMask
+{
+	mask.iter().map(|w| 1.0 - w).collect()
+}
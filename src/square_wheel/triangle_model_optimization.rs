@@ -0,0 +1,229 @@
+use crate::common::{math_types::*, triangle_model::*, triangle_model_loading::*};
+
+// Perform optimizations of model meshes, which are too expensive to perform during model loading.
+pub fn optimize_model(model: &mut TriangleModel)
+{
+	for mesh in &mut model.meshes
+	{
+		remove_degenerate_triangles(mesh);
+		optimize_triangles_order(&mut mesh.triangles, get_vertex_data_num_vertices(&mesh.vertex_data));
+		reorder_vertices(mesh);
+	}
+}
+
+// Remove triangles with duplicated vertices or zero area (in all frames).
+// Returns number of removed triangles.
+pub fn remove_degenerate_triangles(mesh: &mut TriangleModelMesh) -> usize
+{
+	let num_triangles_before = mesh.triangles.len();
+
+	let vertex_data = &mesh.vertex_data;
+	mesh.triangles
+		.retain(|triangle| !is_triangle_degenerate(vertex_data, triangle));
+
+	num_triangles_before - mesh.triangles.len()
+}
+
+// Reorder triangles in order to improve vertex cache usage.
+// Tom Forsyth "Linear-Speed Vertex Cache Optimisation" algorithm is used.
+pub fn optimize_triangles_order(triangles: &mut [Triangle], num_vertices: usize)
+{
+	let mut vertex_triangles = vec![Vec::new(); num_vertices];
+	for (triangle_index, triangle) in triangles.iter().enumerate()
+	{
+		for &v in triangle
+		{
+			if let Some(t) = vertex_triangles.get_mut(v as usize)
+			{
+				t.push(triangle_index as u32);
+			}
+		}
+	}
+
+	let mut vertex_cache_positions = vec![None; num_vertices];
+	let mut vertex_scores = vertex_triangles
+		.iter()
+		.map(|t| calculate_vertex_score(None, t.len()))
+		.collect::<Vec<_>>();
+	let calculate_triangle_score = |vertex_scores: &[f32], triangle: &Triangle| -> f32 {
+		triangle
+			.iter()
+			.map(|&v| vertex_scores.get(v as usize).cloned().unwrap_or(0.0))
+			.sum()
+	};
+	let mut triangle_scores = triangles
+		.iter()
+		.map(|t| calculate_triangle_score(&vertex_scores, t))
+		.collect::<Vec<_>>();
+
+	let mut triangle_added = vec![false; triangles.len()];
+	let mut result = Vec::with_capacity(triangles.len());
+	let mut cache = Vec::<VertexIndex>::with_capacity(VERTEX_CACHE_SIZE + 3);
+	let mut first_not_added_triangle = 0;
+	while result.len() < triangles.len()
+	{
+		// Select best triangle, adjacent to vertices in cache.
+		let mut best_triangle = None;
+		let mut best_score = -1.0;
+		for &v in &cache
+		{
+			for &t in &vertex_triangles[v as usize]
+			{
+				if triangle_scores[t as usize] > best_score
+				{
+					best_score = triangle_scores[t as usize];
+					best_triangle = Some(t as usize);
+				}
+			}
+		}
+
+		// Take next not added triangle if cache contains no vertices of remaining triangles.
+		let best_triangle = if let Some(t) = best_triangle
+		{
+			t
+		}
+		else
+		{
+			while triangle_added[first_not_added_triangle]
+			{
+				first_not_added_triangle += 1;
+			}
+			first_not_added_triangle
+		};
+
+		triangle_added[best_triangle] = true;
+		let triangle = triangles[best_triangle];
+		result.push(triangle);
+
+		for &v in &triangle
+		{
+			if let Some(t) = vertex_triangles.get_mut(v as usize)
+			{
+				t.retain(|&t| t as usize != best_triangle);
+			}
+		}
+
+		// Add triangle vertices to top of the cache.
+		let mut new_cache = Vec::with_capacity(VERTEX_CACHE_SIZE + 3);
+		for &v in triangle.iter().chain(cache.iter())
+		{
+			if (v as usize) < num_vertices && !new_cache.contains(&v)
+			{
+				new_cache.push(v);
+			}
+		}
+		let evicted_vertices = if new_cache.len() > VERTEX_CACHE_SIZE
+		{
+			new_cache.split_off(VERTEX_CACHE_SIZE)
+		}
+		else
+		{
+			Vec::new()
+		};
+		cache = new_cache;
+
+		for &v in &evicted_vertices
+		{
+			vertex_cache_positions[v as usize] = None;
+		}
+		for (position, &v) in cache.iter().enumerate()
+		{
+			vertex_cache_positions[v as usize] = Some(position);
+		}
+
+		// Update scores of affected vertices and triangles.
+		for &v in cache.iter().chain(evicted_vertices.iter())
+		{
+			vertex_scores[v as usize] =
+				calculate_vertex_score(vertex_cache_positions[v as usize], vertex_triangles[v as usize].len());
+		}
+		for &v in cache.iter().chain(evicted_vertices.iter())
+		{
+			for &t in &vertex_triangles[v as usize]
+			{
+				triangle_scores[t as usize] = calculate_triangle_score(&vertex_scores, &triangles[t as usize]);
+			}
+		}
+	}
+
+	triangles.copy_from_slice(&result);
+}
+
+// Reorder vertices in order of first usage and remove unused vertices.
+pub fn reorder_vertices(mesh: &mut TriangleModelMesh)
+{
+	let num_vertices = get_vertex_data_num_vertices(&mesh.vertex_data);
+
+	let mut vertex_remap_table = vec![None; num_vertices];
+	let mut vertices_ordered = Vec::with_capacity(num_vertices);
+	for triangle in &mut mesh.triangles
+	{
+		for v in triangle
+		{
+			let remapped_index = vertex_remap_table[*v as usize].get_or_insert_with(|| {
+				vertices_ordered.push(*v as u32);
+				(vertices_ordered.len() - 1) as VertexIndex
+			});
+			*v = *remapped_index;
+		}
+	}
+
+	mesh.vertex_data = select_vertices(&mesh.vertex_data, &vertices_ordered);
+}
+
+const VERTEX_CACHE_SIZE: usize = 32;
+
+fn calculate_vertex_score(cache_position: Option<usize>, num_remaining_triangles: usize) -> f32
+{
+	if num_remaining_triangles == 0
+	{
+		// No triangles left - vertex is useless.
+		return -1.0;
+	}
+
+	const CACHE_DECAY_POWER: f32 = 1.5;
+	const LAST_TRIANGLE_SCORE: f32 = 0.75;
+	const VALENCE_BOOST_SCALE: f32 = 2.0;
+	const VALENCE_BOOST_POWER: f32 = 0.5;
+
+	let cache_score = match cache_position
+	{
+		// Vertices of last added triangle have fixed score.
+		Some(p) if p < 3 => LAST_TRIANGLE_SCORE,
+		Some(p) => (1.0 - ((p - 3) as f32) / ((VERTEX_CACHE_SIZE - 3) as f32)).powf(CACHE_DECAY_POWER),
+		None => 0.0,
+	};
+
+	// Boost vertices with small number of remaining triangles in order to remove them quickly.
+	cache_score + VALENCE_BOOST_SCALE * (num_remaining_triangles as f32).powf(-VALENCE_BOOST_POWER)
+}
+
+fn is_triangle_degenerate(vertex_data: &VertexData, triangle: &Triangle) -> bool
+{
+	if triangle[0] == triangle[1] || triangle[0] == triangle[2] || triangle[1] == triangle[2]
+	{
+		return true;
+	}
+
+	let num_vertices = get_vertex_data_num_vertices(vertex_data);
+	if triangle.iter().any(|&v| v as usize >= num_vertices)
+	{
+		return true;
+	}
+
+	let i = triangle.map(|v| v as usize);
+	match vertex_data
+	{
+		VertexData::NonAnimated(v) => is_zero_area(&[v[i[0]].position, v[i[1]].position, v[i[2]].position]),
+		VertexData::VertexAnimated { variable, .. } => variable
+			.chunks(num_vertices)
+			.all(|v| is_zero_area(&[v[i[0]].position, v[i[1]].position, v[i[2]].position])),
+		// Triangle of skeleton-animated mesh may become non-degenerate in some pose, but this is unlikely.
+		VertexData::SkeletonAnimated(v) => is_zero_area(&[v[i[0]].position, v[i[1]].position, v[i[2]].position]),
+	}
+}
+
+fn is_zero_area(v: &[Vec3f; 3]) -> bool
+{
+	(v[1] - v[0]).cross(v[2] - v[0]).magnitude2() <= 0.0
+}
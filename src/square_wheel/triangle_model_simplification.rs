@@ -0,0 +1,465 @@
+use super::triangle_model_optimization::*;
+use crate::common::{math_types::*, triangle_model::*};
+use std::{cmp::Reverse, collections::BinaryHeap};
+
+// Generate less detailed LODs for model, using meshes of first LOD.
+// Each next LOD has number of triangles reduced by given factor and half max screen size of previous LOD.
+pub fn generate_triangle_model_lods(
+	model: &mut TriangleModel,
+	num_lods: u32,
+	triangles_reduction_factor: f32,
+	first_lod_max_screen_size: f32,
+)
+{
+	let base_lod_meshes = model.meshes[get_triangle_model_lod_meshes_range(model, 0)].to_vec();
+	make_single_lod(model);
+
+	let mut max_screen_size = first_lod_max_screen_size;
+	let mut triangles_scale = 1.0;
+	for _lod in 1 .. num_lods
+	{
+		triangles_scale *= triangles_reduction_factor;
+		let meshes = base_lod_meshes
+			.iter()
+			.map(|mesh| {
+				let target_triangles = ((mesh.triangles.len() as f32) * triangles_scale).ceil() as usize;
+				simplify_mesh(mesh, target_triangles)
+			})
+			.collect();
+
+		add_triangle_model_lod(model, meshes, max_screen_size);
+		max_screen_size *= 0.5;
+	}
+}
+
+// Add LOD with given meshes. LODs should be added in order of decreasing detail level.
+pub fn add_triangle_model_lod(model: &mut TriangleModel, meshes: Vec<TriangleModelMesh>, max_screen_size: f32)
+{
+	if model.lods.is_empty()
+	{
+		make_single_lod(model);
+	}
+
+	model.lods.push(TriangleModelLod {
+		first_mesh: model.meshes.len() as u32,
+		num_meshes: meshes.len() as u32,
+		max_screen_size,
+	});
+	model.meshes.extend(meshes);
+}
+
+// Simplify mesh by collapsing its edges until number of triangles is less or equal to target number.
+// Half-edge collapse is used - one vertex of edge is moved into another one.
+// So, no new vertices are created and this method works for all kinds of vertex data.
+// Cost of collapse is calculated using quadric error metric for first frame positions.
+pub fn simplify_mesh(mesh: &TriangleModelMesh, target_triangles: usize) -> TriangleModelMesh
+{
+	let positions = get_first_frame_positions(&mesh.vertex_data);
+	let num_vertices = positions.len();
+
+	let mut triangles = mesh.triangles.clone();
+	triangles.retain(|t| t.iter().all(|&v| (v as usize) < num_vertices));
+
+	let mut vertex_triangles = vec![Vec::new(); num_vertices];
+	for (triangle_index, triangle) in triangles.iter().enumerate()
+	{
+		for &v in triangle
+		{
+			vertex_triangles[v as usize].push(triangle_index as u32);
+		}
+	}
+
+	let mut quadrics = vec![Quadric::zero(); num_vertices];
+	for triangle in &triangles
+	{
+		let v = triangle.map(|i| positions[i as usize]);
+		let normal = (v[2] - v[0]).cross(v[1] - v[0]);
+		let double_area = normal.magnitude();
+		if double_area <= 0.0
+		{
+			continue;
+		}
+		let normal_normalized = normal / double_area;
+		let quadric = Quadric::from_plane(&normal_normalized, -normal_normalized.dot(v[0]), double_area);
+		for &i in triangle
+		{
+			quadrics[i as usize].add(&quadric);
+		}
+	}
+
+	// Add quadrics for border edges (also texture coordinates seams), in order to preserve mesh border.
+	const BORDER_WEIGHT: f32 = 16.0;
+	for triangle in &triangles
+	{
+		for edge in 0 .. 3
+		{
+			let (v0, v1) = (triangle[edge], triangle[(edge + 1) % 3]);
+			if count_edge_triangles(&triangles, &vertex_triangles, v0, v1) != 1
+			{
+				continue;
+			}
+
+			let p = triangle.map(|i| positions[i as usize]);
+			let edge_vec = positions[v1 as usize] - positions[v0 as usize];
+			let triangle_normal = (p[2] - p[0]).cross(p[1] - p[0]);
+			let border_normal = edge_vec.cross(triangle_normal);
+			let border_normal_len = border_normal.magnitude();
+			if border_normal_len <= 0.0
+			{
+				continue;
+			}
+			let border_normal_normalized = border_normal / border_normal_len;
+			let quadric = Quadric::from_plane(
+				&border_normal_normalized,
+				-border_normal_normalized.dot(positions[v0 as usize]),
+				edge_vec.magnitude2() * BORDER_WEIGHT,
+			);
+			quadrics[v0 as usize].add(&quadric);
+			quadrics[v1 as usize].add(&quadric);
+		}
+	}
+
+	let mut vertex_versions = vec![0u32; num_vertices];
+	let mut vertex_collapsed = vec![false; num_vertices];
+	let mut triangle_removed = vec![false; triangles.len()];
+	let mut num_triangles_left = triangles.len();
+
+	let mut collapses_queue = BinaryHeap::new();
+	let push_vertex_collapses = |queue: &mut BinaryHeap<_>,
+	                             triangles: &[Triangle],
+	                             vertex_triangles: &[Vec<u32>],
+	                             quadrics: &[Quadric],
+	                             vertex_versions: &[u32],
+	                             v: VertexIndex| {
+		for &t in &vertex_triangles[v as usize]
+		{
+			for &dst in &triangles[t as usize]
+			{
+				if dst == v
+				{
+					continue;
+				}
+				// Use error of both vertices, since quadric of destination vertex accumulates previous collapses.
+				let mut quadric = quadrics[v as usize];
+				quadric.add(&quadrics[dst as usize]);
+				let cost = quadric.evaluate(&positions[dst as usize]).max(0.0);
+				queue.push(Reverse(CollapseCandidate {
+					// Positive floats may be compared as integers.
+					cost: cost.to_bits(),
+					src: v,
+					dst,
+					src_version: vertex_versions[v as usize],
+					dst_version: vertex_versions[dst as usize],
+				}));
+			}
+		}
+	};
+
+	for v in 0 .. num_vertices
+	{
+		push_vertex_collapses(
+			&mut collapses_queue,
+			&triangles,
+			&vertex_triangles,
+			&quadrics,
+			&vertex_versions,
+			v as VertexIndex,
+		);
+	}
+
+	while num_triangles_left > target_triangles
+	{
+		let candidate = if let Some(Reverse(c)) = collapses_queue.pop()
+		{
+			c
+		}
+		else
+		{
+			break;
+		};
+
+		let (src, dst) = (candidate.src as usize, candidate.dst as usize);
+		if vertex_collapsed[src] ||
+			vertex_collapsed[dst] ||
+			vertex_versions[src] != candidate.src_version ||
+			vertex_versions[dst] != candidate.dst_version
+		{
+			// Outdated candidate.
+			continue;
+		}
+
+		if !is_collapse_valid(
+			&triangles,
+			&vertex_triangles[src],
+			&positions,
+			candidate.src,
+			candidate.dst,
+		)
+		{
+			continue;
+		}
+
+		// Perform collapse.
+		for &t in &vertex_triangles[src]
+		{
+			let triangle = &mut triangles[t as usize];
+			if triangle.contains(&candidate.dst)
+			{
+				triangle_removed[t as usize] = true;
+				num_triangles_left -= 1;
+			}
+			else
+			{
+				for v in triangle.iter_mut()
+				{
+					if *v == candidate.src
+					{
+						*v = candidate.dst;
+					}
+				}
+			}
+		}
+
+		let src_triangles = std::mem::take(&mut vertex_triangles[src]);
+		for t in src_triangles
+		{
+			if triangle_removed[t as usize]
+			{
+				for &v in &triangles[t as usize]
+				{
+					vertex_triangles[v as usize].retain(|&vt| vt != t);
+				}
+			}
+			else
+			{
+				vertex_triangles[dst].push(t);
+			}
+		}
+
+		let src_quadric = quadrics[src];
+		quadrics[dst].add(&src_quadric);
+		vertex_collapsed[src] = true;
+
+		// Update collapses for affected vertices.
+		let mut affected_vertices = vec![candidate.dst];
+		for &t in &vertex_triangles[dst]
+		{
+			for &v in &triangles[t as usize]
+			{
+				if !affected_vertices.contains(&v)
+				{
+					affected_vertices.push(v);
+				}
+			}
+		}
+		for &v in &affected_vertices
+		{
+			vertex_versions[v as usize] += 1;
+		}
+		for &v in &affected_vertices
+		{
+			push_vertex_collapses(
+				&mut collapses_queue,
+				&triangles,
+				&vertex_triangles,
+				&quadrics,
+				&vertex_versions,
+				v,
+			);
+		}
+	}
+
+	let mut result = TriangleModelMesh {
+		name: mesh.name.clone(),
+		material_name: mesh.material_name.clone(),
+		triangles: triangles
+			.iter()
+			.zip(triangle_removed.iter())
+			.filter(|(_, removed)| !**removed)
+			.map(|(t, _)| *t)
+			.collect(),
+		num_frames: mesh.num_frames,
+		vertex_data: mesh.vertex_data.clone(),
+	};
+
+	// Remove vertices, that are not used anymore.
+	remove_degenerate_triangles(&mut result);
+	reorder_vertices(&mut result);
+
+	result
+}
+
+// Leave only first LOD or create it if model has no LODs.
+fn make_single_lod(model: &mut TriangleModel)
+{
+	if let Some(first_lod) = model.lods.first().cloned()
+	{
+		if model.lods.len() > 1
+		{
+			model.meshes = model.meshes[get_triangle_model_lod_meshes_range(model, 0)].to_vec();
+			model.lods = vec![TriangleModelLod {
+				first_mesh: 0,
+				num_meshes: first_lod.num_meshes,
+				max_screen_size: first_lod.max_screen_size,
+			}];
+		}
+	}
+	else
+	{
+		model.lods = vec![TriangleModelLod {
+			first_mesh: 0,
+			num_meshes: model.meshes.len() as u32,
+			max_screen_size: f32::MAX,
+		}];
+	}
+}
+
+fn get_first_frame_positions(vertex_data: &VertexData) -> Vec<Vec3f>
+{
+	match vertex_data
+	{
+		VertexData::NonAnimated(v) => v.iter().map(|v| v.position).collect(),
+		VertexData::VertexAnimated { constant, variable } =>
+		{
+			variable.iter().take(constant.len()).map(|v| v.position).collect()
+		},
+		VertexData::SkeletonAnimated(v) => v.iter().map(|v| v.position).collect(),
+	}
+}
+
+fn count_edge_triangles(
+	triangles: &[Triangle],
+	vertex_triangles: &[Vec<u32>],
+	v0: VertexIndex,
+	v1: VertexIndex,
+) -> usize
+{
+	vertex_triangles[v0 as usize]
+		.iter()
+		.filter(|&&t| triangles[t as usize].contains(&v1))
+		.count()
+}
+
+// Collapse is valid if edge exists and no one of remaining triangles is flipped.
+fn is_collapse_valid(
+	triangles: &[Triangle],
+	src_triangles: &[u32],
+	positions: &[Vec3f],
+	src: VertexIndex,
+	dst: VertexIndex,
+) -> bool
+{
+	let mut edge_found = false;
+	for &t in src_triangles
+	{
+		let triangle = &triangles[t as usize];
+		if triangle.contains(&dst)
+		{
+			edge_found = true;
+			continue;
+		}
+
+		let p = triangle.map(|i| positions[i as usize]);
+		let p_new = triangle.map(|i| positions[if i == src { dst } else { i } as usize]);
+		let normal = (p[2] - p[0]).cross(p[1] - p[0]);
+		let normal_new = (p_new[2] - p_new[0]).cross(p_new[1] - p_new[0]);
+		if normal.dot(normal_new) <= 0.0
+		{
+			return false;
+		}
+	}
+
+	edge_found
+}
+
+#[derive(PartialEq, Eq, PartialOrd, Ord)]
+struct CollapseCandidate
+{
+	cost: u32,
+	src: VertexIndex,
+	dst: VertexIndex,
+	src_version: u32,
+	dst_version: u32,
+}
+
+// Symmetric 4x4 matrix of plane equation products.
+#[derive(Copy, Clone)]
+struct Quadric
+{
+	a2: f32,
+	ab: f32,
+	ac: f32,
+	ad: f32,
+	b2: f32,
+	bc: f32,
+	bd: f32,
+	c2: f32,
+	cd: f32,
+	d2: f32,
+}
+
+impl Quadric
+{
+	fn zero() -> Self
+	{
+		Self {
+			a2: 0.0,
+			ab: 0.0,
+			ac: 0.0,
+			ad: 0.0,
+			b2: 0.0,
+			bc: 0.0,
+			bd: 0.0,
+			c2: 0.0,
+			cd: 0.0,
+			d2: 0.0,
+		}
+	}
+
+	fn from_plane(normal: &Vec3f, dist: f32, weight: f32) -> Self
+	{
+		let (a, b, c, d) = (normal.x, normal.y, normal.z, dist);
+		Self {
+			a2: a * a * weight,
+			ab: a * b * weight,
+			ac: a * c * weight,
+			ad: a * d * weight,
+			b2: b * b * weight,
+			bc: b * c * weight,
+			bd: b * d * weight,
+			c2: c * c * weight,
+			cd: c * d * weight,
+			d2: d * d * weight,
+		}
+	}
+
+	fn add(&mut self, other: &Quadric)
+	{
+		self.a2 += other.a2;
+		self.ab += other.ab;
+		self.ac += other.ac;
+		self.ad += other.ad;
+		self.b2 += other.b2;
+		self.bc += other.bc;
+		self.bd += other.bd;
+		self.c2 += other.c2;
+		self.cd += other.cd;
+		self.d2 += other.d2;
+	}
+
+	// Calculate sum of weighted squared distances to planes.
+	fn evaluate(&self, p: &Vec3f) -> f32
+	{
+		let (x, y, z) = (p.x, p.y, p.z);
+		x * x * self.a2 +
+			2.0 * x * y * self.ab +
+			2.0 * x * z * self.ac +
+			2.0 * x * self.ad +
+			y * y * self.b2 +
+			2.0 * y * z * self.bc +
+			2.0 * y * self.bd +
+			z * z * self.c2 +
+			2.0 * z * self.cd +
+			self.d2
+	}
+}
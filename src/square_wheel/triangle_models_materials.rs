@@ -0,0 +1,91 @@
+use super::{frame_info::*, resources_manager::*, textures::*};
+use crate::common::triangle_model::*;
+use std::collections::HashMap;
+
+// Materials of triangle models meshes, resolved by mesh material names.
+// Materials are used only for models with USE_MATERIALS flag.
+// Meshes without proper material are drawn with model entity texture.
+pub struct TriangleModelsMaterials
+{
+	resources_manager: ResourcesManagerSharedPtr,
+	// Use model pointer as key.
+	models_materials: HashMap<usize, ModelMaterials>,
+}
+
+struct ModelMaterials
+{
+	// Hold model in order to prevent reusing of its address by another model.
+	_model: SharedResourcePtr<TriangleModel>,
+	meshes_materials: Vec<Option<SharedResourcePtr<TextureWithMips>>>,
+}
+
+impl TriangleModelsMaterials
+{
+	pub fn new(resources_manager: ResourcesManagerSharedPtr) -> Self
+	{
+		Self {
+			resources_manager,
+			models_materials: HashMap::new(),
+		}
+	}
+
+	// Resolve materials for models, that are used first time.
+	pub fn update(&mut self, model_entities: &[ModelEntity])
+	{
+		for model_entity in model_entities
+		{
+			if !model_entity.flags.contains(ModelEntityDrawFlags::USE_MATERIALS)
+			{
+				continue;
+			}
+
+			let key = get_model_key(&model_entity.model);
+			if self.models_materials.contains_key(&key)
+			{
+				continue;
+			}
+
+			let mut r = self.resources_manager.lock().unwrap();
+			let meshes_materials = model_entity
+				.model
+				.meshes
+				.iter()
+				.map(|mesh| {
+					if mesh.material_name.is_empty()
+					{
+						None
+					}
+					else
+					{
+						r.try_get_material_texture(&mesh.material_name)
+					}
+				})
+				.collect();
+
+			self.models_materials.insert(
+				key,
+				ModelMaterials {
+					_model: model_entity.model.clone(),
+					meshes_materials,
+				},
+			);
+		}
+	}
+
+	pub fn get_mesh_material(
+		&self,
+		model: &SharedResourcePtr<TriangleModel>,
+		mesh_index: usize,
+	) -> Option<&SharedResourcePtr<TextureWithMips>>
+	{
+		self.models_materials
+			.get(&get_model_key(model))
+			.and_then(|m| m.meshes_materials.get(mesh_index))
+			.and_then(|m| m.as_ref())
+	}
+}
+
+fn get_model_key(model: &SharedResourcePtr<TriangleModel>) -> usize
+{
+	SharedResourcePtr::as_ptr(model) as usize
+}
@@ -1,6 +1,7 @@
-use super::{fast_math::*, frame_info::*, light::*, textures::*, triangle_model::*};
+use super::{fast_math::*, frame_info::*, light::*, surfaces::*, textures::*, triangle_model_animation::*};
 use crate::common::{
-	bbox::*, bsp_map_compact, clipping::*, clipping_polygon::*, light_cube::*, math_types::*, matrix::*, plane::*,
+	bbox::*, bsp_map_compact, clipping::*, clipping_polygon::*, color::*, light_cube::*, math_types::*, matrix::*,
+	plane::*, triangle_model::*,
 };
 use std::mem::MaybeUninit;
 
@@ -8,12 +9,17 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 	model: &TriangleModel,
 	mesh: &TriangleModelMesh,
 	animation: &AnimationPoint,
+	// Use precalculated pose (if it is present) instead of animation for skeleton-animated models.
+	skeleton_pose: Option<&SkeletonPose>,
 	light: &ModelLightData,
 	model_matrix: &Mat4f,
 	model_view_matrix: &Mat4f,
 	tc_scale: &Vec2f,
 	tc_shift: &Vec2f,
 	dst_vertices: &mut [ModelVertex3d],
+	// Optional output for world-space (unnormalized) normals and tangent space vectors.
+	// Leave it empty if normals are not needed.
+	dst_normals: &mut [ModelVertexNormals],
 )
 {
 	let normals_matrix = get_normals_matrix(model_matrix);
@@ -29,7 +35,7 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 	{
 		VertexData::NonAnimated(v) =>
 		{
-			for (v, dst_v) in v.iter().zip(dst_vertices.iter_mut())
+			for (index, (v, dst_v)) in v.iter().zip(dst_vertices.iter_mut()).enumerate()
 			{
 				let normal_transformed = normals_matrix * v.normal;
 				*dst_v = ModelVertex3d {
@@ -37,6 +43,13 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 					tc: Vec2f::from(v.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
 					light: get_vertex_light(light, &normal_transformed),
 				};
+				write_vertex_normals(
+					dst_normals,
+					index,
+					&normal_transformed,
+					&normals_matrix,
+					&v.tangent_space,
+				);
 			}
 		},
 		VertexData::VertexAnimated { constant, variable } =>
@@ -47,11 +60,12 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 			if perform_lerp
 			{
 				// Perform smooth interpolation.
-				for (((v_v0, v_v1), v_c), dst_v) in frame_vertex_data0
+				for (index, (((v_v0, v_v1), v_c), dst_v)) in frame_vertex_data0
 					.iter()
 					.zip(frame_vertex_data1)
 					.zip(constant.iter())
 					.zip(dst_vertices.iter_mut())
+					.enumerate()
 				{
 					let position_lerped = v_v0.position * lerp0 + v_v1.position * lerp1;
 					let normal_lerped = v_v0.normal * lerp0 + v_v1.normal * lerp1;
@@ -61,6 +75,20 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 						tc: Vec2f::from(v_c.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
 						light: get_vertex_light(light, &normal_transformed),
 					};
+					if !dst_normals.is_empty()
+					{
+						let tangent_space_lerped = VertexTangentSpace {
+							tangent: v_v0.tangent_space.tangent * lerp0 + v_v1.tangent_space.tangent * lerp1,
+							binormal: v_v0.tangent_space.binormal * lerp0 + v_v1.tangent_space.binormal * lerp1,
+						};
+						write_vertex_normals(
+							dst_normals,
+							index,
+							&normal_transformed,
+							&normals_matrix,
+							&tangent_space_lerped,
+						);
+					}
 				}
 			}
 			else
@@ -74,10 +102,11 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 				{
 					frame_vertex_data1
 				};
-				for ((v_v, v_c), dst_v) in frame_vertex_data
+				for (index, ((v_v, v_c), dst_v)) in frame_vertex_data
 					.iter()
 					.zip(constant.iter())
 					.zip(dst_vertices.iter_mut())
+					.enumerate()
 				{
 					let normal_transformed = normals_matrix * v_v.normal;
 					*dst_v = ModelVertex3d {
@@ -85,15 +114,24 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 						tc: Vec2f::from(v_c.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
 						light: get_vertex_light(light, &normal_transformed),
 					};
+					write_vertex_normals(
+						dst_normals,
+						index,
+						&normal_transformed,
+						&normals_matrix,
+						&v_v.tangent_space,
+					);
 				}
 			}
 		},
 		VertexData::SkeletonAnimated(v) =>
 		{
-			if model.frame_bones.is_empty()
+			let num_bones = model.bones.len();
+			let skeleton_pose = skeleton_pose.filter(|p| num_bones > 0 && p.bone_matrices.len() == num_bones);
+			if model.frame_bones.is_empty() && skeleton_pose.is_none()
 			{
 				// No animation - just use source vertces.
-				for (v, dst_v) in v.iter().zip(dst_vertices.iter_mut())
+				for (index, (v, dst_v)) in v.iter().zip(dst_vertices.iter_mut()).enumerate()
 				{
 					let normal_transformed = normals_matrix * v.normal;
 					*dst_v = ModelVertex3d {
@@ -101,14 +139,18 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 						tc: Vec2f::from(v.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
 						light: get_vertex_light(light, &normal_transformed),
 					};
+					write_vertex_normals(
+						dst_normals,
+						index,
+						&normal_transformed,
+						&normals_matrix,
+						&v.tangent_space,
+					);
 				}
 			}
 			else
 			{
-				let num_bones = model.bones.len();
 				debug_assert!(num_bones <= MAX_TRIANGLE_MODEL_BONES);
-				let frame_bones0 = &model.frame_bones[frame0 * num_bones .. (frame0 + 1) * num_bones];
-				let frame_bones1 = &model.frame_bones[frame1 * num_bones .. (frame1 + 1) * num_bones];
 
 				// Use uninitialized memory for buffers of matrices.
 				// It is important, since it is is too costly to use safe zeroing,
@@ -117,10 +159,20 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 				let mut matrices = [MaybeUninit::<Mat4f>::uninit(); MAX_TRIANGLE_MODEL_BONES];
 				let mut normals_matrices = [MaybeUninit::<Mat3f>::uninit(); MAX_TRIANGLE_MODEL_BONES];
 
+				if let Some(pose) = skeleton_pose
+				{
+					// Use matrices of given pose.
+					for (dst_mat, pose_mat) in matrices.iter_mut().zip(&pose.bone_matrices)
+					{
+						dst_mat.write(*pose_mat);
+					}
+				}
 				// This code relies on fact that all bones are sorted in hierarchy order.
-				if perform_lerp
+				else if perform_lerp
 				{
 					// Interpolate between two frames.
+					let frame_bones0 = &model.frame_bones[frame0 * num_bones .. (frame0 + 1) * num_bones];
+					let frame_bones1 = &model.frame_bones[frame1 * num_bones .. (frame1 + 1) * num_bones];
 					for (bone_index, (frame_bone0, frame_bone1)) in
 						frame_bones0.iter().zip(frame_bones1.iter()).enumerate()
 					{
@@ -141,7 +193,8 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 				else
 				{
 					// Use single frame.
-					let frame_bones = if lerp0 > lerp1 { frame_bones0 } else { frame_bones1 };
+					let frame = if lerp0 > lerp1 { frame0 } else { frame1 };
+					let frame_bones = &model.frame_bones[frame * num_bones .. (frame + 1) * num_bones];
 					for (bone_index, frame_bone) in frame_bones.iter().enumerate()
 					{
 						let parent = model.bones[bone_index].parent as usize;
@@ -172,7 +225,7 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 					bone_matrix.write(unsafe { model_view_matrix_weight_scaled * bone_matrix.assume_init_ref() });
 				}
 
-				for (v, dst_v) in v.iter().zip(dst_vertices.iter_mut())
+				for (index, (v, dst_v)) in v.iter().zip(dst_vertices.iter_mut()).enumerate()
 				{
 					let i0 = v.bones_description[0].bone_index as usize;
 					let w0 = v.bones_description[0].weight as f32;
@@ -196,12 +249,39 @@ pub fn animate_and_transform_triangle_mesh_vertices(
 						tc: Vec2f::from(v.tex_coord).mul_element_wise(*tc_scale) + tc_shift,
 						light: get_vertex_light(light, &normal_transformed),
 					};
+					write_vertex_normals(dst_normals, index, &normal_transformed, &normal_mat, &v.tangent_space);
 				}
 			}
 		},
 	}
 }
 
+#[derive(Copy, Clone)]
+pub struct ModelVertexNormals
+{
+	pub normal: Vec3f,
+	pub tangent: Vec3f,
+	pub binormal: Vec3f,
+}
+
+fn write_vertex_normals(
+	dst_normals: &mut [ModelVertexNormals],
+	index: usize,
+	normal: &Vec3f,
+	normals_matrix: &Mat3f,
+	tangent_space: &VertexTangentSpace,
+)
+{
+	if let Some(dst_normal) = dst_normals.get_mut(index)
+	{
+		*dst_normal = ModelVertexNormals {
+			normal: *normal,
+			tangent: normals_matrix * tangent_space.tangent,
+			binormal: normals_matrix * tangent_space.binormal,
+		};
+	}
+}
+
 pub fn get_current_triangle_model_bbox(model: &TriangleModel, animation: &AnimationPoint) -> BBox
 {
 	// Just use maximum bbox.
@@ -211,6 +291,23 @@ pub fn get_current_triangle_model_bbox(model: &TriangleModel, animation: &Animat
 	bbox
 }
 
+// Get bbox of model entity, using its skeleton pose (if it is present) or animation point.
+pub fn get_model_entity_bbox(model: &ModelEntity) -> BBox
+{
+	if let Some(bbox) = model
+		.skeleton_pose
+		.as_ref()
+		.filter(|p| p.bone_matrices.len() == model.model.bones.len())
+		.and_then(|p| p.bbox)
+	{
+		bbox
+	}
+	else
+	{
+		get_current_triangle_model_bbox(&model.model, &model.animation)
+	}
+}
+
 pub fn calculate_triangle_model_screen_polygon(model_bbox_vertices_transformed: &[Vec3f; 8])
 	-> Option<ClippingPolygon>
 {
@@ -277,6 +374,29 @@ pub fn calculate_triangle_model_texture_mip(
 	texture_size: [u32; 2],
 	mip_bias: f32,
 ) -> u32
+{
+	let screen_size = if let Some(s) = calculate_triangle_model_screen_size(model_view_matrix, model_bbox)
+	{
+		s
+	}
+	else
+	{
+		return 0;
+	};
+
+	if screen_size <= 0.0
+	{
+		return MAX_MIP as u32;
+	}
+
+	let approximate_texels_per_pixel = (texture_size[0].max(texture_size[1]) as f32) / screen_size;
+
+	((approximate_texels_per_pixel.log2() + mip_bias).floor().max(0.0) as u32).min(MAX_MIP as u32)
+}
+
+// Calculate approximate size of model projection (in pixels).
+// Returns None if model is (partially) behind camera.
+pub fn calculate_triangle_model_screen_size(model_view_matrix: &Mat4f, model_bbox: &BBox) -> Option<f32>
 {
 	// Make a cube from bbox n order to fix problems with large mip selected for thin model viewed from side.
 	// This method is not so accuratr but it produces good enough results.
@@ -293,7 +413,7 @@ pub fn calculate_triangle_model_texture_mip(
 		let vertex_projected = model_view_matrix * src.extend(1.0);
 		if vertex_projected.w <= 0.0
 		{
-			return 0;
+			return None;
 		}
 		*dst = Vec2f::new(vertex_projected.x, vertex_projected.y) / vertex_projected.w;
 	}
@@ -310,15 +430,30 @@ pub fn calculate_triangle_model_texture_mip(
 		max_y = max_y.max(v.y);
 	}
 
-	let max_dimension = (max_x - min_x).max(max_y - min_y);
-	if max_dimension <= 0.0
+	Some((max_x - min_x).max(max_y - min_y))
+}
+
+// Select LOD based on model screen size.
+// Previous LOD is used for hysteresis - switch LOD only if screen size is far enough from switch threshold.
+pub fn select_triangle_model_lod(model: &TriangleModel, screen_size: f32, prev_lod: Option<u32>) -> u32
+{
+	if model.lods.len() <= 1
 	{
-		return MAX_MIP as u32;
+		return 0;
 	}
 
-	let approximate_texels_per_pixel = (texture_size[0].max(texture_size[1]) as f32) / max_dimension;
+	const HYSTERESIS: f32 = 0.1;
 
-	((approximate_texels_per_pixel.log2() + mip_bias).floor().max(0.0) as u32).min(MAX_MIP as u32)
+	let get_lod_for_size =
+		|size: f32| -> u32 { model.lods[1 ..].iter().filter(|lod| size < lod.max_screen_size).count() as u32 };
+
+	let lod = get_lod_for_size(screen_size);
+	match prev_lod
+	{
+		Some(prev_lod) if lod > prev_lod => get_lod_for_size(screen_size * (1.0 + HYSTERESIS)).max(prev_lod),
+		Some(prev_lod) if lod < prev_lod => get_lod_for_size(screen_size * (1.0 - HYSTERESIS)).min(prev_lod),
+		_ => lod,
+	}
 }
 
 pub fn reject_triangle_model_back_faces(
@@ -498,7 +633,7 @@ fn calculate_model_dynamic_light_cube(
 	light_cube: &mut LightCube,
 )
 {
-	let bbox = get_current_triangle_model_bbox(&model.model, &model.animation);
+	let bbox = get_model_entity_bbox(model);
 	let bbox_center = bbox.get_center();
 
 	// Calculage light for several positions within model bbox.
@@ -538,6 +673,369 @@ fn calculate_model_dynamic_light_cube(
 	light_cube.scale(1.0 / (sample_positions.len() as f32));
 }
 
+// Get scale of dynamic light for models with per-pixel lighting.
+pub fn get_model_dynamic_light_scale(model: &ModelEntity) -> f32
+{
+	match model.lighting
+	{
+		ModelLighting::Default => 1.0,
+		// Dynamic lights are ignored for models with constant light.
+		ModelLighting::ConstantLight(_) => 0.0,
+		ModelLighting::AdvancedLight { grid_light_scale, .. } => grid_light_scale,
+	}
+}
+
+pub fn model_is_affected_by_light(model_bbox: &BBox, model_matrix: &Mat4f, light: &DynamicLightWithShadow) -> bool
+{
+	// Use bounding sphere of model bbox.
+	let center = (model_matrix * model_bbox.get_center().extend(1.0)).truncate();
+	let radius = model_bbox.get_size().magnitude() * 0.5 + light.radius;
+	(light.position - center).magnitude2() < radius * radius
+}
+
+// Lit textures store color, divided by this value, in order to preserve overbright light.
+// Such textures should be drawn with constant light equal to this value.
+pub const MODEL_LIT_TEXTURE_LIGHT_SCALE: f32 = 2.0;
+const MODEL_LIT_TEXTURE_INV_LIGHT_SCALE: f32 = 1.0 / MODEL_LIT_TEXTURE_LIGHT_SCALE;
+
+// Build texture with per-texel light for model mesh, that uses per-pixel lighting.
+// Light is calculated for texels, covered by given triangles in texture space.
+// Static light is interpolated between vertices, dynamic light is calculated for each texel (with shadows).
+// Resulting texture should be drawn with constant light equal to MODEL_LIT_TEXTURE_LIGHT_SCALE.
+// If some triangles overlap in texture space (mirrored model parts, for example), light of only one of them is used.
+pub fn build_triangle_model_lit_texture(
+	texture: &TextureLite,
+	vertices: &[ModelVertex3d],
+	normals: &[ModelVertexNormals],
+	triangles: &[Triangle],
+	view_matrix_inverse: &Mat4f,
+	dynamic_lights: &[&DynamicLightWithShadow],
+	dynamic_light_scale: f32,
+	out_texture_data: &mut [Color32],
+)
+{
+	for triangle in triangles
+	{
+		let v = triangle.map(|index| triangle_vertex_debug_checked_fetch(vertices, index));
+		let n = triangle.map(|index| triangle_vertex_debug_checked_fetch(normals, index).normal);
+		let pos = v.map(|v| view_matrix_transform_vertex(view_matrix_inverse, &v.pos));
+
+		for_each_triangle_texel(&[v[0].tc, v[1].tc, v[2].tc], texture.size, |texel_address, k| {
+			let texel_pos = pos[0] * k[0] + pos[1] * k[1] + pos[2] * k[2];
+			let texel_normal = n[0] * k[0] + n[1] * k[1] + n[2] * k[2];
+			let texel_normal_normalized =
+				texel_normal * inv_sqrt_fast(vec3_len2(&texel_normal).max(MIN_POSITIVE_VALUE));
+
+			let mut total_light = ColorVec::from_color_f32x3_with_one(&[
+				v[0].light[0] * k[0] + v[1].light[0] * k[1] + v[2].light[0] * k[2],
+				v[0].light[1] * k[0] + v[1].light[1] * k[1] + v[2].light[1] * k[2],
+				v[0].light[2] * k[0] + v[1].light[2] * k[1] + v[2].light[2] * k[2],
+			]);
+
+			for light in dynamic_lights
+			{
+				let vec_to_light = light.position - texel_pos;
+				let vec_to_light_len2 = vec3_len2(&vec_to_light).max(MIN_POSITIVE_VALUE);
+				// Limit light radius by subtracting intensity at maximum radius.
+				let distance_factor = inv_fast(vec_to_light_len2) - light.inv_square_radius;
+				if distance_factor <= 0.0
+				{
+					continue;
+				}
+				let diffuse_intensity =
+					vec3_dot(&texel_normal_normalized, &vec_to_light) * inv_sqrt_fast(vec_to_light_len2);
+				if diffuse_intensity <= 0.0
+				{
+					continue;
+				}
+
+				let shadow_factor = get_light_shadow_factor(light, &vec_to_light);
+				total_light = ColorVec::mul_scalar_add(
+					&ColorVec::from_color_f32x3(&light.color),
+					dynamic_light_scale * diffuse_intensity * distance_factor * shadow_factor,
+					&total_light,
+				);
+			}
+
+			// Preserve alpha.
+			let mut total_light_scaled = ColorVec::scalar_mul(&total_light, MODEL_LIT_TEXTURE_INV_LIGHT_SCALE);
+			total_light_scaled.insert::<3>(1.0);
+
+			let texel_value = unsafe { debug_only_checked_fetch(&texture.pixels, texel_address) };
+			let result_color = ColorVec::mul(&ColorVec::from_color32(texel_value), &total_light_scaled);
+			unsafe { debug_only_checked_write(out_texture_data, texel_address, result_color.into()) };
+		});
+	}
+}
+
+// Build texture with per-texel light for model mesh with full material (with normal map and specular).
+// Light grid light and dynamic lights are calculated for each texel.
+// Resulting texture should be drawn with constant light equal to MODEL_LIT_TEXTURE_LIGHT_SCALE.
+pub fn build_triangle_model_material_lit_texture(
+	texture: &Texture,
+	vertices: &[ModelVertex3d],
+	normals: &[ModelVertexNormals],
+	triangles: &[Triangle],
+	view_matrix_inverse: &Mat4f,
+	cam_pos: &Vec3f,
+	light: &ModelLightData,
+	dynamic_lights: &[&DynamicLightWithShadow],
+	dynamic_light_scale: f32,
+	out_texture_data: &mut [Color32],
+)
+{
+	let use_specular = texture.is_metal || texture.has_non_one_roughness;
+
+	for triangle in triangles
+	{
+		let v = triangle.map(|index| triangle_vertex_debug_checked_fetch(vertices, index));
+		let n = triangle.map(|index| triangle_vertex_debug_checked_fetch(normals, index));
+		let pos = v.map(|v| view_matrix_transform_vertex(view_matrix_inverse, &v.pos));
+
+		for_each_triangle_texel(&[v[0].tc, v[1].tc, v[2].tc], texture.size, |texel_address, k| {
+			let texel_value = unsafe { debug_only_checked_fetch(&texture.pixels, texel_address) };
+			let (texel_normal, texel_roughness) = texel_value.packed_normal_roughness.unpack();
+
+			let texel_pos = pos[0] * k[0] + pos[1] * k[1] + pos[2] * k[2];
+			let vertex_normal = n[0].normal * k[0] + n[1].normal * k[1] + n[2].normal * k[2];
+			let normal_unnormalized = if texture.has_normal_map
+			{
+				// Transform normal from texture space into world space.
+				let tangent = n[0].tangent * k[0] + n[1].tangent * k[1] + n[2].tangent * k[2];
+				let binormal = n[0].binormal * k[0] + n[1].binormal * k[1] + n[2].binormal * k[2];
+				tangent * texel_normal.x + binormal * texel_normal.y + vertex_normal * texel_normal.z
+			}
+			else
+			{
+				vertex_normal
+			};
+			let normal = normal_unnormalized * inv_sqrt_fast(vec3_len2(&normal_unnormalized).max(MIN_POSITIVE_VALUE));
+
+			// Calculate reflected view angle and fresnel factor based on it.
+			let vec_to_camera = cam_pos - texel_pos;
+			let vec_to_camera_normal_dot = vec3_dot(&vec_to_camera, &normal);
+			let vec_to_camera_reflected = normal * (2.0 * vec_to_camera_normal_dot) - vec_to_camera;
+			let vec_to_camera_len2 = vec3_len2(&vec_to_camera_reflected).max(MIN_POSITIVE_VALUE);
+			let specular_k = if use_specular
+			{
+				let vec_to_camera_normal_angle_cos =
+					(vec_to_camera_normal_dot * inv_sqrt_fast(vec_to_camera_len2)).max(0.0);
+				let fresnel_factor_base = get_fresnel_factor_base(vec_to_camera_normal_angle_cos);
+				if texture.is_metal
+				{
+					get_specular_k_metal(fresnel_factor_base, texel_roughness)
+				}
+				else
+				{
+					get_specular_k_dielectric(fresnel_factor_base, texel_roughness)
+				}
+			}
+			else
+			{
+				0.0
+			};
+			let one_minus_specular_k = 1.0 - specular_k;
+			let inv_roughness = inv_fast(texel_roughness);
+
+			// Use light cube as ambient light.
+			let constant_light = ColorVec::from_color_f32x3(&get_light_cube_light(&light.light_cube, &normal));
+			let mut total_light_albedo_modulated = ColorVec::scalar_mul(&constant_light, one_minus_specular_k);
+			let mut total_light_direct = ColorVec::scalar_mul(&constant_light, specular_k);
+			// Set alpha component to one to preserve alpha.
+			total_light_albedo_modulated.insert::<3>(1.0);
+
+			let mut add_light = |vec_to_light: &Vec3f, vec_to_light_len2: f32, color: &ColorVec, scale: f32| {
+				let vec_to_light_inv_len = inv_sqrt_fast(vec_to_light_len2);
+				let specular_intensity = if use_specular
+				{
+					let vec_to_camera_reflected_light_angle_cos = vec3_dot(&vec_to_camera_reflected, vec_to_light) *
+						inv_sqrt_fast(vec_to_camera_len2) *
+						vec_to_light_inv_len;
+					get_specular_intensity(vec_to_camera_reflected_light_angle_cos, inv_roughness)
+				}
+				else
+				{
+					0.0
+				};
+
+				if texture.is_metal
+				{
+					// No diffuse light for metalls.
+					total_light_albedo_modulated = ColorVec::mul_scalar_add(
+						color,
+						one_minus_specular_k * specular_intensity * scale,
+						&total_light_albedo_modulated,
+					);
+				}
+				else
+				{
+					let diffuse_intensity = (vec3_dot(&normal, vec_to_light) * vec_to_light_inv_len).max(0.0);
+					total_light_albedo_modulated = ColorVec::mul_scalar_add(
+						color,
+						one_minus_specular_k * diffuse_intensity * scale,
+						&total_light_albedo_modulated,
+					);
+				}
+
+				total_light_direct =
+					ColorVec::mul_scalar_add(color, specular_k * specular_intensity * scale, &total_light_direct);
+			};
+
+			// Directional components of light grid light.
+			for directional_component in &light.directional_components
+			{
+				let direction_vec_len2 = vec3_len2(&directional_component.vector_scaled);
+				if direction_vec_len2 <= MIN_POSITIVE_VALUE
+				{
+					continue;
+				}
+				add_light(
+					&directional_component.vector_scaled,
+					direction_vec_len2,
+					&ColorVec::from_color_f32x3(&directional_component.color),
+					direction_vec_len2 * inv_sqrt_fast(direction_vec_len2),
+				);
+			}
+
+			for light in dynamic_lights
+			{
+				let vec_to_light = light.position - texel_pos;
+				let vec_to_light_len2 = vec3_len2(&vec_to_light).max(MIN_POSITIVE_VALUE);
+				// Limit light radius by subtracting intensity at maximum radius.
+				let distance_factor = inv_fast(vec_to_light_len2) - light.inv_square_radius;
+				if distance_factor <= 0.0
+				{
+					continue;
+				}
+				let shadow_factor = get_light_shadow_factor(light, &vec_to_light);
+				if shadow_factor <= 0.0
+				{
+					continue;
+				}
+				add_light(
+					&vec_to_light,
+					vec_to_light_len2,
+					&ColorVec::from_color_f32x3(&light.color),
+					dynamic_light_scale * distance_factor * shadow_factor,
+				);
+			}
+
+			// Preserve alpha.
+			let mut total_light_albedo_modulated_scaled =
+				ColorVec::scalar_mul(&total_light_albedo_modulated, MODEL_LIT_TEXTURE_INV_LIGHT_SCALE);
+			total_light_albedo_modulated_scaled.insert::<3>(1.0);
+
+			let mut result_color = ColorVec::mul(
+				&ColorVec::from_color32(texel_value.diffuse),
+				&total_light_albedo_modulated_scaled,
+			);
+			if use_specular
+			{
+				result_color = ColorVec::mul_scalar_add(
+					&total_light_direct,
+					255.0 * MODEL_LIT_TEXTURE_INV_LIGHT_SCALE,
+					&result_color,
+				);
+			}
+			unsafe { debug_only_checked_write(out_texture_data, texel_address, result_color.into()) };
+		});
+	}
+}
+
+// Call given function for each texel, covered by triangle with given texture coordinates.
+// Texture coordinates outside texture are wrapped, as it is done in rasterization of tiled textures.
+// Function arguments are texel address and normalized barycentric coordinates of texel center.
+fn for_each_triangle_texel<F: FnMut(usize, &[f32; 3])>(tc: &[Vec2f; 3], texture_size: [u32; 2], mut func: F)
+{
+	// Process also texels with centers slightly outside triangle,
+	// in order to cover all texels that may be fetched during triangle rasterization.
+	const MAX_EDGE_DISTANCE: f32 = 0.75;
+
+	let d1 = tc[1] - tc[0];
+	let d2 = tc[2] - tc[0];
+	let double_area = d1.perp_dot(d2);
+	if double_area.abs() < 1.0 / 64.0
+	{
+		// Degenerate triangle in texture space.
+		return;
+	}
+	let inv_double_area = 1.0 / double_area;
+
+	// Equations for barycentric coordinates of vertices 1 and 2.
+	let b1_dx = d2.y * inv_double_area;
+	let b1_dy = -d2.x * inv_double_area;
+	let b1_k = -(tc[0].x * b1_dx + tc[0].y * b1_dy);
+	let b2_dx = -d1.y * inv_double_area;
+	let b2_dy = d1.x * inv_double_area;
+	let b2_k = -(tc[0].x * b2_dx + tc[0].y * b2_dy);
+
+	// Barycentric coordinate of vertex is proportional to distance to opposite edge.
+	let double_area_abs = double_area.abs();
+	let min_barycentric = [
+		-MAX_EDGE_DISTANCE * (tc[2] - tc[1]).magnitude() / double_area_abs,
+		-MAX_EDGE_DISTANCE * d2.magnitude() / double_area_abs,
+		-MAX_EDGE_DISTANCE * d1.magnitude() / double_area_abs,
+	];
+
+	let size = [texture_size[0] as i32, texture_size[1] as i32];
+	if size[0] <= 0 || size[1] <= 0
+	{
+		return;
+	}
+
+	let tc_min = Vec2f::new(tc[0].x.min(tc[1].x).min(tc[2].x), tc[0].y.min(tc[1].y).min(tc[2].y));
+	let tc_max = Vec2f::new(tc[0].x.max(tc[1].x).max(tc[2].x), tc[0].y.max(tc[1].y).max(tc[2].y));
+	let (x_start, x_end) = get_wrapped_texels_range(tc_min.x, tc_max.x, size[0]);
+	let (y_start, y_end) = get_wrapped_texels_range(tc_min.y, tc_max.y, size[1]);
+
+	for y in y_start .. y_end
+	{
+		// Add 0.5 to get position of texel center.
+		let y_f = (y as f32) + 0.5;
+		let line_address = y.rem_euclid(size[1]) * size[0];
+		for x in x_start .. x_end
+		{
+			let x_f = (x as f32) + 0.5;
+			let b1 = f32_mul_add(b1_dx, x_f, f32_mul_add(b1_dy, y_f, b1_k));
+			let b2 = f32_mul_add(b2_dx, x_f, f32_mul_add(b2_dy, y_f, b2_k));
+			let b0 = 1.0 - b1 - b2;
+			if b0 < min_barycentric[0] || b1 < min_barycentric[1] || b2 < min_barycentric[2]
+			{
+				continue;
+			}
+
+			// Clamp coordinates in order to avoid extrapolation outside triangle.
+			let b = [b0.max(0.0), b1.max(0.0), b2.max(0.0)];
+			let inv_b_sum = 1.0 / (b[0] + b[1] + b[2]);
+			func(
+				(x.rem_euclid(size[0]) + line_address) as usize,
+				&[b[0] * inv_b_sum, b[1] * inv_b_sum, b[2] * inv_b_sum],
+			);
+		}
+	}
+}
+
+// Get range of texels (not wrapped) for given texture coordinates range.
+// Limit range for triangles with huge texture coordinates range (with a lot of texture repeats),
+// since same texels are processed again for each repeat. Use range around center in such case.
+fn get_wrapped_texels_range(tc_min: f32, tc_max: f32, size: i32) -> (i32, i32)
+{
+	const MAX_REPEATS: i32 = 4;
+	let max_range = size * MAX_REPEATS;
+
+	let start = (tc_min - 1.0).floor().max(-(1 << 24) as f32) as i32;
+	let end = (tc_max + 1.0).ceil().min((1 << 24) as f32) as i32;
+	if end - start <= max_range
+	{
+		(start, end)
+	}
+	else
+	{
+		let center = ((tc_min + tc_max) * 0.5) as i32;
+		(center - max_range / 2, center + max_range / 2)
+	}
+}
+
 fn get_model_static_light(map: &bsp_map_compact::BSPMap, model: &ModelEntity) -> bsp_map_compact::LightGridElement
 {
 	match model.lighting
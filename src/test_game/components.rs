@@ -73,6 +73,30 @@ pub struct OtherEntityLocationComponent
 	pub relative_rotation: QuaternionF,
 }
 
+// Ragdoll of skeleton-animated model. Each bone has its own physics body.
+// Location of entity is calculated from location of root body, model pose - from locations of all bodies.
+#[derive(Serialize, Deserialize)]
+pub struct RagdollComponent
+{
+	// Physics body for each model bone. Bones without body follow parent bones.
+	pub bones_bodies: Vec<Option<test_game_physics::ObjectHandle>>,
+	pub root_bone_index: usize,
+	// Location of root body relative to model.
+	pub root_body_position: Vec3f,
+	pub root_body_rotation: QuaternionF,
+}
+
+// Calculate location relative to bone of model of other entity.
+// Location and model (with current animation) of other entity are used.
+#[derive(Serialize, Deserialize)]
+pub struct ModelBoneLocationComponent
+{
+	pub entity: hecs::Entity,
+	pub bone_name: String,
+	pub relative_position: Vec3f,
+	pub relative_rotation: QuaternionF,
+}
+
 // Take location from player controller camera.
 #[derive(Serialize, Deserialize)]
 pub struct PlayerControllerCameraLocationComponent
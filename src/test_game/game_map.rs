@@ -1,6 +1,6 @@
 use super::{
 	commands_processor, commands_queue, components::*, console, frame_info::*, resources_manager::*, test_game_physics,
-	world_spawn, world_update,
+	triangle_model_animation::*, world_spawn, world_update,
 };
 use square_wheel_lib::common::{
 	bsp_map_compact, color::*, material, math_types::*, matrix::*, plane::*, system_window,
@@ -42,6 +42,7 @@ impl GameMap
 			("reset_test_lights", Self::command_reset_test_lights),
 			("add_test_model", Self::command_add_test_model),
 			("reset_test_models", Self::command_reset_test_models),
+			("make_test_models_ragdolls", Self::command_make_test_models_ragdolls),
 			("add_test_decal", Self::command_add_test_decal),
 			("reset_test_decals", Self::command_reset_test_decals),
 			("add_test_sprite", Self::command_add_test_sprite),
@@ -346,6 +347,7 @@ impl GameMap
 					frames: [0, 0],
 					lerp: 0.0,
 				},
+				skeleton_pose: None,
 				model,
 				texture,
 				blending_mode: material::BlendingMode::None,
@@ -361,16 +363,71 @@ impl GameMap
 
 	fn command_reset_test_models(&mut self, _args: commands_queue::CommandArgs)
 	{
-		for (id, (_test_model_component, phys_handle)) in self
-			.ecs
-			.query_mut::<(&TestModelComponent, &test_game_physics::ObjectHandle)>()
+		for (id, (_test_model_component, phys_handle, ragdoll_component)) in self.ecs.query_mut::<(
+			&TestModelComponent,
+			Option<&test_game_physics::ObjectHandle>,
+			Option<&RagdollComponent>,
+		)>()
 		{
-			self.physics.remove_object(*phys_handle);
+			if let Some(phys_handle) = phys_handle
+			{
+				self.physics.remove_object(*phys_handle);
+			}
+			if let Some(ragdoll_component) = ragdoll_component
+			{
+				for body in ragdoll_component.bones_bodies.iter().flatten()
+				{
+					self.physics.remove_object(*body);
+				}
+			}
 			self.ecs_command_buffer.despawn(id);
 		}
 		self.ecs_command_buffer.run_on(&mut self.ecs);
 	}
 
+	fn command_make_test_models_ragdolls(&mut self, _args: commands_queue::CommandArgs)
+	{
+		let mut entities = Vec::new();
+		for (id, (_test_model_component, phys_handle, model_entity)) in
+			self.ecs
+				.query_mut::<(&TestModelComponent, &test_game_physics::ObjectHandle, &ModelEntity)>()
+		{
+			if let Some((ragdoll_bones, root_body_position, root_body_rotation)) = make_ragdoll_bones(model_entity)
+			{
+				entities.push((id, *phys_handle, ragdoll_bones, root_body_position, root_body_rotation));
+			}
+		}
+
+		let num_ragdolls = entities.len();
+		for (id, phys_handle, ragdoll_bones, root_body_position, root_body_rotation) in entities
+		{
+			// Replace single physics object with ragdoll and stop animation.
+			let velocity = self.physics.get_object_velocity(phys_handle);
+			self.physics.remove_object(phys_handle);
+			let _ignore = self
+				.ecs
+				.remove::<(test_game_physics::ObjectHandle, SimpleAnimationComponent)>(id);
+
+			let bodies = self.physics.add_ragdoll(id, &ragdoll_bones, &velocity);
+			self.ecs
+				.insert_one(
+					id,
+					RagdollComponent {
+						bones_bodies: bodies.into_iter().map(Some).collect(),
+						root_bone_index: 0,
+						root_body_position,
+						root_body_rotation,
+					},
+				)
+				.ok();
+		}
+
+		self.console
+			.lock()
+			.unwrap()
+			.add_text(format!("Created {} ragdolls", num_ragdolls));
+	}
+
 	fn command_add_test_decal(&mut self, args: commands_queue::CommandArgs)
 	{
 		if args.len() < 1
@@ -501,6 +558,7 @@ impl GameMap
 							frames: [0, 0],
 							lerp: 0.0,
 						},
+						skeleton_pose: None,
 						model: model.clone(),
 						texture: r.get_texture_lite(&args[1]),
 						blending_mode: material::BlendingMode::None,
@@ -579,6 +637,7 @@ impl GameMap
 					frames: [0, 0],
 					lerp: 0.0,
 				},
+				skeleton_pose: None,
 				model,
 				texture,
 				blending_mode: material::BlendingMode::None,
@@ -828,8 +887,10 @@ impl GameMap
 		world_update::update_player_controller_locations(&mut self.ecs, &self.physics);
 		// Take locations from physics engine.
 		world_update::update_phys_model_locations(&mut self.ecs, &self.physics);
+		world_update::update_ragdolls(&mut self.ecs, &self.physics);
 		// Take locations from other entities. This is needed for entities, attached to other entities.
 		world_update::update_other_entity_locations(&mut self.ecs);
+		world_update::update_model_bone_locations(&mut self.ecs);
 		world_update::update_player_controller_camera_locations(&mut self.ecs, &self.physics);
 
 		// Update locations of dynamic objects.
@@ -904,6 +965,7 @@ impl GameMap
 			view: FrameViewInfo {
 				camera_matrices,
 				color_modulate,
+				color_grading_lut_blend: None,
 				is_third_person_view: false,
 			},
 			world: FrameWorldInfo {
@@ -915,6 +977,8 @@ impl GameMap
 				decals: self.collect_drawable_components(),
 				sprites: self.collect_drawable_components(),
 				portals: self.collect_drawable_components(),
+				fog_volumes: self.collect_drawable_components(),
+				lens_flares: self.collect_drawable_components(),
 			},
 		}
 	}
@@ -958,3 +1022,88 @@ impl Drop for GameMap
 			.remove_command_queue(&self.commands_queue_dyn);
 	}
 }
+
+// Create ragdoll bones description based on current model pose.
+// Also returns location of root bone relative to model.
+fn make_ragdoll_bones(model_entity: &ModelEntity) -> Option<(Vec<test_game_physics::RagdollBone>, Vec3f, QuaternionF)>
+{
+	let model = &model_entity.model;
+	let num_bones = model.bones.len();
+	if !model.bones.iter().any(|b| (b.parent as usize) < num_bones)
+	{
+		// Model has no bones or has no bones hierarchy (MD3 tags).
+		return None;
+	}
+
+	let root_body_location =
+		get_bone_matrix_position_and_rotation(&get_model_entity_bone_model_space_matrix(model_entity, 0)?);
+
+	let locations = (0 .. num_bones)
+		.map(|bone_index| {
+			get_model_entity_bone_matrix(model_entity, bone_index as u32)
+				.map(|m| get_bone_matrix_position_and_rotation(&m))
+		})
+		.collect::<Option<Vec<_>>>()?;
+
+	// Calculate number of descendants and average position of children for each bone.
+	// This relies on fact that all bones are sorted in hierarchy order.
+	let mut num_descendants = vec![0; num_bones];
+	let mut children_positions_sum = vec![(Vec3f::zero(), 0); num_bones];
+	for (bone_index, bone) in model.bones.iter().enumerate().rev()
+	{
+		let parent = bone.parent as usize;
+		if parent < bone_index
+		{
+			num_descendants[parent] += num_descendants[bone_index] + 1;
+			children_positions_sum[parent].0 += locations[bone_index].0;
+			children_positions_sum[parent].1 += 1;
+		}
+	}
+
+	let bones = model
+		.bones
+		.iter()
+		.enumerate()
+		.map(|(bone_index, bone)| {
+			let parent = bone.parent as usize;
+			let parent = if parent < bone_index { Some(parent) } else { None };
+			let position = locations[bone_index].0;
+
+			let (children_positions_sum, num_children) = children_positions_sum[bone_index];
+			let end = if num_children > 0
+			{
+				children_positions_sum / (num_children as f32)
+			}
+			else if let Some(parent) = parent
+			{
+				// Leaf bone - continue direction of parent.
+				position + (position - locations[parent].0) * 0.5
+			}
+			else
+			{
+				position
+			};
+
+			// Make bones with many descendants (spine, pelvis) more stiff than end bones (hands, feet, head).
+			let stiffness = ((num_descendants[bone_index] as f32) / (num_bones as f32)).sqrt();
+
+			test_game_physics::RagdollBone {
+				position,
+				rotation: locations[bone_index].1,
+				parent,
+				end,
+				radius: ((end - position).magnitude() * RAGDOLL_BONE_RADIUS_SCALE)
+					.clamp(RAGDOLL_BONE_MIN_RADIUS, RAGDOLL_BONE_MAX_RADIUS),
+				angle_limit: RAGDOLL_MAX_ANGLE_LIMIT - (RAGDOLL_MAX_ANGLE_LIMIT - RAGDOLL_MIN_ANGLE_LIMIT) * stiffness,
+			}
+		})
+		.collect();
+
+	Some((bones, root_body_location.0, root_body_location.1))
+}
+
+const RAGDOLL_BONE_RADIUS_SCALE: f32 = 0.25;
+const RAGDOLL_BONE_MIN_RADIUS: f32 = 1.0;
+const RAGDOLL_BONE_MAX_RADIUS: f32 = 6.0;
+const RAGDOLL_MIN_ANGLE_LIMIT: f32 = std::f32::consts::PI / 12.0;
+const RAGDOLL_MAX_ANGLE_LIMIT: f32 = std::f32::consts::PI / 2.0;
@@ -1,6 +1,6 @@
-use super::{components::*, frame_info::*, resources_manager, test_game_physics, textures, triangle_model};
+use super::{components::*, frame_info::*, resources_manager, test_game_physics, textures};
 use serde::{Deserialize, Serialize};
-use square_wheel_lib::common::{bbox::*, material::BlendingMode, math_types::*, plane::*};
+use square_wheel_lib::common::{bbox::*, material::BlendingMode, math_types::*, plane::*, triangle_model};
 use std::{
 	collections::HashMap,
 	fs::{File, OpenOptions},
@@ -217,6 +217,10 @@ fn save_shared_resources(
 			{
 				ResourceForSerialization::Named(name.to_string())
 			}
+			else if let Some(name) = resources_manager.get_material_texture_lite_name(texture)
+			{
+				ResourceForSerialization::NamedMaterial(name.to_string())
+			}
 			else
 			{
 				ResourceForSerialization::Direct((**texture).clone())
@@ -240,7 +244,11 @@ fn load_shared_resources(
 	{
 		let model = match model_resource
 		{
-			ResourceForSerialization::Named(name) => resources_manager.get_model(&name),
+			// Models are never saved as materials.
+			ResourceForSerialization::Named(name) | ResourceForSerialization::NamedMaterial(name) =>
+			{
+				resources_manager.get_model(&name)
+			},
 			ResourceForSerialization::Direct(r) => Arc::new(r),
 		};
 		shared_resources.models.insert(key, model);
@@ -251,6 +259,7 @@ fn load_shared_resources(
 		let texture = match texture_resource
 		{
 			ResourceForSerialization::Named(name) => resources_manager.get_texture_lite(&name),
+			ResourceForSerialization::NamedMaterial(name) => resources_manager.get_material_texture_lite(&name),
 			ResourceForSerialization::Direct(r) => Arc::new(r),
 		};
 		shared_resources.lite_textures.insert(key, texture);
@@ -274,6 +283,8 @@ enum ResourceForSerialization<T>
 	// Save resource directly.
 	// It is possible for generated resources or othrer resources, obtained not via ResourcesManager.
 	Direct(T),
+	// Save only name of material, request resource, created from this material, during deserialization.
+	NamedMaterial(String),
 }
 
 fn save_physics(physics: &test_game_physics::TestGamePhysics, file: &mut File) -> Option<()>
@@ -336,6 +347,8 @@ impl<'a> hecs::serialize::row::SerializeContext for SerializeContext<'a>
 		self.try_serialize_component::<PhysicsLocationComponent, S>(entity, &mut map)?;
 		self.try_serialize_component::<LocationKinematicPhysicsObjectComponent, S>(entity, &mut map)?;
 		self.try_serialize_component::<OtherEntityLocationComponent, S>(entity, &mut map)?;
+		self.try_serialize_component::<ModelBoneLocationComponent, S>(entity, &mut map)?;
+		self.try_serialize_component::<RagdollComponent, S>(entity, &mut map)?;
 		self.try_serialize_component::<PlayerControllerCameraLocationComponent, S>(entity, &mut map)?;
 
 		self.try_serialize_component::<ModelEntityLocationLinkComponent, S>(entity, &mut map)?;
@@ -370,6 +383,7 @@ impl<'a> hecs::serialize::row::SerializeContext for SerializeContext<'a>
 		// Non-special drawable components.
 		self.try_serialize_component::<SubmodelEntityWithIndex, S>(entity, &mut map)?;
 		self.try_serialize_component::<DynamicLight, S>(entity, &mut map)?;
+		self.try_serialize_component::<FogVolume, S>(entity, &mut map)?;
 
 		// Perform serialization of components with shared resources, using proxy structs with identical content but changed resource fields.
 		// Collect shared resources and serialize them later.
@@ -459,6 +473,8 @@ impl<'a> hecs::serialize::row::DeserializeContext for DeserializeContext<'a>
 			self.try_deserialize_component::<PhysicsLocationComponent, M>(&key, &mut map, entity)?;
 			self.try_deserialize_component::<LocationKinematicPhysicsObjectComponent, M>(&key, &mut map, entity)?;
 			self.try_deserialize_component::<OtherEntityLocationComponent, M>(&key, &mut map, entity)?;
+			self.try_deserialize_component::<ModelBoneLocationComponent, M>(&key, &mut map, entity)?;
+			self.try_deserialize_component::<RagdollComponent, M>(&key, &mut map, entity)?;
 			self.try_deserialize_component::<PlayerControllerCameraLocationComponent, M>(&key, &mut map, entity)?;
 
 			self.try_deserialize_component::<ModelEntityLocationLinkComponent, M>(&key, &mut map, entity)?;
@@ -494,6 +510,7 @@ impl<'a> hecs::serialize::row::DeserializeContext for DeserializeContext<'a>
 			// Non-special drawable components.
 			self.try_deserialize_component::<SubmodelEntityWithIndex, M>(&key, &mut map, entity)?;
 			self.try_deserialize_component::<DynamicLight, M>(&key, &mut map, entity)?;
+			self.try_deserialize_component::<FogVolume, M>(&key, &mut map, entity)?;
 
 			// Drawable components with shared resources.
 			if key.as_str() == get_component_name::<ModelEntityProxy>()
@@ -620,6 +637,7 @@ impl ModelEntityProxy
 			position: self.position,
 			rotation: self.rotation,
 			animation: self.animation,
+			skeleton_pose: None,
 			model: self.model.to_resource(models)?,
 			texture: self.texture.to_resource(textures)?,
 			blending_mode: self.blending_mode,
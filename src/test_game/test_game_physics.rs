@@ -26,6 +26,21 @@ pub struct TestGamePhysics
 
 pub type ObjectHandle = r3d::RigidBodyHandle;
 
+// Description of single ragdoll bone, used for ragdoll creation.
+pub struct RagdollBone
+{
+	// Initial location of bone origin.
+	pub position: Vec3f,
+	pub rotation: QuaternionF,
+	// Index of parent bone in ragdoll bones list. Parent should be placed before child.
+	pub parent: Option<usize>,
+	// Initial location of bone end. Bone capsule is created between origin and end.
+	pub end: Vec3f,
+	pub radius: f32,
+	// Maximum angle (in radians) between this bone and parent bone for each rotation axis.
+	pub angle_limit: f32,
+}
+
 impl TestGamePhysics
 {
 	pub fn new(map: Arc<bsp_map_compact::BSPMap>, materials: &MaterialsMap) -> Self
@@ -149,6 +164,61 @@ impl TestGamePhysics
 		handle
 	}
 
+	// Create body for each bone and connect bodies with spherical joints.
+	// Returns handles of bodies in the same order as bones.
+	pub fn add_ragdoll(&mut self, entity: hecs::Entity, bones: &[RagdollBone], velocity: &Vec3f) -> Vec<ObjectHandle>
+	{
+		let mut handles: Vec<ObjectHandle> = Vec::with_capacity(bones.len());
+		for bone in bones
+		{
+			let body = r3d::RigidBodyBuilder::dynamic()
+				.user_data(make_user_data(entity, RAGDOLL_FLAG))
+				.translation(r3d::Vector::new(bone.position.x, bone.position.y, bone.position.z))
+				.rotation(quaternion_to_ang_vector(&bone.rotation))
+				.linvel(r3d::Vector::new(velocity.x, velocity.y, velocity.z))
+				.linear_damping(0.2)
+				.angular_damping(0.5)
+				.build();
+
+			// Capsule from body origin to bone end (in body space).
+			let end = bone.rotation.invert().rotate_vector(bone.end - bone.position);
+			let collider = r3d::ColliderBuilder::new(r3d::SharedShape::capsule(
+				r3d::Point::origin(),
+				r3d::Point::new(end.x, end.y, end.z),
+				bone.radius,
+			))
+			.user_data(make_user_data(entity, RAGDOLL_FLAG))
+			.restitution(0.1)
+			.friction(0.8)
+			.active_hooks(r3d::ActiveHooks::FILTER_CONTACT_PAIRS)
+			.build();
+
+			let handle = self.rigid_body_set.insert(body);
+			self.collider_set
+				.insert_with_parent(collider, handle, &mut self.rigid_body_set);
+
+			if let Some(parent_index) = bone.parent
+			{
+				let parent = &bones[parent_index];
+				let anchor = parent.rotation.invert().rotate_vector(bone.position - parent.position);
+				let limits = [-bone.angle_limit, bone.angle_limit];
+				let joint = r3d::SphericalJointBuilder::new()
+					.local_anchor1(r3d::Point::new(anchor.x, anchor.y, anchor.z))
+					.local_anchor2(r3d::Point::origin())
+					.limits(r3d::JointAxis::AngX, limits)
+					.limits(r3d::JointAxis::AngY, limits)
+					.limits(r3d::JointAxis::AngZ, limits)
+					.build();
+				self.multibody_joint_set
+					.insert(handles[parent_index], handle, joint, true);
+			}
+
+			handles.push(handle);
+		}
+
+		handles
+	}
+
 	pub fn get_object_velocity(&self, handle: ObjectHandle) -> Vec3f
 	{
 		let body = &self.rigid_body_set[handle];
@@ -411,10 +481,26 @@ impl PhysicsHooks
 }
 
 const CHARACTER_FLAG: u64 = 1;
+const RAGDOLL_FLAG: u64 = 2;
 const STAIRS_HACK_NORMAL_Z: f32 = 0.3;
 
 impl r3d::PhysicsHooks for PhysicsHooks
 {
+	fn filter_contact_pair(&self, context: &r3d::PairFilterContext) -> Option<r3d::SolverFlags>
+	{
+		// Disable collisions between bodies of same ragdoll, since neighbor bones capsules intersect.
+		let collider1 = &context.colliders[context.collider1];
+		let collider2 = &context.colliders[context.collider2];
+		if additional_data_from_user_data(collider1.user_data) == RAGDOLL_FLAG &&
+			additional_data_from_user_data(collider2.user_data) == RAGDOLL_FLAG &&
+			entity_from_user_data(collider1.user_data) == entity_from_user_data(collider2.user_data)
+		{
+			return None;
+		}
+
+		Some(r3d::SolverFlags::COMPUTE_IMPULSES)
+	}
+
 	fn modify_solver_contacts(&self, context: &mut r3d::ContactModificationContext)
 	{
 		// For colliders with stairs hack modify contact point normal in order to avoid slowing-down while climbing stairs.
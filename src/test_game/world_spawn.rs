@@ -1,4 +1,4 @@
-use super::{components::*, frame_info::*, resources_manager::*, test_game_physics::*};
+use super::{components::*, frame_info::*, resources_manager::*, test_game_physics::*, textures::TextureLiteWithMips};
 use square_wheel_lib::common::{
 	bbox::*, bsp_map_compact, camera_rotation_controller::*, map_file_common, material, math_types::*,
 };
@@ -61,9 +61,27 @@ fn spawn_regular_entity(
 				let bbox = bsp_map_compact::get_submodel_bbox(map, &map.submodels[index]);
 
 				let entity = ecs.spawn((WaterVolumeComponent { bbox },));
+				// Water may be murky.
+				if let Some(fog_volume) = get_entity_fog_volume(map_entity, map, &bbox, 0.0)
+				{
+					ecs.insert_one(entity, fog_volume).ok();
+				}
 				add_entity_common_components(ecs, map, map_entity, entity);
 			}
 		},
+		Some("func_fog") =>
+		{
+			let index = map_entity.submodel_index as usize;
+			if index < map.submodels.len()
+			{
+				let bbox = bsp_map_compact::get_submodel_bbox(map, &map.submodels[index]);
+				if let Some(fog_volume) = get_entity_fog_volume(map_entity, map, &bbox, 1.0 / 512.0)
+				{
+					let entity = ecs.spawn((fog_volume,));
+					add_entity_common_components(ecs, map, map_entity, entity);
+				}
+			}
+		},
 		Some("info_teleport_destination") =>
 		{
 			if let Some(origin) = get_entity_origin(map_entity, map)
@@ -452,11 +470,22 @@ fn spawn_regular_entity(
 					}
 				}
 
-				let texture = resources_manager.get_texture_lite(&texture_file_name);
+				let texture = get_entity_material_texture_lite(map_entity, map, resources_manager)
+					.unwrap_or_else(|| resources_manager.get_texture_lite(&texture_file_name));
 
 				let position = origin;
 				let rotation = get_entity_rotation(map_entity, map);
 
+				let mut flags = ModelEntityDrawFlags::empty();
+				if get_entity_f32(map_entity, map, "per_pixel_lighting").unwrap_or(0.0) != 0.0
+				{
+					flags |= ModelEntityDrawFlags::PER_PIXEL_LIGHTING;
+				}
+				if get_entity_f32(map_entity, map, "use_materials").unwrap_or(0.0) != 0.0
+				{
+					flags |= ModelEntityDrawFlags::USE_MATERIALS;
+				}
+
 				ecs.spawn((
 					SimpleAnimationComponent {},
 					LocationComponent { position, rotation },
@@ -468,11 +497,12 @@ fn spawn_regular_entity(
 							frames: [0, 0],
 							lerp: 0.0,
 						},
+						skeleton_pose: None,
 						model,
 						texture,
 						blending_mode: get_entity_blending_mode(map_entity, map),
 						lighting: ModelLighting::Default,
-						flags: ModelEntityDrawFlags::empty(),
+						flags,
 						ordering_custom_bbox: None,
 					},
 				));
@@ -480,15 +510,13 @@ fn spawn_regular_entity(
 		},
 		Some("misc_sprite") =>
 		{
-			if let (Some(sprite_file_name), Some(origin)) = (
-				get_entity_key_value(map_entity, map, "sprite"),
+			if let (Some(texture), Some(origin)) = (
+				get_entity_texture_lite(map_entity, map, "sprite", resources_manager),
 				get_entity_origin(map_entity, map),
 			)
 			{
 				let scale = get_entity_f32(map_entity, map, "scale").unwrap_or(1.0);
 
-				let texture = resources_manager.get_texture_lite(sprite_file_name);
-
 				let texture_mip0 = &texture[0];
 				let radius = scale *
 					0.25 * ((texture_mip0.size[0] * texture_mip0.size[0] +
@@ -556,15 +584,13 @@ fn spawn_regular_entity(
 		},
 		Some("misc_decal") =>
 		{
-			if let (Some(decal_file_name), Some(origin)) = (
-				get_entity_key_value(map_entity, map, "decal"),
+			if let (Some(texture), Some(origin)) = (
+				get_entity_texture_lite(map_entity, map, "decal", resources_manager),
 				get_entity_origin(map_entity, map),
 			)
 			{
 				let scale = get_entity_f32(map_entity, map, "scale").unwrap_or(1.0);
 
-				let texture = resources_manager.get_texture_lite(decal_file_name);
-
 				let texture_mip0 = &texture[0];
 				let size = Vec3f::new(
 					texture_mip0.size[0].min(texture_mip0.size[1]) as f32,
@@ -620,6 +646,7 @@ fn spawn_regular_entity(
 							frames: [0, 0],
 							lerp: 0.0,
 						},
+						skeleton_pose: None,
 						model: resources_manager.get_model("wall_oil_lamp.iqm"),
 						texture: resources_manager.get_texture_lite("wall_oil_lamp.png"),
 						blending_mode: material::BlendingMode::None,
@@ -930,18 +957,10 @@ fn get_entity_angle_rad(entity: &bsp_map_compact::Entity, map: &bsp_map_compact:
 
 fn get_entity_rotation(entity: &bsp_map_compact::Entity, map: &bsp_map_compact::BSPMap) -> QuaternionF
 {
-	if let Some(angles_str) = get_entity_key_value(entity, map, "angles")
-	{
-		if let Ok(angles) = map_file_common::parse_vec3(angles_str)
-		{
-			// Angles in Quake and Quake editors (like TrenchBroom) are in order YZX.
-			return QuaternionF::from_angle_z(Rad(angles.y * TO_RAD)) *
-				QuaternionF::from_angle_y(Rad(angles.x * TO_RAD)) *
-				QuaternionF::from_angle_x(Rad(angles.z * TO_RAD));
-		}
-	}
-
-	QuaternionF::from_angle_z(Rad(get_entity_angle_rad(entity, map)))
+	map_file_common::get_entity_rotation_from_angles(
+		get_entity_key_value(entity, map, "angles").and_then(|s| map_file_common::parse_vec3(s).ok()),
+		get_entity_f32(entity, map, "angle"),
+	)
 }
 
 fn get_entity_f32(entity: &bsp_map_compact::Entity, map: &bsp_map_compact::BSPMap, key: &str) -> Option<f32>
@@ -949,6 +968,38 @@ fn get_entity_f32(entity: &bsp_map_compact::Entity, map: &bsp_map_compact::BSPMa
 	get_entity_key_value(entity, map, key).unwrap_or("").parse::<f32>().ok()
 }
 
+fn get_entity_fog_volume(
+	entity: &bsp_map_compact::Entity,
+	map: &bsp_map_compact::BSPMap,
+	bbox: &BBox,
+	default_density: f32,
+) -> Option<FogVolume>
+{
+	let density = get_entity_f32(entity, map, "fog_density").unwrap_or(default_density);
+	if density <= 0.0
+	{
+		return None;
+	}
+
+	let mut color = [128.0, 128.0, 128.0];
+	if let Some(entity_color_str) = get_entity_key_value(entity, map, "fog_color")
+	{
+		if let Ok(entity_color) = map_file_common::parse_vec3(entity_color_str)
+		{
+			color = [entity_color.x, entity_color.y, entity_color.z].map(|c| c.clamp(0.0, 255.0));
+		}
+	}
+
+	Some(FogVolume {
+		bbox: *bbox,
+		color,
+		density,
+		height_falloff: get_entity_f32(entity, map, "fog_height_falloff")
+			.unwrap_or(0.0)
+			.max(0.0),
+	})
+}
+
 fn get_entity_origin(entity: &bsp_map_compact::Entity, map: &bsp_map_compact::BSPMap) -> Option<Vec3f>
 {
 	if let Some(origin_str) = get_entity_key_value(entity, map, "origin")
@@ -982,6 +1033,29 @@ fn get_entity_blending_mode(entity: &bsp_map_compact::Entity, map: &bsp_map_comp
 	}
 }
 
+// Get texture of material, specified via "material" key.
+// Texture of material may be animated.
+fn get_entity_material_texture_lite(
+	entity: &bsp_map_compact::Entity,
+	map: &bsp_map_compact::BSPMap,
+	resources_manager: &mut ResourcesManager,
+) -> Option<SharedResourcePtr<TextureLiteWithMips>>
+{
+	get_entity_key_value(entity, map, "material").map(|m| resources_manager.get_material_texture_lite(m))
+}
+
+// Get texture of material (if specified) or image with given key.
+fn get_entity_texture_lite(
+	entity: &bsp_map_compact::Entity,
+	map: &bsp_map_compact::BSPMap,
+	image_key: &str,
+	resources_manager: &mut ResourcesManager,
+) -> Option<SharedResourcePtr<TextureLiteWithMips>>
+{
+	get_entity_material_texture_lite(entity, map, resources_manager)
+		.or_else(|| get_entity_key_value(entity, map, image_key).map(|image| resources_manager.get_texture_lite(image)))
+}
+
 fn get_entity_classname<'a>(entity: &bsp_map_compact::Entity, map: &'a bsp_map_compact::BSPMap) -> Option<&'a str>
 {
 	get_entity_key_value(entity, map, "classname")
@@ -1,5 +1,8 @@
-use super::{components::*, frame_info::*, resources_manager::*, test_game_physics::*};
+use super::{
+	components::*, frame_info::*, resources_manager::*, test_game_physics::*, triangle_model_animation::*,
+};
 use square_wheel_lib::common::{material, math_types::*, system_window};
+use std::sync::Arc;
 
 pub fn update_player_entity(
 	ecs: &mut hecs::World,
@@ -1052,6 +1055,53 @@ pub fn update_phys_model_locations(ecs: &mut hecs::World, physics: &TestGamePhys
 	}
 }
 
+pub fn update_ragdolls(ecs: &mut hecs::World, physics: &TestGamePhysics)
+{
+	for (_id, (ragdoll_component, location_component, model_entity)) in
+		ecs.query_mut::<(&RagdollComponent, &mut LocationComponent, &mut ModelEntity)>()
+	{
+		let root_body = if let Some(Some(b)) = ragdoll_component
+			.bones_bodies
+			.get(ragdoll_component.root_bone_index)
+		{
+			*b
+		}
+		else
+		{
+			continue;
+		};
+
+		// Calculate model location based on root body location.
+		let (root_body_position, root_body_rotation) = physics.get_object_location(root_body);
+		let rotation = root_body_rotation * ragdoll_component.root_body_rotation.invert();
+		let position = root_body_position - rotation.rotate_vector(ragdoll_component.root_body_position);
+		location_component.position = position;
+		location_component.rotation = rotation;
+
+		// Override transformations of all bones with bodies.
+		let inverse_rotation = rotation.invert();
+		let overrides = ragdoll_component
+			.bones_bodies
+			.iter()
+			.enumerate()
+			.filter_map(|(bone_index, body)| {
+				let (body_position, body_rotation) = physics.get_object_location((*body)?);
+				let transform = Mat4f::from_translation(inverse_rotation.rotate_vector(body_position - position)) *
+					Mat4f::from(inverse_rotation * body_rotation);
+				Some(SkeletonBoneOverride {
+					bone_index: bone_index as u32,
+					kind: SkeletonBoneOverrideKind::Transform(transform),
+				})
+			})
+			.collect::<Vec<_>>();
+
+		let pose = model_entity
+			.skeleton_pose
+			.get_or_insert_with(|| Arc::new(SkeletonPose::default()));
+		build_skeleton_pose(&model_entity.model, &[], &overrides, Arc::make_mut(pose));
+	}
+}
+
 pub fn update_other_entity_locations(ecs: &mut hecs::World)
 {
 	for (id, (other_entity_location_component, location_component)) in ecs
@@ -1088,6 +1138,56 @@ pub fn update_other_entity_locations(ecs: &mut hecs::World)
 	}
 }
 
+pub fn update_model_bone_locations(ecs: &mut hecs::World)
+{
+	for (id, (model_bone_location_component, location_component)) in ecs
+		.query::<(&ModelBoneLocationComponent, &mut LocationComponent)>()
+		.into_iter()
+	{
+		if let Ok(mut q) = ecs.query_one::<(&LocationComponent, &ModelEntity)>(model_bone_location_component.entity)
+		{
+			if let Some((src_location_component, model_entity)) = q.get()
+			{
+				let bone_matrix = find_bone(&model_entity.model, &model_bone_location_component.bone_name)
+					.and_then(|bone_index| get_model_entity_bone_model_space_matrix(model_entity, bone_index));
+				if let Some(bone_matrix) = bone_matrix
+				{
+					// Use location component of other entity, since model entity location may be not updated yet.
+					let (bone_position, bone_rotation) = get_bone_matrix_position_and_rotation(&bone_matrix);
+					let position = src_location_component.position +
+						src_location_component.rotation.rotate_vector(bone_position);
+					let rotation = src_location_component.rotation * bone_rotation;
+
+					location_component.position =
+						position + rotation.rotate_vector(model_bone_location_component.relative_position);
+					location_component.rotation = rotation * model_bone_location_component.relative_rotation;
+				}
+				else
+				{
+					ecs_warning(&format!(
+						"Entity {:?} model has no bone {:?}",
+						model_bone_location_component.entity, model_bone_location_component.bone_name
+					));
+				}
+			}
+			else
+			{
+				ecs_warning(&format!(
+					"Entity {:?} missing location or model component",
+					model_bone_location_component.entity
+				));
+			}
+		}
+		else
+		{
+			ecs_warning(&format!(
+				"Entity {:?} missing other entity {:?}",
+				id, model_bone_location_component.entity
+			));
+		}
+	}
+}
+
 pub fn update_player_controller_camera_locations(ecs: &mut hecs::World, physics: &TestGamePhysics)
 {
 	for (id, (player_controller_camera_location_component, location_component)) in ecs